@@ -5,7 +5,7 @@
 //! and action payloads.
 
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{ToTokens, quote};
 use syn::{Data, DeriveInput, Fields, parse_macro_input};
 
 /// Convert a CamelCase or PascalCase string to snake_case.
@@ -24,14 +24,457 @@ fn to_snake_case(s: &str) -> String {
     result
 }
 
+/// Doc comment lines of the item, trimmed and joined with spaces.
+fn doc_text(attrs: &[syn::Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            syn::Meta::NameValue(nv) => match &nv.value {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Options from the `#[ai_action(...)]` attributes of the type.
+struct ActionOptions {
+    /// `version = N`, `1` when absent.
+    version: u32,
+    /// `default`: implement `Default` from the defaults of the fields.
+    default: bool,
+    /// `description = ".."`, shown instead of the doc comment.
+    description: Option<String>,
+}
+
+fn action_options(attrs: &[syn::Attribute]) -> syn::Result<ActionOptions> {
+    let mut options = ActionOptions {
+        version: 1,
+        default: false,
+        description: None,
+    };
+    for attr in attrs.iter().filter(|a| a.path().is_ident("ai_action")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("version") {
+                let lit: syn::LitInt = meta.value()?.parse()?;
+                options.version = lit.base10_parse()?;
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                options.default = true;
+                Ok(())
+            } else if meta.path.is_ident("description") {
+                let lit: syn::LitStr = meta.value()?.parse()?;
+                options.description = Some(lit.value());
+                Ok(())
+            } else {
+                Err(meta.error(
+                    "unknown ai_action attribute, expected `version`, `default` or `description`",
+                ))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+/// Options from the `#[ai(...)]` attributes of a field.
+#[derive(Default)]
+struct FieldOptions {
+    flatten: bool,
+    optional: bool,
+    default: Option<syn::Expr>,
+    /// Bounds from `#[ai(range(min = .., max = ..))]`.
+    range: Option<(Option<syn::Expr>, Option<syn::Expr>)>,
+    one_of: Vec<syn::Lit>,
+    regex: Option<syn::LitStr>,
+}
+
+impl FieldOptions {
+    fn has_checks(&self) -> bool {
+        self.range.is_some() || !self.one_of.is_empty() || self.regex.is_some()
+    }
+}
+
+/// Parse `#[ai(flatten)]`, `#[ai(optional)]`, `#[ai(default = expr)]` and the checks
+/// `#[ai(range(min = a, max = b))]`, `#[ai(one_of(..))]` and `#[ai(regex = "..")]`. Flattened
+/// fields must also carry `#[serde(flatten)]`, otherwise the schema would not match what serde
+/// parses.
+fn field_options(field: &syn::Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("ai")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("flatten") {
+                options.flatten = true;
+                Ok(())
+            } else if meta.path.is_ident("optional") {
+                options.optional = true;
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                options.default = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("range") {
+                let (mut min, mut max) = (None, None);
+                meta.parse_nested_meta(|bound| {
+                    if bound.path.is_ident("min") {
+                        min = Some(bound.value()?.parse()?);
+                    } else if bound.path.is_ident("max") {
+                        max = Some(bound.value()?.parse()?);
+                    } else {
+                        return Err(bound.error("expected `min` or `max`"));
+                    }
+                    Ok(())
+                })?;
+                if min.is_none() && max.is_none() {
+                    return Err(meta.error("`range` needs `min`, `max` or both"));
+                }
+                options.range = Some((min, max));
+                Ok(())
+            } else if meta.path.is_ident("one_of") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                let values =
+                    syn::punctuated::Punctuated::<syn::Lit, syn::Token![,]>::parse_terminated(
+                        &content,
+                    )?;
+                options.one_of = values.into_iter().collect();
+                Ok(())
+            } else if meta.path.is_ident("regex") {
+                options.regex = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error(
+                    "unknown ai attribute, expected `flatten`, `optional`, `default`, `range`, \
+                     `one_of` or `regex`",
+                ))
+            }
+        })?;
+    }
+    if options.flatten && !serde_options(&field.attrs)?.flatten {
+        return Err(syn::Error::new_spanned(
+            field,
+            "`#[ai(flatten)]` fields must also be marked `#[serde(flatten)]`",
+        ));
+    }
+    if options.flatten && (options.optional || options.default.is_some() || options.has_checks()) {
+        return Err(syn::Error::new_spanned(
+            field,
+            "`#[ai(flatten)]` fields cannot be optional or checked, mark the flattened fields \
+             instead",
+        ));
+    }
+    Ok(options)
+}
+
+/// Schema annotation and validation statements for the checks of a field, and a closure
+/// checking one value (`None` without checks).
+fn check_tokens(
+    options: &FieldOptions,
+    field_name: &str,
+) -> (
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+    Option<proc_macro2::TokenStream>,
+) {
+    let mut describe = Vec::new();
+    let mut check = Vec::new();
+    if let Some((min, max)) = &options.range {
+        let bound = |b: &Option<syn::Expr>| match b {
+            Some(expr) => quote! { Some((#expr) as f64) },
+            None => quote! { None },
+        };
+        let (min, max) = (bound(min), bound(max));
+        describe.push(quote! {
+            ty.push_str(", ");
+            ty.push_str(&bevy_real_ai::parse::describe_range(#min, #max));
+        });
+        check.push(quote! { bevy_real_ai::parse::check_range(#field_name, value, #min, #max)?; });
+    }
+    if !options.one_of.is_empty() {
+        let values = &options.one_of;
+        let allowed = quote! { &[#(serde_json::json!(#values)),*] };
+        describe.push(quote! {
+            ty.push_str(", ");
+            ty.push_str(&bevy_real_ai::parse::describe_one_of(#allowed));
+        });
+        check.push(quote! { bevy_real_ai::parse::check_one_of(#field_name, value, #allowed)?; });
+    }
+    if let Some(pattern) = &options.regex {
+        describe.push(quote! {
+            ty.push_str(&format!(", matching {}", #pattern));
+        });
+        // Compiled once per field, on the first check
+        check.push(quote! {{
+            static PATTERN: std::sync::LazyLock<bevy_real_ai::parse::CompiledPattern> =
+                std::sync::LazyLock::new(|| bevy_real_ai::parse::compile_pattern(#field_name, #pattern));
+            bevy_real_ai::parse::check_pattern(#field_name, value, &PATTERN)?;
+        }});
+    }
+    if check.is_empty() {
+        return (quote! {}, quote! {}, None);
+    }
+    (
+        quote! { #(#describe)* },
+        quote! {
+            if let Some(value) = params.get(#field_name).filter(|v| !v.is_null()) {
+                #(#check)*
+            }
+        },
+        Some(quote! {
+            |value: &serde_json::Value| -> Result<(), String> {
+                #(#check)*
+                Ok(())
+            }
+        }),
+    )
+}
+
+/// Example value of a field: its `#[ai(default)]`, else a placeholder for its type adjusted
+/// to its `#[ai(one_of)]` and `#[ai(range)]` checks.
+fn example_tokens(options: &FieldOptions, field_type: &syn::Type) -> proc_macro2::TokenStream {
+    let placeholder = quote! { <#field_type as bevy_real_ai::parse::AiSchemaType>::example() };
+    if let Some(expr) = &options.default {
+        return quote! {
+            serde_json::to_value::<#field_type>(#expr).unwrap_or_else(|_| #placeholder)
+        };
+    }
+    let mut value = placeholder;
+    if !options.one_of.is_empty() {
+        let values = &options.one_of;
+        value = quote! {
+            bevy_real_ai::parse::example_one_of(#value, &[#(serde_json::json!(#values)),*])
+        };
+    }
+    if let Some((min, max)) = &options.range {
+        let bound = |b: &Option<syn::Expr>| match b {
+            Some(expr) => quote! { Some((#expr) as f64) },
+            None => quote! { None },
+        };
+        let (min, max) = (bound(min), bound(max));
+        value = quote! { bevy_real_ai::parse::example_in_range(#value, #min, #max) };
+    }
+    value
+}
+
+/// What serde does with a field or variant, as far as the schema is concerned.
+#[derive(Default)]
+struct SerdeOptions {
+    /// `rename = ".."`, or the `deserialize` name of `rename(..)`.
+    rename: Option<String>,
+    /// `rename_all = ".."` on the container, or its `deserialize` rule.
+    rename_all: Option<String>,
+    /// `skip` or `skip_deserializing`: never read from the model's JSON.
+    skip: bool,
+    /// `flatten`: the field's own fields are read from the parent object.
+    flatten: bool,
+}
+
+/// Read the `#[serde(...)]` attributes the schema depends on, ignoring all others.
+fn serde_options(attrs: &[syn::Attribute]) -> syn::Result<SerdeOptions> {
+    // `rename = ".."` or `rename(serialize = "..", deserialize = "..")`
+    fn deserialize_name(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<String>> {
+        if meta.input.peek(syn::Token![=]) {
+            let name: syn::LitStr = meta.value()?.parse()?;
+            return Ok(Some(name.value()));
+        }
+        let mut name = None;
+        meta.parse_nested_meta(|inner| {
+            let value: syn::LitStr = inner.value()?.parse()?;
+            if inner.path.is_ident("deserialize") {
+                name = Some(value.value());
+            }
+            Ok(())
+        })?;
+        Ok(name)
+    }
+
+    let mut options = SerdeOptions::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                options.rename = deserialize_name(&meta)?;
+            } else if meta.path.is_ident("rename_all") {
+                options.rename_all = deserialize_name(&meta)?;
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                options.skip = true;
+            } else if meta.path.is_ident("flatten") {
+                options.flatten = true;
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                content.parse::<proc_macro2::TokenStream>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+/// Apply a serde `rename_all` rule to a snake_case field or PascalCase variant name. Like
+/// serde, `lowercase` and `UPPERCASE` only change the case, so a field keeps its underscores.
+fn apply_rename_all(
+    rule: &str,
+    name: &str,
+    variant: bool,
+    span: &impl ToTokens,
+) -> syn::Result<String> {
+    let words: Vec<String> = to_snake_case(name)
+        .split('_')
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect();
+    let capitalized = || {
+        words
+            .iter()
+            .map(|w| {
+                let mut chars = w.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+    Ok(match rule {
+        "lowercase" if variant => name.to_ascii_lowercase(),
+        "lowercase" => name.to_string(),
+        "UPPERCASE" => name.to_ascii_uppercase(),
+        "PascalCase" => capitalized(),
+        "camelCase" => {
+            let pascal = capitalized();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_lowercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        }
+        "snake_case" => words.join("_"),
+        "SCREAMING_SNAKE_CASE" => words.join("_").to_uppercase(),
+        "kebab-case" => words.join("-"),
+        "SCREAMING-KEBAB-CASE" => words.join("-").to_uppercase(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                span,
+                format!("unknown serde rename_all rule `{}`", rule),
+            ));
+        }
+    })
+}
+
+/// The name serde reads a field or enum `variant` under, `None` if serde skips it.
+fn serde_name(
+    ident: &syn::Ident,
+    attrs: &[syn::Attribute],
+    rename_all: Option<&str>,
+    variant: bool,
+) -> syn::Result<Option<String>> {
+    let options = serde_options(attrs)?;
+    if options.skip {
+        return Ok(None);
+    }
+    match (options.rename, rename_all) {
+        (Some(rename), _) => Ok(Some(rename)),
+        (None, Some(rule)) => apply_rename_all(rule, &ident.to_string(), variant, ident).map(Some),
+        (None, None) => Ok(Some(ident.to_string())),
+    }
+}
+
+/// `metadata()` method and (for non-generic types) global registration.
+fn metadata_tokens(input: &DeriveInput, action_name: &str) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let type_name = name.to_string();
+    let (version, doc) = match action_options(&input.attrs) {
+        Ok(options) => (
+            options.version,
+            options
+                .description
+                .unwrap_or_else(|| doc_text(&input.attrs)),
+        ),
+        Err(e) => return e.to_compile_error(),
+    };
+
+    let metadata = quote! {
+        bevy_real_ai::actions::AiActionMetadata {
+            name: #action_name,
+            type_name: #type_name,
+            schema: <#name #ty_generics as bevy_real_ai::parse::AiParsable>::schema_description,
+            version: #version,
+            doc: #doc,
+        }
+    };
+
+    let submit = if input.generics.params.is_empty() {
+        quote! { bevy_real_ai::__submit_action_metadata!(#metadata); }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Name, schema, version and documentation of this action type.
+            pub fn metadata() -> bevy_real_ai::actions::AiActionMetadata {
+                #metadata
+            }
+        }
+
+        #submit
+    }
+}
+
 /// Derive macro for AI response parsing and action payload conversion.
 ///
 /// Generates implementations of:
 /// - `AiParsable` - for parsing JSON from AI responses
 /// - `IntoActionPayload` - for converting parsed structs into action payloads
 /// - A static `register` method for registering handlers with `AiActionRegistry`
+/// - A static `metadata` method returning an `AiActionMetadata` (name, schema, version, doc);
+///   with the `inventory` feature of `bevy_real_ai` enabled, non-generic types are also
+///   collected into `bevy_real_ai::actions::all_action_metadata()`
 ///
-/// The struct must also derive `serde::Deserialize` and `serde::Serialize`.
+/// The version defaults to `1` and can be set with `#[ai_action(version = 2)]`. The description
+/// listed in the `ActionCatalog` is the doc comment of the type, or
+/// `#[ai_action(description = "...")]` when the doc comment is written for programmers.
+/// `#[ai_action(default)]` also implements `Default` from the defaults of the fields; without
+/// it the type is free to derive or implement `Default` itself, or not at all.
+///
+/// A field marked `#[ai(flatten)]` (together with `#[serde(flatten)]`) contributes the fields of
+/// its type to the schema and payload instead of a nested object, so shared groups such as a
+/// position can be reused across actions. The field type must derive `AiAction` as well.
+///
+/// Fields the model may leave out are marked `#[ai(optional)]`, filled with `Default::default()`
+/// when missing, or `#[ai(default = expr)]`, filled with `expr`. Both are shown as optional in
+/// the schema, as are `Option` fields, which serde already parses as `None` when missing.
+///
+/// Values can be checked after parsing with `#[ai(range(min = 0.0, max = 100.0))]` (either
+/// bound may be left out), `#[ai(one_of("red", "green", "blue"))]` and `#[ai(regex = "^[a-z]+$")]`.
+/// The checks are listed in the schema, apply to each element of arrays, and a value breaking
+/// one fails the parse with a message naming the field.
+///
+/// `AiParsable::schema_example` is generated too: a filled-in object, shown next to the schema
+/// by `build_typed_prompt` and the typed request templates, taking each field's
+/// `#[ai(default)]`, first `one_of` value or a placeholder for its type moved into its `range`.
+/// Fields whose example would still break their checks, e.g. a placeholder not matching the
+/// `regex`, are left out of it.
+///
+/// The derive also implements `AiSchemaType`, so other derived types can be used as fields,
+/// plain or in a `Vec`/`Option`. Their schema spells out the nested fields, e.g.
+/// `"path": <array of {"x": <number>, "y": <number>}>`.
+///
+/// The struct must also derive `serde::Deserialize` and `serde::Serialize`. The schema follows
+/// `#[serde(rename)]`, `#[serde(rename_all)]` and `#[serde(skip)]`, on fields as well as on the
+/// variants of label enums.
+///
+/// Unit structs are actions without parameters (`struct OpenInventory;`, schema `{}`), and
+/// tuple structs are read like serde reads them: a newtype as its only value, others as a JSON
+/// array with one item per field. `#[ai(...)]` field attributes need named fields.
 ///
 /// The action name is derived from the struct name in snake_case.
 /// For example, `SpawnEntityAction` becomes `"spawn_entity_action"`.
@@ -54,111 +497,232 @@ fn to_snake_case(s: &str) -> String {
 /// // One way is to use with prompt_typed_action:
 /// prompt_typed_action::<SpawnAction>(&backend, "spawn a player at 0,0", entity, &mut pending)?;
 /// ```
-#[proc_macro_derive(AiAction)]
+///
+/// Enums with only unit variants are supported as label sets (e.g. for
+/// `AiRequest::classify`): the schema lists the variant names and the model is
+/// expected to answer with exactly one of them.
+#[proc_macro_derive(AiAction, attributes(ai_action, ai))]
 pub fn derive_ai_action(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+
+    if let Data::Enum(data) = &input.data {
+        return derive_label_enum(&input, data);
+    }
+
     let name = &input.ident;
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
-    // Extract field information for schema generation and action payload
-    let (fields_schema, field_params) = match &input.data {
+    if let Data::Union(_) = &input.data {
+        return syn::Error::new_spanned(name, "AiAction cannot be derived for unions")
+            .to_compile_error()
+            .into();
+    }
+
+    let rename_all = match serde_options(&input.attrs) {
+        Ok(options) => options.rename_all,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    // Extract field information for schema generation, action payload and missing fields
+    let (fields_schema, field_params, field_fills, field_checks, field_examples) = match &input.data
+    {
         Data::Struct(data) => match &data.fields {
             Fields::Named(fields) => {
-                let field_schemas: Vec<_> = fields
-                    .named
-                    .iter()
-                    .map(|f| {
-                        let field_name = f.ident.as_ref().expect("Named field must have ident");
-                        let field_name_str = field_name.to_string();
-                        let field_type = &f.ty;
-                        quote! {
-                            (#field_name_str, <#field_type as bevy_real_ai::parse::AiSchemaType>::type_name())
+                let mut field_schemas = Vec::new();
+                let mut field_param_stmts = Vec::new();
+                let mut field_fill_stmts = Vec::new();
+                let mut field_check_stmts = Vec::new();
+                let mut field_example_stmts = Vec::new();
+                for f in fields.named.iter() {
+                    let options = match field_options(f) {
+                        Ok(options) => options,
+                        Err(e) => return e.to_compile_error().into(),
+                    };
+                    let field_name = f.ident.as_ref().expect("Named field must have ident");
+                    // The key serde reads, which is what the model has to write
+                    let field_name_str =
+                        match serde_name(field_name, &f.attrs, rename_all.as_deref(), false) {
+                            Ok(Some(name)) => name,
+                            // Skipped fields are not part of the JSON at all
+                            Ok(None) => continue,
+                            Err(e) => return e.to_compile_error().into(),
+                        };
+                    let field_type = &f.ty;
+                    if options.flatten {
+                        // The nested fields appear in the parent object, as with serde
+                        field_schemas.push(quote! {
+                            fields.extend(<#field_type as bevy_real_ai::parse::AiParsable>::schema_fields());
+                        });
+                        field_param_stmts.push(quote! {
+                            if let serde_json::Value::Object(map) = serde_json::json!(self.#field_name) {
+                                for (key, value) in map {
+                                    payload = payload.with_param(key, value);
+                                }
+                            }
+                        });
+                        field_fill_stmts.push(quote! {
+                            <#field_type as bevy_real_ai::actions::IntoActionPayload>::fill_missing_params(params);
+                        });
+                        field_check_stmts.push(quote! {
+                            <#field_type as bevy_real_ai::actions::IntoActionPayload>::validate_params(params)?;
+                        });
+                        field_example_stmts.push(quote! {
+                            if let Some(serde_json::Value::Object(nested)) =
+                                <#field_type as bevy_real_ai::parse::AiParsable>::schema_example()
+                            {
+                                example.extend(nested);
+                            }
+                        });
+                    } else {
+                        let default_value = match &options.default {
+                            Some(expr) => {
+                                Some(quote! { serde_json::to_value::<#field_type>(#expr) })
+                            }
+                            None if options.optional => Some(quote! {
+                                serde_json::to_value(<#field_type as std::default::Default>::default())
+                            }),
+                            None => None,
+                        };
+                        let describe_optional = match (&default_value, &options.default) {
+                            (Some(value), Some(_)) => quote! {
+                                ty.push_str(", optional");
+                                if let Ok(default) = #value {
+                                    ty.push_str(&format!(", default {}", default));
+                                }
+                            },
+                            (Some(_), None) => quote! { ty.push_str(", optional"); },
+                            (None, _) => quote! {
+                                if <#field_type as bevy_real_ai::parse::AiSchemaType>::is_optional() {
+                                    ty.push_str(", optional");
+                                }
+                            },
+                        };
+                        let (describe_checks, checks, fits) =
+                            check_tokens(&options, &field_name_str);
+                        let value = example_tokens(&options, field_type);
+                        // An example breaking the field's own checks is left out
+                        field_example_stmts.push(match fits {
+                            Some(fits) => quote! {
+                                let value = #value;
+                                if (#fits)(&value).is_ok() {
+                                    example.insert(#field_name_str.to_string(), value);
+                                }
+                            },
+                            None => quote! {
+                                example.insert(#field_name_str.to_string(), #value);
+                            },
+                        });
+                        field_schemas.push(quote! {
+                            #[allow(unused_mut)]
+                            let mut ty = <#field_type as bevy_real_ai::parse::AiSchemaType>::describe();
+                            #describe_checks
+                            #describe_optional
+                            fields.push((#field_name_str, ty));
+                        });
+                        field_check_stmts.push(checks);
+                        field_param_stmts.push(quote! {
+                            payload = payload.with_param(#field_name_str, serde_json::json!(self.#field_name));
+                        });
+                        if let Some(value) = default_value {
+                            field_fill_stmts.push(quote! {
+                                if let serde_json::Value::Object(map) = params {
+                                    if !map.contains_key(#field_name_str) {
+                                        if let Ok(default) = #value {
+                                            map.insert(#field_name_str.to_string(), default);
+                                        }
+                                    }
+                                }
+                            });
                         }
-                    })
-                    .collect();
-
-                // Generate the with_param calls for each field
-                let field_param_calls: Vec<_> = fields
-                    .named
-                    .iter()
-                    .map(|f| {
-                        let field_name = f.ident.as_ref().expect("Named field must have ident");
-                        let field_name_str = field_name.to_string();
-                        quote! {
-                            .with_param(#field_name_str, serde_json::json!(self.#field_name))
-                        }
-                    })
-                    .collect();
+                    }
+                }
 
                 (
-                    quote! { vec![#(#field_schemas),*] },
-                    quote! { #(#field_param_calls)* },
+                    quote! { #(#field_schemas)* },
+                    quote! { #(#field_param_stmts)* },
+                    quote! { #(#field_fill_stmts)* },
+                    quote! { #(#field_check_stmts)* },
+                    quote! { #(#field_example_stmts)* },
                 )
             }
-            _ => (quote! { vec![] }, quote! {}),
+            _ => (quote! {}, quote! {}, quote! {}, quote! {}, quote! {}),
         },
-        _ => (quote! { vec![] }, quote! {}),
+        _ => (quote! {}, quote! {}, quote! {}, quote! {}, quote! {}),
     };
 
     let struct_name_str = name.to_string();
     let action_name_str = to_snake_case(&struct_name_str);
+    let metadata = metadata_tokens(&input, &action_name_str);
 
-    // For named-field structs, prepare default initializers and type bounds for Default impl
-    let (default_inits, default_type_bounds) = match &input.data {
-        Data::Struct(data) => match &data.fields {
-            Fields::Named(fields) => {
-                let inits: Vec<_> = fields
-                    .named
-                    .iter()
-                    .map(|f| {
-                        let field_name = f.ident.as_ref().expect("Named field must have ident");
-                        quote! { #field_name: std::default::Default::default() }
-                    })
-                    .collect();
-                let types: Vec<_> = fields.named.iter().map(|f| &f.ty).collect();
-                (
-                    quote! { #(#inits),* },
-                    quote! { #(#types: std::default::Default),* },
-                )
-            }
-            _ => (quote! {}, quote! {}),
-        },
-        _ => (quote! {}, quote! {}),
+    let default_impl = match action_options(&input.attrs) {
+        Ok(options) if options.default => default_impl(&input),
+        Ok(_) => quote! {},
+        Err(e) => return e.to_compile_error().into(),
     };
 
-    // Build the merged where clause for Default impl (include existing where predicates if present)
-    let default_where_clause = if default_type_bounds.to_string().trim().is_empty() {
-        quote! {}
-    } else if let Some(wc) = &input.generics.where_clause {
-        let preds = &wc.predicates;
-        quote! { where #preds, #default_type_bounds }
-    } else {
-        quote! { where #default_type_bounds }
+    let shape = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(_) => named_shape(&action_name_str, field_params, field_examples),
+            Fields::Unnamed(fields) => match tuple_shape(&action_name_str, fields) {
+                Ok(shape) => shape,
+                Err(e) => return e.to_compile_error().into(),
+            },
+            Fields::Unit => unit_shape(&action_name_str),
+        },
+        _ => unreachable!("enums and unions are handled above"),
     };
+    let StructShape {
+        schema_description,
+        schema_type_name,
+        describe,
+        example,
+        payload,
+        parse,
+        fill,
+    } = shape;
 
     let expanded = quote! {
         impl #impl_generics bevy_real_ai::parse::AiParsable for #name #ty_generics #where_clause {
             fn schema_description() -> String {
-                let fields: Vec<(&str, &str)> = #fields_schema;
-                let field_descs: Vec<String> = fields
-                    .iter()
-                    .map(|(name, ty)| format!("  \"{}\": <{}>", name, ty))
-                    .collect();
-                format!(
-                    "JSON object with fields:\n{{\n{}\n}}",
-                    field_descs.join(",\n")
-                )
+                #schema_description
+            }
+
+            fn schema_fields() -> Vec<(&'static str, String)> {
+                #[allow(unused_mut)]
+                let mut fields: Vec<(&'static str, String)> = Vec::new();
+                #fields_schema
+                fields
+            }
+
+            fn schema_example() -> Option<serde_json::Value> {
+                #example
             }
 
             fn type_name() -> &'static str {
                 #struct_name_str
             }
 
-            fn parse_from_ai_response(response: &str) -> Result<Self, String>
+            fn parse_from_ai_response(response: &str) -> Result<Self, bevy_real_ai::error::AiError>
             where
                 Self: Sized + serde::de::DeserializeOwned,
             {
-                bevy_real_ai::parse::extract_and_parse_json(response)
+                #parse
+            }
+        }
+
+        // Lets the type be a field, or an array element, of other derived types
+        impl #impl_generics bevy_real_ai::parse::AiSchemaType for #name #ty_generics #where_clause {
+            fn type_name() -> &'static str {
+                #schema_type_name
+            }
+
+            fn describe() -> String {
+                #describe
+            }
+
+            fn example() -> serde_json::Value {
+                <Self as bevy_real_ai::parse::AiParsable>::schema_example()
+                    .unwrap_or(serde_json::Value::Null)
             }
         }
 
@@ -168,8 +732,23 @@ pub fn derive_ai_action(input: TokenStream) -> TokenStream {
             }
 
             fn into_action_payload(self) -> bevy_real_ai::actions::ActionPayload {
-                bevy_real_ai::actions::ActionPayload::new(#action_name_str)
-                    #field_params
+                #payload
+            }
+
+            fn action_metadata() -> Option<bevy_real_ai::actions::AiActionMetadata> {
+                Some(Self::metadata())
+            }
+
+            #[allow(unused_variables)]
+            fn fill_missing_params(params: &mut serde_json::Value) {
+                #field_fills
+                #fill
+            }
+
+            #[allow(unused_variables)]
+            fn validate_params(params: &serde_json::Value) -> Result<(), String> {
+                #field_checks
+                Ok(())
             }
         }
 
@@ -188,19 +767,338 @@ pub fn derive_ai_action(input: TokenStream) -> TokenStream {
                 S: bevy::ecs::system::IntoSystem<bevy::ecs::system::In<Self>, (), M> + 'static,
                 Self: Sized + 'static + Send + Sync,
             {
-                registry.register_typed::<Self, S, M>(
-                    <Self as bevy_real_ai::actions::IntoActionPayload>::action_name(),
-                    system,
-                );
+                registry.register_action::<Self, S, M>(system);
             }
         }
 
-        // Implement Default using field defaults when applicable
-        impl #impl_generics std::default::Default for #name #ty_generics #default_where_clause {
+        #default_impl
+
+        #metadata
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// The parts of the generated impls that depend on the kind of struct.
+struct StructShape {
+    schema_description: proc_macro2::TokenStream,
+    /// `AiSchemaType::type_name` when used as a field.
+    schema_type_name: proc_macro2::TokenStream,
+    /// `AiSchemaType::describe` when used as a field.
+    describe: proc_macro2::TokenStream,
+    /// `AiParsable::schema_example`.
+    example: proc_macro2::TokenStream,
+    payload: proc_macro2::TokenStream,
+    parse: proc_macro2::TokenStream,
+    /// Extra `fill_missing_params` statements.
+    fill: proc_macro2::TokenStream,
+}
+
+/// Structs with named fields are JSON objects.
+fn named_shape(
+    action_name: &str,
+    field_params: proc_macro2::TokenStream,
+    field_examples: proc_macro2::TokenStream,
+) -> StructShape {
+    StructShape {
+        schema_description: quote! {
+            let field_descs: Vec<String> = Self::schema_fields()
+                .iter()
+                .map(|(name, ty)| format!("  \"{}\": <{}>", name, ty))
+                .collect();
+            format!(
+                "JSON object with fields:\n{{\n{}\n}}",
+                field_descs.join(",\n")
+            )
+        },
+        schema_type_name: quote! { "object" },
+        describe: quote! {
+            let field_descs: Vec<String> =
+                <Self as bevy_real_ai::parse::AiParsable>::schema_fields()
+                    .iter()
+                    .map(|(name, ty)| format!("\"{}\": <{}>", name, ty))
+                    .collect();
+            format!("{{{}}}", field_descs.join(", "))
+        },
+        example: quote! {
+            #[allow(unused_mut)]
+            let mut example = serde_json::Map::new();
+            #field_examples
+            Some(serde_json::Value::Object(example))
+        },
+        payload: quote! {
+            #[allow(unused_mut)]
+            let mut payload = bevy_real_ai::actions::ActionPayload::new(#action_name);
+            #field_params
+            payload
+        },
+        parse: quote! { bevy_real_ai::parse::extract_and_parse_action(response) },
+        fill: quote! {},
+    }
+}
+
+/// Tuple structs are read by serde as their only value (newtypes) or as a JSON array.
+fn tuple_shape(action_name: &str, fields: &syn::FieldsUnnamed) -> syn::Result<StructShape> {
+    if let Some(attr) = fields
+        .unnamed
+        .iter()
+        .flat_map(|f| f.attrs.iter())
+        .find(|a| a.path().is_ident("ai"))
+    {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "`#[ai(...)]` field attributes are only supported on structs with named fields",
+        ));
+    }
+    let types: Vec<_> = fields.unnamed.iter().map(|f| &f.ty).collect();
+    let indices: Vec<_> = (0..types.len()).map(syn::Index::from).collect();
+    let shape = if let [ty] = types.as_slice() {
+        StructShape {
+            schema_description: quote! {
+                format!("JSON value: <{}>", <#ty as bevy_real_ai::parse::AiSchemaType>::describe())
+            },
+            schema_type_name: quote! { <#ty as bevy_real_ai::parse::AiSchemaType>::type_name() },
+            describe: quote! { <#ty as bevy_real_ai::parse::AiSchemaType>::describe() },
+            example: quote! { Some(<#ty as bevy_real_ai::parse::AiSchemaType>::example()) },
+            payload: quote! {
+                bevy_real_ai::actions::ActionPayload {
+                    name: #action_name.to_string(),
+                    params: serde_json::json!(self.0),
+                }
+            },
+            parse: quote! { bevy_real_ai::parse::extract_and_parse_action(response) },
+            fill: quote! {},
+        }
+    } else {
+        let items = quote! {
+            let items: Vec<String> = vec![
+                #(format!("<{}>", <#types as bevy_real_ai::parse::AiSchemaType>::describe())),*
+            ];
+        };
+        StructShape {
+            schema_description: quote! {
+                #items
+                format!("JSON array: [{}]", items.join(", "))
+            },
+            schema_type_name: quote! { "array" },
+            describe: quote! {
+                #items
+                format!("[{}]", items.join(", "))
+            },
+            example: quote! {
+                Some(serde_json::Value::Array(vec![
+                    #(<#types as bevy_real_ai::parse::AiSchemaType>::example()),*
+                ]))
+            },
+            payload: quote! {
+                bevy_real_ai::actions::ActionPayload {
+                    name: #action_name.to_string(),
+                    params: serde_json::Value::Array(vec![#(serde_json::json!(self.#indices)),*]),
+                }
+            },
+            parse: quote! { bevy_real_ai::parse::extract_and_parse_action(response) },
+            fill: quote! {},
+        }
+    };
+    Ok(shape)
+}
+
+/// Unit structs are actions without parameters: any reply triggers them.
+fn unit_shape(action_name: &str) -> StructShape {
+    StructShape {
+        schema_description: quote! { "JSON object with no fields: {}".to_string() },
+        schema_type_name: quote! { "object" },
+        describe: quote! { "{}".to_string() },
+        example: quote! { Some(serde_json::json!({})) },
+        payload: quote! {
+            bevy_real_ai::actions::ActionPayload {
+                name: #action_name.to_string(),
+                params: serde_json::Value::Object(serde_json::Map::new()),
+            }
+        },
+        parse: quote! {
+            serde_json::from_value(serde_json::Value::Null)
+                .map_err(|e| bevy_real_ai::error::AiError::parse_failure(response, e.to_string()))
+        },
+        // serde reads unit structs from `null`, the model sends `{}`
+        fill: quote! { *params = serde_json::Value::Null; },
+    }
+}
+
+/// `Default` for `#[ai_action(default)]`, from the defaults of the fields.
+fn default_impl(input: &DeriveInput) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let Data::Struct(syn::DataStruct {
+        fields: Fields::Named(fields),
+        ..
+    }) = &input.data
+    else {
+        return syn::Error::new_spanned(
+            name,
+            "`#[ai_action(default)]` is only supported on structs with named fields",
+        )
+        .to_compile_error();
+    };
+
+    let inits = fields.named.iter().map(|f| {
+        let field_name = f.ident.as_ref().expect("Named field must have ident");
+        quote! { #field_name: std::default::Default::default() }
+    });
+    let types = fields.named.iter().map(|f| &f.ty);
+    // Include existing where predicates, if any
+    let preds = input
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|wc| wc.predicates.iter());
+
+    quote! {
+        impl #impl_generics std::default::Default for #name #ty_generics
+        where
+            #(#preds,)*
+            #(#types: std::default::Default,)*
+        {
             fn default() -> Self {
-                Self { #default_inits }
+                Self { #(#inits),* }
             }
         }
+    }
+}
+
+/// Generate `AiParsable`/`IntoActionPayload` for an enum of unit variants used as a label set.
+fn derive_label_enum(input: &DeriveInput, data: &syn::DataEnum) -> TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    if let Some(variant) = data
+        .variants
+        .iter()
+        .find(|v| !matches!(v.fields, Fields::Unit))
+    {
+        return syn::Error::new_spanned(
+            variant,
+            "AiAction can only be derived for enums whose variants are all unit variants",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    match action_options(&input.attrs) {
+        Ok(options) if options.default => {
+            return syn::Error::new_spanned(
+                name,
+                "`#[ai_action(default)]` is only supported on structs, derive `Default` and mark \
+                 a `#[default]` variant instead",
+            )
+            .to_compile_error()
+            .into();
+        }
+        Ok(_) => {}
+        Err(e) => return e.to_compile_error().into(),
+    }
+    let rename_all = match serde_options(&input.attrs) {
+        Ok(options) => options.rename_all,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let mut labels: Vec<String> = Vec::new();
+    for variant in data.variants.iter() {
+        match serde_name(&variant.ident, &variant.attrs, rename_all.as_deref(), true) {
+            Ok(Some(label)) => labels.push(label),
+            Ok(None) => {}
+            Err(e) => return e.to_compile_error().into(),
+        }
+    }
+    let struct_name_str = name.to_string();
+    let action_name_str = to_snake_case(&struct_name_str);
+    let metadata = metadata_tokens(input, &action_name_str);
+
+    let expanded = quote! {
+        impl #impl_generics bevy_real_ai::parse::AiParsable for #name #ty_generics #where_clause {
+            fn schema_description() -> String {
+                let labels: Vec<String> = [#(#labels),*]
+                    .iter()
+                    .map(|l| format!("\"{}\"", l))
+                    .collect();
+                format!("One of the labels: {}", labels.join(", "))
+            }
+
+            fn type_name() -> &'static str {
+                #struct_name_str
+            }
+
+            fn labels() -> Option<&'static [&'static str]> {
+                Some(&[#(#labels),*])
+            }
+
+            fn schema_example() -> Option<serde_json::Value> {
+                <Self as bevy_real_ai::parse::AiParsable>::labels()
+                    .and_then(|labels| labels.first())
+                    .map(|label| serde_json::json!(label))
+            }
+
+            fn parse_from_ai_response(response: &str) -> Result<Self, bevy_real_ai::error::AiError>
+            where
+                Self: Sized + serde::de::DeserializeOwned,
+            {
+                let label = bevy_real_ai::parse::match_label(response, &[#(#labels),*])
+                    .ok_or_else(|| {
+                        bevy_real_ai::error::AiError::parse_failure(response, "No known label in AI response")
+                    })?;
+                serde_json::from_value(serde_json::Value::String(label.to_string()))
+                    .map_err(|e| bevy_real_ai::error::AiError::parse_failure(response, e.to_string()))
+            }
+        }
+
+        impl #impl_generics bevy_real_ai::parse::AiSchemaType for #name #ty_generics #where_clause {
+            fn type_name() -> &'static str {
+                "string"
+            }
+
+            fn describe() -> String {
+                let labels: Vec<String> = [#(#labels),*]
+                    .iter()
+                    .map(|l| format!("\"{}\"", l))
+                    .collect();
+                format!("one of {}", labels.join(", "))
+            }
+
+            fn example() -> serde_json::Value {
+                <Self as bevy_real_ai::parse::AiParsable>::schema_example()
+                    .unwrap_or(serde_json::Value::Null)
+            }
+        }
+
+        impl #impl_generics bevy_real_ai::actions::IntoActionPayload for #name #ty_generics #where_clause {
+            fn action_name() -> &'static str {
+                #action_name_str
+            }
+
+            fn into_action_payload(self) -> bevy_real_ai::actions::ActionPayload {
+                bevy_real_ai::actions::ActionPayload {
+                    name: #action_name_str.to_string(),
+                    params: serde_json::to_value(&self).unwrap_or(serde_json::Value::Null),
+                }
+            }
+
+            fn action_metadata() -> Option<bevy_real_ai::actions::AiActionMetadata> {
+                Some(Self::metadata())
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Register a handler for this label type with the given registry.
+            /// The handler receives the classified label as `In<Self>`.
+            pub fn register<S, M>(registry: &mut bevy_real_ai::actions::AiActionRegistry, system: S)
+            where
+                S: bevy::ecs::system::IntoSystem<bevy::ecs::system::In<Self>, (), M> + 'static,
+                Self: Sized + 'static + Send + Sync,
+            {
+                registry.register_action::<Self, S, M>(system);
+            }
+        }
+
+        #metadata
     };
 
     TokenStream::from(expanded)
@@ -0,0 +1,64 @@
+//! Example showing intent classification of free-form player text.
+//!
+//! Run with: `cargo run --example classify --release`
+//!
+//! Player lines are routed to "trade / quest / gossip / goodbye" with
+//! `AiRequest::classify`, which uses a short label-only prompt instead of a full
+//! typed action. The classified label is delivered to a registered handler.
+
+use bevy::prelude::*;
+use bevy_real_ai::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The labels the model can choose from. Unit-variant enums deriving `AiAction`
+/// become label sets; the handler receives the chosen variant.
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+enum Intent {
+    Trade,
+    Quest,
+    Gossip,
+    Goodbye,
+}
+
+/// Player lines to classify.
+static LINES: &[&str] = &[
+    "What have you got for sale?",
+    "Is there any work for an adventurer around here?",
+    "Heard anything interesting lately?",
+    "I'll be on my way, farewell.",
+];
+
+#[derive(Resource, Default)]
+struct Classified(usize);
+
+fn main() {
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .use_ai_with_builder(AiModelBuilder::new().with_seed(42).with_progress_tracking())
+        .register_ai_action::<Intent, _, _>(route_intent)
+        .init_resource::<Classified>()
+        .add_systems(Startup, setup)
+        .add_systems(Update, exit_when_done)
+        .run();
+}
+
+fn setup(mut commands: Commands, mut ai: AiRequest) {
+    let shopkeeper = commands.spawn((AI, DialogueReceiver::new())).id();
+    for line in LINES {
+        // Requests are queued until the model has loaded
+        ai.classify::<Intent>(shopkeeper, line);
+    }
+}
+
+/// Handler receiving each classified intent.
+fn route_intent(In(intent): In<Intent>, mut classified: ResMut<Classified>) {
+    let line = LINES.get(classified.0).copied().unwrap_or_default();
+    println!("{:<50} => {:?}", line, intent);
+    classified.0 += 1;
+}
+
+fn exit_when_done(classified: Res<Classified>, mut commands: Commands) {
+    if classified.0 >= LINES.len() {
+        commands.write_message(AppExit::Success);
+    }
+}
@@ -38,7 +38,11 @@ fn main() {
                     .with_seed(42) // Optional: use fixed seed for consistent responses
                     .with_progress_tracking(), // Enable progress tracking for download UI
             )
-            .with_config(AiContextGatherConfig::default().with_radius(50.0)),
+            .with_config(
+                AiContextGatherConfig::default()
+                    .with_radius(50.0)
+                    .with_plane(SpatialPlane::Xy), // Top-down 2D world
+            ),
         )
         .add_systems(Startup, setup_world)
         .add_systems(
@@ -232,10 +236,14 @@ fn gather_npc_context(
     let summaries: Vec<String> = npcs
         .iter()
         .filter(|(ent, _, _, _)| nearby_entities.contains(ent))
-        .map(|(_, npc, inventory, _)| {
+        .map(|(_, npc, inventory, transform)| {
+            // Describe where the NPC is from the player's point of view
+            let location = ai_entity
+                .describe_relative(&npc.name, transform.translation)
+                .unwrap_or_else(|| format!("{} is nearby", npc.name));
             format!(
                 "{}. Description: {}. Possessions: {}.",
-                npc.name,
+                location,
                 npc.description,
                 inventory.items.join(", ")
             )
@@ -314,12 +322,16 @@ fn on_model_download_progress(
                 progress.progress = p; // Already 0.0-100.0 range
             }
         }
+        DownloadState::Verifying => {
+            info!("Verifying model checksum: {}", event.message);
+        }
         DownloadState::Completed => {
             progress.progress = 1.0;
         }
         DownloadState::Error => {
             info!("Model download error: {}", event.message);
         }
+        _ => {}
     }
 }
 
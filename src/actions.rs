@@ -1,10 +1,11 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// A generic action produced by the AI. `name` is the action identifier, and
 /// `params` contains arbitrary JSON parameters for the action.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ActionPayload {
     pub name: String,
     pub params: Value,
@@ -53,6 +54,72 @@ pub trait IntoActionPayload {
 
     /// Convert the typed struct into an `ActionPayload`.
     fn into_action_payload(self) -> ActionPayload;
+
+    /// Fill in fields the model left out of `params` before they are deserialized.
+    /// `#[derive(AiAction)]` fills fields marked `#[ai(optional)]` or `#[ai(default = ..)]`.
+    fn fill_missing_params(_params: &mut serde_json::Value) {}
+
+    /// Check `params` against the `#[ai(range)]`, `#[ai(one_of)]` and `#[ai(regex)]`
+    /// constraints of the derived fields. The message names the offending field.
+    fn validate_params(_params: &serde_json::Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Name, description and schema for the [`ActionCatalog`]; `#[derive(AiAction)]` types
+    /// return their `metadata()`.
+    fn action_metadata() -> Option<AiActionMetadata> {
+        None
+    }
+}
+
+/// Static description of an action type, generated by `#[derive(AiAction)]`.
+#[derive(Clone, Copy, Debug)]
+pub struct AiActionMetadata {
+    /// Action name used to match handlers.
+    pub name: &'static str,
+    /// Rust type name.
+    pub type_name: &'static str,
+    /// Returns the schema description shown to the model.
+    pub schema: fn() -> String,
+    /// Version from `#[ai_action(version = N)]`, `1` by default.
+    pub version: u32,
+    /// `#[ai_action(description = "..")]`, or else the type's doc comment, trimmed.
+    pub doc: &'static str,
+}
+
+impl AiActionMetadata {
+    pub fn schema(&self) -> String {
+        (self.schema)()
+    }
+
+    /// One block describing the action, suitable for listing actions in a prompt.
+    pub fn describe(&self) -> String {
+        if self.doc.is_empty() {
+            format!("{} (v{}):\n{}", self.name, self.version, self.schema())
+        } else {
+            format!(
+                "{} (v{}): {}\n{}",
+                self.name,
+                self.version,
+                self.doc,
+                self.schema()
+            )
+        }
+    }
+}
+
+#[cfg(feature = "inventory")]
+inventory::collect!(AiActionMetadata);
+
+/// Metadata of every `#[derive(AiAction)]` type linked into the binary, sorted by name.
+///
+/// Generic action types are not collected.
+#[cfg(feature = "inventory")]
+pub fn all_action_metadata() -> Vec<&'static AiActionMetadata> {
+    let mut all: Vec<&'static AiActionMetadata> =
+        inventory::iter::<AiActionMetadata>.into_iter().collect();
+    all.sort_by_key(|m| m.name);
+    all
 }
 
 /// Event emitted when an AI response contains an action for an entity to handle.
@@ -81,6 +148,11 @@ pub(crate) fn value_to_action(v: Value) -> Option<ActionPayload> {
 pub trait AiActionHandlerDyn: Send + Sync {
     /// Run the handler with the given action event.
     fn run_with_action(&mut self, event: AiActionEvent, world: &mut World);
+
+    /// Check that the handler could run with the given action, without running it.
+    fn validate(&self, _event: &AiActionEvent) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 /// Boxed handler type for the registry.
@@ -92,17 +164,92 @@ pub struct PendingAiActions {
     pub actions: Vec<AiActionEvent>,
 }
 
+/// System parameter for emitting actions from gameplay code.
+///
+/// Emitted actions are queued in [`PendingAiActions`] and go through the same registry as
+/// actions produced by the AI, which is useful for debugging handlers and scripted sequences.
+///
+/// # Example
+/// ```ignore
+/// fn open_gate_on_key(keys: Res<ButtonInput<KeyCode>>, gate: Single<Entity, With<Gate>>, mut actions: AiActions) {
+///     if keys.just_pressed(KeyCode::KeyG) {
+///         actions.emit_typed(*gate, OpenGate { speed: 2.0 });
+///     }
+/// }
+/// ```
+#[derive(SystemParam)]
+pub struct AiActions<'w> {
+    pending: ResMut<'w, PendingAiActions>,
+}
+
+impl AiActions<'_> {
+    /// Queue `payload` for `entity`.
+    pub fn emit(&mut self, entity: Entity, payload: ActionPayload) {
+        self.pending.actions.push(AiActionEvent {
+            entity,
+            action: payload,
+        });
+    }
+
+    /// Queue a typed action for `entity`.
+    pub fn emit_typed<T: IntoActionPayload>(&mut self, entity: Entity, action: T) {
+        self.emit(entity, action.into_action_payload());
+    }
+
+    /// Number of actions waiting to be handled.
+    pub fn pending(&self) -> usize {
+        self.pending.actions.len()
+    }
+}
+
+/// Entities a scoped handler applies to, see [`AiActionRegistry::register_scoped`].
+pub enum ActionScope {
+    /// Only this entity. The handler is dropped once the entity is despawned.
+    Entity(Entity),
+    /// Entities the filter accepts.
+    Filter(Box<dyn Fn(&EntityRef) -> bool + Send + Sync>),
+}
+
+impl ActionScope {
+    /// Entities with component `C`.
+    pub fn with<C: Component>() -> Self {
+        Self::Filter(Box::new(|entity| entity.contains::<C>()))
+    }
+
+    /// Entities `filter` accepts.
+    pub fn filter(filter: impl Fn(&EntityRef) -> bool + Send + Sync + 'static) -> Self {
+        Self::Filter(Box::new(filter))
+    }
+}
+
+impl From<Entity> for ActionScope {
+    fn from(entity: Entity) -> Self {
+        Self::Entity(entity)
+    }
+}
+
+struct ScopedHandler {
+    scope: ActionScope,
+    handler: AiActionHandler,
+}
+
 /// Registry mapping action names to boxed handlers.
+///
+/// Besides the one handler per name, handlers can be scoped to an entity or a component filter
+/// with [`register_scoped`](Self::register_scoped), so the same action ("attack") runs
+/// different logic depending on which NPC produced it. Entity scopes win over filters, filters
+/// are tried in registration order and the unscoped handler is the fallback.
 #[derive(Resource, Default)]
 pub struct AiActionRegistry {
     handlers: HashMap<String, AiActionHandler>,
+    scoped: HashMap<String, Vec<ScopedHandler>>,
+    /// Metadata of actions registered from derived types, by name.
+    metadata: HashMap<String, AiActionMetadata>,
 }
 
 impl AiActionRegistry {
     pub fn new() -> Self {
-        Self {
-            handlers: HashMap::new(),
-        }
+        Self::default()
     }
 
     /// Register a handler that receives the full `AiActionEvent` as input.
@@ -119,36 +266,9 @@ impl AiActionRegistry {
     where
         S: bevy::ecs::system::IntoSystem<In<AiActionEvent>, (), M> + 'static,
     {
-        let inner_system = bevy::ecs::system::IntoSystem::into_system(system);
-        let name_owned = name.to_string();
-
-        // Create a wrapper that implements AiActionHandlerDyn
-        struct SystemWrapper<Sys> {
-            system: Sys,
-            initialized: bool,
-        }
-
-        impl<Sys> AiActionHandlerDyn for SystemWrapper<Sys>
-        where
-            Sys: bevy::ecs::system::System<In = In<AiActionEvent>, Out = ()> + Send + Sync,
-        {
-            fn run_with_action(&mut self, event: AiActionEvent, world: &mut World) {
-                if !self.initialized {
-                    let _ = self.system.initialize(world);
-                    self.initialized = true;
-                }
-                let _ = self.system.run(event, world);
-                self.system.apply_deferred(world);
-            }
-        }
-
-        self.handlers.insert(
-            name_owned,
-            Box::new(SystemWrapper {
-                system: inner_system,
-                initialized: false,
-            }),
-        );
+        self.metadata.remove(name);
+        self.handlers
+            .insert(name.to_string(), system_handler(system));
     }
 
     /// Register a typed handler system for an action name.
@@ -171,90 +291,908 @@ impl AiActionRegistry {
         T: 'static + Send + Sync + serde::de::DeserializeOwned,
         S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static,
     {
-        let inner_system = bevy::ecs::system::IntoSystem::into_system(system);
-        let name_owned = name.to_string();
-        let name_for_error = name.to_string();
-
-        // Create a wrapper that deserializes T and runs the inner system
-        struct TypedSystemWrapper<T, Sys> {
-            system: Sys,
-            initialized: bool,
-            name: String,
-            _marker: std::marker::PhantomData<T>,
+        self.register_typed_filled(name, system, |_| {}, |_| Ok(()));
+    }
+
+    /// Register a typed handler with an inverse, so the action can be undone with
+    /// [`ActionHistory::undo_last`](crate::history::ActionHistory::undo_last).
+    ///
+    /// Whatever `system` returns is handed to `undo`, e.g. the entity it spawned or the value
+    /// it overwrote. Undo needs an [`ActionHistory`](crate::history::ActionHistory) resource;
+    /// without one, nothing is kept.
+    ///
+    /// # Example
+    /// ```ignore
+    /// registry.register_typed_with_undo::<PlaceBlock, _, _, _, _, _>(
+    ///     "place_block",
+    ///     |In(block): In<PlaceBlock>, mut commands: Commands| commands.spawn(block.bundle()).id(),
+    ///     |In(placed): In<Entity>, mut commands: Commands| commands.entity(placed).despawn(),
+    /// );
+    /// ```
+    pub fn register_typed_with_undo<T, R, S, M, U, MU>(&mut self, name: &str, system: S, undo: U)
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned,
+        R: 'static + Send + Sync,
+        S: bevy::ecs::system::IntoSystem<In<T>, R, M> + 'static,
+        U: bevy::ecs::system::IntoSystem<In<R>, (), MU> + 'static,
+    {
+        let undo: bevy::ecs::system::BoxedSystem<In<R>, ()> =
+            Box::new(bevy::ecs::system::IntoSystem::into_system(undo));
+        self.metadata.remove(name);
+        self.handlers.insert(
+            name.to_string(),
+            typed_handler(name, system, |_| {}, |_| Ok(()), Some(undo)),
+        );
+    }
+
+    /// Counterpart of [`register_action`](Self::register_action) with an inverse, see
+    /// [`register_typed_with_undo`](Self::register_typed_with_undo).
+    pub fn register_action_with_undo<T, R, S, M, U, MU>(&mut self, system: S, undo: U)
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        R: 'static + Send + Sync,
+        S: bevy::ecs::system::IntoSystem<In<T>, R, M> + 'static,
+        U: bevy::ecs::system::IntoSystem<In<R>, (), MU> + 'static,
+    {
+        let undo: bevy::ecs::system::BoxedSystem<In<R>, ()> =
+            Box::new(bevy::ecs::system::IntoSystem::into_system(undo));
+        self.handlers.insert(
+            T::action_name().to_string(),
+            typed_handler(
+                T::action_name(),
+                system,
+                T::fill_missing_params,
+                T::validate_params,
+                Some(undo),
+            ),
+        );
+        self.metadata.remove(T::action_name());
+        if let Some(metadata) = T::action_metadata() {
+            self.metadata.insert(T::action_name().to_string(), metadata);
         }
+    }
 
-        impl<T, Sys> AiActionHandlerDyn for TypedSystemWrapper<T, Sys>
-        where
-            T: 'static + Send + Sync + serde::de::DeserializeOwned,
-            Sys: bevy::ecs::system::System<In = In<T>, Out = ()> + Send + Sync,
-        {
-            fn run_with_action(&mut self, event: AiActionEvent, world: &mut World) {
-                match serde_json::from_value::<T>(event.action.params.clone()) {
-                    Ok(typed) => {
-                        if !self.initialized {
-                            let _ = self.system.initialize(world);
-                            self.initialized = true;
-                        }
-                        let _ = self.system.run(typed, world);
-                        self.system.apply_deferred(world);
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to deserialize typed action for {}: {}",
-                            self.name, e
-                        );
-                    }
-                }
-            }
+    /// Register a typed handler under `T::action_name()`. Unlike
+    /// [`register_typed`](Self::register_typed), fields the model left out are filled in with
+    /// [`IntoActionPayload::fill_missing_params`] and the params are checked with
+    /// [`IntoActionPayload::validate_params`] first.
+    pub fn register_action<T, S, M>(&mut self, system: S)
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static,
+    {
+        self.register_typed_filled(
+            T::action_name(),
+            system,
+            T::fill_missing_params,
+            T::validate_params,
+        );
+        if let Some(metadata) = T::action_metadata() {
+            self.metadata.insert(T::action_name().to_string(), metadata);
         }
+    }
 
+    fn register_typed_filled<T, S, M>(
+        &mut self,
+        name: &str,
+        system: S,
+        fill: fn(&mut serde_json::Value),
+        validate: fn(&serde_json::Value) -> Result<(), String>,
+    ) where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned,
+        S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static,
+    {
+        self.metadata.remove(name);
         self.handlers.insert(
-            name_owned,
-            Box::new(TypedSystemWrapper {
-                system: inner_system,
-                initialized: false,
-                name: name_for_error,
-                _marker: std::marker::PhantomData::<T>,
-            }),
+            name.to_string(),
+            typed_handler(name, system, fill, validate, None),
+        );
+    }
+
+    /// Register a handler receiving the full `AiActionEvent` that only runs for actions
+    /// produced by entities in `scope`. Other entities keep using the handler registered with
+    /// [`register`](Self::register), if any.
+    ///
+    /// # Example
+    /// ```ignore
+    /// registry.register_scoped("attack", ActionScope::with::<Archer>(), |In(event): In<AiActionEvent>, mut commands: Commands| {
+    ///     // Shoot instead of swinging a sword
+    /// });
+    /// ```
+    pub fn register_scoped<S, M>(&mut self, name: &str, scope: impl Into<ActionScope>, system: S)
+    where
+        S: bevy::ecs::system::IntoSystem<In<AiActionEvent>, (), M> + 'static,
+    {
+        self.insert_scoped(name, scope.into(), system_handler(system));
+    }
+
+    /// Typed counterpart of [`register_scoped`](Self::register_scoped), filling and checking
+    /// params like [`register_action`](Self::register_action).
+    pub fn register_action_scoped<T, S, M>(&mut self, scope: impl Into<ActionScope>, system: S)
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static,
+    {
+        let handler = typed_handler(
+            T::action_name(),
+            system,
+            T::fill_missing_params,
+            T::validate_params,
+            None,
         );
+        self.insert_scoped(T::action_name(), scope.into(), handler);
+        if let Some(metadata) = T::action_metadata() {
+            self.metadata.insert(T::action_name().to_string(), metadata);
+        }
+    }
+
+    fn insert_scoped(&mut self, name: &str, scope: ActionScope, handler: AiActionHandler) {
+        let scoped = self.scoped.entry(name.to_string()).or_default();
+        // One handler per entity, the newest wins
+        if let ActionScope::Entity(entity) = scope {
+            scoped.retain(|h| !matches!(h.scope, ActionScope::Entity(e) if e == entity));
+        }
+        scoped.push(ScopedHandler { scope, handler });
     }
 
-    /// Get a mutable reference to a handler by name, if any.
+    /// Drop every handler scoped to `entity`.
+    pub fn remove_scoped(&mut self, entity: Entity) {
+        for scoped in self.scoped.values_mut() {
+            scoped.retain(|h| !matches!(h.scope, ActionScope::Entity(e) if e == entity));
+        }
+        self.scoped.retain(|_, scoped| !scoped.is_empty());
+    }
+
+    /// Names of all registered actions, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .handlers
+            .keys()
+            .chain(self.scoped.keys())
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Metadata of action `name`, if it was registered from a type deriving `AiAction`.
+    pub fn metadata(&self, name: &str) -> Option<&AiActionMetadata> {
+        self.metadata.get(name)
+    }
+
+    /// Whether a handler, scoped or not, is registered for `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.contains_key(name) || self.scoped.contains_key(name)
+    }
+
+    /// Get a reference to the unscoped handler by name, if any.
+    pub fn get(&self, name: &str) -> Option<&AiActionHandler> {
+        self.handlers.get(name)
+    }
+
+    /// Get a mutable reference to the unscoped handler by name, if any.
     pub fn get_mut(&mut self, name: &str) -> Option<&mut AiActionHandler> {
         self.handlers.get_mut(name)
     }
+
+    /// The handler action `name` from `entity` dispatches to.
+    pub fn handler_for(
+        &self,
+        name: &str,
+        entity: Entity,
+        world: &World,
+    ) -> Option<&AiActionHandler> {
+        match self.scoped_index(name, entity, world) {
+            Some(i) => Some(&self.scoped[name][i].handler),
+            None => self.handlers.get(name),
+        }
+    }
+
+    /// Mutable counterpart of [`handler_for`](Self::handler_for).
+    pub fn handler_for_mut(
+        &mut self,
+        name: &str,
+        entity: Entity,
+        world: &World,
+    ) -> Option<&mut AiActionHandler> {
+        match self.scoped_index(name, entity, world) {
+            Some(i) => self
+                .scoped
+                .get_mut(name)
+                .map(|scoped| &mut scoped[i].handler),
+            None => self.handlers.get_mut(name),
+        }
+    }
+
+    fn scoped_index(&self, name: &str, entity: Entity, world: &World) -> Option<usize> {
+        let scoped = self.scoped.get(name)?;
+        scoped
+            .iter()
+            .position(|h| matches!(h.scope, ActionScope::Entity(e) if e == entity))
+            .or_else(|| {
+                let entity_ref = world.get_entity(entity).ok()?;
+                scoped.iter().position(|h| match &h.scope {
+                    ActionScope::Filter(filter) => filter(&entity_ref),
+                    ActionScope::Entity(_) => false,
+                })
+            })
+    }
+
+    /// Drop handlers scoped to entities that no longer exist.
+    fn prune_scoped(&mut self, world: &World) {
+        for scoped in self.scoped.values_mut() {
+            scoped.retain(|h| match h.scope {
+                ActionScope::Entity(e) => world.get_entity(e).is_ok(),
+                ActionScope::Filter(_) => true,
+            });
+        }
+        self.scoped.retain(|_, scoped| !scoped.is_empty());
+    }
+}
+
+fn system_handler<S, M>(system: S) -> AiActionHandler
+where
+    S: bevy::ecs::system::IntoSystem<In<AiActionEvent>, (), M> + 'static,
+{
+    let inner_system = bevy::ecs::system::IntoSystem::into_system(system);
+
+    // Create a wrapper that implements AiActionHandlerDyn
+    struct SystemWrapper<Sys> {
+        system: Sys,
+        initialized: bool,
+    }
+
+    impl<Sys> AiActionHandlerDyn for SystemWrapper<Sys>
+    where
+        Sys: bevy::ecs::system::System<In = In<AiActionEvent>, Out = ()> + Send + Sync,
+    {
+        fn run_with_action(&mut self, event: AiActionEvent, world: &mut World) {
+            if !self.initialized {
+                let _ = self.system.initialize(world);
+                self.initialized = true;
+            }
+            let _ = self.system.run(event, world);
+            self.system.apply_deferred(world);
+        }
+    }
+
+    Box::new(SystemWrapper {
+        system: inner_system,
+        initialized: false,
+    })
+}
+
+fn typed_handler<T, R, S, M>(
+    name: &str,
+    system: S,
+    fill: fn(&mut serde_json::Value),
+    validate: fn(&serde_json::Value) -> Result<(), String>,
+    undo: Option<bevy::ecs::system::BoxedSystem<In<R>, ()>>,
+) -> AiActionHandler
+where
+    T: 'static + Send + Sync + serde::de::DeserializeOwned,
+    R: 'static + Send + Sync,
+    S: bevy::ecs::system::IntoSystem<In<T>, R, M> + 'static,
+{
+    let inner_system = bevy::ecs::system::IntoSystem::into_system(system);
+    let name_for_error = name.to_string();
+
+    // Create a wrapper that deserializes T and runs the inner system
+    struct TypedSystemWrapper<T, R: 'static, Sys> {
+        system: Sys,
+        initialized: bool,
+        name: String,
+        fill: fn(&mut serde_json::Value),
+        validate: fn(&serde_json::Value) -> Result<(), String>,
+        undo: UndoSystem<R>,
+        _marker: std::marker::PhantomData<T>,
+    }
+
+    /// The inverse of a handler, registered with the world on first use.
+    enum UndoSystem<R: 'static> {
+        None,
+        Unregistered(bevy::ecs::system::BoxedSystem<In<R>, ()>),
+        Registered(bevy::ecs::system::SystemId<In<R>, ()>),
+    }
+
+    impl<T, R, Sys> TypedSystemWrapper<T, R, Sys>
+    where
+        T: serde::de::DeserializeOwned,
+        R: 'static + Send + Sync,
+    {
+        fn parse(&self, event: &AiActionEvent) -> Result<T, String> {
+            let mut params = event.action.params.clone();
+            (self.fill)(&mut params);
+            (self.validate)(&params)?;
+            serde_json::from_value::<T>(params).map_err(|e| e.to_string())
+        }
+
+        /// Attach the inverse, fed with the handler's output, to the latest history record.
+        fn record_undo(&mut self, output: R, world: &mut World) {
+            if !world.contains_resource::<crate::history::ActionHistory>() {
+                return;
+            }
+            let id = match std::mem::replace(&mut self.undo, UndoSystem::None) {
+                UndoSystem::None => return,
+                UndoSystem::Unregistered(system) => world.register_boxed_system(system),
+                UndoSystem::Registered(id) => id,
+            };
+            self.undo = UndoSystem::Registered(id);
+            if let Some(mut history) = world.get_resource_mut::<crate::history::ActionHistory>() {
+                history.attach_undo(move |world: &mut World| {
+                    let _ = world.run_system_with(id, output);
+                });
+            }
+        }
+    }
+
+    impl<T, R, Sys> AiActionHandlerDyn for TypedSystemWrapper<T, R, Sys>
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned,
+        R: 'static + Send + Sync,
+        Sys: bevy::ecs::system::System<In = In<T>, Out = R> + Send + Sync,
+    {
+        fn run_with_action(&mut self, event: AiActionEvent, world: &mut World) {
+            match self.parse(&event) {
+                Ok(typed) => {
+                    if !self.initialized {
+                        let _ = self.system.initialize(world);
+                        self.initialized = true;
+                    }
+                    let output = self.system.run(typed, world);
+                    self.system.apply_deferred(world);
+                    if let Ok(output) = output {
+                        self.record_undo(output, world);
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to deserialize typed action for {}: {}",
+                        self.name, e
+                    );
+                }
+            }
+        }
+
+        fn validate(&self, event: &AiActionEvent) -> Result<(), String> {
+            self.parse(event)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid params for {}: {}", self.name, e))
+        }
+    }
+
+    Box::new(TypedSystemWrapper {
+        system: inner_system,
+        initialized: false,
+        name: name_for_error,
+        fill,
+        validate,
+        undo: undo.map_or(UndoSystem::None, UndoSystem::Unregistered),
+        _marker: std::marker::PhantomData::<T>,
+    })
+}
+
+/// Resource toggling dry-run mode for AI actions.
+///
+/// While enabled, pending actions are parsed and validated against their handlers but not
+/// executed; a [`WouldExecute`] event is triggered for each one instead. Can be flipped at
+/// runtime to audit what a new prompt or model would do before trusting it live.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+#[reflect(Resource)]
+pub struct AiDryRun {
+    pub enabled: bool,
+}
+
+/// Event triggered in dry-run mode for every action that would have been executed.
+#[derive(Event, Clone, Debug)]
+pub struct WouldExecute {
+    pub entity: Entity,
+    pub action: ActionPayload,
+    /// Whether a handler is registered for the action.
+    pub has_handler: bool,
+    /// Validation error if the handler would reject the action.
+    pub error: Option<String>,
+}
+
+/// Resource selecting which actions wait for approval before their handler runs.
+///
+/// Held actions are moved to [`PendingApproval`] and announced with
+/// [`AiActionAwaitingApproval`]; a gameplay or UI system then approves, edits or rejects them.
+/// Approved actions run the next time pending actions are handled, within the frame's
+/// `AiFrameBudget::max_actions` and their [`ActionBudgets`](crate::rate_limit::ActionBudgets)
+/// limits. Nothing is held by default.
+///
+/// # Example
+/// ```ignore
+/// app.insert_resource(AiActionApproval::for_actions(["trade", "spawn_boss"]));
+///
+/// fn review(mut approval: ResMut<PendingApproval>, rules: Res<TradeRules>) {
+///     for id in approval.ids() {
+///         let action = approval.get_mut(id).unwrap();
+///         if rules.allows(action) {
+///             approval.approve(id);
+///         } else {
+///             approval.reject(id);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq, Reflect)]
+#[reflect(Resource)]
+pub struct AiActionApproval {
+    /// Hold every action.
+    pub all: bool,
+    /// Names of the actions to hold when `all` is off.
+    pub actions: HashSet<String>,
+}
+
+impl AiActionApproval {
+    /// Hold every action.
+    pub fn all() -> Self {
+        Self {
+            all: true,
+            actions: HashSet::new(),
+        }
+    }
+
+    /// Hold the actions called `names`.
+    pub fn for_actions<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            all: false,
+            actions: names.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Whether action `name` waits for approval.
+    pub fn requires(&self, name: &str) -> bool {
+        self.all || self.actions.contains(name)
+    }
+}
+
+/// An action held for approval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalEntry {
+    pub id: u64,
+    pub entity: Entity,
+    pub action: ActionPayload,
+}
+
+/// Actions held by [`AiActionApproval`], oldest first.
+#[derive(Resource, Debug, Default)]
+pub struct PendingApproval {
+    entries: Vec<ApprovalEntry>,
+    approved: Vec<AiActionEvent>,
+    /// Rejected since the last run, to complete with an error.
+    rejected: Vec<ApprovalEntry>,
+    next_id: u64,
+}
+
+impl PendingApproval {
+    fn hold(&mut self, event: AiActionEvent) -> ApprovalEntry {
+        self.next_id += 1;
+        let entry = ApprovalEntry {
+            id: self.next_id,
+            entity: event.entity,
+            action: event.action,
+        };
+        self.entries.push(entry.clone());
+        entry
+    }
+
+    /// Ids of the held actions, oldest first.
+    pub fn ids(&self) -> Vec<u64> {
+        self.entries.iter().map(|e| e.id).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ApprovalEntry> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&ApprovalEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// The held action `id`, to edit its params before approving it.
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut ActionPayload> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.id == id)
+            .map(|e| &mut e.action)
+    }
+
+    /// Let action `id` run. Returns `false` if no such action is held.
+    pub fn approve(&mut self, id: u64) -> bool {
+        let Some(i) = self.entries.iter().position(|e| e.id == id) else {
+            return false;
+        };
+        let entry = self.entries.remove(i);
+        self.approved.push(AiActionEvent {
+            entity: entry.entity,
+            action: entry.action,
+        });
+        true
+    }
+
+    /// Drop action `id` without running it. [`AiActionRejected`] fires and it completes with
+    /// an error the next time pending actions are handled.
+    pub fn reject(&mut self, id: u64) -> Option<ApprovalEntry> {
+        let i = self.entries.iter().position(|e| e.id == id)?;
+        let entry = self.entries.remove(i);
+        self.rejected.push(entry.clone());
+        Some(entry)
+    }
+
+    /// Let every held action run.
+    pub fn approve_all(&mut self) {
+        for entry in self.entries.drain(..) {
+            self.approved.push(AiActionEvent {
+                entity: entry.entity,
+                action: entry.action,
+            });
+        }
+    }
+
+    /// Drop every held action.
+    pub fn reject_all(&mut self) -> Vec<ApprovalEntry> {
+        let entries = std::mem::take(&mut self.entries);
+        self.rejected.extend(entries.iter().cloned());
+        entries
+    }
+
+    /// Number of actions waiting for a decision.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Event triggered when an action is held in [`PendingApproval`].
+#[derive(Event, Clone, Debug)]
+pub struct AiActionAwaitingApproval {
+    /// Id to pass to [`PendingApproval::approve`] or [`PendingApproval::reject`].
+    pub id: u64,
+    pub entity: Entity,
+    pub action: ActionPayload,
+}
+
+/// Event triggered when an action held in [`PendingApproval`] was rejected, right before it
+/// completes with an error.
+#[derive(Event, Clone, Debug)]
+pub struct AiActionRejected {
+    pub id: u64,
+    pub entity: Entity,
+    pub action: ActionPayload,
+}
+
+/// One action the AI may use, as listed in the [`ActionCatalog`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionCatalogEntry {
+    pub name: String,
+    /// Empty for actions registered without a derived type, or without a description.
+    pub description: String,
+    /// JSON schema description; `None` for actions registered without a derived type.
+    pub schema: Option<String>,
+    pub version: Option<u32>,
+}
+
+/// Every registered action with its description and schema, kept in sync with the
+/// [`AiActionRegistry`]. Use [`prompt_block`](Self::prompt_block) to tell a model what it may
+/// do, or list [`entries`](Self::entries) in a debug UI.
+///
+/// # Example
+/// ```ignore
+/// fn tool_prompt(catalog: Res<ActionCatalog>, mut prompt: ResMut<GlobalSystemPrompt>) {
+///     if catalog.is_changed() {
+///         prompt.set(catalog.prompt_block());
+///     }
+/// }
+/// ```
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct ActionCatalog {
+    /// Entries sorted by name.
+    pub entries: Vec<ActionCatalogEntry>,
+}
+
+impl ActionCatalog {
+    /// Build the catalog of everything registered in `registry`.
+    pub fn from_registry(registry: &AiActionRegistry) -> Self {
+        let entries = registry
+            .names()
+            .into_iter()
+            .map(|name| match registry.metadata(&name) {
+                Some(metadata) => ActionCatalogEntry {
+                    description: metadata.doc.to_string(),
+                    schema: Some(metadata.schema()),
+                    version: Some(metadata.version),
+                    name,
+                },
+                None => ActionCatalogEntry {
+                    name,
+                    description: String::new(),
+                    schema: None,
+                    version: None,
+                },
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ActionCatalogEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// The catalog as one prompt block: a line per action with its description, followed by
+    /// its schema.
+    pub fn prompt_block(&self) -> String {
+        let mut block = String::from("Available actions:");
+        for entry in &self.entries {
+            block.push_str("\n- ");
+            block.push_str(&entry.name);
+            if !entry.description.is_empty() {
+                block.push_str(": ");
+                block.push_str(&entry.description);
+            }
+            if let Some(schema) = &entry.schema {
+                for line in schema.lines() {
+                    block.push_str("\n  ");
+                    block.push_str(line);
+                }
+            }
+        }
+        block
+    }
+}
+
+/// Rebuild the [`ActionCatalog`] when the registry changed.
+fn sync_action_catalog(
+    registry: Option<Res<AiActionRegistry>>,
+    mut catalog: ResMut<ActionCatalog>,
+) {
+    if let Some(registry) = registry.filter(|r| r.is_changed()) {
+        catalog.set_if_neq(ActionCatalog::from_registry(&registry));
+    }
+}
+
+/// Plugin running registered handlers for actions parsed from replies or emitted with
+/// [`AiActions`]. Part of the [`AIDialoguePlugin`](crate::dialogue::AIDialoguePlugin).
+pub struct AiActionsPlugin;
+
+impl Plugin for AiActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiActionRegistry>()
+            .init_resource::<AiDryRun>()
+            .init_resource::<AiActionApproval>()
+            .init_resource::<crate::rate_limit::ActionBudgets>()
+            .init_resource::<PendingApproval>()
+            .init_resource::<crate::journal::CommandJournal>()
+            .init_resource::<PendingAiActions>()
+            .init_resource::<crate::inspect::AiRegistryInfo>()
+            .init_resource::<ActionCatalog>()
+            .register_type::<AiDryRun>()
+            .register_type::<AiActionApproval>()
+            .register_type::<crate::inspect::AiRegistryInfo>()
+            .init_resource::<crate::completion::ActionCompletions>()
+            .add_systems(
+                Update,
+                (
+                    crate::sequence::advance_sequences,
+                    run_registered_actions_world,
+                    crate::completion::poll_action_futures,
+                )
+                    .chain()
+                    .in_set(crate::dialogue::AiSystemSet::RunActions),
+            )
+            .add_systems(
+                Last,
+                (crate::inspect::sync_registry_info, sync_action_catalog),
+            )
+            .add_observer(crate::sequence::on_step_completed);
+    }
 }
 
 /// World-exclusive runner that executes handler systems for pending actions.
 /// This should be scheduled as an exclusive system (`fn(&mut World)`) each frame.
+/// At most `AiFrameBudget::max_actions` actions run per frame; the rest stay pending.
 pub fn run_registered_actions_world(world: &mut World) {
-    // Drain pending actions resource
-    let pending = match world.get_resource_mut::<PendingAiActions>() {
-        Some(mut p) => std::mem::take(&mut p.actions),
-        None => Vec::new(),
+    let max_actions = world
+        .get_resource::<crate::budget::AiFrameBudget>()
+        .map_or(usize::MAX, |b| b.max_actions);
+    // Actions approved since the last run go first and count against the same budget
+    let (approved, approved_left) = match world.get_resource_mut::<PendingApproval>() {
+        Some(mut approval) => {
+            let n = approval.approved.len().min(max_actions);
+            let taken: Vec<AiActionEvent> = approval.approved.drain(..n).collect();
+            (taken, approval.approved.len())
+        }
+        None => (Vec::new(), 0),
     };
+    // Take this frame's share of the pending actions, oldest first
+    let (pending, queued) = match world.get_resource_mut::<PendingAiActions>() {
+        Some(mut p) => {
+            let n = p.actions.len().min(max_actions - approved.len());
+            let taken: Vec<AiActionEvent> = p.actions.drain(..n).collect();
+            (taken, p.actions.len())
+        }
+        None => (Vec::new(), 0),
+    };
+    if let Some(mut usage) = world.get_resource_mut::<crate::budget::AiFrameUsage>() {
+        usage.actions = approved.len() + pending.len();
+        usage.queued_actions = queued + approved_left;
+    }
 
+    // Budgets are checked when actions are about to run, so held ones are checked once approved
+    let pending = hold_for_approval(world, pending);
+    let pending = crate::rate_limit::throttle_actions(world, [approved, pending].concat());
     if pending.is_empty() {
         return;
     }
 
-    // For each action event, run any registered handler
+    if world.get_resource::<AiDryRun>().is_some_and(|d| d.enabled) {
+        for evt in pending.into_iter() {
+            let (has_handler, error) = match world
+                .get_resource::<AiActionRegistry>()
+                .and_then(|r| r.handler_for(&evt.action.name, evt.entity, world))
+            {
+                Some(handler) => (true, handler.validate(&evt).err()),
+                None => (false, None),
+            };
+            info!(
+                "[dry run] would execute '{}' for entity {:?} with {} (handler: {}, error: {:?})",
+                evt.action.name, evt.entity, evt.action.params, has_handler, error
+            );
+            world.trigger(WouldExecute {
+                entity: evt.entity,
+                action: evt.action.clone(),
+                has_handler,
+                error,
+            });
+            world.trigger(crate::completion::AiActionCompleted {
+                entity: evt.entity,
+                action: evt.action,
+                ticket: None,
+                result: Err("not run in dry-run mode".to_string()),
+            });
+        }
+        return;
+    }
+
+    world.resource_scope::<AiActionRegistry, _>(|world, mut registry| registry.prune_scoped(world));
+
+    // For each action event, run the handler registered for its entity
     for evt in pending.into_iter() {
         world.resource_scope::<AiActionRegistry, _>(|world, mut registry| {
-            if let Some(handler) = registry.get_mut(&evt.action.name) {
-                debug!(
-                    "Executing handler '{}' for entity {:?}",
-                    evt.action.name, evt.entity
+            use crate::completion::{ActionCompletions, AiActionCompleted};
+            use crate::history::{ActionHistory, ActionOutcome};
+            use crate::log_sink::{AiLogEvent, AiLogSink};
+
+            let Some(handler) = registry.handler_for_mut(&evt.action.name, evt.entity, world)
+            else {
+                if let Some(mut history) = world.get_resource_mut::<ActionHistory>() {
+                    history.record(evt.entity, &evt.action, ActionOutcome::Unhandled);
+                }
+                let reason = format!("no handler for '{}'", evt.action.name);
+                world.trigger(AiActionCompleted {
+                    entity: evt.entity,
+                    action: evt.action,
+                    ticket: None,
+                    result: Err(reason),
+                });
+                return;
+            };
+            let error = handler.validate(&evt).err();
+            if let Some(sink) = world.get_resource::<AiLogSink>() {
+                sink.log(
+                    evt.entity,
+                    None,
+                    AiLogEvent::Action {
+                        name: evt.action.name.clone(),
+                        params: evt.action.params.clone(),
+                        error: error.clone(),
+                    },
                 );
-                handler.run_with_action(evt, world);
             }
+            if let Some(mut history) = world.get_resource_mut::<ActionHistory>() {
+                let outcome = error
+                    .clone()
+                    .map_or(ActionOutcome::Executed, ActionOutcome::Rejected);
+                history.record(evt.entity, &evt.action, outcome);
+            }
+            if let Some(error) = error {
+                // Typed handlers only log params they can't parse
+                handler.run_with_action(evt.clone(), world);
+                world.trigger(AiActionCompleted {
+                    entity: evt.entity,
+                    action: evt.action,
+                    ticket: None,
+                    result: Err(error),
+                });
+                return;
+            }
+            debug!(
+                "Executing handler '{}' for entity {:?}",
+                evt.action.name, evt.entity
+            );
+            let tracked = match world.get_resource_mut::<ActionCompletions>() {
+                Some(mut completions) => {
+                    completions.begin(evt.clone());
+                    None
+                }
+                None => Some(evt.clone()),
+            };
+            handler.run_with_action(evt, world);
+            // Handlers that took a ticket complete later
+            let finished = match world.get_resource_mut::<ActionCompletions>() {
+                Some(mut completions) if tracked.is_none() => completions.end(),
+                _ => tracked,
+            };
+            if let Some(evt) = finished {
+                world.trigger(AiActionCompleted {
+                    entity: evt.entity,
+                    action: evt.action,
+                    ticket: None,
+                    result: Ok(()),
+                });
+            }
+        });
+    }
+}
+
+/// Move the actions [`AiActionApproval`] holds to [`PendingApproval`] and return the rest.
+/// Completes the actions rejected since the last run with an error.
+fn hold_for_approval(world: &mut World, pending: Vec<AiActionEvent>) -> Vec<AiActionEvent> {
+    let (ready, held): (Vec<_>, Vec<_>) = match world.get_resource::<AiActionApproval>() {
+        Some(approval) => pending
+            .into_iter()
+            .partition(|evt| !approval.requires(&evt.action.name)),
+        None => (pending, Vec::new()),
+    };
+    let Some(mut approval) = world.get_resource_mut::<PendingApproval>() else {
+        return ready.into_iter().chain(held).collect();
+    };
+    let rejected = std::mem::take(&mut approval.rejected);
+    let held: Vec<ApprovalEntry> = held.into_iter().map(|evt| approval.hold(evt)).collect();
+    for entry in rejected {
+        let reason = format!("'{}' was rejected", entry.action.name);
+        world.trigger(AiActionRejected {
+            id: entry.id,
+            entity: entry.entity,
+            action: entry.action.clone(),
+        });
+        world.trigger(crate::completion::AiActionCompleted {
+            entity: entry.entity,
+            action: entry.action,
+            ticket: None,
+            result: Err(reason),
+        });
+    }
+    for entry in held {
+        debug!(
+            "Holding '{}' for entity {:?} for approval",
+            entry.action.name, entry.entity
+        );
+        world.trigger(AiActionAwaitingApproval {
+            id: entry.id,
+            entity: entry.entity,
+            action: entry.action,
         });
     }
+    ready
 }
 
 /// Prompt the AI and parse the response using our custom `AiParsable` trait.
 /// This version uses our own derive macro instead of kalosm's Parse/Schema.
 ///
+/// Blocks until the model answers, which can take seconds; from a system use
+/// [`prompt_typed_action_async`] or `AiRequest::ask_action` instead.
+///
 /// # Arguments
 /// * `backend` - The AI backend
 /// * `user_message` - The user's request (will be formatted with schema instructions)
@@ -287,7 +1225,7 @@ pub fn prompt_typed_action<T>(
     user_message: &str,
     entity: Entity,
     pending: &mut PendingAiActions,
-) -> Result<(T, String), String>
+) -> Result<(T, String), crate::error::AiError>
 where
     T: crate::parse::AiParsable + serde::de::DeserializeOwned,
 {
@@ -307,3 +1245,93 @@ where
 
     Ok((parsed, response))
 }
+
+/// Handle to a typed prompt started with [`prompt_typed_action_async`].
+///
+/// Keep it (e.g. in a `Local` or component) and call [`poll`](Self::poll) each frame until it
+/// yields the result.
+pub struct PendingTypedRequest<T> {
+    entity: Entity,
+    rx: flume::Receiver<Result<(T, String), crate::error::AiError>>,
+    finished: bool,
+}
+
+impl<T: crate::parse::AiParsable> PendingTypedRequest<T> {
+    /// The entity that will receive the action.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// Whether the result has already been returned by `poll`.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Return the result once the model has answered, queueing the action in `pending` like
+    /// [`prompt_typed_action`]. Returns `None` while waiting and after the result was taken.
+    pub fn poll(
+        &mut self,
+        pending: &mut PendingAiActions,
+    ) -> Option<Result<(T, String), crate::error::AiError>> {
+        if self.finished {
+            return None;
+        }
+        let result = match self.rx.try_recv() {
+            Ok(result) => result,
+            Err(flume::TryRecvError::Empty) => return None,
+            Err(flume::TryRecvError::Disconnected) => Err(crate::error::AiError::Cancelled),
+        };
+        self.finished = true;
+        if let Ok((parsed, _)) = &result {
+            pending.actions.push(AiActionEvent {
+                entity: self.entity,
+                action: parsed.clone().into_action_payload(),
+            });
+        }
+        Some(result)
+    }
+}
+
+/// Non-blocking [`prompt_typed_action`]: the prompt and parsing run on the background runtime
+/// and the returned handle is polled for the result, so no frame waits on the model.
+///
+/// # Example
+/// ```ignore
+/// fn spawn_guard(
+///     mut request: Local<Option<PendingTypedRequest<SpawnAction>>>,
+///     ai: Res<LocalAiHandle>,
+///     npc: Single<Entity, With<AI>>,
+///     mut pending: ResMut<PendingAiActions>,
+/// ) {
+///     if let Some(backend) = &ai.backend && request.is_none() {
+///         *request = Some(prompt_typed_action_async(backend, "Spawn a guard", *npc));
+///     }
+///     if let Some(Some(Err(e))) = request.as_mut().map(|r| r.poll(&mut pending)) {
+///         error!("Spawn failed: {}", e);
+///     }
+/// }
+/// ```
+pub fn prompt_typed_action_async<T>(
+    backend: &std::sync::Arc<dyn crate::dialogue::LocalAi>,
+    user_message: &str,
+    entity: Entity,
+) -> PendingTypedRequest<T>
+where
+    T: crate::parse::AiParsable + serde::de::DeserializeOwned,
+{
+    let formatted_prompt = crate::parse::build_typed_prompt::<T>(user_message);
+    let backend = backend.clone();
+    let (tx, rx) = flume::bounded(1);
+    crate::models::TOKIO_RUNTIME.spawn(async move {
+        let messages = vec![crate::rag::AiMessage::user(&formatted_prompt)];
+        let result = backend.prompt(&messages).and_then(|response| {
+            T::parse_from_ai_response(&response).map(|parsed| (parsed, response))
+        });
+        let _ = tx.send(result);
+    });
+    PendingTypedRequest {
+        entity,
+        rx,
+        finished: false,
+    }
+}
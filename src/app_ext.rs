@@ -17,9 +17,10 @@
 //!     .run();
 //! ```
 
-use crate::actions::{AiActionRegistry, IntoActionPayload};
-use crate::dialogue::AIDialoguePlugin;
+use crate::actions::{ActionScope, AiActionRegistry, IntoActionPayload};
+use crate::dialogue::{AIDialoguePlugin, AiResponseEvent};
 use crate::models::{AiModelBuilder, ModelType};
+use crate::rate_limit::{ActionBudgets, ActionLimit};
 use bevy::prelude::*;
 
 /// Extension trait for `App` that provides convenient AI setup methods.
@@ -75,6 +76,49 @@ pub trait AiAppExt {
         T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
         S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static;
 
+    /// Register a typed AI action handler with an inverse, so the action can be undone with
+    /// [`ActionHistory::undo_last`](crate::history::ActionHistory::undo_last). Whatever the
+    /// handler returns is handed to `undo`. Needs an `ActionHistory` resource.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.insert_resource(ActionHistory::default())
+    ///     .register_ai_action_with_undo::<PlaceBlock, _, _, _, _, _>(
+    ///         |In(block): In<PlaceBlock>, mut commands: Commands| commands.spawn(block.bundle()).id(),
+    ///         |In(placed): In<Entity>, mut commands: Commands| commands.entity(placed).despawn(),
+    ///     );
+    /// ```
+    fn register_ai_action_with_undo<T, R, S, M, U, MU>(&mut self, system: S, undo: U) -> &mut Self
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        R: 'static + Send + Sync,
+        S: bevy::ecs::system::IntoSystem<In<T>, R, M> + 'static,
+        U: bevy::ecs::system::IntoSystem<In<R>, (), MU> + 'static;
+
+    /// Register a typed AI action handler that only runs for actions from entities in `scope`,
+    /// e.g. `ActionScope::with::<Archer>()` or a single entity. Other entities fall back to the
+    /// handler registered with [`register_ai_action`](AiAppExt::register_ai_action).
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.register_ai_action::<Attack, _, _>(swing_sword)
+    ///     .register_ai_action_for::<Attack, _, _>(ActionScope::with::<Archer>(), shoot_arrow);
+    /// ```
+    fn register_ai_action_for<T, S, M>(&mut self, scope: ActionScope, system: S) -> &mut Self
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static;
+
+    /// Limit how often each entity may run action `name`; actions over the limit are dropped
+    /// and fire [`AiActionThrottled`](crate::rate_limit::AiActionThrottled).
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.register_ai_action::<SpawnEntity, _, _>(spawn_entity)
+    ///     .limit_ai_action("spawn_entity", ActionLimit::once_per(Duration::from_secs(5)));
+    /// ```
+    fn limit_ai_action(&mut self, name: &str, limit: ActionLimit) -> &mut Self;
+
     /// Register a raw AI action handler by name.
     ///
     /// The handler receives the full `AiActionEvent` as `In<AiActionEvent>`.
@@ -88,6 +132,99 @@ pub trait AiAppExt {
     fn register_ai_action_raw<S, M>(&mut self, name: &str, system: S) -> &mut Self
     where
         S: bevy::ecs::system::IntoSystem<In<crate::actions::AiActionEvent>, (), M> + 'static;
+
+    /// Run `handler` for every AI reply, with the responding entity and the reply text.
+    ///
+    /// Unlike polling `Changed<DialogueReceiver>`, every reply is delivered, even when several
+    /// arrive in the same frame.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.on_ai_response(|In((npc, text)): In<(Entity, String)>, mut log: ResMut<ChatLog>| {
+    ///     log.push(npc, text);
+    /// });
+    /// ```
+    fn on_ai_response<S, M>(&mut self, handler: S) -> &mut Self
+    where
+        S: bevy::ecs::system::IntoSystem<In<(Entity, String)>, (), M> + 'static;
+
+    /// Run `handler` for every `T` action parsed from an AI reply, with the responding entity.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.on_ai_typed_response::<SpawnAction, _, _>(|In((npc, action)): In<(Entity, SpawnAction)>| {
+    ///     info!("{:?} wants to spawn {}", npc, action.name);
+    /// });
+    /// ```
+    fn on_ai_typed_response<T, S, M>(&mut self, handler: S) -> &mut Self
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        S: bevy::ecs::system::IntoSystem<In<(Entity, T)>, (), M> + 'static;
+
+    /// Record deeds in NPC [`OpinionLedger`](crate::opinion::OpinionLedger)s whenever event `E` is triggered.
+    ///
+    /// The mapper returns the NPC the deed was done to and the deed itself, or `None` to ignore the event.
+    /// A ledger is inserted on the NPC if it doesn't have one yet.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.track_deeds::<GiftGiven, _>(|e| Some((e.to, Deed::new("the player", DeedKind::Gift))));
+    /// ```
+    fn track_deeds<E, F>(&mut self, mapper: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&E) -> Option<(Entity, crate::opinion::Deed)> + Send + Sync + 'static;
+
+    /// Adjust NPC [`Relationships`](crate::faction::Relationships) whenever event `E` is triggered.
+    ///
+    /// The mapper returns the NPC, the subject as the NPC refers to them and the change to apply,
+    /// or `None` to ignore the event. Relationships are inserted on the NPC if missing.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.track_relationships::<GiftGiven, _>(|e| Some((e.to, "the player".to_string(), 0.2)));
+    /// ```
+    fn track_relationships<E, F>(&mut self, mapper: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&E) -> Option<(Entity, String, f32)> + Send + Sync + 'static;
+
+    /// Remember event `E` in an NPC's [`SemanticMemory`](crate::memory::SemanticMemory) whenever it is triggered.
+    ///
+    /// The mapper returns the NPC that should remember the event and the text to store, or `None`
+    /// to ignore the event. Captures are timestamped when the event fires and embedded by the
+    /// [`MemoryCapturePlugin`](crate::memory::MemoryCapturePlugin).
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.capture_event::<DamageEvent, _>(|e| Some((e.target, format!("I was hit for {} damage", e.amount))));
+    /// ```
+    fn capture_event<E, F>(&mut self, mapper: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&E) -> Option<(Entity, String)> + Send + Sync + 'static;
+
+    /// Add `snapshot` to the context gathered for every request, see
+    /// [`WorldSnapshot`](crate::snapshot::WorldSnapshot). Replaces an earlier snapshot.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.add_world_snapshot(WorldSnapshot::new().positions().serialized::<Health>("health"));
+    /// ```
+    fn add_world_snapshot(&mut self, snapshot: crate::snapshot::WorldSnapshot) -> &mut Self;
+
+    /// Run `processor` on the text of every reply before it is stored, after the processors
+    /// added before it. See [`crate::postprocess`] for the built-in ones.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.add_ai_response_processor(strip_role_prefix)
+    ///     .add_ai_response_processor(strip_markdown);
+    /// ```
+    fn add_ai_response_processor(
+        &mut self,
+        processor: impl Fn(&mut String, &crate::dialogue::DialogueResponse) + Send + Sync + 'static,
+    ) -> &mut Self;
 }
 
 impl AiAppExt for App {
@@ -110,16 +247,47 @@ impl AiAppExt for App {
         T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
         S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static,
     {
-        let action_name = T::action_name();
-
         // Ensure registry exists (it should if AIDialoguePlugin was added)
         self.world_mut()
             .get_resource_or_init::<AiActionRegistry>()
-            .register_typed::<T, S, M>(action_name, system);
+            .register_action::<T, S, M>(system);
+
+        self
+    }
+
+    fn register_ai_action_with_undo<T, R, S, M, U, MU>(&mut self, system: S, undo: U) -> &mut Self
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        R: 'static + Send + Sync,
+        S: bevy::ecs::system::IntoSystem<In<T>, R, M> + 'static,
+        U: bevy::ecs::system::IntoSystem<In<R>, (), MU> + 'static,
+    {
+        self.world_mut()
+            .get_resource_or_init::<AiActionRegistry>()
+            .register_action_with_undo::<T, R, S, M, U, MU>(system, undo);
 
         self
     }
 
+    fn register_ai_action_for<T, S, M>(&mut self, scope: ActionScope, system: S) -> &mut Self
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        S: bevy::ecs::system::IntoSystem<In<T>, (), M> + 'static,
+    {
+        self.world_mut()
+            .get_resource_or_init::<AiActionRegistry>()
+            .register_action_scoped::<T, S, M>(scope, system);
+
+        self
+    }
+
+    fn limit_ai_action(&mut self, name: &str, limit: ActionLimit) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<ActionBudgets>()
+            .set(name, limit);
+        self
+    }
+
     fn register_ai_action_raw<S, M>(&mut self, name: &str, system: S) -> &mut Self
     where
         S: bevy::ecs::system::IntoSystem<In<crate::actions::AiActionEvent>, (), M> + 'static,
@@ -135,4 +303,130 @@ impl AiAppExt for App {
 
         self
     }
+
+    fn on_ai_response<S, M>(&mut self, handler: S) -> &mut Self
+    where
+        S: bevy::ecs::system::IntoSystem<In<(Entity, String)>, (), M> + 'static,
+    {
+        let id = self.world_mut().register_system(handler);
+        self.add_observer(move |event: On<AiResponseEvent>, mut commands: Commands| {
+            let event = event.event();
+            commands.run_system_with(id, (event.entity, event.text.clone()));
+        });
+        self
+    }
+
+    fn on_ai_typed_response<T, S, M>(&mut self, handler: S) -> &mut Self
+    where
+        T: 'static + Send + Sync + serde::de::DeserializeOwned + IntoActionPayload,
+        S: bevy::ecs::system::IntoSystem<In<(Entity, T)>, (), M> + 'static,
+    {
+        let id = self.world_mut().register_system(handler);
+        self.add_observer(move |event: On<AiResponseEvent>, mut commands: Commands| {
+            let event = event.event();
+            for action in event.actions.iter().filter(|a| a.name == T::action_name()) {
+                let mut params = action.params.clone();
+                T::fill_missing_params(&mut params);
+                let parsed = T::validate_params(&params)
+                    .and_then(|_| serde_json::from_value::<T>(params).map_err(|e| e.to_string()));
+                match parsed {
+                    Ok(value) => commands.run_system_with(id, (event.entity, value)),
+                    Err(e) => warn!(
+                        "Failed to deserialize '{}' response for {:?}: {}",
+                        action.name, event.entity, e
+                    ),
+                }
+            }
+        });
+        self
+    }
+
+    fn track_deeds<E, F>(&mut self, mapper: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&E) -> Option<(Entity, crate::opinion::Deed)> + Send + Sync + 'static,
+    {
+        self.add_observer(move |event: On<E>, mut commands: Commands| {
+            if let Some((npc, deed)) = mapper(event.event()) {
+                commands.queue(move |world: &mut World| {
+                    // The NPC may be despawned before this runs, e.g. by the deed itself
+                    if let Ok(mut npc) = world.get_entity_mut(npc) {
+                        npc.entry::<crate::opinion::OpinionLedger>()
+                            .or_default()
+                            .into_mut()
+                            .record(deed);
+                    }
+                });
+            }
+        });
+        self
+    }
+
+    fn track_relationships<E, F>(&mut self, mapper: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&E) -> Option<(Entity, String, f32)> + Send + Sync + 'static,
+    {
+        self.add_observer(move |event: On<E>, mut commands: Commands| {
+            if let Some((npc, subject, delta)) = mapper(event.event()) {
+                commands.queue(move |world: &mut World| {
+                    // Skipped when the NPC was despawned before this runs
+                    if let Ok(mut npc) = world.get_entity_mut(npc) {
+                        npc.entry::<crate::faction::Relationships>()
+                            .or_default()
+                            .into_mut()
+                            .adjust(subject, delta);
+                    }
+                });
+            }
+        });
+        self
+    }
+
+    fn capture_event<E, F>(&mut self, mapper: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&E) -> Option<(Entity, String)> + Send + Sync + 'static,
+    {
+        self.init_resource::<crate::memory::PendingMemoryCaptures>();
+        self.add_observer(
+            move |event: On<E>, mut pending: ResMut<crate::memory::PendingMemoryCaptures>| {
+                if let Some((npc, text)) = mapper(event.event()) {
+                    pending.captures.push(crate::memory::MemoryCapture {
+                        entity: npc,
+                        text,
+                        metadata: serde_json::json!({ "source": std::any::type_name::<E>() }),
+                        timestamp: std::time::SystemTime::now(),
+                        importance: crate::memory::DEFAULT_IMPORTANCE,
+                    });
+                }
+            },
+        );
+        self
+    }
+
+    fn add_world_snapshot(&mut self, snapshot: crate::snapshot::WorldSnapshot) -> &mut Self {
+        let registered = self
+            .world()
+            .contains_resource::<crate::snapshot::WorldSnapshot>();
+        self.insert_resource(snapshot);
+        if !registered {
+            self.init_resource::<crate::snapshot::SnapshotBaselines>()
+                .add_observer(crate::snapshot::forget_snapshots_on_swap);
+            self.world_mut()
+                .get_resource_or_init::<crate::context::AiSystemContextStore>()
+                .add_system(crate::snapshot::gather_world_snapshot);
+        }
+        self
+    }
+
+    fn add_ai_response_processor(
+        &mut self,
+        processor: impl Fn(&mut String, &crate::dialogue::DialogueResponse) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<crate::postprocess::AiResponseProcessors>()
+            .add(processor);
+        self
+    }
 }
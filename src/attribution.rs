@@ -0,0 +1,79 @@
+//! Telling generated lines apart from authored ones.
+//!
+//! Every response carries a [`ResponseOrigin`]: `Generated` when it came from the model,
+//! `Authored` when it was written by the game (preprogrammed replies, rejected input). The
+//! origin is available on `DialogueResponse`, `AiResponseEvent`, the wire format and
+//! `DialogueReceiver::last_origin`, so analytics, moderation tools and UI can filter on it
+//! without inspecting the text. Nothing is added to the text itself.
+//!
+//! With [`AiAttribution::tag_receivers`] set, the receiver entity also carries an
+//! [`AiGenerated`] component while its last reply is generated, which is convenient for
+//! queries like `Query<&DialogueReceiver, With<AiGenerated>>`. UI entities showing a reply can
+//! get the same marker through [`AiGenerated::from_event`].
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(AiAttribution { tag_receivers: true });
+//!
+//! app.add_observer(|reply: On<AiResponseEvent>, mut commands: Commands| {
+//!     let mut bubble = commands.spawn(Text::new(reply.event().text.clone()));
+//!     if let Some(tag) = AiGenerated::from_event(reply.event()) {
+//!         bubble.insert(tag);
+//!     }
+//! });
+//!
+//! fn style_generated(mut texts: Query<&mut TextColor, Added<AiGenerated>>) {
+//!     for mut color in &mut texts {
+//!         color.0 = Color::srgb(0.7, 0.8, 1.0);
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::dialogue::AiResponseEvent;
+
+/// Where a response's text came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Reflect, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseOrigin {
+    /// Produced by a backend call, including the backend's error messages.
+    #[default]
+    Generated,
+    /// Written by the game: preprogrammed replies and input rejected before prompting.
+    Authored,
+}
+
+impl ResponseOrigin {
+    pub fn is_generated(self) -> bool {
+        self == ResponseOrigin::Generated
+    }
+}
+
+/// Marks an entity whose text was generated by the model.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct AiGenerated {
+    /// The AI entity that produced the text.
+    pub source: Entity,
+    pub request_id: u64,
+}
+
+impl AiGenerated {
+    /// The marker for a reply, or `None` if the reply was authored.
+    pub fn from_event(event: &AiResponseEvent) -> Option<Self> {
+        event.origin.is_generated().then_some(Self {
+            source: event.entity,
+            request_id: event.request_id,
+        })
+    }
+}
+
+/// Attribution options of the dialogue plugin.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiAttribution {
+    /// Keep an [`AiGenerated`] component on receivers whose last reply was generated.
+    pub tag_receivers: bool,
+}
@@ -0,0 +1,429 @@
+//! Offline content baking.
+//!
+//! Runs a list of text or typed prompts through a backend in parallel and writes the results
+//! to a JSON asset, for teams that pre-generate AI content at build time instead of at runtime.
+//! Works from a build script, a test, or a headless `App` (see [`ContentBaker::from_handle`]).
+//!
+//! # Example
+//! ```ignore
+//! let backend = AiModelBuilder::new_with(ModelType::Llama).with_seed(7).build()?;
+//! let baked = ContentBaker::new(backend)
+//!     .with_max_parallel(4)
+//!     .text("tavern_greeting", "Write a one-line greeting for a tavern keeper.")
+//!     .typed::<ItemDescription>("rusty_sword", "Describe a rusty sword.")
+//!     .run()?;
+//! baked.save("assets/baked/dialogue.json")?;
+//!
+//! // Later, at runtime:
+//! let content = BakedContent::load("assets/baked/dialogue.json")?;
+//! let greeting = content.get_text("tavern_greeting");
+//! ```
+//!
+//! # Drift detection
+//! Baked files record a [`BakeFingerprint`] (model id and seed) and a hash of every prompt.
+//! [`ContentBaker::verify`] compares a baked file against the current jobs and configuration
+//! and reports every [`BakeDrift`] that would change the outputs, so a CI step can fail
+//! instead of silently shipping different content:
+//!
+//! ```ignore
+//! let baker = ContentBaker::new(backend)
+//!     .with_fingerprint(BakeFingerprint::from_builder(&builder))
+//!     .jobs(all_jobs());
+//! let drift = baker.verify_file("assets/baked/dialogue.json")?;
+//! assert!(drift.is_empty(), "baked content is stale: {:?}", drift);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::dialogue::{LocalAi, LocalAiHandle};
+use crate::parse::AiParsable;
+use crate::rag::AiMessage;
+
+/// What kind of output a bake job produces.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BakeKind {
+    /// Plain text output.
+    Text,
+    /// JSON output matching an `AiParsable` schema.
+    Typed { schema_description: String },
+}
+
+/// A single prompt to bake, identified by a unique key.
+#[derive(Debug, Clone)]
+pub struct BakeJob {
+    pub key: String,
+    pub prompt: String,
+    pub kind: BakeKind,
+}
+
+impl BakeJob {
+    /// A plain-text job.
+    pub fn text(key: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            prompt: prompt.into(),
+            kind: BakeKind::Text,
+        }
+    }
+
+    /// A typed job whose output must match `T`'s schema.
+    pub fn typed<T: AiParsable>(key: impl Into<String>, prompt: impl Into<String>) -> Self {
+        let prompt = prompt.into();
+        Self {
+            key: key.into(),
+            prompt: crate::parse::build_typed_prompt::<T>(&prompt),
+            kind: BakeKind::Typed {
+                schema_description: T::schema_description(),
+            },
+        }
+    }
+
+    /// Stable hash of the prompt sent to the model.
+    pub fn prompt_hash(&self) -> String {
+        prompt_hash(&self.prompt)
+    }
+
+    /// Run this job synchronously on `backend`.
+    fn run(&self, backend: &Arc<dyn LocalAi>) -> BakedEntry {
+        let messages = [AiMessage::user(&self.prompt)];
+        let result = match &self.kind {
+            BakeKind::Text => backend
+                .prompt(&messages)
+                .map(|text| serde_json::Value::String(text.trim().to_string())),
+            BakeKind::Typed { schema_description } => backend
+                .prompt_typed(&messages, None, schema_description)
+                .map(|(value, _)| value),
+        };
+        match result {
+            Ok(output) => BakedEntry {
+                prompt: self.prompt.clone(),
+                prompt_hash: self.prompt_hash(),
+                output,
+                error: None,
+            },
+            Err(e) => BakedEntry {
+                prompt: self.prompt.clone(),
+                prompt_hash: self.prompt_hash(),
+                output: serde_json::Value::Null,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Baked result for a single job.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BakedEntry {
+    /// The full prompt sent to the model.
+    pub prompt: String,
+    /// Stable hash of `prompt`, see [`prompt_hash`].
+    #[serde(default)]
+    pub prompt_hash: String,
+    /// A JSON string for text jobs, the parsed JSON value for typed jobs, `null` on error.
+    pub output: serde_json::Value,
+    /// Error message if generation failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A set of baked outputs keyed by job key, serialized as a JSON asset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BakedContent {
+    /// Configuration the content was baked with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<BakeFingerprint>,
+    pub entries: BTreeMap<String, BakedEntry>,
+}
+
+impl BakedContent {
+    /// Load baked content from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.as_ref().display(), e))
+    }
+
+    /// Write baked content as pretty-printed JSON, creating parent directories.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BakedEntry> {
+        self.entries.get(key)
+    }
+
+    /// Text output for `key`, if it was baked successfully as text.
+    pub fn get_text(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).and_then(|e| e.output.as_str())
+    }
+
+    /// Typed output for `key`, deserialized into `T`.
+    pub fn get_typed<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.entries
+            .get(key)
+            .and_then(|e| serde_json::from_value(e.output.clone()).ok())
+    }
+
+    /// Keys whose generation failed.
+    pub fn failed(&self) -> impl Iterator<Item = (&String, &BakedEntry)> {
+        self.entries.iter().filter(|(_, e)| e.error.is_some())
+    }
+}
+
+/// Configuration that determines baked outputs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BakeFingerprint {
+    /// Identifier of the model, e.g. from `AiModelBuilder::model_id`.
+    pub model_id: String,
+    /// Generation seed; without one outputs are not reproducible.
+    pub seed: Option<u64>,
+}
+
+impl BakeFingerprint {
+    pub fn new(model_id: impl Into<String>, seed: Option<u64>) -> Self {
+        Self {
+            model_id: model_id.into(),
+            seed,
+        }
+    }
+
+    /// Fingerprint of the model a builder would load.
+    pub fn from_builder(builder: &crate::models::AiModelBuilder) -> Self {
+        Self::new(builder.model_id(), builder.seed())
+    }
+}
+
+/// A difference between baked content and the current bake configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BakeDrift {
+    /// The baked file has no fingerprint to compare against.
+    MissingFingerprint,
+    /// The current baker has no fingerprint, so model and seed changes cannot be detected.
+    NoCurrentFingerprint,
+    /// No seed is configured, so re-baking would not reproduce the outputs.
+    Unseeded,
+    ModelChanged {
+        baked: String,
+        current: String,
+    },
+    SeedChanged {
+        baked: Option<u64>,
+        current: Option<u64>,
+    },
+    /// The prompt for `key` changed since it was baked.
+    PromptChanged {
+        key: String,
+    },
+    /// A job exists that has not been baked yet.
+    Missing {
+        key: String,
+    },
+    /// Baked content exists for a key that is no longer a job.
+    Stale {
+        key: String,
+    },
+}
+
+impl std::fmt::Display for BakeDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BakeDrift::MissingFingerprint => write!(f, "baked content has no fingerprint"),
+            BakeDrift::NoCurrentFingerprint => write!(
+                f,
+                "current baker has no fingerprint; model changes cannot be detected"
+            ),
+            BakeDrift::Unseeded => write!(f, "no seed configured; outputs are not reproducible"),
+            BakeDrift::ModelChanged { baked, current } => {
+                write!(f, "model changed from '{}' to '{}'", baked, current)
+            }
+            BakeDrift::SeedChanged { baked, current } => {
+                write!(f, "seed changed from {:?} to {:?}", baked, current)
+            }
+            BakeDrift::PromptChanged { key } => write!(f, "prompt for '{}' changed", key),
+            BakeDrift::Missing { key } => write!(f, "'{}' has not been baked", key),
+            BakeDrift::Stale { key } => write!(f, "'{}' is baked but no longer a job", key),
+        }
+    }
+}
+
+/// Stable (FNV-1a, 64-bit) hash of a prompt, hex encoded.
+///
+/// Unlike `std::hash`, the result does not change between Rust versions or platforms.
+pub fn prompt_hash(prompt: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in prompt.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Runs many bake jobs through a backend with bounded parallelism.
+pub struct ContentBaker {
+    backend: Arc<dyn LocalAi>,
+    jobs: Vec<BakeJob>,
+    max_parallel: usize,
+    fingerprint: Option<BakeFingerprint>,
+}
+
+impl ContentBaker {
+    /// Create a baker using all available CPU parallelism.
+    pub fn new(backend: Arc<dyn LocalAi>) -> Self {
+        Self {
+            backend,
+            jobs: Vec::new(),
+            max_parallel: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            fingerprint: None,
+        }
+    }
+
+    /// Record the configuration used to bake, enabling drift detection.
+    pub fn with_fingerprint(mut self, fingerprint: BakeFingerprint) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// Create a baker from a headless app's `LocalAiHandle`. Returns None if no model is loaded.
+    pub fn from_handle(handle: &LocalAiHandle) -> Option<Self> {
+        handle.get_backend().map(Self::new)
+    }
+
+    /// Limit how many prompts run at the same time.
+    pub fn with_max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+
+    /// Add a job.
+    pub fn job(mut self, job: BakeJob) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    /// Add a text job.
+    pub fn text(self, key: impl Into<String>, prompt: impl Into<String>) -> Self {
+        self.job(BakeJob::text(key, prompt))
+    }
+
+    /// Add a typed job.
+    pub fn typed<T: AiParsable>(self, key: impl Into<String>, prompt: impl Into<String>) -> Self {
+        self.job(BakeJob::typed::<T>(key, prompt))
+    }
+
+    /// Add several jobs.
+    pub fn jobs(mut self, jobs: impl IntoIterator<Item = BakeJob>) -> Self {
+        self.jobs.extend(jobs);
+        self
+    }
+
+    /// Run all jobs, blocking until every one has finished. Fails without running anything
+    /// when two jobs share a key.
+    pub fn run(&self) -> Result<BakedContent, String> {
+        let mut keys = std::collections::BTreeSet::new();
+        if let Some(job) = self.jobs.iter().find(|job| !keys.insert(job.key.as_str())) {
+            return Err(format!("Duplicate bake job key '{}'", job.key));
+        }
+        let next = AtomicUsize::new(0);
+        let results: Mutex<BTreeMap<String, BakedEntry>> = Mutex::new(BTreeMap::new());
+        let workers = self.max_parallel.min(self.jobs.len()).max(1);
+
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = self.jobs.get(idx) else {
+                            break;
+                        };
+                        let entry = job.run(&self.backend);
+                        results
+                            .lock()
+                            .expect("bake results mutex poisoned")
+                            .insert(job.key.clone(), entry);
+                    }
+                });
+            }
+        });
+
+        Ok(BakedContent {
+            fingerprint: self.fingerprint.clone(),
+            entries: results.into_inner().expect("bake results mutex poisoned"),
+        })
+    }
+
+    /// Compare baked content against the current jobs and fingerprint.
+    /// An empty result means re-baking would reproduce the same content.
+    pub fn verify(&self, baked: &BakedContent) -> Vec<BakeDrift> {
+        let mut drift = Vec::new();
+
+        match (&baked.fingerprint, &self.fingerprint) {
+            (Some(old), Some(new)) => {
+                if old.model_id != new.model_id {
+                    drift.push(BakeDrift::ModelChanged {
+                        baked: old.model_id.clone(),
+                        current: new.model_id.clone(),
+                    });
+                }
+                if old.seed != new.seed {
+                    drift.push(BakeDrift::SeedChanged {
+                        baked: old.seed,
+                        current: new.seed,
+                    });
+                }
+                if new.seed.is_none() {
+                    drift.push(BakeDrift::Unseeded);
+                }
+            }
+            (None, Some(_)) => drift.push(BakeDrift::MissingFingerprint),
+            (_, None) => drift.push(BakeDrift::NoCurrentFingerprint),
+        }
+
+        for job in &self.jobs {
+            match baked.entries.get(&job.key) {
+                Some(entry) if entry.prompt_hash != job.prompt_hash() => {
+                    drift.push(BakeDrift::PromptChanged {
+                        key: job.key.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => drift.push(BakeDrift::Missing {
+                    key: job.key.clone(),
+                }),
+            }
+        }
+
+        for key in baked.entries.keys() {
+            if !self.jobs.iter().any(|j| &j.key == key) {
+                drift.push(BakeDrift::Stale { key: key.clone() });
+            }
+        }
+
+        drift
+    }
+
+    /// Load baked content from `path` and [`verify`](Self::verify) it.
+    pub fn verify_file(&self, path: impl AsRef<Path>) -> Result<Vec<BakeDrift>, String> {
+        Ok(self.verify(&BakedContent::load(path)?))
+    }
+
+    /// Run all jobs and write the results to `path`.
+    pub fn run_to_file(&self, path: impl AsRef<Path>) -> Result<BakedContent, String> {
+        let content = self.run()?;
+        content.save(path)?;
+        Ok(content)
+    }
+}
@@ -0,0 +1,399 @@
+//! Barks: short ambient one-liners.
+//!
+//! Guards muttering about the weather or a merchant calling out to passers-by say many tiny
+//! lines, far too often for a dialogue request each. An entity with a [`BarkProfile`] barks
+//! when sent a [`BarkTrigger`], or when a [`BarkListener`] (usually the player) walks into its
+//! proximity radius. Lines are generated a batch at a time through [`AiGenerate`] and kept in
+//! the entity's [`BarkPool`]; once a topic's pool is full, triggers pick from it in turn
+//! without asking the model again. A cooldown per entity keeps it from barking on every
+//! trigger.
+//!
+//! Each line said is delivered as a [`Bark`] event. The lines are asked for through the
+//! barking entity itself, so the profile adds a [`DialogueReceiver`] to it.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(BarkPlugin);
+//!
+//! commands.spawn((
+//!     AI,
+//!     Name::new("Gate guard"),
+//!     Transform::from_xyz(4.0, 0.0, 0.0),
+//!     BarkProfile::new(["the weather", "spotting a thief"])
+//!         .with_tone("bored, gruff")
+//!         .on_proximity(3.0, "someone walks up to the gate"),
+//! ));
+//! commands.spawn((Player, BarkListener, Transform::default()));
+//!
+//! // Game events bark too
+//! commands.trigger(BarkTrigger::new(guard, "spotting a thief"));
+//!
+//! app.add_observer(|bark: On<Bark>, mut bubbles: ResMut<SpeechBubbles>| {
+//!     bubbles.show(bark.entity, &bark.text);
+//! });
+//! ```
+
+use bevy::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::dialogue::DialogueReceiver;
+use crate::generate::{AiGenerate, AiGeneratePlugin, AiGeneratedBatch};
+
+/// What an entity barks about, and how.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+#[require(BarkPool, DialogueReceiver)]
+pub struct BarkProfile {
+    /// Topics the entity barks about, e.g. "the weather". Triggers for other topics are
+    /// ignored; an empty list accepts any topic.
+    pub topics: Vec<String>,
+    /// Longest line in characters. Longer generated lines are dropped.
+    pub max_chars: usize,
+    /// How the lines sound, e.g. "bored, gruff". Empty leaves it to the persona.
+    pub tone: String,
+    /// Shortest time between two barks of the entity.
+    pub cooldown: Duration,
+    /// Lines kept per topic. Once full, the pool is reused instead of asking the model.
+    pub pool_size: usize,
+    /// Radius within which a [`BarkListener`] triggers a bark, and the topic it barks about.
+    pub proximity: Option<(f32, String)>,
+}
+
+impl Default for BarkProfile {
+    fn default() -> Self {
+        Self {
+            topics: Vec::new(),
+            max_chars: 80,
+            tone: String::new(),
+            cooldown: Duration::from_secs(10),
+            pool_size: 5,
+            proximity: None,
+        }
+    }
+}
+
+impl BarkProfile {
+    pub fn new<S: Into<String>>(topics: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            topics: topics.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_tone(mut self, tone: impl Into<String>) -> Self {
+        self.tone = tone.into();
+        self
+    }
+
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn with_pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    /// Bark about `topic` when a [`BarkListener`] comes within `radius`. The topic is added to
+    /// [`topics`](Self::topics) if missing.
+    pub fn on_proximity(mut self, radius: f32, topic: impl Into<String>) -> Self {
+        let topic = topic.into();
+        if !self.topics.is_empty() && !self.topics.contains(&topic) {
+            self.topics.push(topic.clone());
+        }
+        self.proximity = Some((radius, topic));
+        self
+    }
+
+    /// Whether the entity barks about `topic`.
+    pub fn accepts(&self, topic: &str) -> bool {
+        self.topics.is_empty() || self.topics.iter().any(|t| t == topic)
+    }
+
+    /// Category the lines of `topic` are generated in. Entities with the same tone share it,
+    /// so they do not repeat each other.
+    fn category(&self, topic: &str) -> String {
+        if self.tone.is_empty() {
+            format!("barks:{}", topic)
+        } else {
+            format!("barks:{} ({})", topic, self.tone)
+        }
+    }
+}
+
+/// Lines generated for an entity's [`BarkProfile`], by topic. Added with the profile.
+#[derive(Component, Debug, Clone, Default)]
+pub struct BarkPool {
+    /// Lines by topic, in the order they were generated.
+    pub lines: BTreeMap<String, Vec<String>>,
+    /// Index of the next pooled line per topic.
+    next: HashMap<String, usize>,
+    last_bark: Option<Duration>,
+    listener_near: bool,
+}
+
+impl BarkPool {
+    /// Next pooled line of `topic`, cycling through the pool.
+    fn next_line(&mut self, topic: &str) -> Option<String> {
+        let lines = self.lines.get(topic).filter(|lines| !lines.is_empty())?;
+        let next = self.next.entry(topic.to_string()).or_default();
+        let line = lines[*next % lines.len()].clone();
+        *next = (*next + 1) % lines.len();
+        Some(line)
+    }
+}
+
+/// Marker for entities whose approach makes [`BarkProfile::proximity`] entities bark.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct BarkListener;
+
+/// Event asking `entity` to bark about `topic`. Ignored during the entity's cooldown.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct BarkTrigger {
+    pub entity: Entity,
+    pub topic: String,
+}
+
+impl BarkTrigger {
+    pub fn new(entity: Entity, topic: impl Into<String>) -> Self {
+        Self {
+            entity,
+            topic: topic.into(),
+        }
+    }
+}
+
+/// Event fired with each line an entity barks.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct Bark {
+    pub entity: Entity,
+    pub topic: String,
+    pub text: String,
+    /// Whether the line was reused from the [`BarkPool`] rather than just generated.
+    pub pooled: bool,
+}
+
+/// A batch of lines waiting for the model.
+struct PendingBark {
+    entity: Entity,
+    topic: String,
+    /// Bark the first new line, for triggers that found the pool empty.
+    say: bool,
+}
+
+/// Bark batches waiting for the model, by [`AiGenerate`] id.
+#[derive(Resource, Default)]
+struct PendingBarks {
+    by_batch: HashMap<u64, PendingBark>,
+}
+
+impl PendingBarks {
+    fn waiting(&self, entity: Entity, topic: &str) -> bool {
+        self.by_batch
+            .values()
+            .any(|p| p.entity == entity && p.topic == topic)
+    }
+}
+
+/// Plugin making [`BarkProfile`] entities bark, see the [module docs](self).
+pub struct BarkPlugin;
+
+impl Plugin for BarkPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AiGeneratePlugin>() {
+            app.add_plugins(AiGeneratePlugin::default());
+        }
+        app.register_type::<BarkProfile>()
+            .init_resource::<PendingBarks>()
+            .add_systems(Update, trigger_proximity_barks)
+            .add_observer(on_bark_trigger)
+            .add_observer(on_bark_lines);
+    }
+}
+
+/// Trigger a bark when the first [`BarkListener`] comes within an entity's proximity radius.
+fn trigger_proximity_barks(
+    listeners: Query<&Transform, With<BarkListener>>,
+    mut barkers: Query<(Entity, &BarkProfile, &Transform, &mut BarkPool)>,
+    mut commands: Commands,
+) {
+    for (entity, profile, transform, mut pool) in &mut barkers {
+        let Some((radius, topic)) = &profile.proximity else {
+            continue;
+        };
+        let near = listeners
+            .iter()
+            .any(|l| l.translation.distance(transform.translation) <= *radius);
+        if near && !pool.listener_near {
+            commands.trigger(BarkTrigger::new(entity, topic.clone()));
+        }
+        if pool.listener_near != near {
+            pool.listener_near = near;
+        }
+    }
+}
+
+/// Bark a pooled line, or ask for more lines when the pool is not full yet.
+fn on_bark_trigger(
+    trigger: On<BarkTrigger>,
+    mut barkers: Query<(&BarkProfile, &mut BarkPool)>,
+    mut pending: ResMut<PendingBarks>,
+    mut generate: AiGenerate,
+    time: Res<Time<Real>>,
+    mut commands: Commands,
+) {
+    let BarkTrigger { entity, topic } = trigger.event().clone();
+    let Ok((profile, mut pool)) = barkers.get_mut(entity) else {
+        debug!("Bark trigger for {:?}, which has no BarkProfile", entity);
+        return;
+    };
+    if !profile.accepts(&topic) {
+        return;
+    }
+    let now = time.elapsed();
+    if pool
+        .last_bark
+        .is_some_and(|last| now.saturating_sub(last) < profile.cooldown)
+    {
+        return;
+    }
+    pool.last_bark = Some(now);
+
+    let line = pool.next_line(&topic);
+    let have = pool.lines.get(&topic).map_or(0, Vec::len);
+    if have < profile.pool_size && !pending.waiting(entity, &topic) {
+        let mut what = format!(
+            "short lines you would call out about {}, each under {} characters",
+            topic, profile.max_chars
+        );
+        if !profile.tone.is_empty() {
+            what.push_str(&format!(", sounding {}", profile.tone));
+        }
+        let id = generate.batch(
+            Some(entity),
+            profile.category(&topic),
+            &what,
+            profile.pool_size - have,
+        );
+        pending.by_batch.insert(
+            id,
+            PendingBark {
+                entity,
+                topic: topic.clone(),
+                say: line.is_none(),
+            },
+        );
+    }
+    if let Some(text) = line {
+        commands.trigger(Bark {
+            entity,
+            topic,
+            text,
+            pooled: true,
+        });
+    }
+}
+
+/// Pool generated lines that fit the profile, barking the first if a trigger is waiting.
+fn on_bark_lines(
+    batch: On<AiGeneratedBatch>,
+    mut pending: ResMut<PendingBarks>,
+    mut barkers: Query<(&BarkProfile, &mut BarkPool)>,
+    mut commands: Commands,
+) {
+    let Some(request) = pending.by_batch.remove(&batch.id) else {
+        return;
+    };
+    let Ok((profile, mut pool)) = barkers.get_mut(request.entity) else {
+        return;
+    };
+    let fresh: Vec<String> = batch
+        .values
+        .iter()
+        .map(|line| line.trim().trim_matches('"').trim().to_string())
+        .filter(|line| !line.is_empty() && line.chars().count() <= profile.max_chars)
+        .collect();
+    let lines = pool.lines.entry(request.topic.clone()).or_default();
+    let old_len = lines.len();
+    let room = profile.pool_size.saturating_sub(old_len);
+    lines.extend(fresh.into_iter().take(room));
+    // Say the new lines before repeating old ones
+    if lines.len() > old_len {
+        pool.next.insert(request.topic.clone(), old_len);
+    }
+    if request.say
+        && let Some(text) = pool.next_line(&request.topic)
+    {
+        commands.trigger(Bark {
+            entity: request.entity,
+            topic: request.topic,
+            text,
+            pooled: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::{ScriptedAi, ai_test_app};
+
+    #[test]
+    fn pooled_lines_cycle_per_topic() {
+        let mut pool = BarkPool::default();
+        assert_eq!(pool.next_line("weather"), None);
+        pool.lines
+            .insert("weather".into(), vec!["Rain again.".into(), "Cold.".into()]);
+        assert_eq!(pool.next_line("weather").as_deref(), Some("Rain again."));
+        assert_eq!(pool.next_line("weather").as_deref(), Some("Cold."));
+        assert_eq!(pool.next_line("weather").as_deref(), Some("Rain again."));
+
+        let profile = BarkProfile::new(["weather"]).on_proximity(3.0, "greeting");
+        assert!(profile.accepts("greeting"));
+        assert!(!profile.accepts("thieves"));
+        assert!(BarkProfile::default().accepts("thieves"));
+        assert_eq!(
+            profile.with_tone("gruff").category("weather"),
+            "barks:weather (gruff)"
+        );
+    }
+
+    #[test]
+    fn documented_setup_barks_generated_lines() {
+        let mut app = ai_test_app(ScriptedAi::new([
+            r#"{"values": ["Rain again.", "Cold out here."]}"#,
+        ]));
+        app.add_plugins(BarkPlugin);
+        // As in the module docs: no DialogueReceiver spawned by hand
+        let guard = app
+            .world_mut()
+            .spawn((
+                crate::context::AI,
+                Transform::default(),
+                BarkProfile::new(["the weather"]),
+            ))
+            .id();
+        app.world_mut()
+            .trigger(BarkTrigger::new(guard, "the weather"));
+
+        let mut barks = Vec::new();
+        for _ in 0..100 {
+            app.update();
+            let pool = app.world().get::<BarkPool>(guard).unwrap();
+            if !pool.lines.is_empty() {
+                barks = pool.lines["the weather"].clone();
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(barks, ["Rain again.", "Cold out here."]);
+        assert!(app.world().resource::<PendingBarks>().by_batch.is_empty());
+    }
+}
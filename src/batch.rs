@@ -0,0 +1,365 @@
+//! Coalesce short requests into one model call.
+//!
+//! Crowds of NPCs asking cheap one-line questions (is this hostile, which mood fits) would
+//! otherwise be generated one after another. With [`AiBatchingPlugin`], context-free requests
+//! of the kinds enabled in [`AiBatching`] are held for up to `window_frames` frames, sent as one
+//! prompt of numbered items and the reply is split back into one response per request. Only
+//! backends whose [`LocalAi::supports_batching`] returns `true` are batched.
+//!
+//! A request is only batched when nothing about its requester changes the prompt: no
+//! preprogrammed response and no raw player text. Everything else takes the normal path.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiBatchingPlugin::default());
+//!
+//! fn assess(mut queue: ResMut<DialogueRequestQueue>, guards: Query<Entity, With<Guard>>) {
+//!     for guard in guards.iter() {
+//!         queue.push(DialogueRequest::classify::<Threat>(guard, "A cloaked figure nears the gate"));
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use std::sync::Arc;
+
+use crate::actions::ActionPayload;
+use crate::attribution::ResponseOrigin;
+use crate::dialogue::{
+    AiResponseLimit, DialogueReceiver, DialogueRequest, DialogueRequestKind, DialogueRequestQueue,
+    DialogueResponse, LocalAi, LocalAiHandle, render_prompt, text_reply_actions, translate_reply,
+};
+use crate::prompts::{BATCH, PromptTemplates};
+use crate::rag::AiMessage;
+use crate::tokenizer::AiTokenizer;
+
+/// Plugin batching short requests, see the [module docs](self).
+#[derive(Default)]
+pub struct AiBatchingPlugin {
+    pub settings: AiBatching,
+}
+
+/// Which requests are batched and how long they wait, editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiBatching {
+    /// Batch classification requests.
+    pub classify: bool,
+    /// Batch text requests sent without context.
+    pub text: bool,
+    /// Frames a request waits for others to join its batch; `0` only joins requests queued
+    /// in the same frame.
+    pub window_frames: u32,
+    /// Largest number of requests answered by one model call.
+    pub max_items: usize,
+}
+
+impl Default for AiBatching {
+    fn default() -> Self {
+        Self {
+            classify: true,
+            text: false,
+            window_frames: 2,
+            max_items: 8,
+        }
+    }
+}
+
+impl AiBatching {
+    /// Whether requests of `kind` are batched.
+    pub fn batches(&self, kind: &DialogueRequestKind) -> bool {
+        match kind {
+            DialogueRequestKind::Classify { .. } => self.classify,
+            DialogueRequestKind::Text {
+                include_context, ..
+            } => self.text && !include_context,
+            DialogueRequestKind::Typed { .. } | DialogueRequestKind::Data { .. } => false,
+        }
+    }
+}
+
+/// Requests held back for the next batch.
+#[derive(Resource, Default)]
+struct PendingBatch {
+    requests: Vec<DialogueRequest>,
+    /// Frames the oldest request has waited.
+    frames: u32,
+}
+
+impl Plugin for AiBatchingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .init_resource::<PendingBatch>()
+            .register_type::<AiBatching>()
+            .add_systems(
+                Update,
+                batch_requests
+                    .in_set(crate::dialogue::AiSystemSet::HandleRequests)
+                    .after(crate::rate_limit::throttle_requests)
+                    .before(crate::dialogue::handle_dialogue_requests),
+            );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn batch_requests(
+    settings: Res<AiBatching>,
+    mut pending: ResMut<PendingBatch>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    ai_handle: Res<LocalAiHandle>,
+    mut receivers: Query<&mut DialogueReceiver>,
+    mut transcripts: Query<&mut crate::transcript::Transcript>,
+    templates: Option<Res<PromptTemplates>>,
+    limit: Option<Res<AiResponseLimit>>,
+    tokenizer: Option<Res<AiTokenizer>>,
+    moderation: Option<Res<crate::moderation::AiOutputModeration>>,
+    locale: Option<Res<crate::locale::AiLocale>>,
+    lenient: Option<Res<crate::parse::LenientJson>>,
+    mut status: Option<ResMut<crate::status::AiPipelineStatus>>,
+) {
+    let Some(backend) = ai_handle
+        .backend
+        .as_ref()
+        .filter(|backend| backend.supports_batching())
+    else {
+        // Held requests go back to the normal path
+        for request in pending.requests.drain(..) {
+            queue.clear_in_flight(request.id);
+            queue.push(request);
+        }
+        pending.frames = 0;
+        return;
+    };
+
+    let taken = queue.take_matching(|request| {
+        !request.player_text
+            && settings.batches(&request.kind)
+            && receivers
+                .get(request.entity)
+                .is_ok_and(|receiver| receiver.preprogrammed.is_none())
+    });
+    for request in &taken {
+        queue.mark_in_flight(request);
+        if let Ok(mut receiver) = receivers.get_mut(request.entity) {
+            receiver.bypass_change_detection().request_sent(request.id);
+        }
+    }
+    pending.requests.extend(taken);
+    if pending.requests.is_empty() {
+        return;
+    }
+    let max_items = settings.max_items.max(1);
+    if pending.requests.len() < max_items && pending.frames < settings.window_frames {
+        pending.frames += 1;
+        return;
+    }
+    pending.frames = 0;
+
+    let limit = limit.as_deref().copied().unwrap_or_default();
+    let tokenizer = tokenizer.as_deref().cloned().unwrap_or_default();
+    let moderation = moderation
+        .as_deref()
+        .filter(|m| !m.filters.is_empty())
+        .cloned();
+    let translation = locale
+        .as_deref()
+        .and_then(|locale| locale.translation(templates.as_deref()));
+    let lenient = lenient.as_deref().copied();
+    let requests = std::mem::take(&mut pending.requests);
+    for chunk in requests.chunks(max_items) {
+        for request in chunk {
+            if let Ok(mut transcript) = transcripts.get_mut(request.entity) {
+                transcript.push_user(request.id, request.kind.as_user_message());
+            }
+            if let Some(status) = status.as_mut() {
+                status.request_sent(request.id, request.entity);
+            }
+        }
+        let prompt = match chunk {
+            [single] => single.kind.as_user_message().to_string(),
+            _ => batch_prompt(templates.as_deref(), chunk),
+        };
+        debug!("Sending a batch of {} requests", chunk.len());
+
+        let backend: Arc<dyn LocalAi> = backend.clone();
+        let tx = ai_handle.tx.clone();
+        let chunk = chunk.to_vec();
+        let tokenizer = tokenizer.clone();
+        let moderation = moderation.clone();
+        let translation = translation.clone();
+        crate::models::TOKIO_RUNTIME.spawn(async move {
+            let messages = [AiMessage::skip_default_context(), AiMessage::user(prompt)];
+            let answers = match backend.prompt(&messages) {
+                Ok(reply) if chunk.len() == 1 => vec![Ok(reply)],
+                Ok(reply) => split_numbered_reply(&reply, chunk.len())
+                    .into_iter()
+                    .map(|answer| answer.ok_or_else(|| "no answer in batched reply".to_string()))
+                    .collect(),
+                Err(e) => vec![Err(e.to_string()); chunk.len()],
+            };
+            for (request, answer) in chunk.into_iter().zip(answers) {
+                let (text, mut oversized, failed) = match answer {
+                    Ok(text) => {
+                        let (text, over) = limit.apply(text, true, &*tokenizer.0);
+                        let rejected = over.is_some_and(|o| !o.truncated);
+                        (text, over, rejected)
+                    }
+                    Err(e) => (format!("(ai error: {})", e), None, true),
+                };
+                let (mut response, actions, mut failed) =
+                    answer_request(&request.kind, text, failed, lenient);
+                // Text for the player is translated the same way as a single request's
+                if let Some(translation) = translation
+                    .as_ref()
+                    .filter(|_| !failed && matches!(request.kind, DialogueRequestKind::Text { .. }))
+                {
+                    let (translated, over) = translate_reply(
+                        translation,
+                        &*backend,
+                        request.id,
+                        response,
+                        &limit,
+                        &*tokenizer.0,
+                    );
+                    failed |= over.is_some_and(|o| !o.truncated);
+                    oversized = over.or(oversized);
+                    response = translated;
+                }
+                let mut actions = Some(actions);
+                let blocked = moderation
+                    .as_ref()
+                    .filter(|_| !failed)
+                    .and_then(|moderation| {
+                        moderation.apply(&*backend, &request.kind, &mut response, &mut actions)
+                    });
+                failed |=
+                    blocked.is_some() && !matches!(request.kind, DialogueRequestKind::Text { .. });
+                let _ = tx
+                    .send_async(DialogueResponse {
+                        request_id: request.id,
+                        entity: request.entity,
+                        response,
+                        kind: request.kind,
+                        actions,
+                        oversized,
+                        origin: ResponseOrigin::Generated,
+                        clarification: None,
+                        blocked,
+                        dispatched: 0,
+                        error: failed,
+                    })
+                    .await;
+            }
+        });
+    }
+}
+
+fn batch_prompt(templates: Option<&PromptTemplates>, requests: &[DialogueRequest]) -> String {
+    let items = requests
+        .iter()
+        .enumerate()
+        .map(|(i, request)| {
+            // One line per item, so the numbering stays unambiguous
+            let text = request
+                .kind
+                .as_user_message()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{}. {}", i + 1, text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let count = requests.len().to_string();
+    render_prompt(templates, BATCH, &[("count", &count), ("items", &items)]).unwrap_or(items)
+}
+
+/// The reply text and actions for one answered item, as the normal path produces them, and
+/// whether the reply is an error. `failed` marks `text` as an error already.
+fn answer_request(
+    kind: &DialogueRequestKind,
+    text: String,
+    failed: bool,
+    lenient: Option<crate::parse::LenientJson>,
+) -> (String, Vec<ActionPayload>, bool) {
+    if failed {
+        return (text, Vec::new(), true);
+    }
+    match kind {
+        DialogueRequestKind::Classify {
+            labels,
+            action_name,
+            ..
+        } => {
+            let label_refs: Vec<&str> = labels.iter().map(String::as_str).collect();
+            match crate::parse::match_label(&text, &label_refs) {
+                Some(label) => (
+                    label.to_string(),
+                    vec![ActionPayload {
+                        name: action_name.clone(),
+                        params: serde_json::Value::String(label.to_string()),
+                    }],
+                    false,
+                ),
+                None => (
+                    format!("(ai error: no known label in response: {})", text.trim()),
+                    Vec::new(),
+                    true,
+                ),
+            }
+        }
+        _ => {
+            let actions = text_reply_actions(&text, kind, lenient);
+            (text, actions, false)
+        }
+    }
+}
+
+/// Split a reply to a numbered batch into `count` answers. Lines start with the item number
+/// (`1.`, `1)` or `1:`); unnumbered lines continue the previous answer.
+pub fn split_numbered_reply(reply: &str, count: usize) -> Vec<Option<String>> {
+    let mut answers: Vec<Option<String>> = vec![None; count];
+    let mut current = None;
+    for line in reply.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let number = line[..digits].parse::<usize>().ok();
+        let rest = line[digits..].strip_prefix(['.', ')', ':']);
+        match (number, rest) {
+            (Some(n), Some(rest)) if (1..=count).contains(&n) => {
+                answers[n - 1] = Some(rest.trim().to_string());
+                current = Some(n - 1);
+            }
+            (Some(_), Some(_)) => current = None,
+            _ => {
+                if let Some(answer) = current.and_then(|i| answers[i].as_mut()) {
+                    answer.push('\n');
+                    answer.push_str(line);
+                }
+            }
+        }
+    }
+    answers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_numbered_replies() {
+        let reply = "Here you go:\n1. hostile\n2) friendly\n   and curious\n\n3: neutral\n9. extra";
+        assert_eq!(
+            split_numbered_reply(reply, 4),
+            [
+                Some("hostile".to_string()),
+                Some("friendly\nand curious".to_string()),
+                Some("neutral".to_string()),
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Leaf nodes for behavior trees and other tick-based AI.
+//!
+//! Behavior trees and utility AI evaluate their nodes every frame and expect an answer right
+//! away: running, succeeded or failed. Model replies take many frames, so the nodes here send
+//! their request on the first tick and report [`AiNodeStatus::Running`] until the reply
+//! arrives through the [`AiNodes`] system param:
+//!
+//! - [`AiDecisionNode`] asks the model to pick one of several branches.
+//! - [`AiUtteranceNode`] has the model say a line.
+//!
+//! The nodes are plain structs, so they fit in whatever tree crate the game uses: keep one in
+//! the leaf's component or state and call `tick` from the system that evaluates that leaf.
+//! Requests go through the regular dialogue queue with the entity's gathered context.
+//! A node fails when its reply is an error or stale, its request is throttled, or no reply
+//! arrives within [`AiNodeReplies::timeout`].
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiBehaviorPlugin);
+//!
+//! #[derive(Component)]
+//! struct ThreatResponse(AiDecisionNode);
+//!
+//! fn tick_threat_response(mut nodes: AiNodes, mut leaves: Query<(Entity, &mut ThreatResponse, &mut LeafState)>) {
+//!     for (npc, mut leaf, mut state) in leaves.iter_mut() {
+//!         *state = match leaf.0.tick(npc, &mut nodes) {
+//!             AiNodeStatus::Running => LeafState::Running,
+//!             AiNodeStatus::Success => LeafState::Branch(leaf.0.choice().unwrap()),
+//!             AiNodeStatus::Failure => LeafState::Failed,
+//!         };
+//!     }
+//! }
+//!
+//! commands.spawn((AI, DialogueReceiver::new(), ThreatResponse(
+//!     AiDecisionNode::new("Bandits block the road. What do you do?", ["fight", "flee", "bargain"]),
+//! )));
+//! ```
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::dialogue::{
+    AiResponseEvent, AiStaleResponse, DialogueRequest, DialogueRequestQueue, render_prompt,
+};
+use crate::prompts::{CONVERSATION_LINE, DECISION, PromptTemplates};
+use crate::rate_limit::AiRequestThrottled;
+
+/// Plugin collecting replies for [`AiDecisionNode`] and [`AiUtteranceNode`].
+pub struct AiBehaviorPlugin;
+
+impl Plugin for AiBehaviorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiNodeReplies>()
+            .add_observer(collect_node_replies)
+            .add_observer(drop_stale_node_replies)
+            .add_observer(drop_throttled_node_requests);
+    }
+}
+
+/// Result of ticking a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum AiNodeStatus {
+    /// Waiting for the model; tick again next frame.
+    Running,
+    Success,
+    Failure,
+}
+
+/// Replies to node requests not picked up yet, by request id.
+#[derive(Resource, Debug)]
+pub struct AiNodeReplies {
+    /// Time a node waits for its reply before failing.
+    pub timeout: Duration,
+    /// `Time<Real>` elapsed when each request still waiting was sent.
+    waiting: HashMap<u64, Duration>,
+    /// `Err` for requests that failed, went stale or were throttled.
+    replies: HashMap<u64, Result<String, String>>,
+}
+
+impl Default for AiNodeReplies {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            waiting: HashMap::new(),
+            replies: HashMap::new(),
+        }
+    }
+}
+
+impl AiNodeReplies {
+    /// Number of node requests still waiting for the model.
+    pub fn waiting(&self) -> usize {
+        self.waiting.len()
+    }
+}
+
+/// System parameter nodes tick with.
+#[derive(SystemParam)]
+pub struct AiNodes<'w> {
+    queue: ResMut<'w, DialogueRequestQueue>,
+    replies: ResMut<'w, AiNodeReplies>,
+    templates: Option<Res<'w, PromptTemplates>>,
+    time: Res<'w, Time<Real>>,
+}
+
+impl AiNodes<'_> {
+    fn send(&mut self, request: DialogueRequest) -> u64 {
+        let id = request.id;
+        self.replies.waiting.insert(id, self.time.elapsed());
+        self.queue.push(request);
+        id
+    }
+
+    /// The reply to request `id`: `Some(Ok)` once it arrived, `Some(Err)` if it failed or
+    /// timed out.
+    fn take(&mut self, id: u64) -> Option<Result<String, String>> {
+        if let Some(reply) = self.replies.replies.remove(&id) {
+            return Some(reply);
+        }
+        let sent = *self.replies.waiting.get(&id)?;
+        if self.time.elapsed().saturating_sub(sent) < self.replies.timeout {
+            return None;
+        }
+        self.replies.waiting.remove(&id);
+        Some(Err("no reply arrived in time".to_string()))
+    }
+
+    /// Forget request `id`, for nodes reset before their reply arrived.
+    fn cancel(&mut self, id: u64) {
+        self.replies.waiting.remove(&id);
+        self.replies.replies.remove(&id);
+    }
+}
+
+/// Node asking the model which of `branches` to take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiDecisionNode {
+    pub question: String,
+    pub branches: Vec<String>,
+    request: Option<u64>,
+    choice: Option<usize>,
+}
+
+impl AiDecisionNode {
+    pub fn new(
+        question: impl Into<String>,
+        branches: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            question: question.into(),
+            branches: branches.into_iter().map(Into::into).collect(),
+            request: None,
+            choice: None,
+        }
+    }
+
+    /// Ask on the first tick, then succeed once the reply names a branch. Fails if the reply
+    /// names none of them or the request failed.
+    pub fn tick(&mut self, entity: Entity, nodes: &mut AiNodes) -> AiNodeStatus {
+        if self.choice.is_some() {
+            return AiNodeStatus::Success;
+        }
+        let Some(id) = self.request else {
+            let branches = self.branches.join(", ");
+            let prompt = render_prompt(
+                nodes.templates.as_deref(),
+                DECISION,
+                &[("question", &self.question), ("branches", &branches)],
+            )
+            .unwrap_or_else(|_| format!("{}\nAnswer with one of: {}", self.question, branches));
+            self.request = Some(nodes.send(DialogueRequest::text(entity, prompt)));
+            return AiNodeStatus::Running;
+        };
+        match nodes.take(id) {
+            None => AiNodeStatus::Running,
+            Some(Ok(reply)) => {
+                let labels: Vec<&str> = self.branches.iter().map(String::as_str).collect();
+                self.choice = crate::parse::match_label(&reply, &labels)
+                    .and_then(|label| labels.iter().position(|l| *l == label));
+                if self.choice.is_some() {
+                    AiNodeStatus::Success
+                } else {
+                    debug!("No branch of '{}' in reply: {}", self.question, reply);
+                    AiNodeStatus::Failure
+                }
+            }
+            Some(Err(e)) => {
+                debug!("Decision '{}' failed: {}", self.question, e);
+                AiNodeStatus::Failure
+            }
+        }
+    }
+
+    /// Index of the chosen branch, once the node succeeded.
+    pub fn choice(&self) -> Option<usize> {
+        self.choice
+    }
+
+    /// Name of the chosen branch, once the node succeeded.
+    pub fn chosen(&self) -> Option<&str> {
+        self.choice.map(|i| self.branches[i].as_str())
+    }
+
+    /// Forget the answer so the next tick asks again.
+    pub fn reset(&mut self, nodes: &mut AiNodes) {
+        if let Some(id) = self.request.take() {
+            nodes.cancel(id);
+        }
+        self.choice = None;
+    }
+}
+
+/// Node having the model say one line. The line also lands in the entity's
+/// [`DialogueReceiver`](crate::dialogue::DialogueReceiver) like any reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiUtteranceNode {
+    /// What the line should be about.
+    pub prompt: String,
+    request: Option<u64>,
+    line: Option<String>,
+}
+
+impl AiUtteranceNode {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            request: None,
+            line: None,
+        }
+    }
+
+    /// Ask on the first tick, then succeed once the line arrived. Fails if the request failed.
+    pub fn tick(&mut self, entity: Entity, nodes: &mut AiNodes) -> AiNodeStatus {
+        if self.line.is_some() {
+            return AiNodeStatus::Success;
+        }
+        let Some(id) = self.request else {
+            let prompt = render_prompt(
+                nodes.templates.as_deref(),
+                CONVERSATION_LINE,
+                &[("body", &self.prompt)],
+            )
+            .unwrap_or_else(|_| self.prompt.clone());
+            self.request = Some(nodes.send(DialogueRequest::text(entity, prompt)));
+            return AiNodeStatus::Running;
+        };
+        match nodes.take(id) {
+            None => AiNodeStatus::Running,
+            Some(Ok(line)) => {
+                self.line = Some(line);
+                AiNodeStatus::Success
+            }
+            Some(Err(e)) => {
+                debug!("Utterance '{}' failed: {}", self.prompt, e);
+                AiNodeStatus::Failure
+            }
+        }
+    }
+
+    /// The line, once the node succeeded.
+    pub fn line(&self) -> Option<&str> {
+        self.line.as_deref()
+    }
+
+    /// Forget the line so the next tick asks for a new one.
+    pub fn reset(&mut self, nodes: &mut AiNodes) {
+        if let Some(id) = self.request.take() {
+            nodes.cancel(id);
+        }
+        self.line = None;
+    }
+}
+
+fn collect_node_replies(response: On<AiResponseEvent>, mut replies: ResMut<AiNodeReplies>) {
+    if replies.waiting.remove(&response.request_id).is_some() {
+        let reply = if response.error {
+            Err(response.text.clone())
+        } else {
+            Ok(response.text.clone())
+        };
+        replies.replies.insert(response.request_id, reply);
+    }
+}
+
+fn drop_stale_node_replies(stale: On<AiStaleResponse>, mut replies: ResMut<AiNodeReplies>) {
+    if replies.waiting.remove(&stale.request_id).is_some() {
+        replies
+            .replies
+            .insert(stale.request_id, Err("the reply was stale".to_string()));
+    }
+}
+
+fn drop_throttled_node_requests(
+    throttled: On<AiRequestThrottled>,
+    mut replies: ResMut<AiNodeReplies>,
+) {
+    if replies.waiting.remove(&throttled.request_id).is_some() {
+        replies.replies.insert(
+            throttled.request_id,
+            Err("the request was throttled".to_string()),
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Per-frame limits on the main-thread work done by the dialogue plugin.
+//!
+//! Model inference always runs in the background, but some work still happens on the main
+//! thread: popping requests and assembling prompts, running context-gathering systems (which
+//! need exclusive world access), applying responses and running action handlers. Each of these
+//! stages handles at most the number of items allowed by [`AiFrameBudget`]; anything left over
+//! stays queued for the next frame. [`AiFrameUsage`] records what each stage did in the last
+//! frame and how much is still waiting, so the cost can be monitored.
+//!
+//! The default budget is [unbounded](AiFrameBudget::unbounded), like systems run without an
+//! `AiFrameBudget` resource (e.g. when scheduled manually); set the limits a game needs.
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(AiFrameBudget {
+//!     max_gathers: 2,
+//!     ..default()
+//! });
+//!
+//! fn watch(usage: Res<AiFrameUsage>) {
+//!     if usage.queued_requests > 100 {
+//!         warn!("AI requests are piling up");
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+
+/// How many items each AI stage may process per frame. Unbounded by default.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiFrameBudget {
+    /// Dialogue requests dispatched to the backend.
+    pub max_requests: usize,
+    /// Context gathers (each runs every registered context system for one entity).
+    pub max_gathers: usize,
+    /// Responses applied to their receivers.
+    pub max_responses: usize,
+    /// Action handlers executed.
+    pub max_actions: usize,
+}
+
+impl Default for AiFrameBudget {
+    fn default() -> Self {
+        Self::unbounded()
+    }
+}
+
+impl AiFrameBudget {
+    /// No limits; every stage drains its whole queue each frame.
+    pub fn unbounded() -> Self {
+        Self {
+            max_requests: usize::MAX,
+            max_gathers: usize::MAX,
+            max_responses: usize::MAX,
+            max_actions: usize::MAX,
+        }
+    }
+}
+
+/// Work done by each AI stage in the last frame, and what is still queued.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiFrameUsage {
+    pub requests: usize,
+    pub gathers: usize,
+    pub responses: usize,
+    pub actions: usize,
+    pub queued_requests: usize,
+    pub queued_gathers: usize,
+    pub queued_responses: usize,
+    pub queued_actions: usize,
+}
+
+impl AiFrameUsage {
+    /// Whether any stage left work for the next frame.
+    pub fn has_backlog(&self) -> bool {
+        self.queued_requests > 0
+            || self.queued_gathers > 0
+            || self.queued_responses > 0
+            || self.queued_actions > 0
+    }
+}
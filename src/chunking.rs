@@ -0,0 +1,276 @@
+//! Ways of splitting documents into chunks for retrieval.
+//!
+//! Retrieval finds chunks, not documents, so how a text is cut decides what the model gets to
+//! read. A [`Chunker`] picks the cut:
+//!
+//! - [`Chunker::Markdown`] keeps paragraphs together and starts a chunk at every heading, for
+//!   lore books with one topic per section;
+//! - [`Chunker::Sentences`] packs whole sentences, for prose without structure;
+//! - [`Chunker::FixedSize`] cuts every few hundred characters and repeats the end of a chunk at
+//!   the start of the next, for logs and lists where facts straddle any boundary.
+//!
+//! [`DocumentStore::chunker`](crate::documents::DocumentStore::chunker) applies to every
+//! document, and a document can pick its own with a `chunking:` header line, parsed by
+//! [`Chunker::parse`]:
+//!
+//! ```text
+//! chunking: fixed 300 60
+//! Day 1: the caravan left Oakvale.
+//! Day 2: ...
+//! ```
+
+/// How to split a text into chunks. Sizes are in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunker {
+    /// Windows of `chars` characters cut between words, each starting with the last `overlap`
+    /// characters of the previous one.
+    FixedSize { chars: usize, overlap: usize },
+    /// Whole sentences, packed up to `max_chars`.
+    Sentences { max_chars: usize },
+    /// Paragraphs packed up to `max_chars`, with a new chunk at every markdown heading.
+    Markdown { max_chars: usize },
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Chunker::Markdown { max_chars: 800 }
+    }
+}
+
+impl Chunker {
+    /// Read a chunker written as `markdown [max]`, `sentences [max]` or
+    /// `fixed [chars] [overlap]`. Missing sizes take the defaults (800 characters, overlap of
+    /// an eighth).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut words = spec.split_whitespace();
+        let kind = words.next().unwrap_or_default().to_lowercase();
+        let sizes = words
+            .map(|w| {
+                w.parse::<usize>()
+                    .map_err(|_| format!("Invalid chunk size '{}' in '{}'", w, spec))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let size = |i: usize, default: usize| sizes.get(i).copied().unwrap_or(default);
+        match kind.as_str() {
+            "markdown" | "headings" => Ok(Chunker::Markdown {
+                max_chars: size(0, 800),
+            }),
+            "sentences" | "sentence" => Ok(Chunker::Sentences {
+                max_chars: size(0, 800),
+            }),
+            "fixed" => {
+                let chars = size(0, 800);
+                Ok(Chunker::FixedSize {
+                    chars,
+                    overlap: size(1, chars / 8),
+                })
+            }
+            other => Err(format!(
+                "Unknown chunker '{}', expected markdown, sentences or fixed",
+                other
+            )),
+        }
+    }
+
+    /// Split `text` into trimmed, non-empty chunks.
+    pub fn split(&self, text: &str) -> Vec<String> {
+        match *self {
+            Chunker::FixedSize { chars, overlap } => split_fixed(text, chars.max(1), overlap),
+            Chunker::Sentences { max_chars } => pack(sentences(text), max_chars.max(1), " "),
+            Chunker::Markdown { max_chars } => split_markdown(text, max_chars.max(1)),
+        }
+    }
+}
+
+/// Words packed into windows of `chars`, each repeating the previous window's last `overlap`
+/// characters worth of words.
+fn split_fixed(text: &str, chars: usize, overlap: usize) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let lens: Vec<usize> = words.iter().map(|w| w.chars().count()).collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        let mut end = start;
+        let mut len = 0;
+        while end < words.len() && (end == start || len + 1 + lens[end] <= chars) {
+            len += lens[end] + usize::from(end > start);
+            end += 1;
+        }
+        chunks.push(words[start..end].join(" "));
+        if end == words.len() {
+            break;
+        }
+        // Step back over whole words fitting in the overlap, always moving forward
+        let mut next = end;
+        let mut kept = 0;
+        while next > start + 1 && kept + lens[next - 1] < overlap {
+            kept += lens[next - 1] + 1;
+            next -= 1;
+        }
+        start = next;
+    }
+    chunks
+}
+
+/// Sentences of `text`, ending at `.`, `!` or `?` followed by whitespace, or at blank lines.
+fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut current = String::new();
+        let mut chars = paragraph.chars().peekable();
+        while let Some(c) = chars.next() {
+            current.push(c);
+            if matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|n| n.is_whitespace()) {
+                sentences.push(std::mem::take(&mut current));
+            }
+        }
+        sentences.push(current);
+    }
+    sentences
+        .into_iter()
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Join `pieces` with `separator` into chunks of up to `max_chars`, splitting pieces longer
+/// than that between words.
+fn pack(pieces: Vec<String>, max_chars: usize, separator: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    // Characters in `current`, which `len()` would count in bytes
+    let mut len = 0;
+    let separator_len = separator.chars().count();
+    for piece in pieces {
+        let piece_len = piece.chars().count();
+        if !current.is_empty() && len + separator_len + piece_len > max_chars {
+            chunks.push(std::mem::take(&mut current));
+            len = 0;
+        }
+        if piece_len <= max_chars {
+            if !current.is_empty() {
+                current.push_str(separator);
+                len += separator_len;
+            }
+            current.push_str(&piece);
+            len += piece_len;
+            continue;
+        }
+        for word in piece.split_whitespace() {
+            let word_len = word.chars().count();
+            if !current.is_empty() && len + word_len + 1 > max_chars {
+                chunks.push(std::mem::take(&mut current));
+                len = 0;
+            }
+            if !current.is_empty() {
+                current.push(' ');
+                len += 1;
+            }
+            current.push_str(word);
+            len += word_len;
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Paragraphs packed up to `max_chars`, starting over at each heading.
+fn split_markdown(text: &str, max_chars: usize) -> Vec<String> {
+    let mut sections: Vec<Vec<String>> = Vec::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if paragraph.starts_with('#') || sections.is_empty() {
+            sections.push(Vec::new());
+        }
+        if let Some(section) = sections.last_mut() {
+            section.push(paragraph.to_string());
+        }
+    }
+    sections
+        .into_iter()
+        .flat_map(|section| pack(section, max_chars, "\n\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunkers_cut_where_they_say() {
+        let lore =
+            "# The Lich\nIt fears silver.\n\nIt sleeps by day.\n\n# The Vault\nThe code is 4-1-7.";
+        assert_eq!(
+            Chunker::default().split(lore),
+            [
+                "# The Lich\nIt fears silver.\n\nIt sleeps by day.",
+                "# The Vault\nThe code is 4-1-7."
+            ]
+        );
+        assert_eq!(
+            Chunker::Markdown { max_chars: 9 }.split("one two three four"),
+            ["one two", "three", "four"]
+        );
+
+        let prose = "The gate opens at dawn. Who opens it?\nThe warden!  He is old.";
+        assert_eq!(
+            Chunker::Sentences { max_chars: 40 }.split(prose),
+            [
+                "The gate opens at dawn. Who opens it?",
+                "The warden! He is old."
+            ]
+        );
+
+        let log = "day1 left oakvale day2 crossed river day3 met bandits";
+        assert_eq!(
+            Chunker::FixedSize {
+                chars: 20,
+                overlap: 10
+            }
+            .split(log),
+            [
+                "day1 left oakvale",
+                "oakvale day2 crossed",
+                "crossed river day3",
+                "day3 met bandits"
+            ]
+        );
+        assert_eq!(
+            Chunker::FixedSize {
+                chars: 3,
+                overlap: 100
+            }
+            .split("a b c"),
+            ["a b", "b c"]
+        );
+
+        // Sizes count characters, not bytes
+        assert_eq!(
+            Chunker::Markdown { max_chars: 7 }.split("ōne twō thrēē"),
+            ["ōne twō", "thrēē"]
+        );
+        assert_eq!(
+            Chunker::FixedSize {
+                chars: 7,
+                overlap: 0
+            }
+            .split("ōne twō thrēē"),
+            ["ōne twō", "thrēē"]
+        );
+
+        assert_eq!(
+            Chunker::parse("fixed 300 60"),
+            Ok(Chunker::FixedSize {
+                chars: 300,
+                overlap: 60
+            })
+        );
+        assert_eq!(
+            Chunker::parse("Sentences"),
+            Ok(Chunker::Sentences { max_chars: 800 })
+        );
+        assert!(Chunker::parse("paragraphs").is_err());
+        assert!(Chunker::parse("fixed lots").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Follow-up questions for typed requests the model can't complete.
+//!
+//! The built-in typed prompt lets the model answer
+//! `{"needs_clarification": {"question": "..."}}` when the request lacks information for a
+//! required field ("Give Bob the sword" when there are two swords). Instead of producing a
+//! broken action, the dialogue plugin then:
+//!
+//! 1. shows the question as a normal reply on the entity's `DialogueReceiver`,
+//! 2. fires [`AiClarificationRequested`] and puts a [`PendingClarification`] on the entity,
+//! 3. turns the entity's next player text request (the answer, sent with
+//!    [`DialogueRequest::player_text`](crate::dialogue::DialogueRequest::player_text)) into a
+//!    follow-up typed request containing the original request, the question and the answer.
+//!    Other text requests for the entity are sent as usual.
+//!
+//! Remove [`PendingClarification`] to drop the follow-up, e.g. when the player walks away.
+//!
+//! # Example
+//! ```ignore
+//! app.add_observer(|ask: On<AiClarificationRequested>, mut ui: ResMut<ChatUi>| {
+//!     ui.open_input(ask.event().entity, &ask.event().question);
+//! });
+//!
+//! fn submit(mut queue: ResMut<DialogueRequestQueue>, ui: Res<ChatUi>) {
+//!     // The answer is merged into a new `GiveItem` request automatically.
+//!     queue.push(DialogueRequest::player_text(ui.target, ui.text.clone()));
+//! }
+//! ```
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dialogue::DialogueRequestKind;
+
+/// Key of the clarification object the model may return for typed requests.
+pub const CLARIFICATION_KEY: &str = "needs_clarification";
+
+/// A typed reply asking the player for missing information.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NeedsClarification {
+    pub question: String,
+}
+
+impl NeedsClarification {
+    /// Recognize `{"needs_clarification": {"question": "..."}}` (or the question as a plain string).
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let question = match value.get(CLARIFICATION_KEY)? {
+            Value::String(question) => question.as_str(),
+            other => other.get("question")?.as_str()?,
+        };
+        let question = question.trim();
+        (!question.is_empty()).then(|| Self {
+            question: question.to_string(),
+        })
+    }
+}
+
+/// The typed request waiting for the player's answer to `question`.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct PendingClarification {
+    pub request_id: u64,
+    pub question: String,
+    /// The typed request that asked the question.
+    pub kind: DialogueRequestKind,
+}
+
+impl PendingClarification {
+    /// The follow-up typed request for `answer`.
+    pub fn follow_up(&self, answer: &str) -> DialogueRequestKind {
+        let mut kind = self.kind.clone();
+        if let DialogueRequestKind::Typed { user_message, .. } = &mut kind {
+            *user_message = format!(
+                "{}\n\nYou asked: {}\nThe player answered: {}\nNow provide the JSON action.",
+                user_message, self.question, answer
+            );
+        }
+        kind
+    }
+}
+
+/// Fired when a typed request came back with a question instead of an action.
+#[derive(Event, Debug, Clone)]
+pub struct AiClarificationRequested {
+    pub entity: Entity,
+    pub request_id: u64,
+    pub question: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn recognizes_clarification_replies() {
+        let q = NeedsClarification::from_value(
+            &json!({ "needs_clarification": { "question": "Which sword?" } }),
+        );
+        assert_eq!(q.unwrap().question, "Which sword?");
+        assert!(
+            NeedsClarification::from_value(&json!({ "needs_clarification": "Who?" })).is_some()
+        );
+        assert!(NeedsClarification::from_value(&json!({ "item": "sword" })).is_none());
+        assert!(NeedsClarification::from_value(&json!({ "needs_clarification": "" })).is_none());
+    }
+
+    #[test]
+    fn follow_up_keeps_the_original_request() {
+        let pending = PendingClarification {
+            request_id: 1,
+            question: "Which sword?".to_string(),
+            kind: DialogueRequestKind::Typed {
+                user_message: "Give Bob the sword".to_string(),
+                schema_description: "{}".to_string(),
+                action_name: "give".to_string(),
+            },
+        };
+        let DialogueRequestKind::Typed {
+            user_message,
+            action_name,
+            ..
+        } = pending.follow_up("The rusty one")
+        else {
+            panic!("expected a typed request");
+        };
+        assert!(user_message.starts_with("Give Bob the sword"));
+        assert!(user_message.contains("The player answered: The rusty one"));
+        assert_eq!(action_name, "give");
+    }
+}
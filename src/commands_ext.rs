@@ -0,0 +1,80 @@
+//! `EntityCommands` extension for queueing dialogue from any system.
+//!
+//! Large gameplay systems often already hold `Commands` but not the [`AiRequest`] system param.
+//! [`AiEntityCommandsExt`] queues the same requests as `AiRequest` on the entity itself; the
+//! request is built when commands are applied, using the current `PromptTemplates`.
+//!
+//! # Example
+//! ```ignore
+//! fn on_interact(mut commands: Commands, npc: Single<Entity, With<AI>>) {
+//!     commands.entity(*npc).ask_ai("Greet the player");
+//!     commands.entity(*npc).ask_ai_action::<SpawnAction>("Spawn a guard next to you");
+//! }
+//! ```
+//!
+//! [`AiRequest`]: crate::dialogue::AiRequest
+
+use bevy::prelude::*;
+
+use crate::dialogue::{
+    DialogueRequest, DialogueRequestQueue, plain_text_request, typed_action_request,
+};
+use crate::parse::AiParsable;
+use crate::prompts::PromptTemplates;
+
+/// Dialogue requests queued through `commands.entity(e)`.
+pub trait AiEntityCommandsExt {
+    /// Ask the entity's AI a plain text question without context (like `AiRequest::ask_text`).
+    fn ask_ai(&mut self, prompt: impl Into<String>) -> &mut Self;
+
+    /// Ask with context gathering (like `AiRequest::inquire`).
+    fn inquire_ai(&mut self, prompt: impl Into<String>) -> &mut Self;
+
+    /// Ask for a typed action `T` (like `AiRequest::ask_action`).
+    fn ask_ai_action<T: AiParsable + 'static>(&mut self, prompt: impl Into<String>) -> &mut Self;
+}
+
+fn push_request(world: &mut World, request: DialogueRequest) {
+    match world.get_resource_mut::<DialogueRequestQueue>() {
+        Some(mut queue) => queue.push(request),
+        None => warn!(
+            "Dropping AI request for {:?}: AIDialoguePlugin is not installed",
+            request.entity
+        ),
+    }
+}
+
+impl AiEntityCommandsExt for EntityCommands<'_> {
+    fn ask_ai(&mut self, prompt: impl Into<String>) -> &mut Self {
+        let prompt = prompt.into();
+        self.queue(move |entity: EntityWorldMut| {
+            let id = entity.id();
+            let world = entity.into_world_mut();
+            let request =
+                plain_text_request(world.get_resource::<PromptTemplates>(), id, prompt, false);
+            push_request(world, request);
+        })
+    }
+
+    fn inquire_ai(&mut self, prompt: impl Into<String>) -> &mut Self {
+        let prompt = prompt.into();
+        self.queue(move |entity: EntityWorldMut| {
+            let id = entity.id();
+            let world = entity.into_world_mut();
+            let request =
+                plain_text_request(world.get_resource::<PromptTemplates>(), id, prompt, true);
+            push_request(world, request);
+        })
+    }
+
+    fn ask_ai_action<T: AiParsable + 'static>(&mut self, prompt: impl Into<String>) -> &mut Self {
+        let prompt = prompt.into();
+        self.queue(move |entity: EntityWorldMut| {
+            let id = entity.id();
+            let world = entity.into_world_mut();
+            let request =
+                typed_action_request::<T>(world.get_resource::<PromptTemplates>(), id, prompt);
+            push_request(world, request);
+        })
+    }
+}
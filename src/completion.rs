@@ -0,0 +1,181 @@
+//! Completion of actions whose effect takes time.
+//!
+//! A handler returns as soon as it has started its work, but "walk to the gate" is only done
+//! when the NPC arrives. Handlers that need longer take an [`ActionTicket`] from the
+//! [`ActionTickets`] system param and complete it once the effect is over, from any later
+//! system. Futures can be tracked directly with [`ActionTickets::complete_when`].
+//!
+//! Every action taken from the pending queue ends with an [`AiActionCompleted`] event: right
+//! after the handler for actions without a ticket, once the ticket is completed otherwise, and
+//! with an error for actions that were invalid, unhandled, over their
+//! [`ActionBudgets`](crate::rate_limit::ActionBudgets) limit, rejected in
+//! [`PendingApproval`](crate::actions::PendingApproval) or skipped by
+//! [`AiDryRun`](crate::actions::AiDryRun). Loops feeding results back to the
+//! model, like [`ActionSequence`](crate::sequence::ActionSequence), wait for that event instead
+//! of assuming success.
+//!
+//! # Example
+//! ```ignore
+//! app.register_ai_action::<GoTo, _, _>(
+//!     |In(goto): In<GoTo>, mut tickets: ActionTickets, mut commands: Commands| {
+//!         let ticket = tickets.issue();
+//!         commands.entity(goto.npc).insert(PathTarget { to: goto.target, ticket });
+//!     },
+//! );
+//!
+//! fn arrive(mut tickets: ActionTickets, walkers: Query<(Entity, &Transform, &PathTarget)>, mut commands: Commands) {
+//!     for (npc, transform, path) in walkers.iter() {
+//!         if transform.translation.distance(path.to) < 0.5 {
+//!             tickets.succeed(path.ticket);
+//!             commands.entity(npc).remove::<PathTarget>();
+//!         }
+//!     }
+//! }
+//!
+//! app.add_observer(|done: On<AiActionCompleted>| {
+//!     info!("{} finished: {:?}", done.action.name, done.result);
+//! });
+//! ```
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+
+use crate::actions::{ActionPayload, AiActionEvent};
+
+/// Token of an action that completes later, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActionTicket(u64);
+
+/// Event triggered when an action taken from the pending queue is over.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct AiActionCompleted {
+    pub entity: Entity,
+    pub action: ActionPayload,
+    /// The ticket the handler took, `None` if it finished right away.
+    pub ticket: Option<ActionTicket>,
+    /// `Err` with the reason if the action could not run or the ticket failed.
+    pub result: Result<(), String>,
+}
+
+/// Open tickets and the action currently being handled.
+#[derive(Resource)]
+pub struct ActionCompletions {
+    /// The action whose handler is running, and whether it took a ticket.
+    current: Option<(AiActionEvent, bool)>,
+    open: HashMap<ActionTicket, AiActionEvent>,
+    next_id: u64,
+    tx: flume::Sender<(ActionTicket, Result<(), String>)>,
+    rx: flume::Receiver<(ActionTicket, Result<(), String>)>,
+}
+
+impl Default for ActionCompletions {
+    fn default() -> Self {
+        let (tx, rx) = flume::unbounded();
+        Self {
+            current: None,
+            open: HashMap::new(),
+            next_id: 0,
+            tx,
+            rx,
+        }
+    }
+}
+
+impl ActionCompletions {
+    /// Number of tickets not completed yet.
+    pub fn open(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn is_open(&self, ticket: ActionTicket) -> bool {
+        self.open.contains_key(&ticket)
+    }
+
+    pub(crate) fn begin(&mut self, event: AiActionEvent) {
+        self.current = Some((event, false));
+    }
+
+    /// End the current action; returns it if the handler finished without a ticket.
+    pub(crate) fn end(&mut self) -> Option<AiActionEvent> {
+        self.current
+            .take()
+            .and_then(|(event, ticketed)| (!ticketed).then_some(event))
+    }
+
+    fn issue(&mut self) -> ActionTicket {
+        self.next_id += 1;
+        let ticket = ActionTicket(self.next_id);
+        match self.current.as_mut() {
+            Some((event, ticketed)) => {
+                *ticketed = true;
+                self.open.insert(ticket, event.clone());
+            }
+            None => warn!("Action ticket issued outside of an action handler"),
+        }
+        ticket
+    }
+}
+
+/// System parameter for handlers whose action completes later.
+#[derive(SystemParam)]
+pub struct ActionTickets<'w, 's> {
+    completions: ResMut<'w, ActionCompletions>,
+    commands: Commands<'w, 's>,
+}
+
+impl ActionTickets<'_, '_> {
+    /// Take a ticket for the action being handled. Its [`AiActionCompleted`] is held back
+    /// until the ticket is completed. Only meaningful inside an action handler.
+    pub fn issue(&mut self) -> ActionTicket {
+        self.completions.issue()
+    }
+
+    /// Take a ticket completed with the output of `future`, run on the background runtime.
+    pub fn complete_when<F>(&mut self, future: F) -> ActionTicket
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let ticket = self.issue();
+        let tx = self.completions.tx.clone();
+        crate::models::TOKIO_RUNTIME.spawn(async move {
+            let _ = tx.send_async((ticket, future.await)).await;
+        });
+        ticket
+    }
+
+    /// Complete `ticket` with `result`. Returns `false` if it was not open.
+    pub fn complete(&mut self, ticket: ActionTicket, result: Result<(), String>) -> bool {
+        let Some(event) = self.completions.open.remove(&ticket) else {
+            return false;
+        };
+        self.commands.trigger(AiActionCompleted {
+            entity: event.entity,
+            action: event.action,
+            ticket: Some(ticket),
+            result,
+        });
+        true
+    }
+
+    pub fn succeed(&mut self, ticket: ActionTicket) -> bool {
+        self.complete(ticket, Ok(()))
+    }
+
+    pub fn fail(&mut self, ticket: ActionTicket, reason: impl Into<String>) -> bool {
+        self.complete(ticket, Err(reason.into()))
+    }
+
+    pub fn is_open(&self, ticket: ActionTicket) -> bool {
+        self.completions.is_open(ticket)
+    }
+}
+
+/// Complete the tickets whose futures finished.
+pub(crate) fn poll_action_futures(mut tickets: ActionTickets) {
+    let finished: Vec<_> = tickets.completions.rx.try_iter().collect();
+    for (ticket, result) in finished {
+        tickets.complete(ticket, result);
+    }
+}
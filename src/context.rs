@@ -1,5 +1,8 @@
 use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::spatial::{RelativePlacement, SpatialPlane};
 
 /// Marker component that indicates an entity should be considered for AI context gathering.
 /// Only entities with this component will be scanned for nearby context information.
@@ -13,18 +16,25 @@ pub struct AIAware;
 pub struct AI;
 
 /// Configuration for on-demand context gathering.
-#[derive(Resource, Debug, Clone)]
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
 pub struct AiContextGatherConfig {
     /// Search radius (world units) around the requester entity.
     pub radius: f32,
     /// Maximum number of documents to collect per gather request.
     pub max_docs: usize,
+    /// Plane used when phrasing relative positions (see [`crate::spatial`]).
+    pub plane: SpatialPlane,
 }
 
 impl AiContextGatherConfig {
     /// Create a new `AiContextGatherConfig` with the given radius and max_docs.
     pub fn new(radius: f32, max_docs: usize) -> Self {
-        Self { radius, max_docs }
+        Self {
+            radius,
+            max_docs,
+            plane: SpatialPlane::default(),
+        }
     }
 
     pub fn with_radius(mut self, radius: f32) -> Self {
@@ -36,6 +46,12 @@ impl AiContextGatherConfig {
         self.max_docs = max_docs;
         self
     }
+
+    /// Set the plane used for relative spatial phrasing (use `SpatialPlane::Xy` for 2D games).
+    pub fn with_plane(mut self, plane: SpatialPlane) -> Self {
+        self.plane = plane;
+        self
+    }
 }
 
 impl Default for AiContextGatherConfig {
@@ -43,22 +59,59 @@ impl Default for AiContextGatherConfig {
         Self {
             radius: 10.0,
             max_docs: 8,
+            plane: SpatialPlane::default(),
         }
     }
 }
 
+/// Event fired when a context gather for `entity` produced no messages, e.g. because nobody
+/// was in range. Games can answer with a canned "I don't know anything about that" instead of
+/// letting the model improvise from the bare system prompt.
+///
+/// It fires while the request the gather was for still waits in the
+/// [`DialogueRequestQueue`](crate::dialogue::DialogueRequestQueue), so an observer can take it
+/// out with [`take_matching`](crate::dialogue::DialogueRequestQueue::take_matching) before it
+/// is sent.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AiContextEmptyEvent {
+    pub entity: Entity,
+}
+
 /// Resource used to queue on-demand gather requests for entities.
 /// Multiple AI entities can request gathers; they are processed sequentially from the queue.
-/// Push entities onto this queue to trigger gather runs; one will be processed per world update.
+/// Push entities onto this queue to trigger gather runs; `AiFrameBudget::max_gathers` are processed
+/// per world update.
+/// The second field holds the optional prompt each gather was requested for.
 #[derive(Resource, Default, Debug)]
-pub struct ContextGatherRequest(pub Vec<Entity>);
+pub struct ContextGatherRequest(pub Vec<Entity>, pub HashMap<Entity, String>);
+
+/// Marks an [`AiContext`](crate::rag::AiContext) produced by a gather. It is gathered again
+/// for every prompt; contexts inserted by the game are kept as they are.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct GatheredContext;
 
 impl ContextGatherRequest {
+    /// A queue of gathers for `entities`, without prompts.
+    pub fn new(entities: Vec<Entity>) -> Self {
+        Self(entities, HashMap::new())
+    }
+
     /// Request a gather for the given entity (adds to end of queue).
     pub fn request(&mut self, entity: Entity) {
         self.0.push(entity);
     }
 
+    /// Request a gather for the prompt `query`, readable by systems through [`AiEntity::query`].
+    pub fn request_with_query(&mut self, entity: Entity, query: impl Into<String>) {
+        self.1.insert(entity, query.into());
+        self.0.push(entity);
+    }
+
+    /// Take the prompt a gather for `entity` was requested with, if any.
+    pub fn take_query(&mut self, entity: Entity) -> Option<String> {
+        self.1.remove(&entity)
+    }
+
     /// Pop the next entity to gather for (removes from front of queue).
     pub fn next(&mut self) -> Option<Entity> {
         if self.0.is_empty() {
@@ -68,6 +121,20 @@ impl ContextGatherRequest {
         }
     }
 
+    /// Whether a gather for `entity` is queued.
+    pub fn is_queued(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+
+    /// Number of queued gather requests.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Check if there are pending gather requests.
     pub fn has_pending(&self) -> bool {
         !self.0.is_empty()
@@ -79,6 +146,10 @@ impl ContextGatherRequest {
 #[derive(Resource, Debug, Clone, Copy)]
 pub struct AiCurrentContextEntity(pub Entity);
 
+/// Temporary resource holding the prompt the current gather was requested for, if any.
+#[derive(Resource, Debug, Clone)]
+pub struct AiCurrentContextQuery(pub String);
+
 /// Custom system parameter providing easy access to the current AI context entity,
 /// the context gathering configuration, and spatial queries.
 /// Systems can use this parameter to get the entity being processed by the gather function
@@ -86,6 +157,7 @@ pub struct AiCurrentContextEntity(pub Entity);
 #[derive(SystemParam)]
 pub struct AiEntity<'w, 's> {
     current: Res<'w, AiCurrentContextEntity>,
+    query: Option<Res<'w, AiCurrentContextQuery>>,
     config: Res<'w, AiContextGatherConfig>,
     transforms: Query<'w, 's, &'static Transform, With<AI>>,
     aware_entities: Query<'w, 's, (Entity, &'static Transform), With<AIAware>>,
@@ -97,6 +169,11 @@ impl<'w, 's> AiEntity<'w, 's> {
         self.current.0
     }
 
+    /// The prompt this gather was requested for, if known.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_ref().map(|q| q.0.as_str())
+    }
+
     /// Get the Transform component of the AI entity being processed.
     /// Returns None if the entity has no Transform.
     pub fn transform(&self) -> Option<&Transform> {
@@ -190,6 +267,20 @@ impl<'w, 's> AiEntity<'w, 's> {
         nearby.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
         nearby
     }
+
+    /// Get where `target` is relative to the AI entity's position and facing.
+    /// Returns None if the AI entity has no Transform.
+    pub fn relative_placement(&self, target: Vec3) -> Option<RelativePlacement> {
+        self.transform()
+            .map(|t| RelativePlacement::compute(t, target, self.config.plane))
+    }
+
+    /// Describe where a named target is from the AI entity's perspective,
+    /// e.g. "Bob is 5m to your left".
+    /// Returns None if the AI entity has no Transform.
+    pub fn describe_relative(&self, name: &str, target: Vec3) -> Option<String> {
+        self.relative_placement(target).map(|p| p.describe(name))
+    }
 }
 
 impl<'w, 's> std::ops::Deref for AiEntity<'w, 's> {
@@ -210,12 +301,18 @@ pub type AiContextSystem = Box<dyn System<In = (), Out = Option<crate::rag::AiMe
 #[derive(Resource, Default)]
 pub struct AiSystemContextStore {
     systems: Vec<AiContextSystem>,
+    /// Knowledge tags of each system, empty for systems every entity runs.
+    scopes: Vec<Vec<String>>,
+    /// Whether each system reads player-authored text.
+    untrusted: Vec<bool>,
 }
 
 impl AiSystemContextStore {
     pub fn new() -> Self {
         Self {
             systems: Vec::new(),
+            scopes: Vec::new(),
+            untrusted: Vec::new(),
         }
     }
 
@@ -235,6 +332,50 @@ impl AiSystemContextStore {
         system: impl IntoSystem<(), Option<crate::rag::AiMessage>, M> + 'static,
     ) {
         self.systems.push(Box::new(IntoSystem::into_system(system)));
+        self.scopes.push(Vec::new());
+        self.untrusted.push(false);
+    }
+
+    /// Add a context-gathering system reading player-authored text, such as chat or item
+    /// names. Its documents are wrapped in delimiters telling the model not to follow them, see
+    /// [`crate::sanitize`].
+    pub fn add_untrusted_system<M>(
+        &mut self,
+        system: impl IntoSystem<(), Option<crate::rag::AiMessage>, M> + 'static,
+    ) {
+        self.systems.push(Box::new(IntoSystem::into_system(system)));
+        self.scopes.push(Vec::new());
+        self.untrusted.push(true);
+    }
+
+    /// Add a context-gathering system that only runs for entities whose
+    /// [`KnowledgeScope`](crate::knowledge::KnowledgeScope) includes one of `tags`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// store.add_scoped_system(["boss_lore"], |_: AiEntity| {
+    ///     Some(AiMessage::system("The Lich fears silver."))
+    /// });
+    /// ```
+    pub fn add_scoped_system<M>(
+        &mut self,
+        tags: impl IntoIterator<Item = impl Into<String>>,
+        system: impl IntoSystem<(), Option<crate::rag::AiMessage>, M> + 'static,
+    ) {
+        self.systems.push(Box::new(IntoSystem::into_system(system)));
+        self.scopes.push(tags.into_iter().map(Into::into).collect());
+        self.untrusted.push(false);
+    }
+
+    /// Knowledge tags of the system at `index`, empty if it runs for every entity.
+    pub fn scope(&self, index: usize) -> &[String] {
+        self.scopes.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether the system at `index` was added with
+    /// [`add_untrusted_system`](Self::add_untrusted_system).
+    pub fn is_untrusted(&self, index: usize) -> bool {
+        self.untrusted.get(index).copied().unwrap_or(false)
     }
 
     /// Get a reference to all registered systems.
@@ -242,43 +383,151 @@ impl AiSystemContextStore {
         &self.systems
     }
 
+    /// Names of all registered systems, in registration order.
+    pub fn system_names(&self) -> Vec<String> {
+        self.systems.iter().map(|s| s.name().to_string()).collect()
+    }
+
     /// Get a mutable reference to all registered systems (for internal use).
     pub fn systems_mut(&mut self) -> &mut [AiContextSystem] {
         &mut self.systems
     }
 }
 
-/// Process one on-demand context gather request from the queue.
+/// Plugin gathering context for requests that include it, by running the systems in the
+/// [`AiSystemContextStore`]. Part of the [`AIDialoguePlugin`](crate::dialogue::AIDialoguePlugin).
+#[derive(Clone)]
+pub struct AiContextPlugin {
+    pub gather_config: AiContextGatherConfig,
+}
+
+impl Default for AiContextPlugin {
+    fn default() -> Self {
+        Self {
+            gather_config: AiContextGatherConfig::new(5.0, 8),
+        }
+    }
+}
+
+impl Plugin for AiContextPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiSystemContextStore>()
+            .insert_resource(self.gather_config.clone())
+            .insert_resource(ContextGatherRequest::default())
+            .register_type::<AiContextGatherConfig>()
+            .add_systems(
+                Update,
+                gather_on_request_world.in_set(crate::dialogue::AiSystemSet::GatherContext),
+            );
+    }
+}
+
+/// Process queued on-demand context gather requests, up to `AiFrameBudget::max_gathers`.
 /// This function should be run as a Bevy system each frame.
 pub fn gather_on_request_world(world: &mut World) {
+    let max_gathers = world
+        .get_resource::<crate::budget::AiFrameBudget>()
+        .map_or(usize::MAX, |b| b.max_gathers);
+    let mut gathered = 0;
+    while gathered < max_gathers && gather_next(world) {
+        gathered += 1;
+    }
+
+    let queued = world
+        .get_resource::<ContextGatherRequest>()
+        .map_or(0, |r| r.len());
+    if let Some(mut usage) = world.get_resource_mut::<crate::budget::AiFrameUsage>() {
+        usage.gathers = gathered;
+        usage.queued_gathers = queued;
+    }
+}
+
+/// Run every context system for the next queued entity. Returns `false` if nothing was queued.
+fn gather_next(world: &mut World) -> bool {
     // Pop the next entity from the queue
-    let ent_opt = {
+    let (ent_opt, query) = {
         let mut req = match world.get_resource_mut::<ContextGatherRequest>() {
             Some(r) => r,
-            None => return,
+            None => return false,
         };
-        req.next()
+        let ent = req.next();
+        let query = ent.and_then(|e| req.take_query(e));
+        (ent, query)
     };
-    let Some(ent) = ent_opt else { return };
+    let Some(ent) = ent_opt else { return false };
+    // Despawned while its request waited for the gather
+    if world.get_entity(ent).is_err() {
+        return true;
+    }
 
-    // Insert the temporary resource so systems can read which entity they're processing
-    world.insert_resource(AiCurrentContextEntity(ent));
+    let messages = run_context_systems(world, ent, query);
+
+    // Attach collected messages as an `AiContext` component on the requester entity if any were returned
+    use crate::rag::AiContext;
+    if !messages.is_empty() {
+        let mut context = AiContext::new();
+        for (_, msg) in messages {
+            // Messages from systems should be converted to system context
+            if let crate::rag::AiMessage::System(_) = msg {
+                context.add_message(msg);
+            } else {
+                // If a system returns a user/assistant message, convert to system context
+                context.add_context(format!("{:?}", msg));
+            }
+        }
+        // Safe to insert component even if present; replace existing context
+        world.entity_mut(ent).insert((context, GatheredContext));
+    } else {
+        // Context gathered for an earlier prompt no longer applies
+        if world.get::<GatheredContext>(ent).is_some() {
+            world
+                .entity_mut(ent)
+                .remove::<(AiContext, GatheredContext)>();
+        }
+        world.trigger(AiContextEmptyEvent { entity: ent });
+    }
+    true
+}
+
+/// Run every registered context system for `entity` and return each produced message with the
+/// name of the system that produced it, in registration order. Does not touch `AiContext`.
+pub fn run_context_systems(
+    world: &mut World,
+    entity: Entity,
+    query: Option<String>,
+) -> Vec<(String, crate::rag::AiMessage)> {
+    // Insert the temporary resources so systems can read which entity they're processing
+    world.insert_resource(AiCurrentContextEntity(entity));
+    if let Some(query) = query {
+        world.insert_resource(AiCurrentContextQuery(query));
+    }
 
     // Get the number of systems to run
     let num_systems = {
         match world.get_resource::<AiSystemContextStore>() {
             Some(store) => store.systems.len(),
-            None => return,
+            None => {
+                world.remove_resource::<AiCurrentContextEntity>();
+                world.remove_resource::<AiCurrentContextQuery>();
+                return Vec::new();
+            }
         }
     };
 
     // Collect messages from all systems
     let mut messages = Vec::new();
+    let scope = world
+        .get::<crate::knowledge::KnowledgeScope>(entity)
+        .cloned();
+    let guard = world
+        .get_resource::<crate::sanitize::ContextInjectionGuard>()
+        .cloned();
 
     // Run each system with () input - systems read AiCurrentContextEntity from world
     for i in 0..num_systems {
         world.resource_scope::<AiSystemContextStore, ()>(|world, mut store| {
-            if i < store.systems.len() {
+            if i < store.systems.len() && crate::knowledge::visible(scope.as_ref(), store.scope(i))
+            {
                 // Take ownership of the system
                 let mut system = store.systems.remove(i);
 
@@ -293,7 +542,22 @@ pub fn gather_on_request_world(world: &mut World) {
 
                 // Collect the returned message if present
                 if let Ok(Some(msg)) = result {
-                    messages.push(msg);
+                    let name = system.name().to_string();
+                    match guard_context(guard.as_ref(), store.is_untrusted(i), msg) {
+                        Ok(msg) => messages.push((name, msg)),
+                        Err((text, reason)) => {
+                            warn!(
+                                "Left context from {} out for {:?}: {}",
+                                name, entity, reason
+                            );
+                            world.trigger(crate::sanitize::AiContextFlagged {
+                                entity,
+                                system: name,
+                                text,
+                                reason,
+                            });
+                        }
+                    }
                 }
 
                 store.systems.insert(i, system);
@@ -301,23 +565,31 @@ pub fn gather_on_request_world(world: &mut World) {
         });
     }
 
-    // Remove the temporary resource
+    // Remove the temporary resources
     world.remove_resource::<AiCurrentContextEntity>();
+    world.remove_resource::<AiCurrentContextQuery>();
+    messages
+}
 
-    // Attach collected messages as an `AiContext` component on the requester entity if any were returned
-    use crate::rag::AiContext;
-    if !messages.is_empty() {
-        let mut context = AiContext::new();
-        for msg in messages {
-            // Messages from systems should be converted to system context
-            if let crate::rag::AiMessage::System(text) = msg {
-                context.add_context(text);
-            } else {
-                // If a system returns a user/assistant message, convert to system context
-                context.add_context(format!("{:?}", msg));
-            }
-        }
-        // Safe to insert component even if present; replace existing context
-        world.entity_mut(ent).insert(context);
+/// Check a document gathered by an untrusted system with the guard, and delimit it. Returns
+/// the document text and the reason if it was flagged.
+fn guard_context(
+    guard: Option<&crate::sanitize::ContextInjectionGuard>,
+    untrusted: bool,
+    msg: crate::rag::AiMessage,
+) -> Result<crate::rag::AiMessage, (String, String)> {
+    use crate::rag::AiMessage;
+    let AiMessage::System(text) = &msg else {
+        return Ok(msg);
+    };
+    if untrusted
+        && let Some(guard) = guard
+        && let Err(reason) = guard.classifier.moderate(text)
+    {
+        return Err((text.to_string(), reason));
+    }
+    if untrusted {
+        return Ok(AiMessage::system(crate::sanitize::delimit_untrusted(text)));
     }
+    Ok(msg)
 }
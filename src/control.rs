@@ -0,0 +1,539 @@
+//! Developer HTTP endpoint for poking at a running game.
+//!
+//! Requires the `control` feature. [`AiControlPlugin`] serves a small JSON API from a
+//! background thread; every request is answered by a system on the main thread, so the data is
+//! always a consistent snapshot of the world:
+//!
+//! | Route            | Returns                                                         |
+//! |------------------|-----------------------------------------------------------------|
+//! | `GET /status`    | whether a model is loaded and the queue lengths                 |
+//! | `GET /actions`   | registered actions and context systems                          |
+//! | `GET /queue`     | requests waiting to be dispatched                               |
+//! | `GET /history`   | the most recent prompts and replies                             |
+//! | `POST /prompt`   | queues `{"entity": <Entity::to_bits>, "prompt": "..."}`, returns its `request_id` |
+//!
+//! Every request must carry the token logged at startup (also in [`AiControlServer::token`])
+//! in an `X-Ai-Control-Token` header, and a `Host` (and `Origin`, if any) naming `localhost`,
+//! `127.0.0.1` or `[::1]`, so web pages cannot reach it through CSRF or DNS rebinding. It binds
+//! to `127.0.0.1:7878` by default; don't expose it beyond your machine and don't ship it in
+//! release builds.
+//!
+//! # Example
+//! ```ignore
+//! #[cfg(debug_assertions)]
+//! app.add_plugins(AiControlPlugin::default());
+//! ```
+//! ```sh
+//! curl -H "X-Ai-Control-Token: $TOKEN" localhost:7878/history
+//! curl -H "X-Ai-Control-Token: $TOKEN" -d '{"entity": 4294967302, "prompt": "What do you sell?"}' localhost:7878/prompt
+//! ```
+
+use bevy::prelude::*;
+use flume::{Receiver, Sender, unbounded};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use crate::attribution::ResponseOrigin;
+use crate::dialogue::{AiResponseEvent, DialogueRequest, DialogueRequestQueue, LocalAiHandle};
+use crate::inspect::AiRegistryInfo;
+
+/// How long a connection waits for the main thread to answer.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Header carrying the [`AiControlServer::token`].
+pub const TOKEN_HEADER: &str = "X-Ai-Control-Token";
+
+/// Plugin serving the control endpoint.
+pub struct AiControlPlugin {
+    pub addr: String,
+    /// Number of prompt/reply pairs kept for `/history`.
+    pub history_len: usize,
+    /// Token requests must carry, random for each run when `None`.
+    pub token: Option<String>,
+}
+
+impl Default for AiControlPlugin {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:7878".to_string(),
+            history_len: 50,
+            token: None,
+        }
+    }
+}
+
+impl AiControlPlugin {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Use a fixed token instead of a random one, e.g. for scripts.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+}
+
+/// The address the control endpoint listens on, once bound, and the token it requires.
+#[derive(Resource, Debug, Clone)]
+pub struct AiControlServer {
+    pub addr: SocketAddr,
+    pub token: String,
+}
+
+/// A token that is hard to guess, from the randomly keyed std hasher.
+fn random_token() -> String {
+    use std::hash::{BuildHasher, Hasher};
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    (0..2)
+        .map(|i| {
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u128(nanos + i);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+/// One exchange recorded for `/history`.
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    request_id: u64,
+    entity: Entity,
+    prompt: String,
+    response: String,
+    origin: ResponseOrigin,
+    actions: Vec<String>,
+}
+
+#[derive(Resource)]
+struct ControlHistory {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+}
+
+struct ControlRequest {
+    method: String,
+    path: String,
+    body: String,
+    host: Option<String>,
+    origin: Option<String>,
+    token: Option<String>,
+}
+
+struct ControlCall {
+    request: ControlRequest,
+    answer: Sender<(u16, Value)>,
+}
+
+#[derive(Resource)]
+struct ControlChannel(Receiver<ControlCall>);
+
+impl Plugin for AiControlPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(&self.addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("AI control endpoint could not bind {}: {}", self.addr, e);
+                return;
+            }
+        };
+        let (tx, rx) = unbounded();
+        let token = self.token.clone().unwrap_or_else(random_token);
+        if let Ok(addr) = listener.local_addr() {
+            info!(
+                "AI control endpoint listening on http://{} ({}: {})",
+                addr, TOKEN_HEADER, token
+            );
+            app.insert_resource(AiControlServer {
+                addr,
+                token: token.clone(),
+            });
+        }
+        std::thread::spawn(move || serve(listener, tx, token));
+
+        app.insert_resource(ControlChannel(rx))
+            .insert_resource(ControlHistory {
+                entries: VecDeque::new(),
+                capacity: self.history_len.max(1),
+            })
+            .add_observer(record_history)
+            .add_systems(Last, answer_control_calls);
+    }
+}
+
+fn serve(listener: TcpListener, tx: Sender<ControlCall>, token: String) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        if tx.is_disconnected() {
+            return;
+        }
+        let tx = tx.clone();
+        let token = token.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &tx, &token) {
+                debug!("AI control connection failed: {}", e);
+            }
+        });
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    tx: &Sender<ControlCall>,
+    token: &str,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+    let (status, body) = match read_request(&mut stream) {
+        Ok(request) if !request.host.as_deref().is_some_and(is_local_host) => {
+            (403, json!({ "error": "Host must be localhost" }))
+        }
+        Ok(request)
+            if request
+                .origin
+                .as_deref()
+                .is_some_and(|o| !is_local_origin(o)) =>
+        {
+            (
+                403,
+                json!({ "error": "cross-origin requests are not allowed" }),
+            )
+        }
+        Ok(request) if request.token.as_deref() != Some(token) => (
+            401,
+            json!({ "error": format!("missing or wrong {} header", TOKEN_HEADER) }),
+        ),
+        Ok(request) => {
+            let (answer, answered) = unbounded();
+            if tx.send(ControlCall { request, answer }).is_err() {
+                (503, json!({ "error": "app is shutting down" }))
+            } else {
+                answered
+                    .recv_timeout(ANSWER_TIMEOUT)
+                    .unwrap_or((503, json!({ "error": "app did not answer in time" })))
+            }
+        }
+        Err(e) => (400, json!({ "error": e })),
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason_phrase(status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<ControlRequest, String> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err("malformed request line".to_string());
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut content_length = 0;
+    let (mut host, mut origin, mut token) = (None, None, None);
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().map_err(|_| "bad content-length")?;
+        } else if name.eq_ignore_ascii_case("host") {
+            host = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("origin") {
+            origin = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case(TOKEN_HEADER) {
+            token = Some(value.to_string());
+        }
+    }
+    if content_length > 64 * 1024 {
+        return Err("body too large".to_string());
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(ControlRequest {
+        method,
+        path,
+        body: String::from_utf8(body).map_err(|_| "body is not UTF-8")?,
+        host,
+        origin,
+        token,
+    })
+}
+
+/// Whether a `Host` header names this machine, with or without a port.
+fn is_local_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        // `[::1]:7878`
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "localhost" | "127.0.0.1" | "::1"
+    )
+}
+
+/// Whether an `Origin` header is a page served from this machine.
+fn is_local_origin(origin: &str) -> bool {
+    origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .is_some_and(is_local_host)
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Service Unavailable",
+    }
+}
+
+fn record_history(event: On<AiResponseEvent>, mut history: ResMut<ControlHistory>) {
+    let event = event.event();
+    if history.entries.len() == history.capacity {
+        history.entries.pop_front();
+    }
+    history.entries.push_back(HistoryEntry {
+        request_id: event.request_id,
+        entity: event.entity,
+        prompt: event.kind.as_user_message().to_string(),
+        response: event.text.clone(),
+        origin: event.origin,
+        actions: event.actions.iter().map(|a| a.name.clone()).collect(),
+    });
+}
+
+fn answer_control_calls(
+    channel: Res<ControlChannel>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    registry: Option<Res<AiRegistryInfo>>,
+    handle: Option<Res<LocalAiHandle>>,
+    history: Res<ControlHistory>,
+) {
+    for call in channel.0.try_iter() {
+        let request = &call.request;
+        let answer = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/status") => (
+                200,
+                json!({
+                    "model_loaded": handle.as_ref().is_some_and(|h| h.is_loaded()),
+                    "queued_requests": queue.len(),
+                    "queued_responses": handle.as_ref().map_or(0, |h| h.rx.len()),
+                }),
+            ),
+            ("GET", "/actions") => (
+                200,
+                json!({
+                    "actions": registry.as_ref().map(|r| r.actions.clone()).unwrap_or_default(),
+                    "context_systems": registry.as_ref().map(|r| r.context_systems.clone()).unwrap_or_default(),
+                }),
+            ),
+            ("GET", "/queue") => (200, Value::Array(queue.iter().map(request_json).collect())),
+            ("GET", "/history") => (
+                200,
+                Value::Array(history.entries.iter().map(history_json).collect()),
+            ),
+            ("POST", "/prompt") => match parse_prompt(&request.body) {
+                Ok(request) => {
+                    let id = request.id;
+                    queue.push(request);
+                    (200, json!({ "request_id": id }))
+                }
+                Err(e) => (400, json!({ "error": e })),
+            },
+            _ => (404, json!({ "error": "unknown route" })),
+        };
+        let _ = call.answer.send(answer);
+    }
+}
+
+fn parse_prompt(body: &str) -> Result<DialogueRequest, String> {
+    let value: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let entity = value
+        .get("entity")
+        .and_then(Value::as_u64)
+        .and_then(Entity::try_from_bits)
+        .ok_or("expected \"entity\": <Entity::to_bits>")?;
+    let prompt = value
+        .get("prompt")
+        .and_then(Value::as_str)
+        .ok_or("expected \"prompt\": \"...\"")?;
+    Ok(DialogueRequest::text(entity, prompt))
+}
+
+fn request_json(request: &DialogueRequest) -> Value {
+    json!({
+        "request_id": request.id,
+        "entity": request.entity.to_bits(),
+        "kind": request.kind,
+    })
+}
+
+fn history_json(entry: &HistoryEntry) -> Value {
+    json!({
+        "request_id": entry.request_id,
+        "entity": entry.entity.to_bits(),
+        "prompt": entry.prompt,
+        "response": entry.response,
+        "origin": entry.origin,
+        "actions": entry.actions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialogue::{AIDialoguePlugin, DialogueReceiver};
+
+    fn call(addr: SocketAddr, request: String) -> std::thread::JoinHandle<String> {
+        std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    fn finish(app: &mut App, pending: std::thread::JoinHandle<String>) -> (String, Value) {
+        while !pending.is_finished() {
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let response = pending.join().unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.to_string(), serde_json::from_str(body).unwrap())
+    }
+
+    fn wait(app: &mut App, pending: std::thread::JoinHandle<String>) -> Value {
+        let (head, body) = finish(app, pending);
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        body
+    }
+
+    #[test]
+    fn serves_status_and_injects_prompts() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(AIDialoguePlugin::default())
+            .add_plugins(AiControlPlugin::new("127.0.0.1:0"));
+        let server = app.world().resource::<AiControlServer>().clone();
+        let addr = server.addr;
+        let headers = format!("Host: 127.0.0.1\r\n{}: {}\r\n", TOKEN_HEADER, server.token);
+        let npc = app
+            .world_mut()
+            .spawn((crate::context::AI, DialogueReceiver::new()))
+            .id();
+
+        let status = wait(
+            &mut app,
+            call(addr, format!("GET /status HTTP/1.1\r\n{}\r\n", headers)),
+        );
+        assert_eq!(status["model_loaded"], true);
+
+        let body = json!({ "entity": npc.to_bits(), "prompt": "Hello" }).to_string();
+        let request = format!(
+            "POST /prompt HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+            headers,
+            body.len(),
+            body
+        );
+        let queued = wait(&mut app, call(addr, request));
+        let request_id = queued["request_id"].as_u64().unwrap();
+
+        for _ in 0..50 {
+            app.update();
+            if app
+                .world()
+                .get::<DialogueReceiver>(npc)
+                .unwrap()
+                .last_response
+                .is_some()
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let history = wait(
+            &mut app,
+            call(addr, format!("GET /history HTTP/1.1\r\n{}\r\n", headers)),
+        );
+        assert_eq!(history[0]["request_id"], request_id);
+        assert_eq!(history[0]["prompt"], "Hello");
+    }
+
+    #[test]
+    fn rejects_requests_without_token_or_from_other_sites() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(AIDialoguePlugin::default())
+            .add_plugins(AiControlPlugin::new("127.0.0.1:0").with_token("secret"));
+        let addr = app.world().resource::<AiControlServer>().addr;
+        let body = r#"{"entity": 1, "prompt": "Hello"}"#;
+        let post = |headers: &str| {
+            format!(
+                "POST /prompt HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+                headers,
+                body.len(),
+                body
+            )
+        };
+        let cases = [
+            // No token
+            ("Host: localhost:7878\r\n", "401"),
+            ("Host: localhost\r\nX-Ai-Control-Token: guess\r\n", "401"),
+            // DNS rebinding: the page's own name reaches the local port
+            (
+                "Host: evil.example:7878\r\nX-Ai-Control-Token: secret\r\n",
+                "403",
+            ),
+            // CSRF from another site
+            (
+                "Host: localhost\r\nOrigin: https://evil.example\r\nX-Ai-Control-Token: secret\r\n",
+                "403",
+            ),
+            (
+                "Host: [::1]:7878\r\nOrigin: http://localhost:8080\r\nX-Ai-Control-Token: secret\r\n",
+                "200",
+            ),
+        ];
+        for (headers, status) in cases {
+            let (head, _) = finish(&mut app, call(addr, post(headers)));
+            assert!(
+                head.starts_with(&format!("HTTP/1.1 {}", status)),
+                "{:?}: {}",
+                headers,
+                head
+            );
+        }
+        // Only the accepted request was queued
+        assert_eq!(app.world().resource::<DialogueRequestQueue>().len(), 1);
+    }
+}
@@ -0,0 +1,367 @@
+//! NPC-to-NPC conversations.
+//!
+//! Link two or more AI entities (each with a `DialogueReceiver`, usually with an
+//! [`AiPersona`]) into a [`Conversation`] entity and the [`ConversationPlugin`] will take
+//! turns automatically: each participant is prompted with what the previous speaker said,
+//! until the turn limit is reached or the end condition hook returns `true`.
+//!
+//! A turn whose request is dropped by the [`AiRateLimiter`](crate::rate_limit::AiRateLimiter)
+//! or deduplication, or whose reply is dropped as stale, ends the conversation, and so does a
+//! participant that was despawned.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(ConversationPlugin);
+//!
+//! commands.spawn(
+//!     Conversation::new(vec![guard, merchant])
+//!         .with_topic("the bandits seen on the north road")
+//!         .with_max_turns(6)
+//!         .with_end_condition(|_, line| line.text.to_lowercase().contains("farewell")),
+//! );
+//!
+//! app.add_observer(|line: On<ConversationLineEvent>| info!("{}", line.text));
+//! ```
+
+use bevy::prelude::*;
+use std::sync::Arc;
+
+use crate::dialogue::{
+    AiRequestDeduplicated, AiStaleResponse, DialogueReceiver, DialogueRequest, DialogueRequestQueue,
+};
+use crate::persona::AiPersona;
+use crate::prompts::{CONVERSATION_LINE, PromptTemplates};
+use crate::rate_limit::AiRequestThrottled;
+
+/// Hook deciding whether a conversation should end after a line was spoken.
+pub type ConversationEndCondition =
+    Arc<dyn Fn(&Conversation, &ConversationLine) -> bool + Send + Sync>;
+
+/// A single spoken line in a conversation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversationLine {
+    pub speaker: Entity,
+    pub text: String,
+}
+
+/// Component describing an ongoing conversation between AI entities.
+#[derive(Component, Clone)]
+pub struct Conversation {
+    /// Entities taking part, in speaking order.
+    pub participants: Vec<Entity>,
+    /// Optional subject used to open the conversation.
+    pub topic: Option<String>,
+    /// Maximum number of lines before the conversation ends; `0` ends it without any.
+    pub max_turns: usize,
+    lines: Vec<ConversationLine>,
+    current: usize,
+    awaiting: Option<u64>,
+    finished: bool,
+    end_condition: Option<ConversationEndCondition>,
+}
+
+impl Conversation {
+    /// Create a conversation between `participants`, the first one speaking first.
+    pub fn new(participants: Vec<Entity>) -> Self {
+        Self {
+            participants,
+            topic: None,
+            max_turns: 8,
+            lines: Vec::new(),
+            current: 0,
+            awaiting: None,
+            finished: false,
+            end_condition: None,
+        }
+    }
+
+    pub fn with_topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    pub fn with_max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    /// End the conversation as soon as `condition` returns `true` for a new line.
+    pub fn with_end_condition(
+        mut self,
+        condition: impl Fn(&Conversation, &ConversationLine) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.end_condition = Some(Arc::new(condition));
+        self
+    }
+
+    /// Lines spoken so far, oldest first.
+    pub fn lines(&self) -> &[ConversationLine] {
+        &self.lines
+    }
+
+    /// The participant whose turn it is.
+    pub fn current_speaker(&self) -> Option<Entity> {
+        self.participants.get(self.current).copied()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Stop the conversation; no further turns are requested.
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.awaiting = None;
+    }
+}
+
+impl std::fmt::Debug for Conversation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Conversation")
+            .field("participants", &self.participants)
+            .field("topic", &self.topic)
+            .field("max_turns", &self.max_turns)
+            .field("lines", &self.lines)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+/// Event fired whenever a participant speaks a line.
+#[derive(Event, Clone, Debug)]
+pub struct ConversationLineEvent {
+    pub conversation: Entity,
+    pub speaker: Entity,
+    pub text: String,
+}
+
+/// Event fired once a conversation has ended.
+#[derive(Event, Clone, Debug)]
+pub struct ConversationEndedEvent {
+    pub conversation: Entity,
+    /// Number of lines spoken.
+    pub turns: usize,
+}
+
+/// Plugin driving [`Conversation`] entities. Requires `AIDialoguePlugin`.
+pub struct ConversationPlugin;
+
+impl Plugin for ConversationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, drive_conversations)
+            .add_observer(on_turn_throttled)
+            .add_observer(on_turn_deduplicated)
+            .add_observer(on_turn_stale);
+    }
+}
+
+fn display_name(entity: Entity, personas: &Query<&AiPersona>, names: &Query<&Name>) -> String {
+    if let Ok(persona) = personas.get(entity) {
+        persona.name.clone()
+    } else if let Ok(name) = names.get(entity) {
+        name.as_str().to_string()
+    } else {
+        "someone".to_string()
+    }
+}
+
+/// Build the prompt for the participant whose turn it is.
+fn turn_prompt(
+    conversation: &Conversation,
+    speaker: Entity,
+    personas: &Query<&AiPersona>,
+    names: &Query<&Name>,
+    templates: Option<&PromptTemplates>,
+) -> String {
+    let others = conversation
+        .participants
+        .iter()
+        .filter(|p| **p != speaker)
+        .map(|p| display_name(*p, personas, names))
+        .collect::<Vec<_>>()
+        .join(" and ");
+
+    let body = match conversation.lines.last() {
+        None => match &conversation.topic {
+            Some(topic) => format!("Start a conversation with {} about {}.", others, topic),
+            None => format!("Start a conversation with {}.", others),
+        },
+        Some(line) => format!(
+            "{} says to you: \"{}\"\nReply to them.",
+            display_name(line.speaker, personas, names),
+            line.text
+        ),
+    };
+
+    let render =
+        |templates: &PromptTemplates| templates.render(CONVERSATION_LINE, &[("body", &body)]);
+    match templates {
+        Some(templates) => render(templates),
+        None => render(&PromptTemplates::default()),
+    }
+    .unwrap_or(body)
+}
+
+/// Advance every active conversation: collect finished turns and request the next one.
+fn drive_conversations(
+    mut conversations: Query<(Entity, &mut Conversation)>,
+    receivers: Query<&DialogueReceiver>,
+    personas: Query<&AiPersona>,
+    names: Query<&Name>,
+    templates: Option<Res<PromptTemplates>>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    mut commands: Commands,
+) {
+    for (conversation_entity, mut conversation) in conversations.iter_mut() {
+        if conversation.finished {
+            continue;
+        }
+        let Some(speaker) = conversation.current_speaker() else {
+            conversation.finish();
+            commands.trigger(ConversationEndedEvent {
+                conversation: conversation_entity,
+                turns: 0,
+            });
+            continue;
+        };
+        if let Some(gone) = conversation
+            .participants
+            .iter()
+            .copied()
+            .find(|p| receivers.get(*p).is_err())
+        {
+            warn!(
+                "Conversation {:?}: participant {:?} has no DialogueReceiver; ending conversation",
+                conversation_entity, gone
+            );
+            end_conversation(conversation_entity, &mut conversation, &mut commands);
+            continue;
+        }
+
+        match conversation.awaiting {
+            Some(request_id) => {
+                // Wait for the speaker's receiver to hold the answer to our request
+                let Ok(receiver) = receivers.get(speaker) else {
+                    continue;
+                };
+                if receiver.last_request_id != Some(request_id) {
+                    continue;
+                }
+                let text = receiver.last_response.clone().unwrap_or_default();
+                let line = ConversationLine { speaker, text };
+
+                conversation.awaiting = None;
+                conversation.lines.push(line.clone());
+                commands.trigger(ConversationLineEvent {
+                    conversation: conversation_entity,
+                    speaker,
+                    text: line.text.clone(),
+                });
+
+                let ended_by_hook = conversation
+                    .end_condition
+                    .clone()
+                    .map(|condition| condition(&conversation, &line))
+                    .unwrap_or(false);
+                if ended_by_hook || conversation.lines.len() >= conversation.max_turns {
+                    conversation.finish();
+                    commands.trigger(ConversationEndedEvent {
+                        conversation: conversation_entity,
+                        turns: conversation.lines.len(),
+                    });
+                } else {
+                    conversation.current =
+                        (conversation.current + 1) % conversation.participants.len();
+                }
+            }
+            None => {
+                // A limit of zero turns ends the conversation before anyone speaks
+                if conversation.lines.len() >= conversation.max_turns {
+                    conversation.finish();
+                    commands.trigger(ConversationEndedEvent {
+                        conversation: conversation_entity,
+                        turns: conversation.lines.len(),
+                    });
+                    continue;
+                }
+                let prompt = turn_prompt(
+                    &conversation,
+                    speaker,
+                    &personas,
+                    &names,
+                    templates.as_deref(),
+                );
+                let request = DialogueRequest::text(speaker, prompt);
+                conversation.awaiting = Some(request.id);
+                queue.push(request);
+            }
+        }
+    }
+}
+
+fn end_conversation(entity: Entity, conversation: &mut Conversation, commands: &mut Commands) {
+    conversation.finish();
+    commands.trigger(ConversationEndedEvent {
+        conversation: entity,
+        turns: conversation.lines.len(),
+    });
+}
+
+/// End the conversation awaiting `request_id`, whose reply will never come.
+fn end_dropped_turn(
+    conversations: &mut Query<(Entity, &mut Conversation)>,
+    request_id: u64,
+    why: &str,
+    commands: &mut Commands,
+) {
+    let Some((entity, mut conversation)) = conversations
+        .iter_mut()
+        .find(|(_, c)| !c.finished && c.awaiting == Some(request_id))
+    else {
+        return;
+    };
+    debug!(
+        "Ending conversation {:?}: turn request {} {}",
+        entity, request_id, why
+    );
+    end_conversation(entity, &mut conversation, commands);
+}
+
+fn on_turn_throttled(
+    throttled: On<AiRequestThrottled>,
+    mut conversations: Query<(Entity, &mut Conversation)>,
+    mut commands: Commands,
+) {
+    end_dropped_turn(
+        &mut conversations,
+        throttled.request_id,
+        "was throttled",
+        &mut commands,
+    );
+}
+
+fn on_turn_deduplicated(
+    deduplicated: On<AiRequestDeduplicated>,
+    mut conversations: Query<(Entity, &mut Conversation)>,
+    mut commands: Commands,
+) {
+    end_dropped_turn(
+        &mut conversations,
+        deduplicated.request_id,
+        "was dropped as a duplicate",
+        &mut commands,
+    );
+}
+
+fn on_turn_stale(
+    stale: On<AiStaleResponse>,
+    mut conversations: Query<(Entity, &mut Conversation)>,
+    mut commands: Commands,
+) {
+    end_dropped_turn(
+        &mut conversations,
+        stale.request_id,
+        "got a stale reply",
+        &mut commands,
+    );
+}
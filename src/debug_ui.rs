@@ -0,0 +1,227 @@
+//! In-game inspector window for tuning prompts.
+//!
+//! Requires the `bevy_egui` feature. [`AiDebugUiPlugin`] inserts an
+//! [`AiDebugLog`] and shows an "AI Debug" egui window listing every entity with a
+//! `DialogueReceiver`:
+//!
+//! - requests still waiting in the queue,
+//! - the last prompt exactly as sent, including persona and gathered context,
+//! - the last raw reply and the actions parsed from it,
+//! - prompt and reply token counts for the session,
+//!
+//! plus the model load status at the top. Toggle the window with [`AiDebugUi::open`] or
+//! [`AiDebugUiPlugin::toggle_key`].
+//!
+//! # Example
+//! ```ignore
+//! #[cfg(debug_assertions)]
+//! app.add_plugins(AiDebugUiPlugin::default());
+//! ```
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
+
+use crate::dialogue::{
+    DialogueReceiver, DialogueRequestQueue, LocalAiHandle, ModelDownloadProgressEvent,
+    ModelLoadCompleteEvent, PendingModelLoads,
+};
+use crate::inspect::AiDebugLog;
+use crate::models::DownloadState;
+use crate::rag::AiMessage;
+
+/// Plugin adding the AI debug window. Adds `EguiPlugin` unless it is already present.
+pub struct AiDebugUiPlugin {
+    /// Key toggling the window; `None` disables the shortcut.
+    pub toggle_key: Option<KeyCode>,
+    /// Whether the window starts open.
+    pub open: bool,
+}
+
+impl Default for AiDebugUiPlugin {
+    fn default() -> Self {
+        Self {
+            toggle_key: Some(KeyCode::F9),
+            open: true,
+        }
+    }
+}
+
+/// State of the debug window.
+#[derive(Resource, Debug, Clone)]
+pub struct AiDebugUi {
+    pub open: bool,
+    pub toggle_key: Option<KeyCode>,
+}
+
+/// Latest load state of each model, fed by the model load events.
+#[derive(Resource, Default)]
+struct ModelStatus(Vec<(String, String)>);
+
+impl ModelStatus {
+    fn set(&mut self, model: &str, status: String) {
+        match self.0.iter_mut().find(|(name, _)| name == model) {
+            Some(entry) => entry.1 = status,
+            None => self.0.push((model.to_string(), status)),
+        }
+    }
+}
+
+impl Plugin for AiDebugUiPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin::default());
+        }
+        app.init_resource::<AiDebugLog>()
+            .init_resource::<ModelStatus>()
+            .insert_resource(AiDebugUi {
+                open: self.open,
+                toggle_key: self.toggle_key,
+            })
+            .add_observer(track_download_progress)
+            .add_observer(track_load_complete)
+            .add_systems(Update, toggle_debug_ui)
+            .add_systems(EguiPrimaryContextPass, draw_debug_ui);
+    }
+}
+
+fn track_download_progress(event: On<ModelDownloadProgressEvent>, mut status: ResMut<ModelStatus>) {
+    let event = event.event();
+    let text = match (event.state, event.progress) {
+        (DownloadState::InProgress, Some(progress)) => {
+            format!("downloading {:.0}%: {}", progress, event.message)
+        }
+        (DownloadState::InProgress, None) => format!("downloading: {}", event.message),
+        (DownloadState::Verifying, _) => "verifying checksum".to_string(),
+        (DownloadState::Completed, _) => "downloaded".to_string(),
+        (DownloadState::Error, _) => format!("download failed: {}", event.message),
+    };
+    status.set(&event.model_name, text);
+}
+
+fn track_load_complete(event: On<ModelLoadCompleteEvent>, mut status: ResMut<ModelStatus>) {
+    let event = event.event();
+    let text = match &event.error_message {
+        Some(error) => format!("failed: {}", error),
+        None => "loaded".to_string(),
+    };
+    status.set(&event.model_name, text);
+}
+
+fn toggle_debug_ui(keys: Option<Res<ButtonInput<KeyCode>>>, mut ui: ResMut<AiDebugUi>) {
+    if let (Some(keys), Some(key)) = (keys, ui.toggle_key)
+        && keys.just_pressed(key)
+    {
+        ui.open = !ui.open;
+    }
+}
+
+fn message_label(message: &AiMessage) -> (&'static str, String) {
+    match message {
+        AiMessage::System(text) => ("system", text.to_string()),
+        AiMessage::User(text) => ("user", text.to_string()),
+        other => ("other", format!("{:?}", other)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_debug_ui(
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<AiDebugUi>,
+    log: Res<AiDebugLog>,
+    queue: Res<DialogueRequestQueue>,
+    handle: Option<Res<LocalAiHandle>>,
+    loads: Option<Res<PendingModelLoads>>,
+    models: Res<ModelStatus>,
+    receivers: Query<(Entity, Option<&Name>), With<DialogueReceiver>>,
+) -> Result {
+    if !ui_state.open {
+        return Ok(());
+    }
+    let ctx = contexts.ctx_mut()?;
+    egui::Window::new("AI Debug")
+        .open(&mut ui_state.open)
+        .default_width(420.0)
+        .show(ctx, |ui| {
+            let loaded = handle.as_ref().is_some_and(|h| h.is_loaded());
+            ui.label(format!(
+                "Model: {}",
+                if loaded { "loaded" } else { "not loaded" }
+            ));
+            if let Some(loads) = &loads {
+                for loader in &loads.loaders {
+                    if !models.0.iter().any(|(name, _)| *name == loader.model_name) {
+                        ui.label(format!("  {}: loading", loader.model_name));
+                    }
+                }
+            }
+            for (name, status) in &models.0 {
+                ui.label(format!("  {}: {}", name, status));
+            }
+            ui.label(format!(
+                "Queued requests: {}, queued responses: {}",
+                queue.len(),
+                handle.as_ref().map_or(0, |h| h.rx.len())
+            ));
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (entity, name) in &receivers {
+                    let title = match name {
+                        Some(name) => format!("{} ({})", name, entity),
+                        None => entity.to_string(),
+                    };
+                    egui::CollapsingHeader::new(title)
+                        .id_salt(entity)
+                        .show(ui, |ui| {
+                            let pending: Vec<_> =
+                                queue.iter().filter(|r| r.entity == entity).collect();
+                            ui.label(format!("Pending requests: {}", pending.len()));
+                            for request in pending {
+                                ui.label(format!(
+                                    "  #{}: {}",
+                                    request.id,
+                                    request.kind.as_user_message()
+                                ));
+                            }
+
+                            let Some(entry) = log.get(entity) else {
+                                ui.label("No requests recorded yet.");
+                                return;
+                            };
+                            ui.label(format!(
+                                "Tokens: {} sent, {} received",
+                                entry.prompt_tokens, entry.response_tokens
+                            ));
+                            if let Some(id) = entry.request_id {
+                                ui.label(format!("Last request: #{}", id));
+                            }
+                            egui::CollapsingHeader::new("Last prompt")
+                                .id_salt((entity, "prompt"))
+                                .show(ui, |ui| {
+                                    for message in &entry.prompt {
+                                        let (role, text) = message_label(message);
+                                        ui.label(egui::RichText::new(role).strong());
+                                        ui.monospace(text);
+                                    }
+                                });
+                            egui::CollapsingHeader::new("Last raw response")
+                                .id_salt((entity, "response"))
+                                .show(ui, |ui| {
+                                    ui.monospace(entry.raw_response.as_deref().unwrap_or("(none)"));
+                                });
+                            egui::CollapsingHeader::new(format!(
+                                "Parsed actions ({})",
+                                entry.actions.len()
+                            ))
+                            .id_salt((entity, "actions"))
+                            .show(ui, |ui| {
+                                for action in &entry.actions {
+                                    ui.monospace(format!("{}: {}", action.name, action.params));
+                                }
+                            });
+                        });
+                }
+            });
+        });
+    Ok(())
+}
@@ -21,26 +21,68 @@ pub struct ModelLoadCompleteEvent {
     pub error_message: Option<String>,
 }
 
+/// Event fired when the loaded backend is dropped, e.g. with [`LocalAiHandle::unload`].
+///
+/// Requests sent afterwards stay queued until a model is loaded again.
+#[derive(Event, Clone, Debug)]
+pub struct ModelUnloadedEvent;
+
+/// Event fired when a model load replaces a backend that was already loaded.
+///
+/// Fired after [`ModelLoadCompleteEvent`]; see [`AiModelSwapPlugin`](crate::swap::AiModelSwapPlugin).
+#[derive(Event, Clone, Debug)]
+pub struct AiBackendSwapped {
+    pub model_name: String,
+}
+
 /// Resource to track pending model loads via channels
+///
+/// Removing a loader (or clearing `loaders`) cancels its load, stopping any download in progress.
 #[derive(Resource, Default)]
 pub struct PendingModelLoads {
     /// List of pending model load operations
     pub loaders: Vec<PendingModelLoad>,
 }
 
+impl PendingModelLoads {
+    /// Cancel the loads of `model_name`. Returns whether any load was pending.
+    pub fn cancel(&mut self, model_name: &str) -> bool {
+        let before = self.loaders.len();
+        self.loaders
+            .retain(|loader| loader.model_name != model_name);
+        self.loaders.len() != before
+    }
+}
+
 /// A pending model load operation with result and progress channels
 pub struct PendingModelLoad {
     /// Name of the model being loaded
     pub model_name: String,
     /// Channel receiver for the built model result
-    pub result_receiver: crossbeam_channel::Receiver<Result<Arc<dyn LocalAi>, String>>,
+    pub result_receiver: crossbeam_channel::Receiver<Result<Arc<dyn LocalAi>, AiError>>,
     /// Optional channel receiver for download progress updates
     pub progress_receiver:
         Option<crossbeam_channel::Receiver<crate::models::ModelDownloadProgress>>,
+    /// The loading task, aborted when the loader is dropped.
+    task: Option<tokio::task::AbortHandle>,
+}
+
+impl Drop for PendingModelLoad {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take()
+            && !task.is_finished()
+        {
+            debug!("Cancelling load of model '{}'", self.model_name);
+            task.abort();
+        }
+    }
 }
 
 /// Component for entities that can receive dialogue responses
 use crate::actions::{ActionPayload, AiActionEvent};
+use crate::attribution::ResponseOrigin;
+use crate::clarify::{AiClarificationRequested, NeedsClarification, PendingClarification};
+use crate::error::AiError;
 
 /// Component for entities that can receive dialogue responses
 #[derive(Component, Debug, Clone)]
@@ -51,6 +93,31 @@ pub struct DialogueReceiver {
     pub last_response: Option<String>,
     /// Actions parsed from the last AI response (if any)
     pub actions: Vec<ActionPayload>,
+    /// Id of the request that produced `last_response`.
+    pub last_request_id: Option<u64>,
+    /// Origin of `last_response`.
+    pub last_origin: Option<ResponseOrigin>,
+    /// What to do with replies to requests older than `latest_request_id`.
+    pub response_policy: ResponsePolicy,
+    /// Id of the newest request sent to the backend for this entity.
+    pub latest_request_id: Option<u64>,
+}
+
+/// How a [`DialogueReceiver`] treats a reply that arrives after a newer request for the same
+/// entity was sent, e.g. a slow answer to a question the player already replaced.
+/// [`AiStaleResponse`] fires for such replies unless they are accepted.
+/// Replies to [`DialogueRequestKind::Data`] requests never update the receiver and are never
+/// stale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum ResponsePolicy {
+    /// Apply every reply in arrival order.
+    #[default]
+    AcceptAll,
+    /// Drop stale replies: the receiver keeps its state and their actions do not run.
+    DiscardStale,
+    /// Deliver stale replies as an [`AiResponseEvent`] with `stale` set (and record them in the
+    /// transcript), without updating the receiver or running their actions.
+    FlagStale,
 }
 
 impl DialogueReceiver {
@@ -59,16 +126,38 @@ impl DialogueReceiver {
             preprogrammed: None,
             last_response: None,
             actions: Vec::new(),
+            last_request_id: None,
+            last_origin: None,
+            response_policy: ResponsePolicy::default(),
+            latest_request_id: None,
         }
     }
 
     pub fn new_with_preprogrammed(response: impl ToString) -> Self {
         Self {
             preprogrammed: Some(response.to_string()),
-            last_response: None,
-            actions: Vec::new(),
+            ..Self::new()
         }
     }
+
+    pub fn with_response_policy(mut self, policy: ResponsePolicy) -> Self {
+        self.response_policy = policy;
+        self
+    }
+
+    /// Whether the reply to `request_id` is older than the newest request sent.
+    pub fn is_stale(&self, request_id: u64) -> bool {
+        self.latest_request_id
+            .is_some_and(|latest| request_id < latest)
+    }
+
+    /// Record that `request_id` was sent to the backend.
+    pub(crate) fn request_sent(&mut self, request_id: u64) {
+        self.latest_request_id = Some(
+            self.latest_request_id
+                .map_or(request_id, |id| id.max(request_id)),
+        );
+    }
 }
 
 impl Default for DialogueReceiver {
@@ -77,7 +166,9 @@ impl Default for DialogueReceiver {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum DialogueRequestKind {
     /// Text message with a flag controlling whether gathered context should be included.
     Text {
@@ -89,6 +180,17 @@ pub enum DialogueRequestKind {
         schema_description: String,
         action_name: String,
     },
+    /// Structured data matching a schema, delivered without running any action.
+    Data {
+        user_message: String,
+        schema_description: String,
+    },
+    /// Classification into one of a fixed set of labels. The response is the matched label.
+    Classify {
+        user_message: String,
+        labels: Vec<String>,
+        action_name: String,
+    },
 }
 
 impl DialogueRequestKind {
@@ -112,10 +214,36 @@ impl DialogueRequestKind {
         }
     }
 
+    /// Create a request for structured data of type `T`.
+    pub fn data<T>(user_message: String) -> Self
+    where
+        T: AiParsable,
+    {
+        Self::Data {
+            user_message,
+            schema_description: T::schema_description(),
+        }
+    }
+
+    /// Create a classification request from a label enum deriving `AiAction`.
+    pub fn classify<Labels>(utterance: &str) -> Self
+    where
+        Labels: AiParsable,
+    {
+        let labels = Labels::labels().unwrap_or(&[]);
+        Self::Classify {
+            user_message: crate::parse::build_classification_prompt(utterance, labels),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            action_name: Labels::action_name().to_string(),
+        }
+    }
+
     pub fn as_user_message(&self) -> &str {
         match self {
             DialogueRequestKind::Text { message, .. } => message.as_str(),
             DialogueRequestKind::Typed { user_message, .. } => user_message.as_str(),
+            DialogueRequestKind::Data { user_message, .. } => user_message.as_str(),
+            DialogueRequestKind::Classify { user_message, .. } => user_message.as_str(),
         }
     }
 
@@ -125,35 +253,80 @@ impl DialogueRequestKind {
                 include_context, ..
             } => *include_context,
             DialogueRequestKind::Typed { .. } => true,
+            DialogueRequestKind::Data { .. } => true,
+            // Classification is kept cheap: no gathered or default context.
+            DialogueRequestKind::Classify { .. } => false,
         }
     }
+
+    /// Whether this asks for structured data that is not part of the conversation.
+    pub fn is_data(&self) -> bool {
+        matches!(self, DialogueRequestKind::Data { .. })
+    }
+}
+
+/// Allocate a new, process-unique dialogue request id.
+pub fn next_request_id() -> u64 {
+    static NEXT_REQUEST_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+    NEXT_REQUEST_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
 }
 
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct DialogueRequest {
+    /// Unique id used to correlate this request with its response.
+    pub id: u64,
     pub entity: Entity,
     pub kind: DialogueRequestKind,
+    /// The message is raw player input and is cleaned by the `PlayerInputSanitizer`.
+    pub player_text: bool,
 }
 
 impl DialogueRequest {
+    /// Create a request of any `kind`, with a fresh id.
+    pub fn new(entity: Entity, kind: DialogueRequestKind) -> Self {
+        Self {
+            id: next_request_id(),
+            entity,
+            kind,
+            player_text: false,
+        }
+    }
+
     pub fn text(entity: Entity, prompt: impl Into<String>) -> Self {
         Self {
+            id: next_request_id(),
             entity,
             kind: DialogueRequestKind::Text {
                 message: prompt.into(),
                 include_context: true,
             },
+            player_text: false,
         }
     }
 
+    /// Create a text request for free-form player input, cleaned by the `PlayerInputSanitizer`.
+    pub fn player_text(entity: Entity, text: impl Into<String>) -> Self {
+        Self::text(entity, text).sanitized()
+    }
+
+    /// Mark the message as raw player input so it is sanitized before prompting.
+    /// Only text requests are sanitized.
+    pub fn sanitized(mut self) -> Self {
+        self.player_text = true;
+        self
+    }
+
     /// Create a text request that will *not* include gathered context when sent to the model.
     pub fn text_no_context(entity: Entity, prompt: impl Into<String>) -> Self {
         Self {
+            id: next_request_id(),
             entity,
             kind: DialogueRequestKind::Text {
                 message: prompt.into(),
                 include_context: false,
             },
+            player_text: false,
         }
     }
 
@@ -163,44 +336,209 @@ impl DialogueRequest {
         Action: AiParsable,
     {
         Self {
+            id: next_request_id(),
             entity,
             kind: DialogueRequestKind::typed::<Action>(user_message.to_string()),
+            player_text: false,
+        }
+    }
+
+    /// Create a request for structured data of type `T`; see [`AiRequest::ask_typed`].
+    pub fn data<T>(entity: Entity, user_message: impl ToString) -> Self
+    where
+        T: AiParsable,
+    {
+        Self {
+            id: next_request_id(),
+            entity,
+            kind: DialogueRequestKind::data::<T>(user_message.to_string()),
+            player_text: false,
+        }
+    }
+
+    /// Create a classification request for a label enum.
+    pub fn classify<Labels>(entity: Entity, utterance: impl AsRef<str>) -> Self
+    where
+        Labels: AiParsable,
+    {
+        Self {
+            id: next_request_id(),
+            entity,
+            kind: DialogueRequestKind::classify::<Labels>(utterance.as_ref()),
+            player_text: false,
         }
     }
 }
 
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct DialogueResponse {
+    /// Id of the `DialogueRequest` this response answers.
+    pub request_id: u64,
     pub entity: Entity,
     pub response: String,
     pub kind: DialogueRequestKind,
     /// Optional pre-parsed actions (when the response was produced as structured actions).
     pub actions: Option<Vec<ActionPayload>>,
+    /// Set when the reply exceeded the [`AiResponseLimit`] and was truncated or rejected.
+    pub oversized: Option<ResponseOversize>,
+    /// Whether the text came from the model or was written by the game.
+    pub origin: ResponseOrigin,
+    /// Set when a typed request was answered with a question instead of an action.
+    pub clarification: Option<NeedsClarification>,
+    /// Set when [`AiOutputModeration`](crate::moderation::AiOutputModeration) replaced the
+    /// reply.
+    pub blocked: Option<crate::moderation::BlockedOutput>,
+    /// Leading `actions` already dispatched while the reply was generated, see
+    /// [`AiEarlyActions`](crate::streaming::AiEarlyActions).
+    pub dispatched: usize,
+    /// Set when `response` is an `(ai error: ...)` message instead of an answer.
+    pub error: bool,
+}
+
+impl DialogueResponse {
+    /// A reply generated for request `request_id`, without actions.
+    pub fn new(
+        request_id: u64,
+        entity: Entity,
+        kind: DialogueRequestKind,
+        response: impl Into<String>,
+    ) -> Self {
+        Self {
+            request_id,
+            entity,
+            response: response.into(),
+            kind,
+            actions: None,
+            oversized: None,
+            origin: ResponseOrigin::Generated,
+            clarification: None,
+            blocked: None,
+            dispatched: 0,
+            error: false,
+        }
+    }
+
+    /// Pre-parsed actions of the reply.
+    pub fn with_actions(mut self, actions: Vec<ActionPayload>) -> Self {
+        self.actions = Some(actions);
+        self
+    }
+
+    /// Mark `response` as an `(ai error: ...)` message instead of an answer.
+    pub fn failed(mut self) -> Self {
+        self.error = true;
+        self
+    }
+}
+
+/// Event fired for every response applied to a `DialogueReceiver`, so observers see each
+/// reply even when several arrive in the same frame.
+///
+/// A stale reply dropped by [`ResponsePolicy::DiscardStale`] fires only [`AiStaleResponse`],
+/// so code waiting for the reply to a request id should observe both events.
+#[derive(Event, Debug, Clone)]
+pub struct AiResponseEvent {
+    pub entity: Entity,
+    pub request_id: u64,
+    /// The trimmed reply text, as stored in `DialogueReceiver::last_response`.
+    pub text: String,
+    pub kind: DialogueRequestKind,
+    /// Actions parsed from the reply.
+    pub actions: Vec<ActionPayload>,
+    pub origin: ResponseOrigin,
+    pub clarification: Option<NeedsClarification>,
+    /// Set for replies overtaken by a newer request under [`ResponsePolicy::FlagStale`].
+    pub stale: bool,
+    /// Set when `text` is an `(ai error: ...)` message instead of an answer: the backend
+    /// failed, the reply was rejected as too large or blocked, or no label matched.
+    pub error: bool,
+}
+
+/// Event fired for every reply a [`ResponsePolicy`] kept from its receiver, under both
+/// [`DiscardStale`](ResponsePolicy::DiscardStale) and [`FlagStale`](ResponsePolicy::FlagStale).
+/// It always fires before the flagged [`AiResponseEvent`], and is the only event of a discarded
+/// reply: code waiting for a request id should treat it as that request's end.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct AiStaleResponse {
+    pub entity: Entity,
+    pub request_id: u64,
+    /// The newest request sent for the entity.
+    pub latest_request_id: u64,
+    /// Kind of the stale request.
+    pub kind: DialogueRequestKind,
+    /// The trimmed reply text, which did not reach the receiver.
+    pub text: String,
+}
+
+/// Event fired for every request [`DialogueRequestQueue`] dropped as a duplicate of a pending
+/// one. The dropped request is never answered: like
+/// [`AiRequestThrottled`](crate::rate_limit::AiRequestThrottled), code waiting for a request id
+/// should treat it as that request's end.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AiRequestDeduplicated {
+    pub entity: Entity,
+    pub request_id: u64,
 }
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 /// Resource holding the queue of outgoing dialogue requests
+///
+/// With [`set_dedup`](Self::set_dedup) on, a pushed request is dropped when a request with the
+/// same entity and kind is already queued or waiting for its reply, so double-clicks and retry
+/// loops do not generate (and act) twice. Every dropped request fires [`AiRequestDeduplicated`].
 #[derive(Resource, Default)]
 pub struct DialogueRequestQueue {
     queue: VecDeque<DialogueRequest>,
     mutex: std::sync::Mutex<()>,
+    dedup: bool,
+    /// Requests taken from the queue and not answered yet, tracked while `dedup` is on.
+    in_flight: Vec<DialogueRequest>,
+    /// Duplicates dropped since [`AiRequestDeduplicated`] last fired.
+    dropped: Vec<DialogueRequest>,
 }
 
 impl DialogueRequestQueue {
     pub fn new() -> Self {
-        Self {
-            queue: VecDeque::new(),
-            mutex: std::sync::Mutex::new(()),
-        }
+        Self::default()
     }
 
     pub fn len(&self) -> usize {
         self.queue.len()
     }
 
+    /// Drop pushed requests that duplicate a pending one.
+    pub fn set_dedup(&mut self, enabled: bool) {
+        self.dedup = enabled;
+        if !enabled {
+            self.in_flight.clear();
+        }
+    }
+
+    pub fn dedup(&self) -> bool {
+        self.dedup
+    }
+
+    /// Whether a request for the same entity with the same kind is queued or, with dedup on,
+    /// waiting for its reply.
+    pub fn is_duplicate(&self, request: &DialogueRequest) -> bool {
+        self.queue
+            .iter()
+            .chain(self.in_flight.iter())
+            .any(|pending| pending.entity == request.entity && pending.kind == request.kind)
+    }
+
     pub fn push(&mut self, request: DialogueRequest) {
         let _lock = self.mutex.lock().unwrap();
+        if self.dedup && self.is_duplicate(&request) {
+            debug!(
+                "Dropped duplicate DialogueRequest {} for entity {:?}",
+                request.id, request.entity
+            );
+            self.dropped.push(request);
+            return;
+        }
         debug!(
             "Queued DialogueRequest for entity {:?}: {:?}",
             request.entity, request.kind
@@ -212,38 +550,311 @@ impl DialogueRequestQueue {
         let _lock = self.mutex.lock().unwrap();
         self.queue.pop_front()
     }
+
+    /// Take the requests dropped as duplicates.
+    pub(crate) fn take_dropped(&mut self) -> Vec<DialogueRequest> {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Remember `request` as waiting for its reply, for dedup.
+    pub(crate) fn mark_in_flight(&mut self, request: &DialogueRequest) {
+        if self.dedup {
+            self.in_flight.push(request.clone());
+        }
+    }
+
+    /// Forget the in-flight request `request_id`, e.g. once it is answered.
+    pub(crate) fn clear_in_flight(&mut self, request_id: u64) {
+        self.in_flight.retain(|request| request.id != request_id);
+    }
+
+    /// Forget the in-flight requests not matching `keep`.
+    pub(crate) fn retain_in_flight(&mut self, mut keep: impl FnMut(&DialogueRequest) -> bool) {
+        self.in_flight.retain(|request| keep(request));
+    }
+
+    /// Put `requests` back at the front of the queue, in order.
+    pub(crate) fn requeue_front(&mut self, requests: Vec<DialogueRequest>) {
+        let _lock = self.mutex.lock().unwrap();
+        for request in requests.into_iter().rev() {
+            self.queue.push_front(request);
+        }
+    }
+
+    /// Queued requests, next to be dispatched first.
+    pub fn iter(&self) -> impl Iterator<Item = &DialogueRequest> {
+        self.queue.iter()
+    }
+
+    /// Remove and return the queued requests matching `pred`, keeping the order of the rest.
+    pub fn take_matching(
+        &mut self,
+        mut pred: impl FnMut(&DialogueRequest) -> bool,
+    ) -> Vec<DialogueRequest> {
+        let _lock = self.mutex.lock().unwrap();
+        let (taken, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queue)
+            .into_iter()
+            .partition(|request| pred(request));
+        self.queue = kept.into();
+        taken
+    }
+}
+
+/// Render template `name`, falling back to the built-in templates when `templates` is `None`
+/// or its version of the template does not render.
+pub(crate) fn render_prompt(
+    templates: Option<&crate::prompts::PromptTemplates>,
+    name: &str,
+    vars: &[(&str, &str)],
+) -> Result<String, String> {
+    let built_in = || crate::prompts::PromptTemplates::default().render(name, vars);
+    match templates {
+        Some(templates) => templates.render(name, vars).or_else(|e| {
+            warn!("{}, using the built-in template", e);
+            built_in().map_err(|_| e)
+        }),
+        None => built_in(),
+    }
+}
+
+/// Build a text request with `prompt` wrapped in the `plain_text` template.
+pub(crate) fn plain_text_request(
+    templates: Option<&crate::prompts::PromptTemplates>,
+    entity: Entity,
+    prompt: String,
+    include_context: bool,
+) -> DialogueRequest {
+    let user_message = render_prompt(
+        templates,
+        crate::prompts::PLAIN_TEXT,
+        &[("prompt", &prompt)],
+    )
+    .unwrap_or(prompt);
+    if include_context {
+        DialogueRequest::text(entity, user_message)
+    } else {
+        DialogueRequest::text_no_context(entity, user_message)
+    }
+}
+
+/// Build a typed request with `prompt` and the schema of `Action` in the `typed_action` template.
+pub(crate) fn typed_action_request<Action: AiParsable>(
+    templates: Option<&crate::prompts::PromptTemplates>,
+    entity: Entity,
+    prompt: String,
+) -> DialogueRequest {
+    let schema_description = Action::schema_description();
+    let example = crate::parse::schema_example_block::<Action>();
+    let user_message = render_prompt(
+        templates,
+        crate::prompts::TYPED_ACTION,
+        &[
+            ("prompt", &prompt),
+            ("schema", &schema_description),
+            ("example", &example),
+        ],
+    )
+    .unwrap_or_else(|_| {
+        format!(
+            "{}\nProvide a JSON action matching the following schema:\n{}{}",
+            prompt, schema_description, example
+        )
+    });
+    DialogueRequest::typed::<Action>(entity, user_message)
+}
+
+/// Build a classification request for `Labels` with the `classify` template.
+pub(crate) fn classify_request<Labels: AiParsable>(
+    templates: Option<&crate::prompts::PromptTemplates>,
+    entity: Entity,
+    utterance: &str,
+) -> DialogueRequest {
+    let labels = Labels::labels().unwrap_or(&[]);
+    let user_message = render_prompt(
+        templates,
+        crate::prompts::CLASSIFY,
+        &[("utterance", utterance), ("labels", &labels.join(", "))],
+    )
+    .unwrap_or_else(|_| crate::parse::build_classification_prompt(utterance, labels));
+    DialogueRequest::new(
+        entity,
+        DialogueRequestKind::Classify {
+            user_message,
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            action_name: Labels::action_name().to_string(),
+        },
+    )
+}
+
+/// Build a data request with `prompt` wrapped in the `typed_data` template.
+pub(crate) fn typed_data_request<T: AiParsable>(
+    templates: Option<&crate::prompts::PromptTemplates>,
+    entity: Entity,
+    prompt: String,
+) -> DialogueRequest {
+    let schema_description = T::schema_description();
+    let example = crate::parse::schema_example_block::<T>();
+    let user_message = render_prompt(
+        templates,
+        crate::prompts::TYPED_DATA,
+        &[
+            ("prompt", &prompt),
+            ("schema", &schema_description),
+            ("example", &example),
+        ],
+    )
+    .unwrap_or_else(|_| {
+        format!(
+            "{}\nRespond with only a JSON value matching the following schema:\n{}{}",
+            prompt, schema_description, example
+        )
+    });
+    DialogueRequest::data::<T>(entity, user_message)
+}
+
+/// Structured reply to [`AiRequest::ask_typed`], inserted on the asking entity.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct TypedResponse<T: Send + Sync + 'static> {
+    pub request_id: u64,
+    pub value: T,
+}
+
+/// Event with the outcome of an [`AiRequest::ask_typed`] request, including failures.
+#[derive(Event, Debug, Clone)]
+pub struct AiTypedResponse<T: Send + Sync + 'static> {
+    pub entity: Entity,
+    pub request_id: u64,
+    pub result: Result<T, AiError>,
+}
+
+/// Delivers the reply of an [`AiRequest::ask_typed`] request, or the reason it has none.
+pub(crate) type TypedDelivery =
+    Box<dyn FnOnce(&mut World, Entity, Result<String, AiError>) + Send + Sync>;
+
+/// Open [`AiRequest::ask_typed`] requests by id, delivered whether or not the asking entity
+/// has a [`DialogueReceiver`].
+#[derive(Resource, Default)]
+pub(crate) struct TypedRequests(HashMap<u64, TypedDelivery>);
+
+/// Turn the reply to typed request `request_id` into its [`AiTypedResponse`].
+pub(crate) fn deliver_typed(
+    world: &mut World,
+    request_id: u64,
+    entity: Entity,
+    reply: Result<String, AiError>,
+) {
+    let deliver = world
+        .get_resource_mut::<TypedRequests>()
+        .and_then(|mut typed| typed.0.remove(&request_id));
+    if let Some(deliver) = deliver {
+        deliver(world, entity, reply);
+    }
+}
+
+/// Call `deliver` with the reply to data request `request_id` once it arrives, whether or not
+/// the asking entity has a [`DialogueReceiver`].
+pub(crate) fn await_typed(commands: &mut Commands, request_id: u64, deliver: TypedDelivery) {
+    commands.queue(move |world: &mut World| {
+        world
+            .get_resource_or_init::<TypedRequests>()
+            .0
+            .insert(request_id, deliver);
+    });
+}
+
+/// Typed requests dropped by the rate limiter fail right away.
+fn fail_throttled_typed(
+    throttled: On<crate::rate_limit::AiRequestThrottled>,
+    mut commands: Commands,
+) {
+    let (request_id, entity) = (throttled.request_id, throttled.entity);
+    commands.queue(move |world: &mut World| {
+        deliver_typed(world, request_id, entity, Err(AiError::Cancelled));
+    });
+}
+
+/// Typed requests dropped as duplicates fail right away.
+fn fail_duplicate_typed(duplicate: On<AiRequestDeduplicated>, mut commands: Commands) {
+    let (request_id, entity) = (duplicate.request_id, duplicate.entity);
+    commands.queue(move |world: &mut World| {
+        deliver_typed(world, request_id, entity, Err(AiError::Cancelled));
+    });
+}
+
+/// Fire [`AiRequestDeduplicated`] for the requests the queue dropped.
+fn report_duplicate_requests(mut queue: ResMut<DialogueRequestQueue>, mut commands: Commands) {
+    for request in queue.take_dropped() {
+        commands.trigger(AiRequestDeduplicated {
+            entity: request.entity,
+            request_id: request.id,
+        });
+    }
 }
 
 /// System parameter for enqueueing AI requests
 #[derive(bevy::ecs::system::SystemParam)]
 pub struct AiRequest<'w, 's> {
     queue: ResMut<'w, DialogueRequestQueue>,
-    // Second lifetime to satisfy SystemParam signature requirements.
-    _marker: std::marker::PhantomData<&'s ()>,
+    templates: Option<Res<'w, crate::prompts::PromptTemplates>>,
+    commands: Commands<'w, 's>,
 }
 
 impl<'w, 's> AiRequest<'w, 's> {
+    /// Render template `name` from the `PromptTemplates` resource, falling back to the
+    /// built-in templates when the resource is missing.
+    fn render(&self, name: &str, vars: &[(&str, &str)]) -> Result<String, String> {
+        render_prompt(self.templates.as_deref(), name, vars)
+    }
+
     /// Convenience method to push a simple text prompt for an entity.
     ///
-    /// Adds a short instruction to the prompt to encourage a plain, human-readable
+    /// Wraps the prompt in the `plain_text` template to encourage a plain, human-readable
     /// response (no JSON, code blocks, or structured action output).
     pub fn ask_text(&mut self, ai_entity: Entity, prompt: impl ToString) {
-        let user_message = format!(
-            "{}\n\nPlease respond in plain text only (no JSON or code blocks).",
-            prompt.to_string()
+        let request = plain_text_request(
+            self.templates.as_deref(),
+            ai_entity,
+            prompt.to_string(),
+            false,
         );
-        self.queue
-            .push(DialogueRequest::text_no_context(ai_entity, user_message));
+        self.queue.push(request);
     }
 
     /// Inquire with context gathering.
     pub fn inquire(&mut self, ai_entity: Entity, prompt: impl ToString) {
-        let user_message = format!(
-            "{}\n\nPlease respond in plain text only (no JSON or code blocks).",
-            prompt.to_string()
+        let request = plain_text_request(
+            self.templates.as_deref(),
+            ai_entity,
+            prompt.to_string(),
+            true,
         );
+        self.queue.push(request);
+    }
+
+    /// Render template `name` with `vars` and send it without context gathering.
+    pub fn ask_template(
+        &mut self,
+        ai_entity: Entity,
+        name: &str,
+        vars: &[(&str, &str)],
+    ) -> Result<(), String> {
+        let user_message = self.render(name, vars)?;
+        self.queue
+            .push(DialogueRequest::text_no_context(ai_entity, user_message));
+        Ok(())
+    }
+
+    /// Render template `name` with `vars` and send it with context gathering.
+    pub fn inquire_template(
+        &mut self,
+        ai_entity: Entity,
+        name: &str,
+        vars: &[(&str, &str)],
+    ) -> Result<(), String> {
+        let user_message = self.render(name, vars)?;
         self.queue
             .push(DialogueRequest::text(ai_entity, user_message));
+        Ok(())
     }
 
     /// Ask for a typed [AiParsable] according to the schema of the provided `Action` type.
@@ -251,14 +862,85 @@ impl<'w, 's> AiRequest<'w, 's> {
     where
         Action: AiParsable,
     {
-        let schema_description = Action::schema_description();
-        let user_message = format!(
-            "{}\nProvide a JSON action matching the following schema:\n{}",
+        let request = typed_action_request::<Action>(
+            self.templates.as_deref(),
+            ai_entity,
             prompt.to_string(),
-            schema_description
         );
-        self.queue
-            .push(DialogueRequest::typed::<Action>(ai_entity, user_message));
+        self.queue.push(request);
+    }
+
+    /// Ask for structured data of type `T`, e.g. an item description or a stat block.
+    ///
+    /// Unlike [`ask_action`](Self::ask_action) nothing is routed through the
+    /// `AiActionRegistry`: the parsed value is inserted on the entity as a [`TypedResponse<T>`]
+    /// and [`AiTypedResponse<T>`] is triggered, also when the reply could not be parsed or the
+    /// rate limiter or deduplication dropped the request ([`AiError::Cancelled`]). The entity needs no
+    /// [`DialogueReceiver`], and the reply leaves its receiver and transcript unchanged.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+    /// struct ItemText { name: String, lore: String }
+    ///
+    /// ai.ask_typed::<ItemText>(blacksmith, "Describe the sword you just forged");
+    ///
+    /// fn show_item(items: Query<&TypedResponse<ItemText>, Changed<TypedResponse<ItemText>>>) {
+    ///     for item in items.iter() {
+    ///         info!("{}: {}", item.value.name, item.value.lore);
+    ///     }
+    /// }
+    /// ```
+    pub fn ask_typed<T>(&mut self, ai_entity: Entity, prompt: impl ToString)
+    where
+        T: AiParsable + serde::de::DeserializeOwned,
+    {
+        let request =
+            typed_data_request::<T>(self.templates.as_deref(), ai_entity, prompt.to_string());
+        let request_id = request.id;
+        // Turns the reply into `T` once it arrives, see `poll_responses_receiver`
+        let deliver: TypedDelivery = Box::new(move |world, entity, reply| {
+            let result = reply.and_then(|text| T::parse_from_ai_response(&text));
+            if let Ok(value) = &result
+                && let Ok(mut entity) = world.get_entity_mut(entity)
+            {
+                entity.insert(TypedResponse {
+                    request_id,
+                    value: value.clone(),
+                });
+            }
+            world.trigger(AiTypedResponse {
+                entity,
+                request_id,
+                result,
+            });
+        });
+        // Registered before the push, so a duplicate fails through `fail_duplicate_typed`
+        await_typed(&mut self.commands, request_id, deliver);
+        self.queue.push(request);
+    }
+
+    /// Classify a free-form `utterance` into one of the labels of `Labels`, an enum of unit
+    /// variants deriving `AiAction`.
+    ///
+    /// Uses a short, context-free prompt and only expects a single label back, so it is much
+    /// cheaper than a typed action. The matched label is stored in `DialogueReceiver::last_response`
+    /// and dispatched as an action, so a handler registered for `Labels` receives it as `In<Labels>`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+    /// enum Intent { Trade, Quest, Gossip, Goodbye }
+    ///
+    /// ai.classify::<Intent>(npc, "Got anything to sell?");
+    /// ```
+    pub fn classify<Labels>(&mut self, ai_entity: Entity, utterance: impl AsRef<str>)
+    where
+        Labels: AiParsable,
+    {
+        let request =
+            classify_request::<Labels>(self.templates.as_deref(), ai_entity, utterance.as_ref());
+        self.queue.push(request);
     }
 }
 
@@ -272,7 +954,7 @@ pub struct PromptResult {
 pub trait LocalAi: Send + Sync + 'static {
     /// Accepts an iterator of `Message` so backends can distinguish
     /// between system/context and user messages without string parsing.
-    fn prompt(&self, messages: &[AiMessage]) -> Result<String, String>;
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError>;
 
     /// Prompt with an optional existing session, returning the response and updated session.
     /// This allows conversation history to be preserved across calls.
@@ -281,7 +963,7 @@ pub trait LocalAi: Send + Sync + 'static {
         &self,
         messages: &[AiMessage],
         _session: Option<kalosm::language::BoxedChatSession>,
-    ) -> Result<PromptResult, String> {
+    ) -> Result<PromptResult, AiError> {
         // Default implementation ignores session and just calls prompt
         match self.prompt(messages) {
             Ok(response) => Ok(PromptResult {
@@ -292,6 +974,49 @@ pub trait LocalAi: Send + Sync + 'static {
         }
     }
 
+    /// Like [`prompt`](Self::prompt), passing each piece of the reply to `on_text` as it is
+    /// generated, for [early action dispatch](crate::streaming::AiEarlyActions). The default
+    /// implementation passes the whole reply at once.
+    fn prompt_streaming(
+        &self,
+        messages: &[AiMessage],
+        on_text: &mut dyn FnMut(&str),
+    ) -> Result<String, AiError> {
+        let reply = self.prompt(messages)?;
+        on_text(&reply);
+        Ok(reply)
+    }
+
+    /// The tokenizer of the underlying model, if the backend knows it.
+    ///
+    /// The dialogue plugin installs it as the [`AiTokenizer`](crate::tokenizer::AiTokenizer)
+    /// resource once the backend is available.
+    fn tokenizer(&self) -> Option<Arc<dyn crate::tokenizer::Tokenizer>> {
+        None
+    }
+
+    /// The device a local model actually runs on, or `None` for remote backends.
+    ///
+    /// Differs from [`AiModelBuilder::with_device`](crate::models::AiModelBuilder::with_device)
+    /// when the requested GPU could not be used.
+    fn device(&self) -> Option<crate::models::Device> {
+        None
+    }
+
+    /// Whether short prompts may be combined into one numbered prompt by the
+    /// [`AiBatchingPlugin`](crate::batch::AiBatchingPlugin). Leave `false` for backends that
+    /// cannot reliably answer several items in one reply.
+    fn supports_batching(&self) -> bool {
+        false
+    }
+
+    /// Cheap check that the backend can currently serve requests, e.g. a ping or model-list
+    /// call for remote backends. Used by [`AiHealthCheckPlugin`](crate::health::AiHealthCheckPlugin);
+    /// local backends are always available.
+    fn health_check(&self) -> Result<(), AiError> {
+        Ok(())
+    }
+
     fn get_model(&self) -> BoxedChatModel {
         unimplemented!("get_model is not implemented for this LocalAi backend");
     }
@@ -310,7 +1035,7 @@ pub trait LocalAi: Send + Sync + 'static {
             serde_json::Value,
             Option<kalosm::language::BoxedChatSession>,
         ),
-        String,
+        AiError,
     > {
         let prompt_res = self.prompt_with_session(messages, session)?;
         match crate::parse::extract_and_parse_json::<serde_json::Value>(&prompt_res.response) {
@@ -357,15 +1082,207 @@ impl LocalAiHandle {
     pub fn get_backend(&self) -> Option<Arc<dyn LocalAi>> {
         self.backend.clone()
     }
+
+    /// Drop the backend to free its memory, e.g. during a cutscene. Returns whether a model was
+    /// loaded.
+    ///
+    /// [`ModelUnloadedEvent`] fires on the next update. The weights are released once requests
+    /// still running on the model finish; new requests wait in the queue until a model is
+    /// loaded again with [`start_model_load`].
+    pub fn unload(&mut self) -> bool {
+        self.backend.take().is_some()
+    }
 }
 
-use crate::context::{AiContextGatherConfig, AiSystemContextStore, ContextGatherRequest};
+use crate::context::AiContextGatherConfig;
+
+/// Global system prompt sent with every context-including request, whatever the backend.
+///
+/// When `text` is `None` (the default) each backend applies its own default system context
+/// (see [`DEFAULT_SYSTEM_CONTEXT`](crate::models::DEFAULT_SYSTEM_CONTEXT) for `AIModel`).
+/// When set, it replaces that default and can be changed at runtime.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct GlobalSystemPrompt {
+    pub text: Option<String>,
+}
+
+impl GlobalSystemPrompt {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: Some(text.into()),
+        }
+    }
+
+    pub fn set(&mut self, text: impl Into<String>) {
+        self.text = Some(text.into());
+    }
+
+    /// Go back to each backend's own default context.
+    pub fn clear(&mut self) {
+        self.text = None;
+    }
+}
+
+/// What happens to a reply larger than the [`AiResponseLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect)]
+#[non_exhaustive]
+pub enum OversizePolicy {
+    /// Keep the longest prefix within the limit (at a character boundary).
+    #[default]
+    Truncate,
+    /// Replace the reply with an `(ai error: ...)` message.
+    Reject,
+}
+
+/// Hard cap on the size of accepted replies, applied on the background task before any parsing
+/// and again to the translation of a reply, see [`AiLocale`](crate::locale::AiLocale).
+///
+/// Protects the parser, UI and memory from runaway generations that ignore their stop
+/// conditions. Typed replies are always rejected when too large, since truncated JSON is
+/// useless. An [`AiResponseOversized`] event is fired for every reply that hit the limit.
+///
+/// Token limits are counted with the [`AiTokenizer`](crate::tokenizer::AiTokenizer) resource.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Resource)]
+#[non_exhaustive]
+pub struct AiResponseLimit {
+    pub max_bytes: usize,
+    /// Optional cap in model tokens, checked in addition to `max_bytes`.
+    pub max_tokens: Option<usize>,
+    pub policy: OversizePolicy,
+}
+
+impl Default for AiResponseLimit {
+    fn default() -> Self {
+        Self::bytes(256 * 1024)
+    }
+}
+
+impl AiResponseLimit {
+    pub fn bytes(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            max_tokens: None,
+            policy: OversizePolicy::default(),
+        }
+    }
+
+    /// Limit by token count, keeping the default byte cap as a backstop.
+    pub fn tokens(max_tokens: usize) -> Self {
+        Self::default().with_max_tokens(max_tokens)
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: OversizePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Enforce the limit on `text`. `can_truncate` is `false` for structured replies.
+    pub fn apply(
+        &self,
+        mut text: String,
+        can_truncate: bool,
+        tokenizer: &dyn crate::tokenizer::Tokenizer,
+    ) -> (String, Option<ResponseOversize>) {
+        let bytes = text.len();
+        let tokens = self.max_tokens.map(|_| tokenizer.count(&text));
+        let over_bytes = bytes > self.max_bytes;
+        let over_tokens = tokens.zip(self.max_tokens).is_some_and(|(n, max)| n > max);
+        if !over_bytes && !over_tokens {
+            return (text, None);
+        }
+        let truncated = can_truncate && self.policy == OversizePolicy::Truncate;
+        if truncated {
+            let mut end = bytes.min(self.max_bytes);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            if let Some(max_tokens) = self.max_tokens {
+                end = tokenizer.truncate_index(&text[..end], max_tokens);
+            }
+            text.truncate(end);
+        } else if over_bytes {
+            text = format!(
+                "(ai error: response too large: {} bytes exceeds limit of {})",
+                bytes, self.max_bytes
+            );
+        } else {
+            text = format!(
+                "(ai error: response too large: {} tokens exceeds limit of {})",
+                tokens.unwrap_or_default(),
+                self.max_tokens.unwrap_or_default()
+            );
+        }
+        let oversize = ResponseOversize {
+            bytes,
+            limit: self.max_bytes,
+            tokens,
+            token_limit: self.max_tokens,
+            truncated,
+        };
+        (text, Some(oversize))
+    }
+}
+
+/// Details of a reply that exceeded the [`AiResponseLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseOversize {
+    /// Size of the reply as generated.
+    pub bytes: usize,
+    pub limit: usize,
+    /// Token count of the reply as generated, if a token limit was set.
+    pub tokens: Option<usize>,
+    pub token_limit: Option<usize>,
+    /// `true` if the reply was truncated, `false` if it was rejected.
+    pub truncated: bool,
+}
+
+/// Event fired when a reply exceeded the [`AiResponseLimit`].
+#[derive(Event, Debug, Clone)]
+#[non_exhaustive]
+pub struct AiResponseOversized {
+    pub entity: Entity,
+    pub request_id: u64,
+    pub oversize: ResponseOversize,
+}
+
+/// Stages of the dialogue pipeline, run in this order in `Update`.
+///
+/// Order your own systems against them, e.g. `.before(AiSystemSet::GatherContext)` for a system
+/// that updates state read by context gatherers.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AiSystemSet {
+    /// Queued `DialogueRequest`s are sent to the backend.
+    HandleRequests,
+    /// Context-gathering systems run for entities that requested context.
+    GatherContext,
+    /// Finished replies are applied to `DialogueReceiver`s.
+    PollResponses,
+    /// Handlers run for pending AI actions.
+    RunActions,
+    /// Background model loads are checked for progress and completion.
+    PollModelLoads,
+}
 
 /// Plugin that adds NPC dialogue capabilities with the provided LocalAi backend.
+///
+/// Adds [`AiCorePlugin`], [`AiActionsPlugin`](crate::actions::AiActionsPlugin) and
+/// [`AiContextPlugin`](crate::context::AiContextPlugin). Add those individually to use only
+/// part of the pipeline, e.g. prompting and actions without context gathering.
+#[derive(Clone)]
 pub struct AIDialoguePlugin {
     backend: Option<Arc<dyn LocalAi>>,
     builder: Option<crate::models::AiModelBuilder>,
     pub gather_config: AiContextGatherConfig,
+    /// Initial value of the [`GlobalSystemPrompt`] resource.
+    pub system_context: Option<String>,
 }
 
 impl AIDialoguePlugin {
@@ -393,8 +1310,15 @@ impl AIDialoguePlugin {
             backend: self.backend.clone(),
             builder: self.builder.clone(),
             gather_config,
+            system_context: self.system_context.clone(),
         }
     }
+
+    /// Replace the default system context for every backend (see [`GlobalSystemPrompt`]).
+    pub fn with_system_context(mut self, context: &str) -> Self {
+        self.system_context = Some(context.to_string());
+        self
+    }
 }
 
 impl Default for AIDialoguePlugin {
@@ -404,15 +1328,70 @@ impl Default for AIDialoguePlugin {
         Self {
             backend: None,
             builder: None,
-            gather_config: AiContextGatherConfig {
-                radius: 5.0,
-                max_docs: 8,
-            },
+            gather_config: AiContextGatherConfig::new(5.0, 8),
+            system_context: None,
         }
     }
 }
 
 impl Plugin for AIDialoguePlugin {
+    fn build(&self, app: &mut App) {
+        // Parts added by the app itself are kept
+        if !app.is_plugin_added::<AiCorePlugin>() {
+            app.add_plugins(AiCorePlugin {
+                backend: self.backend.clone(),
+                builder: self.builder.clone(),
+                system_context: self.system_context.clone(),
+            });
+        }
+        if !app.is_plugin_added::<crate::actions::AiActionsPlugin>() {
+            app.add_plugins(crate::actions::AiActionsPlugin);
+        }
+        if !app.is_plugin_added::<crate::context::AiContextPlugin>() {
+            app.add_plugins(crate::context::AiContextPlugin {
+                gather_config: self.gather_config.clone(),
+            });
+        }
+    }
+}
+
+/// The prompting pipeline: the backend handle, the request queue, response polling, model
+/// loading and the settings they read. Replies are applied to `DialogueReceiver`s; parsed
+/// actions run only with the [`AiActionsPlugin`](crate::actions::AiActionsPlugin) and context
+/// is gathered only with the [`AiContextPlugin`](crate::context::AiContextPlugin).
+#[derive(Clone, Default)]
+pub struct AiCorePlugin {
+    backend: Option<Arc<dyn LocalAi>>,
+    builder: Option<crate::models::AiModelBuilder>,
+    /// Initial value of the [`GlobalSystemPrompt`] resource.
+    pub system_context: Option<String>,
+}
+
+impl AiCorePlugin {
+    /// Create a plugin with a prebuilt backend.
+    pub fn with_backend(backend: Arc<dyn LocalAi>) -> Self {
+        Self {
+            backend: Some(backend),
+            ..Default::default()
+        }
+    }
+
+    /// Create a plugin loading a model from `builder` in the background.
+    pub fn with_builder(builder: crate::models::AiModelBuilder) -> Self {
+        Self {
+            builder: Some(builder),
+            ..Default::default()
+        }
+    }
+
+    /// Replace the default system context for every backend (see [`GlobalSystemPrompt`]).
+    pub fn with_system_context(mut self, context: &str) -> Self {
+        self.system_context = Some(context.to_string());
+        self
+    }
+}
+
+impl Plugin for AiCorePlugin {
     fn build(&self, app: &mut App) {
         if let Some(_) = app.world().get_resource::<LocalAiHandle>() {
             // Already added; skip
@@ -433,30 +1412,84 @@ impl Plugin for AIDialoguePlugin {
             LocalAiHandle::new(Arc::new(MockAi {}))
         };
 
+        // Direct backends may already know their tokenizer; loaded models replace it later.
+        let initial_tokenizer = ai_handle
+            .backend
+            .as_ref()
+            .and_then(|b| b.tokenizer())
+            .map(crate::tokenizer::AiTokenizer)
+            .unwrap_or_default();
+
         // Insert the AI handle and other resources.
         app.insert_resource(ai_handle)
             .insert_resource(DialogueRequestQueue::default())
-            .insert_resource(AiSystemContextStore::new())
-            .insert_resource(self.gather_config.clone())
-            .insert_resource(ContextGatherRequest::default())
             .insert_resource(PendingModelLoads::default())
-            // Register the AiActionEvent and registry for handlers
-            .insert_resource(crate::actions::AiActionRegistry::default())
-            .insert_resource(crate::actions::PendingAiActions::default());
+            .init_resource::<crate::prompts::PromptTemplates>()
+            .init_resource::<crate::budget::AiFrameBudget>()
+            .init_resource::<crate::budget::AiFrameUsage>()
+            .init_resource::<AiResponseLimit>()
+            .init_resource::<crate::sanitize::PlayerInputSanitizer>()
+            .init_resource::<crate::attribution::AiAttribution>()
+            .init_resource::<crate::status::AiPipelineStatus>()
+            .init_resource::<crate::rate_limit::AiRateLimiter>()
+            .insert_resource(initial_tokenizer)
+            .insert_resource(GlobalSystemPrompt {
+                text: self.system_context.clone(),
+            });
+
+        // Make the tweakable pieces visible to editor/inspector tooling
+        app.register_type::<crate::persona::AiPersona>()
+            .register_type::<crate::prompts::PromptTemplates>()
+            .register_type::<GlobalSystemPrompt>()
+            .register_type::<crate::budget::AiFrameBudget>()
+            .register_type::<crate::budget::AiFrameUsage>()
+            .register_type::<AiResponseLimit>()
+            .register_type::<crate::attribution::AiAttribution>()
+            .register_type::<crate::attribution::AiGenerated>()
+            .register_type::<crate::rate_limit::AiCooldown>();
 
         // Schedule dialogue request handling first, then gather (which may have been triggered by dialogue),
         // then response polling. This ensures context is gathered in the same frame as the request is made.
-        app.add_systems(
+        app.configure_sets(
             Update,
             (
-                handle_dialogue_requests,
-                crate::context::gather_on_request_world,
-                poll_responses_receiver,
-                crate::actions::run_registered_actions_world,
-                poll_pending_model_loads,
+                AiSystemSet::HandleRequests,
+                AiSystemSet::GatherContext,
+                AiSystemSet::PollResponses,
+                AiSystemSet::RunActions,
+                AiSystemSet::PollModelLoads,
             )
                 .chain(),
-        );
+        )
+        .add_systems(
+            Update,
+            (
+                (
+                    crate::rate_limit::throttle_requests,
+                    handle_dialogue_requests,
+                    report_duplicate_requests,
+                )
+                    .chain()
+                    .in_set(AiSystemSet::HandleRequests),
+                (poll_responses_receiver, forget_despawned_requests)
+                    .chain()
+                    .in_set(AiSystemSet::PollResponses),
+                (
+                    poll_pending_model_loads,
+                    notify_model_unloaded,
+                    crate::status::update_pipeline_status,
+                )
+                    .chain()
+                    .in_set(AiSystemSet::PollModelLoads),
+            ),
+        )
+        .init_resource::<TypedRequests>()
+        .add_observer(fail_throttled_typed)
+        .add_observer(fail_duplicate_typed)
+        .add_observer(forget_throttled_request)
+        .add_observer(crate::status::track_model_progress)
+        .add_observer(crate::status::track_model_loads)
+        .add_observer(crate::status::track_model_unloads);
 
         // If a builder was provided, spawn the model loading task asynchronously
         if let Some(builder) = self.builder.clone() {
@@ -475,16 +1508,19 @@ impl Plugin for AIDialoguePlugin {
     }
 }
 
+const MAX_PROGRESS_EVENTS_PER_FRAME: usize = 16;
+
 /// System that polls for completed model loads and triggers events
 fn poll_pending_model_loads(
     mut pending: ResMut<PendingModelLoads>,
     mut ai_handle: ResMut<LocalAiHandle>,
     mut commands: Commands,
 ) {
-    // Poll progress receivers and trigger progress events
+    // Poll progress receivers and trigger progress events. Only a few updates per loader are
+    // forwarded each frame; the rest follow on later frames.
     for loader in pending.loaders.iter() {
         if let Some(ref progress_rx) = loader.progress_receiver {
-            while let Ok(progress) = progress_rx.try_recv() {
+            for progress in progress_rx.try_iter().take(MAX_PROGRESS_EVENTS_PER_FRAME) {
                 commands.trigger(ModelDownloadProgressEvent {
                     model_name: loader.model_name.clone(),
                     state: progress.state,
@@ -498,26 +1534,40 @@ fn poll_pending_model_loads(
     // Poll result receivers and trigger completion events
     let mut completed_indices = Vec::new();
     for (idx, loader) in pending.loaders.iter().enumerate() {
-        if let Ok(result) = loader.result_receiver.try_recv() {
-            match result {
-                Ok(new_backend) => {
-                    ai_handle.backend = Some(new_backend);
-                    commands.trigger(ModelLoadCompleteEvent {
-                        model_name: loader.model_name.clone(),
-                        success: true,
-                        error_message: None,
-                    });
+        let result = match loader.result_receiver.try_recv() {
+            Ok(result) => result,
+            Err(crossbeam_channel::TryRecvError::Empty) => continue,
+            // The loading task panicked
+            Err(crossbeam_channel::TryRecvError::Disconnected) => Err(AiError::ModelLoad(
+                "model loading task stopped unexpectedly".to_string(),
+            )),
+        };
+        match result {
+            Ok(new_backend) => {
+                if let Some(tokenizer) = new_backend.tokenizer() {
+                    commands.insert_resource(crate::tokenizer::AiTokenizer(tokenizer));
                 }
-                Err(e) => {
-                    commands.trigger(ModelLoadCompleteEvent {
+                let replaced = ai_handle.backend.replace(new_backend).is_some();
+                commands.trigger(ModelLoadCompleteEvent {
+                    model_name: loader.model_name.clone(),
+                    success: true,
+                    error_message: None,
+                });
+                if replaced {
+                    commands.trigger(AiBackendSwapped {
                         model_name: loader.model_name.clone(),
-                        success: false,
-                        error_message: Some(e),
                     });
                 }
             }
-            completed_indices.push(idx);
+            Err(e) => {
+                commands.trigger(ModelLoadCompleteEvent {
+                    model_name: loader.model_name.clone(),
+                    success: false,
+                    error_message: Some(e.to_string()),
+                });
+            }
         }
+        completed_indices.push(idx);
     }
 
     // Remove completed loaders (in reverse order to preserve indices)
@@ -526,6 +1576,19 @@ fn poll_pending_model_loads(
     }
 }
 
+/// Fire [`ModelUnloadedEvent`] when the backend goes away.
+fn notify_model_unloaded(
+    ai_handle: Res<LocalAiHandle>,
+    mut was_loaded: Local<bool>,
+    mut commands: Commands,
+) {
+    let loaded = ai_handle.is_loaded();
+    if *was_loaded && !loaded {
+        commands.trigger(ModelUnloadedEvent);
+    }
+    *was_loaded = loaded;
+}
+
 /// System condition used with `run_if` to determine if the model has finished loading.
 /// Returns `true` when the `LocalAiHandle` has a backend (model loaded), otherwise `false`.
 pub fn on_model_load_complete(ai_handle: Option<Res<LocalAiHandle>>) -> bool {
@@ -543,16 +1606,12 @@ pub fn start_model_load(
     let progress_receiver = builder.take_progress_receiver();
 
     // Create a channel for the built model result
-    let (result_tx, result_rx) = crossbeam_channel::unbounded::<Result<Arc<dyn LocalAi>, String>>();
+    let (result_tx, result_rx) =
+        crossbeam_channel::unbounded::<Result<Arc<dyn LocalAi>, AiError>>();
 
-    // Spawn a thread that builds the model and sends the result
-    std::thread::spawn(move || match builder.build() {
-        Ok(arc_model) => {
-            let _ = result_tx.send(Ok(arc_model));
-        }
-        Err(e) => {
-            let _ = result_tx.send(Err(e));
-        }
+    // Build on the shared runtime; the task is aborted if the loader is dropped
+    let task = crate::models::TOKIO_RUNTIME.spawn(async move {
+        let _ = result_tx.send(builder.build_async().await);
     });
 
     // Add to pending model loads resource
@@ -560,82 +1619,323 @@ pub fn start_model_load(
         model_name,
         result_receiver: result_rx,
         progress_receiver,
+        task: Some(task.abort_handle()),
     });
 }
 
+/// Optional resources shaping how requests are prompted, grouped to stay within the system
+/// parameter limit of `handle_dialogue_requests`.
+#[derive(bevy::ecs::system::SystemParam)]
+pub(crate) struct PromptSettings<'w> {
+    global_prompt: Option<Res<'w, GlobalSystemPrompt>>,
+    response_limit: Option<Res<'w, AiResponseLimit>>,
+    tokenizer: Option<Res<'w, crate::tokenizer::AiTokenizer>>,
+    sanitizer: Option<Res<'w, crate::sanitize::PlayerInputSanitizer>>,
+    debug_log: Option<ResMut<'w, crate::inspect::AiDebugLog>>,
+    log_sink: Option<Res<'w, crate::log_sink::AiLogSink>>,
+    locale: Option<Res<'w, crate::locale::AiLocale>>,
+    moderation: Option<Res<'w, crate::moderation::AiOutputModeration>>,
+    templates: Option<Res<'w, crate::prompts::PromptTemplates>>,
+    early_actions: Option<Res<'w, crate::streaming::AiEarlyActions>>,
+    lenient_json: Option<Res<'w, crate::parse::LenientJson>>,
+    snapshots: Option<ResMut<'w, crate::snapshot::SnapshotBaselines>>,
+}
+
 /// System that handles outgoing requests: if NPC has preprogrammed response, respond immediately; else, spawn a thread to call the backend and send result to the response channel.
-/// Requests are kept in the queue until the model is loaded.
-fn handle_dialogue_requests(
+/// Requests are kept in the queue until the model is loaded, and at most
+/// `AiFrameBudget::max_requests` are dispatched per frame.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_dialogue_requests(
+    mut commands: Commands,
     mut queue: ResMut<DialogueRequestQueue>,
     ai_handle: Res<LocalAiHandle>,
-    query: Query<&DialogueReceiver>,
+    mut query: Query<(&mut DialogueReceiver, Option<&PendingClarification>)>,
     mut gather_req: Option<ResMut<crate::context::ContextGatherRequest>>,
     gather_store: Option<Res<crate::context::AiSystemContextStore>>,
-    ctx_query: Query<&crate::rag::AiContext>,
+    ctx_query: Query<(
+        &crate::rag::AiContext,
+        Option<&crate::context::GatheredContext>,
+    )>,
+    mut transcripts: Query<&mut crate::transcript::Transcript>,
+    personas: Query<&crate::persona::AiPersona>,
+    recaps: Query<&crate::swap::ConversationRecap>,
+    mut status: Option<ResMut<crate::status::AiPipelineStatus>>,
+    budget: Option<Res<crate::budget::AiFrameBudget>>,
+    mut usage: Option<ResMut<crate::budget::AiFrameUsage>>,
+    mut settings: PromptSettings,
+    mut gathered: Local<std::collections::HashSet<u64>>,
 ) {
     // Get the backend, or return early if not loaded yet (requests stay queued)
     let Some(backend) = &ai_handle.backend else {
+        if let Some(usage) = usage.as_mut() {
+            usage.requests = 0;
+            usage.queued_requests = queue.len();
+        }
         return;
     };
 
-    while let Some(req) = queue.pop() {
+    let max_requests = budget.map_or(usize::MAX, |b| b.max_requests);
+    let sanitizer = settings.sanitizer.as_deref().cloned().unwrap_or_default();
+    let tokenizer = settings.tokenizer.as_deref().cloned().unwrap_or_default();
+    let mut dispatched = 0;
+    // Requests waiting for their context gather, put back in order after the loop
+    let mut deferred = Vec::new();
+    while dispatched < max_requests {
+        let Some(mut req) = queue.pop() else { break };
+        // Context is gathered for the prompt before it is built: the request waits in the queue
+        // until the gather ran. Context inserted by the game is used as it is.
+        if let (Some(gr), Some(store)) = (gather_req.as_mut(), gather_store.as_ref())
+            && req.kind.include_context()
+            && !store.systems().is_empty()
+            && !query
+                .get(req.entity)
+                .is_ok_and(|(receiver, _)| receiver.preprogrammed.is_some())
+            && !ctx_query
+                .get(req.entity)
+                .is_ok_and(|(_, gathered)| gathered.is_none())
+        {
+            if !gathered.contains(&req.id) {
+                // One gather per entity at a time, so each request gets the one for its prompt
+                if !gr.is_queued(req.entity) {
+                    gathered.insert(req.id);
+                    gr.request_with_query(req.entity, req.kind.as_user_message());
+                }
+                deferred.push(req);
+                continue;
+            }
+            if gr.is_queued(req.entity) {
+                deferred.push(req);
+                continue;
+            }
+            gathered.remove(&req.id);
+        }
+        dispatched += 1;
+        queue.mark_in_flight(&req);
+        // Not a change readers of the receiver care about. Data replies leave the receiver
+        // alone, so they neither make earlier requests stale nor go stale themselves.
+        if !req.kind.is_data()
+            && let Ok((mut receiver, _)) = query.get_mut(req.entity)
+        {
+            receiver.bypass_change_detection().request_sent(req.id);
+        }
+        // Clean raw player text before it is recorded, used for gathering or prompted
+        let mut delimit_player_text = req.player_text
+            && matches!(req.kind, DialogueRequestKind::Text { .. })
+            && sanitizer.delimit;
+        if req.player_text
+            && let DialogueRequestKind::Text { message, .. } = &mut req.kind
+        {
+            match sanitizer.clean(message) {
+                Ok(cleaned) => *message = cleaned,
+                Err(reason) => {
+                    let _ = ai_handle.tx.send(DialogueResponse {
+                        request_id: req.id,
+                        entity: req.entity,
+                        response: format!("(ai error: player input rejected: {})", reason),
+                        kind: req.kind.clone(),
+                        actions: None,
+                        oversized: None,
+                        origin: ResponseOrigin::Authored,
+                        clarification: None,
+                        blocked: None,
+                        dispatched: 0,
+                        error: true,
+                    });
+                    commands.trigger(crate::sanitize::PlayerInputRejected {
+                        entity: req.entity,
+                        request_id: req.id,
+                        reason,
+                    });
+                    continue;
+                }
+            }
+        }
+        // The player's answer to a pending question becomes the follow-up typed request. Text
+        // sent by the game itself, e.g. barks or scheduled turns, does not answer it.
+        if req.player_text
+            && let Ok((_, Some(pending))) = query.get(req.entity)
+            && let DialogueRequestKind::Text { message, .. } = &req.kind
+        {
+            let answer = if delimit_player_text {
+                sanitizer.delimit(message)
+            } else {
+                message.clone()
+            };
+            req.kind = pending.follow_up(&answer);
+            delimit_player_text = false;
+            commands.entity(req.entity).remove::<PendingClarification>();
+        }
+        // Record the prompt on the requester's transcript, if it keeps one
+        if !req.kind.is_data()
+            && let Ok(mut transcript) = transcripts.get_mut(req.entity)
+        {
+            transcript.push_user(req.id, req.kind.as_user_message());
+        }
+
         // If receiver has a preprogrammed response, short-circuit and send directly
-        if let Ok(receiver) = query.get(req.entity) {
+        if let Ok((receiver, _)) = query.get(req.entity) {
             if let Some(pre) = &receiver.preprogrammed {
                 let _ = ai_handle.tx.send(DialogueResponse {
+                    request_id: req.id,
                     entity: req.entity,
                     response: pre.clone(),
                     kind: req.kind.clone(),
-                    actions: None,
+                    actions: Some(parse_response_actions(pre, &req.kind)),
+                    oversized: None,
+                    origin: ResponseOrigin::Authored,
+                    clarification: None,
+                    blocked: None,
+                    dispatched: 0,
+                    error: false,
                 });
                 continue;
             }
         }
 
-        // Signal an on-demand gather for the requester only if the request needs context,
-        // there are context-gathering systems registered, and the entity doesn't already have
-        // collected context.
-        if req.kind.include_context() {
-            if let (Some(gr), Some(store)) = (gather_req.as_mut(), gather_store.as_ref()) {
-                if !store.systems().is_empty() {
-                    // Avoid re-gathering if the entity already has an `AiContext` component
-                    if ctx_query.get(req.entity).is_err() {
-                        gr.request(req.entity);
-                    }
-                }
-            }
+        // Build message vector: include a marker message to suppress the
+        // backend's default system context if the request opted out of context or the
+        // global system prompt replaces it.
+        let global = settings
+            .global_prompt
+            .as_ref()
+            .and_then(|g| g.text.as_deref());
+        let ctx = ctx_query.get(req.entity).ok().map(|(ctx, _)| ctx);
+        // Text messages are `Arc<str>`, so copying the entity's context only bumps refcounts.
+        let mut messages: Vec<AiMessage> =
+            Vec::with_capacity(5 + ctx.map_or(0, |c| c.messages().len()));
+        if !req.kind.include_context() || global.is_some() {
+            messages.push(crate::rag::AiMessage::skip_default_context());
         }
-
-        // Build message vector: include a sentinel System message to suppress the
-        // default system context if the request opted out of context.
-        let mut messages: Vec<AiMessage> = Vec::new();
-        if !req.kind.include_context() {
-            messages.push(crate::rag::AiMessage::no_default_system_context());
+        if req.kind.include_context()
+            && let Some(text) = global
+        {
+            messages.push(AiMessage::system(text));
         }
-        if let Ok(ctx) = ctx_query.get(req.entity) {
+        // The persona is context too: requests without context are answered out of character
+        if req.kind.include_context()
+            && let Ok(persona) = personas.get(req.entity)
+        {
+            messages.push(persona.to_message());
+        }
+        // What was said before the backend was swapped
+        if req.kind.include_context()
+            && let Ok(recap) = recaps.get(req.entity)
+        {
+            messages.push(recap.to_message());
+        }
+        let context_start = messages.len();
+        if let Some(ctx) = ctx {
             // Include gathered context only when the request indicates it should be included.
             if req.kind.include_context() {
                 messages.extend_from_slice(ctx.messages());
+                if let Some(snapshots) = settings.snapshots.as_mut() {
+                    snapshots.prompt_sent(req.entity);
+                }
             }
         }
+        let context_range = context_start..messages.len();
         // Add the user message from the request kind
-        messages.push(AiMessage::user(req.kind.as_user_message()));
+        if delimit_player_text {
+            messages.push(AiMessage::user(
+                sanitizer.delimit(req.kind.as_user_message()),
+            ));
+        } else {
+            messages.push(AiMessage::user(req.kind.as_user_message()));
+        }
+
+        if let Some(log) = settings.debug_log.as_mut() {
+            log.record_prompt(req.entity, req.id, messages.clone(), &*tokenizer.0);
+        }
+        if let Some(sink) = &settings.log_sink {
+            sink.log(
+                req.entity,
+                Some(req.id),
+                crate::log_sink::AiLogEvent::Prompt {
+                    messages: crate::log_sink::logged_messages(&messages, context_range),
+                },
+            );
+        }
 
         // Call backend on a background task and send result to the response channel
         let backend = backend.clone();
         let tx = ai_handle.tx.clone();
-        let msgs = messages.clone();
+        let msgs = messages;
         let entity = req.entity;
+        let request_id = req.id;
         let kind = req.kind.clone();
+        let limit = settings
+            .response_limit
+            .as_deref()
+            .copied()
+            .unwrap_or_default();
+        let tokenizer = tokenizer.clone();
+        let translation = settings
+            .locale
+            .as_deref()
+            .and_then(|locale| locale.translation(settings.templates.as_deref()));
+        let moderation = settings
+            .moderation
+            .as_deref()
+            .filter(|m| !m.filters.is_empty())
+            .cloned();
+        let lenient = settings.lenient_json.as_deref().copied();
+        // Actions must not run before moderation checked their reply
+        let mut early = settings
+            .early_actions
+            .as_deref()
+            .filter(|_| moderation.is_none() && matches!(kind, DialogueRequestKind::Text { .. }))
+            .map(|early| crate::streaming::EarlyDispatch::new(early, entity, kind.clone()));
+        if let Some(status) = status.as_mut() {
+            status.request_sent(request_id, entity);
+        }
 
         crate::models::TOKIO_RUNTIME.spawn(async move {
+            let mut oversized = None;
+            let mut clarification = None;
+            let mut early_dispatched = 0;
+            // Set when the reply is replaced with an error message
+            let mut failed = false;
             // Compute both the textual response and any pre-parsed actions for typed requests
-            let (result, actions_opt) = match &kind {
+            let (mut result, mut actions_opt) = match &kind {
                 DialogueRequestKind::Text { .. } => {
-                    let r = backend
-                        .prompt(&msgs)
-                        .unwrap_or_else(|e| format!("(ai error: {})", e));
-                    (r, None)
+                    let r = match early.as_mut() {
+                        Some(early) => {
+                            backend.prompt_streaming(&msgs, &mut |piece| early.push(piece))
+                        }
+                        None => backend.prompt(&msgs),
+                    }
+                    .unwrap_or_else(|e| {
+                        failed = true;
+                        format!("(ai error: {})", e)
+                    });
+                    let (r, over) = limit.apply(r, true, &*tokenizer.0);
+                    failed |= over.is_some_and(|o| !o.truncated);
+                    oversized = over;
+                    // Parse any JSON actions here so big replies don't stall the frame
+                    let mut actions = text_reply_actions(&r, &kind, lenient);
+                    if let Some(early) = &early {
+                        actions = early.finish(actions);
+                        early_dispatched = early.actions.len();
+                    }
+                    // Only text for the player is translated, before the limit and moderation
+                    // check what the player reads
+                    let r = match &translation {
+                        Some(translation) if !failed => {
+                            let (r, over) = translate_reply(
+                                translation,
+                                &*backend,
+                                request_id,
+                                r,
+                                &limit,
+                                &*tokenizer.0,
+                            );
+                            failed |= over.is_some_and(|o| !o.truncated);
+                            oversized = over.or(oversized);
+                            r
+                        }
+                        _ => r,
+                    };
+                    (r, Some(actions))
                 }
                 DialogueRequestKind::Typed {
                     schema_description,
@@ -643,104 +1943,353 @@ fn handle_dialogue_requests(
                     ..
                 } => match backend.prompt_typed(&msgs, None, schema_description) {
                     Ok((val, _)) => {
-                        let mut actions: Vec<ActionPayload> = Vec::new();
-                        match &val {
-                            serde_json::Value::Object(map) => {
-                                actions.push(crate::actions::ActionPayload {
-                                    name: action_name.clone(),
-                                    params: serde_json::Value::Object(map.clone()),
-                                });
-                            }
-                            serde_json::Value::Array(arr) => {
-                                for v in arr.iter().cloned() {
-                                    actions.push(crate::actions::ActionPayload {
-                                        name: action_name.clone(),
-                                        params: v,
-                                    });
-                                }
+                        // The model may ask for missing information instead of guessing
+                        if let Some(question) = NeedsClarification::from_value(&val) {
+                            let text = question.question.clone();
+                            clarification = Some(question);
+                            (text, Some(Vec::new()))
+                        } else {
+                            let s = serde_json::to_string(&val).unwrap_or_else(|_| {
+                                failed = true;
+                                "(ai error: failed to serialize typed response)".to_string()
+                            });
+                            let (s, over) = limit.apply(s, false, &*tokenizer.0);
+                            if over.is_some() {
+                                failed = true;
+                                oversized = over;
+                                (s, Some(Vec::new()))
+                            } else {
+                                let actions = actions_from_value(val, &kind);
+                                (s, Some(actions))
                             }
-                            _ => {}
                         }
+                    }
+                    Err(e) => {
+                        failed = true;
+                        (format!("(ai error: {})", e), None)
+                    }
+                },
+                DialogueRequestKind::Data {
+                    schema_description, ..
+                } => match backend.prompt_typed(&msgs, None, schema_description) {
+                    Ok((val, _)) => {
                         let s = serde_json::to_string(&val).unwrap_or_else(|_| {
+                            failed = true;
                             "(ai error: failed to serialize typed response)".to_string()
                         });
-                        (s, Some(actions))
+                        let (s, over) = limit.apply(s, false, &*tokenizer.0);
+                        failed |= over.is_some();
+                        oversized = over;
+                        (s, Some(Vec::new()))
+                    }
+                    Err(e) => {
+                        failed = true;
+                        (format!("(ai error: {})", e), Some(Vec::new()))
+                    }
+                },
+                DialogueRequestKind::Classify {
+                    labels,
+                    action_name,
+                    ..
+                } => match backend
+                    .prompt(&msgs)
+                    .map(|text| limit.apply(text, true, &*tokenizer.0))
+                {
+                    Ok((text, Some(over))) if !over.truncated => {
+                        failed = true;
+                        oversized = Some(over);
+                        (text, Some(Vec::new()))
+                    }
+                    Ok((text, over)) => {
+                        oversized = over;
+                        let label_refs: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+                        match crate::parse::match_label(&text, &label_refs) {
+                            Some(label) => (
+                                label.to_string(),
+                                Some(vec![ActionPayload {
+                                    name: action_name.clone(),
+                                    params: serde_json::Value::String(label.to_string()),
+                                }]),
+                            ),
+                            None => {
+                                failed = true;
+                                (
+                                    format!(
+                                        "(ai error: no known label in response: {})",
+                                        text.trim()
+                                    ),
+                                    Some(Vec::new()),
+                                )
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        failed = true;
+                        (format!("(ai error: {})", e), Some(Vec::new()))
                     }
-                    Err(e) => (format!("(ai error: {})", e), None),
                 },
             };
 
+            let blocked = moderation.filter(|_| !failed).and_then(|moderation| {
+                moderation.apply(&*backend, &kind, &mut result, &mut actions_opt)
+            });
+            if blocked.is_some() {
+                clarification = None;
+                // Blocked text keeps a replacement line; everything else becomes an error
+                failed |= !matches!(kind, DialogueRequestKind::Text { .. });
+            }
+
             let _ = tx
                 .send_async(DialogueResponse {
+                    request_id,
                     entity,
                     response: result,
                     kind,
                     actions: actions_opt,
+                    oversized,
+                    origin: ResponseOrigin::Generated,
+                    clarification,
+                    blocked,
+                    dispatched: early_dispatched,
+                    error: failed,
                 })
                 .await;
         });
     }
+    queue.requeue_front(deferred);
+    gathered.retain(|id| queue.iter().any(|request| request.id == *id));
+
+    if let Some(usage) = usage.as_mut() {
+        usage.requests = dispatched;
+        usage.queued_requests = queue.len();
+    }
+}
+
+/// Actions in a text reply, retried with [`LenientJson`](crate::parse::LenientJson) when
+/// none parse strictly.
+pub(crate) fn text_reply_actions(
+    text: &str,
+    kind: &DialogueRequestKind,
+    lenient: Option<crate::parse::LenientJson>,
+) -> Vec<ActionPayload> {
+    let actions = parse_response_actions(text, kind);
+    if !actions.is_empty() {
+        return actions;
+    }
+    let Some(lenient) = lenient else {
+        return actions;
+    };
+    // Scanned like `extract_and_parse_json`, so text around the JSON is skipped
+    crate::parse::find_lenient_values(text, &lenient)
+        .into_iter()
+        .map(|value| actions_from_value(value, kind))
+        .find(|actions| !actions.is_empty())
+        .unwrap_or(actions)
+}
+
+/// Translate a text reply for the player and enforce `limit` on the translation. Keeps the
+/// original reply when translating fails.
+pub(crate) fn translate_reply(
+    translation: &crate::locale::Translation,
+    backend: &dyn LocalAi,
+    request_id: u64,
+    text: String,
+    limit: &AiResponseLimit,
+    tokenizer: &dyn crate::tokenizer::Tokenizer,
+) -> (String, Option<ResponseOversize>) {
+    match translation.translate(backend, &text) {
+        Ok(translated) => limit.apply(translated, true, tokenizer),
+        Err(e) => {
+            warn!("Keeping untranslated reply {}: {}", request_id, e);
+            (text, None)
+        }
+    }
+}
+
+/// Interpret a parsed JSON reply as actions. Typed requests wrap each object with the
+/// requested action name; other requests expect `{"name": ..., "params": ...}` objects.
+pub(crate) fn actions_from_value(
+    value: serde_json::Value,
+    kind: &DialogueRequestKind,
+) -> Vec<ActionPayload> {
+    let typed_name = match kind {
+        DialogueRequestKind::Typed { action_name, .. } => Some(action_name),
+        _ => None,
+    };
+    let items = match value {
+        serde_json::Value::Array(arr) => arr,
+        v @ serde_json::Value::Object(_) => vec![v],
+        _ => return Vec::new(),
+    };
+    items
+        .into_iter()
+        .filter_map(|v| match typed_name {
+            Some(name) => Some(ActionPayload {
+                name: name.clone(),
+                params: v,
+            }),
+            None => crate::actions::value_to_action(v),
+        })
+        .collect()
+}
+
+/// Parse the actions contained in a reply, or none if the reply is not JSON.
+///
+/// Runs on the background task for generated replies; exposed for code that builds
+/// `DialogueResponse`s itself.
+pub fn parse_response_actions(response: &str, kind: &DialogueRequestKind) -> Vec<ActionPayload> {
+    serde_json::from_str::<serde_json::Value>(response)
+        .map(|v| actions_from_value(v, kind))
+        .unwrap_or_default()
+}
+
+/// Stop tracking requests whose entity was despawned; their replies are not waited for.
+fn forget_despawned_requests(
+    mut queue: ResMut<DialogueRequestQueue>,
+    status: Option<ResMut<crate::status::AiPipelineStatus>>,
+    entities: Query<()>,
+) {
+    if queue
+        .in_flight
+        .iter()
+        .any(|r| entities.get(r.entity).is_err())
+    {
+        queue.retain_in_flight(|request| entities.get(request.entity).is_ok());
+    }
+    if let Some(mut status) = status
+        && status
+            .in_flight
+            .iter()
+            .any(|r| entities.get(r.entity).is_err())
+    {
+        status
+            .in_flight
+            .retain(|request| entities.get(request.entity).is_ok());
+    }
+}
+
+/// A throttled request is never answered.
+fn forget_throttled_request(
+    throttled: On<crate::rate_limit::AiRequestThrottled>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    status: Option<ResMut<crate::status::AiPipelineStatus>>,
+) {
+    queue.clear_in_flight(throttled.request_id);
+    if let Some(mut status) = status {
+        status.request_lost(throttled.request_id);
+    }
 }
 
-/// Poll channel and apply responses to receivers.
+/// Poll channel and apply responses to receivers, at most `AiFrameBudget::max_responses` per frame.
+#[allow(clippy::too_many_arguments)]
 fn poll_responses_receiver(
     mut query: Query<&mut DialogueReceiver>,
+    mut transcripts: Query<&mut crate::transcript::Transcript>,
     ai_handle: Res<LocalAiHandle>,
     mut pending: Option<ResMut<crate::actions::PendingAiActions>>,
     mut commands: Commands,
+    budget: Option<Res<crate::budget::AiFrameBudget>>,
+    usage: Option<ResMut<crate::budget::AiFrameUsage>>,
+    attribution: Option<Res<crate::attribution::AiAttribution>>,
+    mut debug_log: Option<ResMut<crate::inspect::AiDebugLog>>,
+    tokenizer: Option<Res<crate::tokenizer::AiTokenizer>>,
+    log_sink: Option<Res<crate::log_sink::AiLogSink>>,
+    mut status: Option<ResMut<crate::status::AiPipelineStatus>>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    processors: Option<Res<crate::postprocess::AiResponseProcessors>>,
+    early: Option<Res<crate::streaming::AiEarlyActions>>,
 ) {
-    // Drain all available responses without blocking
-    while let Ok(resp) = ai_handle.rx.try_recv() {
+    // Reply text as stored, after the response processors
+    let cleaned = |resp: &DialogueResponse| {
+        let mut text = resp.response.trim().to_string();
+        if let Some(processors) = &processors {
+            processors.apply(&mut text, resp);
+        }
+        text
+    };
+    let tag_receivers = attribution.is_some_and(|a| a.tag_receivers);
+    let max_responses = budget.map_or(usize::MAX, |b| b.max_responses);
+    let mut applied = 0;
+    // Drain available responses without blocking; the rest wait for the next frame
+    for resp in ai_handle.rx.try_iter().take(max_responses) {
+        // Actions sent early by this reply's request are queued before the rest of its actions
+        if let Some(early) = &early {
+            early.dispatch(pending.as_deref_mut(), &mut commands);
+        }
+        applied += 1;
+        queue.clear_in_flight(resp.request_id);
+        if let Some(status) = status.as_mut() {
+            status.response_received(resp.request_id, resp.entity, &resp.response);
+        }
+        if let Some(blocked) = &resp.blocked {
+            warn!(
+                "Blocked reply {} of {:?}: {}",
+                resp.request_id, resp.entity, blocked.reason
+            );
+            commands.trigger(crate::moderation::AiOutputBlocked {
+                entity: resp.entity,
+                request_id: resp.request_id,
+                text: blocked.text.clone(),
+                reason: blocked.reason.clone(),
+            });
+        }
+        let is_data = resp.kind.is_data();
+        if is_data {
+            let reply = if resp.error {
+                Err(AiError::Backend(resp.response.clone()))
+            } else {
+                Ok(cleaned(&resp))
+            };
+            let (request_id, entity) = (resp.request_id, resp.entity);
+            commands.queue(move |world: &mut World| {
+                deliver_typed(world, request_id, entity, reply);
+            });
+        }
         if let Ok(mut receiver) = query.get_mut(resp.entity) {
-            // Prefer any pre-parsed actions provided on the response (set for typed requests), otherwise try to interpret the response text as JSON actions.
-            let mut actions: Vec<ActionPayload> = Vec::new();
-
-            if let Some(pre) = resp.actions.clone() {
-                actions = pre;
-            } else if let Ok(json_val) = serde_json::from_str::<serde_json::Value>(&resp.response) {
-                match json_val {
-                    serde_json::Value::Object(map) => {
-                        match &resp.kind {
-                            DialogueRequestKind::Typed { action_name, .. } => {
-                                // Wrap the object as an action with the typed action name
-                                actions.push(crate::actions::ActionPayload {
-                                    name: action_name.clone(),
-                                    params: serde_json::Value::Object(map),
-                                });
-                            }
-                            _ => {
-                                if let Some(serde_json::Value::String(_)) = map.get("name") {
-                                    if let Some(action) = crate::actions::value_to_action(
-                                        serde_json::Value::Object(map),
-                                    ) {
-                                        actions.push(action);
-                                    }
-                                }
-                            }
-                        }
+            if !is_data
+                && receiver.is_stale(resp.request_id)
+                && receiver.response_policy != ResponsePolicy::AcceptAll
+            {
+                let latest_request_id = receiver.latest_request_id.unwrap_or_default();
+                debug!(
+                    "Stale response {} for {:?} (latest request {})",
+                    resp.request_id, resp.entity, latest_request_id
+                );
+                let text = cleaned(&resp);
+                commands.trigger(AiStaleResponse {
+                    entity: resp.entity,
+                    request_id: resp.request_id,
+                    latest_request_id,
+                    kind: resp.kind.clone(),
+                    text: text.clone(),
+                });
+                if receiver.response_policy == ResponsePolicy::FlagStale {
+                    if let Ok(mut transcript) = transcripts.get_mut(resp.entity) {
+                        transcript.push_assistant(resp.request_id, &text);
                     }
-                    serde_json::Value::Array(arr) => match &resp.kind {
-                        DialogueRequestKind::Typed { action_name, .. } => {
-                            for v in arr.into_iter() {
-                                actions.push(crate::actions::ActionPayload {
-                                    name: action_name.clone(),
-                                    params: v,
-                                });
-                            }
-                        }
-                        _ => {
-                            for v in arr.into_iter() {
-                                if let Some(action) = crate::actions::value_to_action(v) {
-                                    actions.push(action);
-                                }
-                            }
-                        }
-                    },
-                    _ => {}
+                    commands.trigger(AiResponseEvent {
+                        entity: resp.entity,
+                        request_id: resp.request_id,
+                        text,
+                        kind: resp.kind.clone(),
+                        actions: Vec::new(),
+                        origin: resp.origin,
+                        clarification: None,
+                        stale: true,
+                        error: resp.error,
+                    });
                 }
+                continue;
             }
 
-            for action in actions.iter() {
+            // Actions are parsed by the background task; only responses pushed onto the channel
+            // by other code (without `actions`) are parsed here.
+            let actions = match resp.actions.clone() {
+                Some(pre) => pre,
+                None => parse_response_actions(&resp.response, &resp.kind),
+            };
+
+            for action in actions.iter().skip(resp.dispatched) {
                 let event = AiActionEvent {
                     entity: resp.entity,
                     action: action.clone(),
@@ -761,19 +2310,136 @@ fn poll_responses_receiver(
                 commands.trigger(event);
             }
 
-            // Store parsed actions
-            receiver.actions = actions;
+            if let Some(oversize) = resp.oversized {
+                warn!(
+                    "Response {} for {:?} was {} bytes (limit {}), {}",
+                    resp.request_id,
+                    resp.entity,
+                    oversize.bytes,
+                    oversize.limit,
+                    if oversize.truncated {
+                        "truncated"
+                    } else {
+                        "rejected"
+                    }
+                );
+                commands.trigger(AiResponseOversized {
+                    entity: resp.entity,
+                    request_id: resp.request_id,
+                    oversize,
+                });
+            }
+
+            if let Some(log) = debug_log.as_mut() {
+                let tokenizer = tokenizer.as_deref().cloned().unwrap_or_default();
+                log.record_response(
+                    resp.entity,
+                    resp.request_id,
+                    &resp.response,
+                    &actions,
+                    &*tokenizer.0,
+                );
+            }
 
-            receiver.last_response = Some(resp.response.trim().to_string());
+            if let Some(sink) = &log_sink {
+                use crate::log_sink::AiLogEvent;
+                let error = if resp.error {
+                    Some(resp.response.clone())
+                } else {
+                    resp.oversized.as_ref().map(|o| {
+                        format!(
+                            "reply of {} bytes exceeded the limit of {}",
+                            o.bytes, o.limit
+                        )
+                    })
+                };
+                sink.log(
+                    resp.entity,
+                    Some(resp.request_id),
+                    AiLogEvent::Response {
+                        raw: resp.response.clone(),
+                        origin: resp.origin,
+                    },
+                );
+                sink.log(
+                    resp.entity,
+                    Some(resp.request_id),
+                    AiLogEvent::Parsed {
+                        actions: actions.clone(),
+                        error,
+                    },
+                );
+            }
+
+            if let Some(question) = &resp.clarification {
+                commands.entity(resp.entity).insert(PendingClarification {
+                    request_id: resp.request_id,
+                    question: question.question.clone(),
+                    kind: resp.kind.clone(),
+                });
+                commands.trigger(AiClarificationRequested {
+                    entity: resp.entity,
+                    request_id: resp.request_id,
+                    question: question.question.clone(),
+                });
+            }
+
+            let text = cleaned(&resp);
+            if !is_data && let Ok(mut transcript) = transcripts.get_mut(resp.entity) {
+                transcript.push_assistant(resp.request_id, &text);
+            }
+            commands.trigger(AiResponseEvent {
+                entity: resp.entity,
+                request_id: resp.request_id,
+                text: text.clone(),
+                kind: resp.kind.clone(),
+                actions: actions.clone(),
+                origin: resp.origin,
+                clarification: resp.clarification.clone(),
+                stale: false,
+                error: resp.error,
+            });
+
+            // Data replies go to their requester, not the receiver
+            if !is_data {
+                receiver.actions = actions;
+                receiver.last_response = Some(text);
+                receiver.last_request_id = Some(resp.request_id);
+                receiver.last_origin = Some(resp.origin);
+            }
+
+            if tag_receivers {
+                if resp.origin.is_generated() {
+                    commands
+                        .entity(resp.entity)
+                        .insert(crate::attribution::AiGenerated {
+                            source: resp.entity,
+                            request_id: resp.request_id,
+                        });
+                } else {
+                    commands
+                        .entity(resp.entity)
+                        .remove::<crate::attribution::AiGenerated>();
+                }
+            }
         }
     }
+    // Actions of replies still being generated
+    if let Some(early) = &early {
+        early.dispatch(pending.as_deref_mut(), &mut commands);
+    }
+
+    if let Some(mut usage) = usage {
+        usage.responses = applied;
+        usage.queued_responses = ai_handle.rx.len();
+    }
 }
 
 /// A very small mock AI backend used by default and for tests.
 pub struct MockAi {}
 
 impl LocalAi for MockAi {
-    fn prompt(&self, messages: &[AiMessage]) -> Result<String, String> {
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
         // Return the first user message content when present, else debug-join messages.
         for m in messages.iter() {
             let dbg = format!("{:?}", m);
@@ -793,3 +2459,98 @@ impl LocalAi for MockAi {
         Ok(format!("mock: {}", combined))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearing_pending_loads_aborts_the_task() {
+        let (_tx, result_receiver) = crossbeam_channel::unbounded();
+        let task = crate::models::TOKIO_RUNTIME.spawn(std::future::pending::<()>());
+        let mut pending = PendingModelLoads::default();
+        pending.loaders.push(PendingModelLoad {
+            model_name: "llama".to_string(),
+            result_receiver,
+            progress_receiver: None,
+            task: Some(task.abort_handle()),
+        });
+
+        assert!(!pending.cancel("phi"));
+        assert!(pending.cancel("llama"));
+        let result = crate::models::TOKIO_RUNTIME.block_on(task);
+        assert!(result.is_err_and(|e| e.is_cancelled()));
+    }
+
+    #[test]
+    fn swapped_backend_receives_a_recap_of_the_conversation() {
+        struct SystemEcho;
+        impl LocalAi for SystemEcho {
+            fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
+                let system: Vec<&str> = messages
+                    .iter()
+                    .filter_map(|m| match m {
+                        AiMessage::System(text) => Some(&**text),
+                        _ => None,
+                    })
+                    .collect();
+                Ok(system.join("\n"))
+            }
+        }
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(AIDialoguePlugin::default())
+            .add_plugins(crate::swap::AiModelSwapPlugin::default());
+        let npc = app
+            .world_mut()
+            .spawn((
+                DialogueReceiver::new(),
+                crate::transcript::Transcript::new(),
+            ))
+            .id();
+        crate::test_helpers::ask_ai_and_wait(&mut app, npc, "Where is the mine?", 50).unwrap();
+
+        let swap = |app: &mut App, model_name: &str| {
+            let (tx, result_receiver) = crossbeam_channel::unbounded();
+            tx.send(Ok(Arc::new(SystemEcho) as Arc<dyn LocalAi>))
+                .unwrap();
+            app.world_mut()
+                .resource_mut::<PendingModelLoads>()
+                .loaders
+                .push(PendingModelLoad {
+                    model_name: model_name.to_string(),
+                    result_receiver,
+                    progress_receiver: None,
+                    task: None,
+                });
+            app.update();
+        };
+        swap(&mut app, "larger");
+        assert!(
+            app.world()
+                .get::<crate::swap::ConversationRecap>(npc)
+                .is_some()
+        );
+
+        let reply = crate::test_helpers::ask_ai_and_wait(&mut app, npc, "And then?", 50).unwrap();
+        assert!(reply.contains("Earlier in this conversation"));
+        assert!(reply.contains("User: Where is the mine?"));
+
+        // The next swap recaps what was said since
+        swap(&mut app, "largest");
+        let recap = app.world().get::<crate::swap::ConversationRecap>(npc);
+        assert!(recap.unwrap().text.contains("User: And then?"));
+
+        // Nothing to replay: the old recap is dropped
+        app.world_mut()
+            .resource_mut::<crate::swap::AiModelSwapSettings>()
+            .replay_entries = 0;
+        swap(&mut app, "smallest");
+        assert!(
+            app.world()
+                .get::<crate::swap::ConversationRecap>(npc)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,638 @@
+//! Lore documents loaded as Bevy assets.
+//!
+//! `.lore.txt` and `.lore.md` files load as [`AiDocument`] assets, leaving plain `.txt` and
+//! `.md` files to other loaders. The [`AiDocumentPlugin`] loads every file under
+//! `assets/lore/` by default. Each loaded document is split into chunks by the
+//! [`DocumentStore::chunker`] (paragraphs and headings unless set, see [`crate::chunking`]),
+//! embedded with the [`AiEmbedder`] on a background thread, and stored in the
+//! [`DocumentStore`]. Documents whose embedding fails are tried again a few seconds later. When
+//! a file changes (with Bevy's `file_watcher` feature) its chunks are replaced, and they are
+//! dropped when the asset is removed. With [`AiDocumentPlugin::index_path`] set, the chunks are
+//! saved to that file, also in the background, and documents unchanged on the next launch are
+//! not embedded again.
+//!
+//! Requests that ask something of an NPC then get the chunks closest to the question in their
+//! context, so NPCs can answer from the lore. Documents may start with header lines:
+//!
+//! - `scope: boss_lore, sages` makes the document visible only to entities whose
+//!   [`KnowledgeScope`](crate::knowledge::KnowledgeScope) allows one of the tags;
+//! - `chunking: sentences 400` splits it with its own [`Chunker`].
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(DefaultPlugins)
+//!     .add_plugins(AIDialoguePlugin::default())
+//!     .add_plugins(AiRagPlugin::with_embedder(embedder))
+//!     .add_plugins(AiDocumentPlugin::default());
+//!
+//! // assets/lore/history.lore.md
+//! // # The Old War
+//! // The Old War ended when the river kings signed the Ash Treaty...
+//! ```
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetEventSystems, AssetLoader, LoadContext, LoadedFolder};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::bake::prompt_hash;
+use crate::chunking::Chunker;
+use crate::context::{AiEntity, AiSystemContextStore};
+use crate::embedding::AiEmbedder;
+use crate::knowledge::KnowledgeScope;
+use crate::rag::AiMessage;
+use crate::retrieval::{DEFAULT_KEYWORD_WEIGHT, TermCounts, hybrid_scores};
+use crate::vector_index::{read_index, write_index};
+
+/// A text document for the model to draw knowledge from.
+#[derive(Asset, TypePath, Debug, Clone, PartialEq)]
+pub struct AiDocument {
+    pub text: String,
+    /// Knowledge tags from a `scope:` header line.
+    pub scope: Vec<String>,
+    /// Chunker from a `chunking:` header line, instead of the store's.
+    pub chunker: Option<Chunker>,
+}
+
+impl AiDocument {
+    /// Read a document, taking leading `scope: a, b` and `chunking: ...` lines as its header.
+    pub fn parse(text: &str) -> Self {
+        let mut document = Self {
+            text: String::new(),
+            scope: Vec::new(),
+            chunker: None,
+        };
+        let mut body = text;
+        loop {
+            let (line, rest) = body.split_once('\n').unwrap_or((body, ""));
+            let line = line.trim();
+            if let Some(tags) = line.strip_prefix("scope:") {
+                document.scope = tags
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            } else if let Some(spec) = line.strip_prefix("chunking:") {
+                match Chunker::parse(spec) {
+                    Ok(chunker) => document.chunker = Some(chunker),
+                    Err(e) => warn!("Ignoring chunking header: {}", e),
+                }
+            } else {
+                break;
+            }
+            body = rest;
+        }
+        document.text = body.trim().to_string();
+        document
+    }
+
+    pub fn with_chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = Some(chunker);
+        self
+    }
+}
+
+/// Loads `.lore.txt` and `.lore.md` files as [`AiDocument`]s.
+#[derive(Default, TypePath)]
+pub struct AiDocumentLoader;
+
+impl AssetLoader for AiDocumentLoader {
+    type Asset = AiDocument;
+    type Settings = ();
+    type Error = std::io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<AiDocument, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text = String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(AiDocument::parse(&text))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lore.txt", "lore.md"]
+    }
+}
+
+/// A piece of a document, with its embedding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentChunk {
+    /// Asset path of the document.
+    pub source: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+    pub scope: Vec<String>,
+    /// Hash of the document and chunker the chunk came from.
+    pub hash: String,
+}
+
+/// A chunk returned by [`DocumentStore::search`], with its score for the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentMatch<'a> {
+    pub chunk: &'a DocumentChunk,
+    pub score: f32,
+}
+
+/// Resource indexing document chunks for retrieval.
+#[derive(Resource, Debug, Clone)]
+pub struct DocumentStore {
+    chunks: Vec<DocumentChunk>,
+    /// Word counts of each chunk, in the order of `chunks`.
+    terms: Vec<TermCounts>,
+    /// Chunks read by [`load_index`](Self::load_index), reused by unchanged documents.
+    saved: Vec<DocumentChunk>,
+    /// How documents without their own chunker are split.
+    pub chunker: Chunker,
+    /// How many chunks the gather system adds to the context.
+    pub recall_k: usize,
+    /// Chunks scoring lower than this are left out.
+    pub min_score: f32,
+    /// Share of keyword matching in the score, see [`crate::retrieval`].
+    pub keyword_weight: f32,
+}
+
+impl Default for DocumentStore {
+    fn default() -> Self {
+        Self {
+            chunks: Vec::new(),
+            terms: Vec::new(),
+            saved: Vec::new(),
+            chunker: Chunker::default(),
+            recall_k: 3,
+            min_score: 0.3,
+            keyword_weight: DEFAULT_KEYWORD_WEIGHT,
+        }
+    }
+}
+
+impl DocumentStore {
+    /// Chunk, embed and store `document` under `source`, replacing earlier chunks of the same
+    /// source. Returns the number of chunks stored.
+    ///
+    /// Documents already stored or in a loaded index with the same text, scope and chunker
+    /// are not embedded again.
+    pub fn insert(
+        &mut self,
+        embedder: &AiEmbedder,
+        source: impl Into<String>,
+        document: &AiDocument,
+    ) -> Result<usize, String> {
+        let source = source.into();
+        if let Some(count) = self.reuse(&source, document) {
+            return Ok(count);
+        }
+        let hash = self.hash_of(document);
+        let chunks = embed_document(embedder, &source, document, self.chunker, &hash)?;
+        Ok(self.replace(&source, chunks))
+    }
+
+    /// Hash of `document` split by its chunker, or the store's.
+    fn hash_of(&self, document: &AiDocument) -> String {
+        let chunker = document.chunker.unwrap_or(self.chunker);
+        prompt_hash(&format!(
+            "{:?}\n{:?}\n{}",
+            chunker, document.scope, document.text
+        ))
+    }
+
+    /// Keep or restore the chunks of `document` already stored or saved, without embedding.
+    /// Returns their number, `None` if it needs embedding.
+    fn reuse(&mut self, source: &str, document: &AiDocument) -> Option<usize> {
+        let hash = self.hash_of(document);
+        let same = |c: &DocumentChunk| c.source == source && c.hash == hash;
+        let current = self.chunks.iter().filter(|c| same(c)).count();
+        if current > 0 {
+            return Some(current);
+        }
+        let saved: Vec<DocumentChunk> = self.saved.iter().filter(|c| same(c)).cloned().collect();
+        (!saved.is_empty()).then(|| self.replace(source, saved))
+    }
+
+    /// Replace the chunks of `source`. Returns the number stored.
+    fn replace(&mut self, source: &str, chunks: Vec<DocumentChunk>) -> usize {
+        self.remove(source);
+        let count = chunks.len();
+        self.terms
+            .extend(chunks.iter().map(|c| TermCounts::new(&c.text)));
+        self.chunks.extend(chunks);
+        count
+    }
+
+    /// Write the stored chunks to `path`, see [`crate::vector_index`].
+    pub fn save_index(&self, path: impl AsRef<Path>, embedder: &AiEmbedder) -> Result<(), String> {
+        write_index(path, embedder, &self.chunks)
+    }
+
+    /// Read chunks saved by [`save_index`](Self::save_index) for reuse by
+    /// [`insert`](Self::insert). Fails, keeping nothing, if the file was written by another
+    /// embedder or format. Returns the number of chunks read.
+    pub fn load_index(
+        &mut self,
+        path: impl AsRef<Path>,
+        embedder: &AiEmbedder,
+    ) -> Result<usize, String> {
+        self.saved.clear();
+        self.saved = read_index(path, embedder)?;
+        Ok(self.saved.len())
+    }
+
+    /// Drop the chunks of `source`. Returns how many were dropped.
+    pub fn remove(&mut self, source: &str) -> usize {
+        let before = self.chunks.len();
+        (self.chunks, self.terms) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .zip(std::mem::take(&mut self.terms))
+            .filter(|(c, _)| c.source != source)
+            .unzip();
+        before - self.chunks.len()
+    }
+
+    /// The `k` chunks best matching `query` and its `embedding`, best first.
+    pub fn search(&self, query: &str, embedding: &[f32], k: usize) -> Vec<DocumentMatch<'_>> {
+        let candidates: Vec<(&TermCounts, &[f32])> = self
+            .terms
+            .iter()
+            .zip(&self.chunks)
+            .map(|(terms, c)| (terms, c.embedding.as_slice()))
+            .collect();
+        let scores = hybrid_scores(query, embedding, &candidates, self.keyword_weight);
+        let mut matches: Vec<DocumentMatch> = self
+            .chunks
+            .iter()
+            .zip(scores)
+            .map(|(chunk, score)| DocumentMatch { chunk, score })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(k);
+        matches
+    }
+
+    pub fn chunks(&self) -> &[DocumentChunk] {
+        &self.chunks
+    }
+
+    /// Distinct sources, sorted.
+    pub fn sources(&self) -> Vec<&str> {
+        let mut sources: Vec<&str> = self.chunks.iter().map(|c| c.source.as_str()).collect();
+        sources.sort_unstable();
+        sources.dedup();
+        sources
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+/// Chunk and embed `document`, splitting it with `chunker` unless it has its own.
+fn embed_document(
+    embedder: &AiEmbedder,
+    source: &str,
+    document: &AiDocument,
+    chunker: Chunker,
+    hash: &str,
+) -> Result<Vec<DocumentChunk>, String> {
+    let chunker = document.chunker.unwrap_or(chunker);
+    let texts = chunker.split(&document.text);
+    let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+    let embeddings = embedder.embed_many(&refs)?;
+    Ok(texts
+        .into_iter()
+        .zip(embeddings)
+        .map(|(text, embedding)| DocumentChunk {
+            source: source.to_string(),
+            text,
+            embedding,
+            scope: document.scope.clone(),
+            hash: hash.to_string(),
+        })
+        .collect())
+}
+
+/// Plugin loading and indexing lore documents, see the [module docs](self).
+pub struct AiDocumentPlugin {
+    /// Folder under `assets/` loaded on startup, `None` to load documents yourself.
+    pub folder: Option<String>,
+    /// Chunker for documents without a `chunking:` header.
+    pub chunker: Chunker,
+    pub recall_k: usize,
+    /// File the embedded chunks are saved to and reused from on the next launch.
+    pub index_path: Option<PathBuf>,
+}
+
+impl Default for AiDocumentPlugin {
+    fn default() -> Self {
+        Self {
+            folder: Some("lore".to_string()),
+            chunker: Chunker::default(),
+            recall_k: 3,
+            index_path: None,
+        }
+    }
+}
+
+/// Resource keeping the lore folder and its documents loaded; its load state tells when every
+/// document is in.
+#[derive(Resource, Debug, Clone)]
+pub struct LoreFolder(pub Handle<LoadedFolder>);
+
+/// How long a document whose embedding failed waits before it is tried again.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Outcome of work [`index_documents`] started on a background thread.
+enum IndexTask {
+    Read(Result<Vec<DocumentChunk>, String>),
+    Embedded {
+        id: AssetId<AiDocument>,
+        source: String,
+        hash: String,
+        chunks: Result<Vec<DocumentChunk>, String>,
+    },
+    Saved(Result<(), String>),
+}
+
+/// Documents to index once loaded and embeddable, and the source of every indexed one.
+#[derive(Resource)]
+struct DocumentIndexState {
+    pending: HashSet<AssetId<AiDocument>>,
+    /// Documents being embedded in the background.
+    embedding: HashSet<AssetId<AiDocument>>,
+    /// When documents whose embedding failed are tried again.
+    retry_at: HashMap<AssetId<AiDocument>, Duration>,
+    sources: HashMap<AssetId<AiDocument>, String>,
+    index_path: Option<PathBuf>,
+    /// Whether reading the saved index started, which waits for an embedder.
+    index_read: bool,
+    /// Whether the saved index is read; documents wait for it to reuse its chunks.
+    index_ready: bool,
+    /// Whether the store changed since the index was saved.
+    changed: bool,
+    saving: bool,
+    tx: flume::Sender<IndexTask>,
+    rx: flume::Receiver<IndexTask>,
+}
+
+impl Default for DocumentIndexState {
+    fn default() -> Self {
+        let (tx, rx) = flume::unbounded();
+        Self {
+            pending: HashSet::new(),
+            embedding: HashSet::new(),
+            retry_at: HashMap::new(),
+            sources: HashMap::new(),
+            index_path: None,
+            index_read: false,
+            index_ready: false,
+            changed: false,
+            saving: false,
+            tx,
+            rx,
+        }
+    }
+}
+
+impl DocumentIndexState {
+    /// Run `work` on a background thread, reporting its outcome to the next
+    /// [`index_documents`].
+    fn spawn(&self, work: impl FnOnce() -> IndexTask + Send + 'static) {
+        let tx = self.tx.clone();
+        crate::models::TOKIO_RUNTIME.spawn_blocking(move || {
+            let _ = tx.send(work());
+        });
+    }
+}
+
+impl Plugin for AiDocumentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AiDocument>()
+            .register_asset_loader(AiDocumentLoader)
+            .insert_resource(DocumentStore {
+                chunker: self.chunker,
+                recall_k: self.recall_k,
+                ..default()
+            })
+            .insert_resource(DocumentIndexState {
+                index_path: self.index_path.clone(),
+                ..default()
+            })
+            .add_systems(PostUpdate, index_documents.after(AssetEventSystems));
+        if let Some(folder) = self.folder.clone() {
+            app.add_systems(
+                Startup,
+                move |server: Res<AssetServer>, mut commands: Commands| {
+                    commands.insert_resource(LoreFolder(server.load_folder(folder.clone())));
+                },
+            );
+        }
+        app.world_mut()
+            .get_resource_or_init::<AiSystemContextStore>()
+            .add_system(gather_document_context);
+    }
+}
+
+/// Index added and changed documents, and drop removed ones. Embedding and reading or writing
+/// the index run in the background.
+fn index_documents(
+    mut events: MessageReader<AssetEvent<AiDocument>>,
+    documents: Res<Assets<AiDocument>>,
+    server: Res<AssetServer>,
+    embedder: Option<Res<AiEmbedder>>,
+    mut state: ResMut<DocumentIndexState>,
+    mut store: ResMut<DocumentStore>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed();
+    let finished: Vec<IndexTask> = state.rx.try_iter().collect();
+    for task in finished {
+        match task {
+            IndexTask::Read(read) => {
+                state.index_ready = true;
+                match read {
+                    Ok(chunks) => {
+                        info!("Read {} chunks of the document index", chunks.len());
+                        store.saved = chunks;
+                    }
+                    Err(e) => info!("Not reusing the document index: {}", e),
+                }
+            }
+            IndexTask::Embedded {
+                id,
+                source,
+                hash,
+                chunks,
+            } => {
+                state.embedding.remove(&id);
+                // Removed, or changed and queued again, while it was embedded
+                let current = documents.get(id).map(|document| store.hash_of(document));
+                let chunks = match chunks {
+                    Ok(chunks) => chunks,
+                    Err(e) => {
+                        warn!("Failed to index {}, trying again: {}", source, e);
+                        if current.is_some() {
+                            state.pending.insert(id);
+                            state.retry_at.insert(id, now + RETRY_DELAY);
+                        }
+                        continue;
+                    }
+                };
+                if current != Some(hash) {
+                    continue;
+                }
+                let count = store.replace(&source, chunks);
+                debug!("Indexed {} chunks of {}", count, source);
+                state.sources.insert(id, source);
+                state.changed = true;
+            }
+            IndexTask::Saved(saved) => {
+                state.saving = false;
+                if let Err(e) = saved {
+                    warn!("Failed to save the document index: {}", e);
+                }
+            }
+        }
+    }
+
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                state.pending.insert(*id);
+                state.retry_at.remove(id);
+            }
+            AssetEvent::Removed { id } => {
+                state.pending.remove(id);
+                state.retry_at.remove(id);
+                if let Some(source) = state.sources.remove(id) {
+                    store.remove(&source);
+                    state.changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    let Some(embedder) = embedder else {
+        return;
+    };
+    if !state.index_read {
+        state.index_read = true;
+        match state.index_path.clone() {
+            Some(path) => {
+                let embedder = embedder.clone();
+                state.spawn(move || IndexTask::Read(read_index(path, &embedder)));
+            }
+            None => state.index_ready = true,
+        }
+    }
+    if !state.index_ready {
+        return;
+    }
+    let ready: Vec<AssetId<AiDocument>> = state
+        .pending
+        .iter()
+        .filter(|id| !state.embedding.contains(id))
+        .filter(|id| state.retry_at.get(id).is_none_or(|at| now >= *at))
+        .copied()
+        .collect();
+    for id in ready {
+        state.pending.remove(&id);
+        state.retry_at.remove(&id);
+        let Some(document) = documents.get(id) else {
+            continue;
+        };
+        let source = server
+            .get_path(id)
+            .map_or_else(|| id.to_string(), |path| path.to_string());
+        if let Some(count) = store.reuse(&source, document) {
+            debug!("Reused {} chunks of {}", count, source);
+            state.sources.insert(id, source);
+            state.changed = true;
+            continue;
+        }
+        state.embedding.insert(id);
+        let hash = store.hash_of(document);
+        let (embedder, document, chunker) = (embedder.clone(), document.clone(), store.chunker);
+        state.spawn(move || IndexTask::Embedded {
+            chunks: embed_document(&embedder, &source, &document, chunker, &hash),
+            id,
+            source,
+            hash,
+        });
+    }
+    // Saved once the documents in flight are in, one write at a time
+    if state.changed
+        && !state.saving
+        && state.embedding.is_empty()
+        && let Some(path) = state.index_path.clone()
+    {
+        state.changed = false;
+        state.saving = true;
+        let (embedder, chunks) = (embedder.clone(), store.chunks.clone());
+        state.spawn(move || IndexTask::Saved(write_index(path, &embedder, &chunks)));
+    }
+}
+
+/// Context-gathering system adding the document chunks closest to the current prompt.
+pub fn gather_document_context(
+    ai_entity: AiEntity,
+    store: Option<Res<DocumentStore>>,
+    embedder: Option<Res<AiEmbedder>>,
+    scopes: Query<&KnowledgeScope>,
+) -> Option<AiMessage> {
+    let (store, embedder, query) = (store?, embedder?, ai_entity.query()?);
+    if store.is_empty() || store.recall_k == 0 {
+        return None;
+    }
+    let embedding = match embedder.embed(query) {
+        Ok(embedding) => embedding,
+        Err(e) => {
+            warn!("Document search failed: {}", e);
+            return None;
+        }
+    };
+    let scope = scopes.get(ai_entity.entity()).ok();
+    let texts: Vec<&str> = store
+        .search(query, &embedding, store.len())
+        .into_iter()
+        .filter(|m| m.score >= store.min_score && crate::knowledge::visible(scope, &m.chunk.scope))
+        .take(store.recall_k)
+        .map(|m| m.chunk.text.as_str())
+        .collect();
+    if texts.is_empty() {
+        return None;
+    }
+    Some(AiMessage::system(format!(
+        "What you know:\n- {}",
+        texts.join("\n- ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_headers_set_scope_and_chunker() {
+        let doc = AiDocument::parse(
+            "scope: boss_lore, sages\nchunking: sentences 20\n# The Lich\nIt fears silver. It sleeps by day.",
+        );
+        assert_eq!(doc.scope, ["boss_lore", "sages"]);
+        assert_eq!(doc.chunker, Some(Chunker::Sentences { max_chars: 20 }));
+        assert_eq!(doc.text, "# The Lich\nIt fears silver. It sleeps by day.");
+
+        let plain = AiDocument::parse("No header here.\nscope: not a header");
+        assert!(plain.scope.is_empty() && plain.chunker.is_none());
+        assert_eq!(plain.text, "No header here.\nscope: not a header");
+    }
+}
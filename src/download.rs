@@ -0,0 +1,277 @@
+//! Resumable, checksum-verified downloads of Hugging Face model files.
+//!
+//! Files are first written to `<file>.part`. When a download is interrupted, the next attempt
+//! (or the next launch) asks the server for the remaining bytes with an HTTP range request
+//! instead of starting over. Once complete, the file is checked against the expected SHA-256
+//! (reported as [`DownloadState::Verifying`]) and only then renamed to its final name.
+
+use bevy::log::warn;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::error::AiError;
+use crate::models::{AiModelBuilder, DownloadState, ModelDownloadProgress};
+
+/// Attempts per file before giving up; each retry resumes where the last one stopped.
+const MAX_ATTEMPTS: u32 = 5;
+
+type ProgressSender = crossbeam_channel::Sender<ModelDownloadProgress>;
+
+/// Where a Hugging Face file is stored in the model cache.
+pub(crate) fn cached_path(cache_dir: &Path, model_id: &str, revision: &str, file: &str) -> PathBuf {
+    cache_dir.join(model_id).join(revision).join(file)
+}
+
+/// Path of the partial download of `dest`.
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Download `model_id/file` at `revision` into `dest` unless it is already there, resuming a
+/// partial download and verifying `sha256` (hex) when given.
+pub(crate) async fn download_model_file(
+    model_id: &str,
+    revision: &str,
+    file: &str,
+    dest: &Path,
+    sha256: Option<&str>,
+    progress: Option<&ProgressSender>,
+) -> Result<PathBuf, AiError> {
+    if dest.is_file() {
+        let Some(expected) = sha256 else {
+            return Ok(dest.to_path_buf());
+        };
+        report(progress, DownloadState::Verifying, file, None);
+        match verify(dest, expected).await {
+            Ok(()) => return Ok(dest.to_path_buf()),
+            // A corrupted cache entry: fetch it again
+            Err(e) => {
+                warn!("Downloading {} again: {}", file, e);
+                let _ = tokio::fs::remove_file(dest).await;
+            }
+        }
+    }
+    if let Some(dir) = dest.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| AiError::ModelLoad(format!("Cannot create {}: {}", dir.display(), e)))?;
+    }
+
+    let url = format!(
+        "https://huggingface.co/{}/resolve/{}/{}",
+        model_id, revision, file
+    );
+    let part = part_path(dest);
+    let client = reqwest::Client::new();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match fetch(&client, &url, &part, file, progress).await {
+            Ok(()) => break,
+            Err(e) if e.is_retryable() && attempt < MAX_ATTEMPTS => {
+                report(
+                    progress,
+                    DownloadState::InProgress,
+                    &format!("{} (retrying: {})", file, e),
+                    None,
+                );
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Some(expected) = sha256 {
+        report(progress, DownloadState::Verifying, file, None);
+        if let Err(e) = verify(&part, expected).await {
+            let _ = tokio::fs::remove_file(&part).await;
+            return Err(e);
+        }
+    }
+    tokio::fs::rename(&part, dest)
+        .await
+        .map_err(|e| AiError::ModelLoad(format!("Cannot move {}: {}", part.display(), e)))?;
+    report(progress, DownloadState::Completed, file, Some(100.0));
+    Ok(dest.to_path_buf())
+}
+
+/// Fetch the rest of `url` into `part`, starting at its current length.
+async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    label: &str,
+    progress: Option<&ProgressSender>,
+) -> Result<(), AiError> {
+    let offset = tokio::fs::metadata(part).await.map_or(0, |m| m.len());
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header("Range", format!("bytes={}-", offset));
+    }
+    if let Ok(token) = std::env::var("HF_TOKEN") {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| AiError::Network(format!("Download of {} failed: {}", url, e)))?;
+
+    let status = response.status();
+    let append = match status {
+        reqwest::StatusCode::PARTIAL_CONTENT => true,
+        reqwest::StatusCode::OK => false,
+        // The partial file already holds every byte
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        _ if status.is_server_error() => {
+            return Err(AiError::Network(format!("HTTP {} from {}", status, url)));
+        }
+        _ => {
+            return Err(AiError::ModelLoad(format!("HTTP {} from {}", status, url)));
+        }
+    };
+    let mut written = if append { offset } else { 0 };
+    let total = response
+        .content_length()
+        .map(|len| len.saturating_add(written));
+
+    let mut out = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await
+        .map_err(|e| AiError::ModelLoad(format!("Cannot write {}: {}", part.display(), e)))?;
+    let mut last_percent = None;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AiError::Network(format!("Download of {} interrupted: {}", url, e)))?
+    {
+        out.write_all(&chunk)
+            .await
+            .map_err(|e| AiError::ModelLoad(format!("Cannot write {}: {}", part.display(), e)))?;
+        written += chunk.len() as u64;
+        let percent = total.map(|total| download_percent(written, total));
+        if percent != last_percent {
+            last_percent = percent;
+            report(
+                progress,
+                DownloadState::InProgress,
+                label,
+                percent.map(f32::from),
+            );
+        }
+    }
+    out.flush()
+        .await
+        .map_err(|e| AiError::ModelLoad(format!("Cannot write {}: {}", part.display(), e)))?;
+    match total {
+        Some(total) if written < total => Err(AiError::Network(format!(
+            "Download of {} ended after {} of {} bytes",
+            url, written, total
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check the SHA-256 of `path` against `expected` (hex, case-insensitive).
+pub(crate) async fn verify(path: &Path, expected: &str) -> Result<(), AiError> {
+    let path = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || sha256_file(&path))
+        .await
+        .map_err(|e| AiError::ModelLoad(e.to_string()))??;
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(AiError::ModelLoad(format!(
+            "Checksum mismatch: expected {}, got {}",
+            expected, actual
+        )))
+    }
+}
+
+fn sha256_file(path: &Path) -> Result<String, AiError> {
+    use std::io::Read;
+
+    let open_error =
+        |e: std::io::Error| AiError::ModelLoad(format!("Cannot read {}: {}", path.display(), e));
+    let mut file = std::fs::File::open(path).map_err(open_error)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf).map_err(open_error)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Whole percent of `total` bytes that are `written`, clamped to `0..=100`.
+fn download_percent(written: u64, total: u64) -> u8 {
+    if total == 0 {
+        return 100;
+    }
+    (written as u128 * 100 / total as u128).min(100) as u8
+}
+
+fn report(
+    progress: Option<&ProgressSender>,
+    state: DownloadState,
+    message: &str,
+    value: Option<f32>,
+) {
+    AiModelBuilder::report_progress(
+        progress,
+        ModelDownloadProgress {
+            state,
+            message: message.to_string(),
+            progress: value,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_sha256_of_downloaded_files() {
+        let dir = std::env::temp_dir().join(format!("ai_download_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.gguf");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let runtime = &crate::models::TOKIO_RUNTIME;
+        let expected = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+        assert!(runtime.block_on(verify(&path, expected)).is_ok());
+        let err = runtime
+            .block_on(verify(&path, &"0".repeat(64)))
+            .unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"));
+
+        assert_eq!(part_path(&path), dir.join("model.gguf.part"));
+        assert_eq!(
+            cached_path(&dir, "org/repo", "main", "model.gguf"),
+            dir.join("org/repo/main/model.gguf")
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn download_percent_does_not_overflow() {
+        assert_eq!(download_percent(0, 200), 0);
+        assert_eq!(download_percent(50, 200), 25);
+        assert_eq!(download_percent(u64::MAX / 2, u64::MAX), 49);
+        assert_eq!(download_percent(u64::MAX, u64::MAX), 100);
+        // Servers may send more than they announced
+        assert_eq!(download_percent(300, 200), 100);
+        assert_eq!(download_percent(10, 0), 100);
+    }
+}
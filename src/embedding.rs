@@ -0,0 +1,94 @@
+//! Text embeddings.
+//!
+//! [`AiEmbedder`] turns text into vectors for semantic search (memory recall, RAG). Build one
+//! with [`AiModelBuilder::build_embedder`](crate::models::AiModelBuilder::build_embedder), which
+//! loads a kalosm Bert model and runs it on the shared tokio runtime, or wrap any custom
+//! [`LocalEmbedder`] backend with [`AiEmbedder::new`].
+//!
+//! # Example
+//! ```ignore
+//! let embedder = AiModelBuilder::new().build_embedder()?;
+//! app.insert_resource(embedder);
+//!
+//! fn search(embedder: Res<AiEmbedder>) {
+//!     let query = embedder.embed("where is the blacksmith?").unwrap();
+//!     let docs = embedder.embed_many(&["The forge is north.", "Bread is sold here."]).unwrap();
+//!     let best = docs.iter().map(|d| cosine_similarity(&query, d));
+//! }
+//! ```
+
+use bevy::prelude::*;
+use std::sync::Arc;
+
+/// Backend producing embedding vectors.
+pub trait LocalEmbedder: Send + Sync + 'static {
+    /// Embed a single text.
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String>;
+
+    /// Embed several texts at once. The default implementation embeds them one by one.
+    fn embed_many(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        texts.iter().map(|text| self.embed(text)).collect()
+    }
+
+    /// Identifies the model behind the vectors, so saved indexes from another model are not
+    /// reused. Defaults to the backend's type name.
+    fn model_id(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+/// Resource giving systems access to an embedding backend.
+#[derive(Resource, Clone)]
+pub struct AiEmbedder {
+    backend: Arc<dyn LocalEmbedder>,
+}
+
+impl AiEmbedder {
+    pub fn new(backend: Arc<dyn LocalEmbedder>) -> Self {
+        Self { backend }
+    }
+
+    /// Embed a single text.
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        self.backend.embed(text)
+    }
+
+    /// Embed several texts in one batch, in input order.
+    pub fn embed_many(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        self.backend.embed_many(texts)
+    }
+
+    /// The underlying backend.
+    pub fn backend(&self) -> &Arc<dyn LocalEmbedder> {
+        &self.backend
+    }
+}
+
+/// Cosine similarity of two vectors in `[-1, 1]`; `0.0` for empty or mismatched inputs.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_similarity_of_vectors() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 2.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+    }
+}
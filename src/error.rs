@@ -0,0 +1,86 @@
+//! Error type shared by backends, model loading and response parsing.
+
+use thiserror::Error;
+
+/// Number of response characters shown in a [`AiError::ParseFailure`] message.
+const RAW_PREVIEW_CHARS: usize = 200;
+
+/// What went wrong talking to a model.
+///
+/// Branch on the kind to decide how to recover, e.g. retry on [`AiError::Timeout`] but not on
+/// [`AiError::ParseFailure`]; [`AiError::is_retryable`] covers the common case.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AiError {
+    /// The model could not be downloaded, loaded or configured.
+    #[error("failed to load model: {0}")]
+    ModelLoad(String),
+    /// The request could not reach the backend or the backend answered with an error status.
+    #[error("network error: {0}")]
+    Network(String),
+    /// The backend did not answer in time.
+    #[error("request timed out: {0}")]
+    Timeout(String),
+    /// The reply did not contain the expected JSON.
+    #[error("{reason}. Response was: {}", preview(raw))]
+    ParseFailure { raw: String, reason: String },
+    /// The request was cancelled before it completed.
+    #[error("request cancelled")]
+    Cancelled,
+    /// No backend is loaded, or the backend reported itself unavailable.
+    #[error("AI backend unavailable: {0}")]
+    BackendUnavailable(String),
+    /// Any other failure reported by a backend.
+    #[error("{0}")]
+    Backend(String),
+}
+
+impl AiError {
+    pub fn parse_failure(raw: impl Into<String>, reason: impl Into<String>) -> Self {
+        AiError::ParseFailure {
+            raw: raw.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            AiError::Network(_) | AiError::Timeout(_) | AiError::BackendUnavailable(_)
+        )
+    }
+}
+
+impl From<String> for AiError {
+    fn from(message: String) -> Self {
+        AiError::Backend(message)
+    }
+}
+
+impl From<&str> for AiError {
+    fn from(message: &str) -> Self {
+        AiError::Backend(message.to_string())
+    }
+}
+
+fn preview(raw: &str) -> String {
+    match raw.char_indices().nth(RAW_PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}...", &raw[..end]),
+        None => raw.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_failures_show_a_preview_of_the_reply() {
+        let err = AiError::parse_failure("x".repeat(300), "No JSON found");
+        let message = err.to_string();
+        assert!(message.starts_with("No JSON found. Response was: xxx"));
+        assert!(message.ends_with("..."));
+        assert!(!err.is_retryable());
+        assert!(AiError::Timeout("30s".into()).is_retryable());
+    }
+}
@@ -0,0 +1,269 @@
+//! Allegiances and personal relationships in NPC context.
+//!
+//! [`Faction`] puts an entity in a group, and the [`FactionRelations`] resource says how groups
+//! stand toward each other. [`Relationships`] holds what one NPC thinks of particular
+//! characters, from -1.0 (hatred) to 1.0 (complete trust); games move those values with
+//! [`Relationships::adjust`] or
+//! [`AiAppExt::track_relationships`](crate::app_ext::AiAppExt::track_relationships) when
+//! something happens. The [`FactionPlugin`] gathers both into context as plain lines:
+//!
+//! ```text
+//! You are a member of the Town Watch.
+//! The Town Watch is hostile to the Bandits.
+//! Bob trusts the player (0.7).
+//! ```
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(FactionPlugin)
+//!     .track_relationships::<GiftGiven, _>(|e| Some((e.to, "the player".to_string(), 0.2)));
+//! app.world_mut()
+//!     .resource_mut::<FactionRelations>()
+//!     .set("Town Watch", "Bandits", Stance::Hostile);
+//!
+//! commands.spawn((
+//!     AI,
+//!     Name::new("Bob"),
+//!     Faction::new("Town Watch"),
+//!     Relationships::new().with("the player", 0.7),
+//! ));
+//! ```
+
+use bevy::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::context::{AiEntity, AiSystemContextStore};
+use crate::rag::AiMessage;
+
+/// Group an entity belongs to.
+#[derive(Component, Debug, Clone, PartialEq, Eq, Hash, Reflect)]
+#[reflect(Component)]
+pub struct Faction(pub String);
+
+impl Faction {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+
+/// How two factions stand toward each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Reflect)]
+pub enum Stance {
+    Hostile,
+    Unfriendly,
+    #[default]
+    Neutral,
+    Friendly,
+    Allied,
+}
+
+impl Stance {
+    /// Words linking two factions, e.g. "is hostile to".
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stance::Hostile => "is hostile to",
+            Stance::Unfriendly => "is unfriendly to",
+            Stance::Neutral => "is neutral toward",
+            Stance::Friendly => "is friendly with",
+            Stance::Allied => "is allied with",
+        }
+    }
+}
+
+/// Resource holding the stance between pairs of factions. Stances are mutual.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct FactionRelations {
+    stances: HashMap<(String, String), Stance>,
+}
+
+fn pair(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+impl FactionRelations {
+    pub fn set(&mut self, a: &str, b: &str, stance: Stance) {
+        self.stances.insert(pair(a, b), stance);
+    }
+
+    /// Stance between `a` and `b`; neutral unless set.
+    pub fn stance(&self, a: &str, b: &str) -> Stance {
+        self.stances.get(&pair(a, b)).copied().unwrap_or_default()
+    }
+
+    /// Every faction with a stance set toward `faction`, sorted by name.
+    pub fn toward(&self, faction: &str) -> Vec<(&str, Stance)> {
+        let mut others: Vec<(&str, Stance)> = self
+            .stances
+            .iter()
+            .filter_map(|((a, b), stance)| {
+                if a == faction {
+                    Some((b.as_str(), *stance))
+                } else if b == faction {
+                    Some((a.as_str(), *stance))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        others.sort_by(|x, y| x.0.cmp(y.0));
+        others
+    }
+}
+
+/// Component holding what an NPC thinks of other characters, from -1.0 to 1.0.
+#[derive(Component, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Relationships {
+    /// Value by subject, as the NPC refers to them (e.g. "the player").
+    values: BTreeMap<String, f32>,
+}
+
+impl Relationships {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, subject: impl Into<String>, value: f32) -> Self {
+        self.set(subject, value);
+        self
+    }
+
+    /// Value toward `subject`; 0.0 for strangers.
+    pub fn get(&self, subject: &str) -> f32 {
+        self.values.get(subject).copied().unwrap_or(0.0)
+    }
+
+    pub fn set(&mut self, subject: impl Into<String>, value: f32) {
+        self.values.insert(subject.into(), value.clamp(-1.0, 1.0));
+    }
+
+    /// Move the value toward `subject` by `delta`, clamped to -1.0..=1.0. Returns the new value.
+    pub fn adjust(&mut self, subject: impl Into<String>, delta: f32) -> f32 {
+        let value = self.values.entry(subject.into()).or_insert(0.0);
+        *value = (*value + delta).clamp(-1.0, 1.0);
+        *value
+    }
+
+    pub fn remove(&mut self, subject: &str) {
+        self.values.remove(subject);
+    }
+
+    /// Subjects and values, sorted by subject.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.values.iter().map(|(s, v)| (s.as_str(), *v))
+    }
+}
+
+/// Verb for a relationship value, in third and second person.
+fn relationship_verb(value: f32) -> (&'static str, &'static str) {
+    match value {
+        v if v <= -0.6 => ("hates", "hate"),
+        v if v <= -0.2 => ("distrusts", "distrust"),
+        v if v < 0.2 => ("is indifferent to", "are indifferent to"),
+        v if v < 0.6 => ("likes", "like"),
+        _ => ("trusts", "trust"),
+    }
+}
+
+/// Lines describing `faction` and `relationships` for the NPC called `name`.
+pub fn describe_allegiances(
+    name: Option<&str>,
+    faction: Option<&Faction>,
+    relations: &FactionRelations,
+    relationships: Option<&Relationships>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(faction) = faction {
+        lines.push(format!("You are a member of the {}.", faction.0));
+        for (other, stance) in relations.toward(&faction.0) {
+            lines.push(format!(
+                "The {} {} the {}.",
+                faction.0,
+                stance.label(),
+                other
+            ));
+        }
+    }
+    for (subject, value) in relationships.into_iter().flat_map(Relationships::iter) {
+        let (third, second) = relationship_verb(value);
+        lines.push(match name {
+            Some(name) => format!("{} {} {} ({:.1}).", name, third, subject, value),
+            None => format!("You {} {} ({:.1}).", second, subject, value),
+        });
+    }
+    lines
+}
+
+/// Context-gathering system adding the requester's allegiances and relationships.
+pub fn gather_allegiance_context(
+    ai_entity: AiEntity,
+    relations: Option<Res<FactionRelations>>,
+    npcs: Query<(Option<&Name>, Option<&Faction>, Option<&Relationships>)>,
+) -> Option<AiMessage> {
+    let (name, faction, relationships) = npcs.get(ai_entity.entity()).ok()?;
+    let default_relations = FactionRelations::default();
+    let lines = describe_allegiances(
+        name.map(Name::as_str),
+        faction,
+        relations.as_deref().unwrap_or(&default_relations),
+        relationships,
+    );
+    (!lines.is_empty()).then(|| AiMessage::system(lines.join("\n")))
+}
+
+/// Plugin registering [`gather_allegiance_context`] with the context store.
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FactionRelations>()
+            .register_type::<Faction>()
+            .register_type::<Relationships>();
+        app.world_mut()
+            .get_resource_or_init::<AiSystemContextStore>()
+            .add_system(gather_allegiance_context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allegiances_read_as_plain_lines() {
+        let mut relations = FactionRelations::default();
+        relations.set("Town Watch", "Bandits", Stance::Hostile);
+        relations.set("Merchants", "Town Watch", Stance::Allied);
+        relations.set("Bandits", "Merchants", Stance::Unfriendly);
+        assert_eq!(relations.stance("Bandits", "Town Watch"), Stance::Hostile);
+
+        let mut relationships = Relationships::new().with("the player", 0.5);
+        assert!((relationships.adjust("the player", 0.2) - 0.7).abs() < 1e-6);
+        assert_eq!(relationships.adjust("Greta", -3.0), -1.0);
+
+        let faction = Faction::new("Town Watch");
+        assert_eq!(
+            describe_allegiances(
+                Some("Bob"),
+                Some(&faction),
+                &relations,
+                Some(&relationships)
+            ),
+            [
+                "You are a member of the Town Watch.",
+                "The Town Watch is hostile to the Bandits.",
+                "The Town Watch is allied with the Merchants.",
+                "Bob hates Greta (-1.0).",
+                "Bob trusts the player (0.7).",
+            ]
+        );
+        assert_eq!(
+            describe_allegiances(None, None, &relations, Some(&relationships))[1],
+            "You trust the player (0.7)."
+        );
+    }
+}
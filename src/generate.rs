@@ -0,0 +1,433 @@
+//! Small content generation: names, item descriptions, barks.
+//!
+//! [`AiGenerate`] asks for a batch of short texts in one request ("20 tavern names") without a
+//! hand-written schema or prompt. Each batch belongs to a category (`names:tavern`), and values
+//! already generated in that category are listed as taken in the next prompt and dropped if
+//! the model repeats them, so a town never gets two taverns called "The Prancing Pony".
+//!
+//! Results arrive as [`AiGeneratedBatch`] events. With a seed in [`AiGenerateCache`], batches are
+//! kept by seed, category, count and their order in the category: the third batch asked for in
+//! a category gets the cached third batch without calling the model, and
+//! [`AiGenerateCache::save`] writes them out so a seeded world is the same on every launch.
+//!
+//! Batches are sent through the dialogue pipeline by a generator entity, or by the NPC whose
+//! voice a bark should have; that NPC needs no `DialogueReceiver`. A batch whose request is
+//! throttled or gets no reply within [`AiGeneratePlugin::timeout`] arrives empty.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiGeneratePlugin::default().with_seed(7));
+//!
+//! fn name_taverns(mut generate: AiGenerate) {
+//!     generate.names("tavern", 20);
+//! }
+//!
+//! app.add_observer(|batch: On<AiGeneratedBatch>, mut towns: ResMut<Towns>| {
+//!     if batch.category == "names:tavern" {
+//!         towns.tavern_names.extend(batch.values.iter().cloned());
+//!     }
+//! });
+//! ```
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::dialogue::{
+    DialogueReceiver, DialogueRequest, DialogueRequestKind, DialogueRequestQueue, await_typed,
+    deliver_typed, render_prompt,
+};
+use crate::error::AiError;
+use crate::prompts::{GENERATE, PromptTemplates, TYPED_DATA};
+
+/// Schema of a generated batch.
+const GENERATE_SCHEMA: &str = "JSON object with fields:\n{\n  \"values\": [string]\n}";
+
+/// Taken values listed in a prompt, newest first.
+const MAX_TAKEN_IN_PROMPT: usize = 40;
+
+/// Event fired with a generated batch.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct AiGeneratedBatch {
+    /// Id returned by the [`AiGenerate`] call.
+    pub id: u64,
+    /// Category of the batch, e.g. `names:tavern`.
+    pub category: String,
+    /// New values, without repeats of earlier batches. May be fewer than asked for.
+    pub values: Vec<String>,
+    /// Whether the values came from the cache instead of the model.
+    pub cached: bool,
+}
+
+/// Resource holding generated values: every value per category, for de-duplication, and
+/// seeded batches for reuse.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AiGenerateCache {
+    /// Batches are cached only with a seed; change it for a different world.
+    pub seed: Option<u64>,
+    /// Every value generated per category, oldest first.
+    pub used: BTreeMap<String, Vec<String>>,
+    /// Cached batches by [`batch_key`](Self::batch_key).
+    pub batches: BTreeMap<String, Vec<String>>,
+}
+
+impl AiGenerateCache {
+    /// Load a cache saved with [`save`](Self::save).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.as_ref().display(), e))
+    }
+
+    /// Write the cache as pretty-printed JSON, creating parent directories.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Key of the `ordinal`th batch (from 0) of `count` values asked for in `category`:
+    /// `seed/category/count/ordinal`.
+    pub fn batch_key(&self, category: &str, count: usize, ordinal: usize) -> Option<String> {
+        self.seed
+            .map(|seed| format!("{}/{}/{}/{}", seed, category, count, ordinal))
+    }
+
+    /// Values generated so far in `category`, oldest first.
+    pub fn used(&self, category: &str) -> &[String] {
+        self.used.get(category).map_or(&[], Vec::as_slice)
+    }
+
+    /// Keep the values of `batch` not yet used in `category` (ignoring case), and record them.
+    pub fn add_unique(&mut self, category: &str, batch: Vec<String>) -> Vec<String> {
+        let used = self.used.entry(category.to_string()).or_default();
+        let mut fresh = Vec::new();
+        for value in batch {
+            let value = value.trim().to_string();
+            if value.is_empty() || used.iter().any(|u| u.eq_ignore_ascii_case(&value)) {
+                continue;
+            }
+            used.push(value.clone());
+            fresh.push(value);
+        }
+        fresh
+    }
+}
+
+/// Plugin answering [`AiGenerate`] calls, see the [module docs](self).
+pub struct AiGeneratePlugin {
+    pub seed: Option<u64>,
+    /// Time a batch may wait for the model before it arrives empty.
+    pub timeout: Duration,
+}
+
+impl Default for AiGeneratePlugin {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl AiGeneratePlugin {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Entity sending batches that have no voice of their own.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct AiGeneratorEntity;
+
+/// A batch waiting for the model.
+struct PendingBatch {
+    id: u64,
+    category: String,
+    count: usize,
+    ordinal: usize,
+    entity: Entity,
+    /// `Time<Real>` elapsed when the batch was asked for.
+    sent_at: Duration,
+}
+
+/// Batches waiting for the model, by request id, and the generator entity.
+#[derive(Resource)]
+struct PendingBatches {
+    generator: Entity,
+    timeout: Duration,
+    by_request: HashMap<u64, PendingBatch>,
+    /// Batches asked for so far in each category, for the cache key.
+    asked: HashMap<String, usize>,
+}
+
+impl Plugin for AiGeneratePlugin {
+    fn build(&self, app: &mut App) {
+        let generator = app
+            .world_mut()
+            .spawn((
+                Name::new("AI generator"),
+                AiGeneratorEntity,
+                DialogueReceiver::new(),
+            ))
+            .id();
+        let cache = app
+            .world_mut()
+            .get_resource_or_init::<AiGenerateCache>()
+            .into_inner();
+        cache.seed = cache.seed.or(self.seed);
+        app.insert_resource(PendingBatches {
+            generator,
+            timeout: self.timeout,
+            by_request: HashMap::new(),
+            asked: HashMap::new(),
+        })
+        .add_systems(Update, expire_batches);
+    }
+}
+
+/// System parameter asking for generated content.
+#[derive(SystemParam)]
+pub struct AiGenerate<'w, 's> {
+    queue: ResMut<'w, DialogueRequestQueue>,
+    pending: ResMut<'w, PendingBatches>,
+    cache: ResMut<'w, AiGenerateCache>,
+    templates: Option<Res<'w, PromptTemplates>>,
+    time: Res<'w, Time<Real>>,
+    commands: Commands<'w, 's>,
+}
+
+impl AiGenerate<'_, '_> {
+    /// `count` names for a `kind` of thing, e.g. `names("tavern", 20)`. Category `names:<kind>`.
+    pub fn names(&mut self, kind: &str, count: usize) -> u64 {
+        self.batch(
+            None,
+            format!("names:{}", kind),
+            &format!("distinct names for a {}", kind),
+            count,
+        )
+    }
+
+    /// `count` one- or two-sentence descriptions of `item`. Category `descriptions:<item>`.
+    pub fn descriptions(&mut self, item: &str, count: usize) -> u64 {
+        self.batch(
+            None,
+            format!("descriptions:{}", item),
+            &format!(
+                "different one- or two-sentence descriptions of {}, for an item tooltip",
+                item
+            ),
+            count,
+        )
+    }
+
+    /// `count` short lines `npc` would call out `when` something happens, in its own voice.
+    /// Category `barks:<when>`.
+    pub fn barks(&mut self, npc: Entity, when: &str, count: usize) -> u64 {
+        self.batch(
+            Some(npc),
+            format!("barks:{}", when),
+            &format!("short lines you would call out when {}", when),
+            count,
+        )
+    }
+
+    /// `count` values described by `what` (e.g. "rumors overheard at a market"), in
+    /// `category`. Requests are sent by `voice`, or the generator entity.
+    pub fn batch(
+        &mut self,
+        voice: Option<Entity>,
+        category: impl Into<String>,
+        what: &str,
+        count: usize,
+    ) -> u64 {
+        let category = category.into();
+        let id = crate::dialogue::next_request_id();
+        let asked = self.pending.asked.entry(category.clone()).or_default();
+        let ordinal = *asked;
+        *asked += 1;
+        if let Some(values) = self
+            .cache
+            .batch_key(&category, count, ordinal)
+            .and_then(|key| self.cache.batches.get(&key))
+        {
+            self.commands.trigger(AiGeneratedBatch {
+                id,
+                category,
+                values: values.clone(),
+                cached: true,
+            });
+            return id;
+        }
+
+        let taken: Vec<&str> = self
+            .cache
+            .used(&category)
+            .iter()
+            .rev()
+            .take(MAX_TAKEN_IN_PROMPT)
+            .map(String::as_str)
+            .collect();
+        let taken = if taken.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nThese are taken, do not repeat them: {}.",
+                taken.join(", ")
+            )
+        };
+        let count_text = count.to_string();
+        let templates = self.templates.as_deref();
+        let prompt = render_prompt(
+            templates,
+            GENERATE,
+            &[("count", &count_text), ("what", what), ("taken", &taken)],
+        )
+        .unwrap_or_else(|_| format!("Give {} {}.{}", count, what, taken));
+        let user_message = render_prompt(
+            templates,
+            TYPED_DATA,
+            &[
+                ("prompt", &prompt),
+                ("schema", GENERATE_SCHEMA),
+                ("example", ""),
+            ],
+        )
+        .unwrap_or_else(|_| format!("{}\n{}", prompt, GENERATE_SCHEMA));
+        let request = DialogueRequest {
+            id: crate::dialogue::next_request_id(),
+            entity: voice.unwrap_or(self.pending.generator),
+            kind: DialogueRequestKind::Data {
+                user_message,
+                schema_description: GENERATE_SCHEMA.to_string(),
+            },
+            player_text: false,
+        };
+        let request_id = request.id;
+        self.pending.by_request.insert(
+            request_id,
+            PendingBatch {
+                id,
+                category,
+                count,
+                ordinal,
+                entity: request.entity,
+                sent_at: self.time.elapsed(),
+            },
+        );
+        self.queue.push(request);
+        // Data replies reach their requester with or without a receiver
+        await_typed(
+            &mut self.commands,
+            request_id,
+            Box::new(move |world, _, reply| finish_batch(world, request_id, reply)),
+        );
+        id
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchReply {
+    values: Vec<serde_json::Value>,
+}
+
+/// Keep the new values of a batch reply, cache them, and fire [`AiGeneratedBatch`]. Failed
+/// batches arrive empty.
+fn finish_batch(world: &mut World, request_id: u64, reply: Result<String, AiError>) {
+    let Some(batch) = world
+        .get_resource_mut::<PendingBatches>()
+        .and_then(|mut pending| pending.by_request.remove(&request_id))
+    else {
+        return;
+    };
+    let values =
+        match reply.and_then(|text| crate::parse::extract_and_parse_json::<BatchReply>(&text)) {
+            Ok(reply) => reply
+                .values
+                .into_iter()
+                .map(|v| match v {
+                    serde_json::Value::String(s) => s,
+                    other => other.to_string(),
+                })
+                .take(batch.count)
+                .collect(),
+            Err(e) => {
+                warn!("Generating {} failed: {}", batch.category, e);
+                Vec::new()
+            }
+        };
+    let mut cache = world.get_resource_or_init::<AiGenerateCache>();
+    let values = cache.add_unique(&batch.category, values);
+    if let Some(key) = cache.batch_key(&batch.category, batch.count, batch.ordinal)
+        && !values.is_empty()
+    {
+        cache.batches.insert(key, values.clone());
+    }
+    world.trigger(AiGeneratedBatch {
+        id: batch.id,
+        category: batch.category,
+        values,
+        cached: false,
+    });
+}
+
+/// Give up on batches the model did not answer in time.
+fn expire_batches(time: Res<Time<Real>>, pending: Res<PendingBatches>, mut commands: Commands) {
+    let now = time.elapsed();
+    for (&request_id, batch) in &pending.by_request {
+        if now.saturating_sub(batch.sent_at) < pending.timeout {
+            continue;
+        }
+        let (entity, timeout) = (batch.entity, pending.timeout);
+        commands.queue(move |world: &mut World| {
+            let error = AiError::Timeout(format!("no batch after {:?}", timeout));
+            deliver_typed(world, request_id, entity, Err(error));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_values_are_dropped_per_category() {
+        let mut cache = AiGenerateCache::default();
+        let names = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            cache.add_unique(
+                "names:tavern",
+                names(&["The Boar", " The Boar ", "Ale House"])
+            ),
+            ["The Boar", "Ale House"]
+        );
+        assert_eq!(
+            cache.add_unique("names:tavern", names(&["the boar", "Red Lion", ""])),
+            ["Red Lion"]
+        );
+        assert_eq!(
+            cache.add_unique("names:ship", names(&["The Boar"])),
+            ["The Boar"]
+        );
+        assert_eq!(cache.batch_key("names:tavern", 3, 0), None);
+        cache.seed = Some(7);
+        assert_eq!(
+            cache.batch_key("names:tavern", 3, 1).as_deref(),
+            Some("7/names:tavern/3/1")
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Gizmos visualising spatial context gathering, for tuning the gather radius in-game.
+//!
+//! Requires the `debug_gizmos` feature. [`AiAwarenessGizmoPlugin`] draws, for every entity
+//! with [`ShowAiAwarenessGizmo`]:
+//!
+//! - the [`AiContextGatherConfig::radius`] as a circle in the configured [`SpatialPlane`],
+//! - a line to each [`AIAware`] entity inside the radius, brighter for the nearest
+//!   [`AiContextGatherConfig::max_docs`] which are the ones gathered into the prompt.
+//!
+//! The increase/decrease keys change the radius live, so you can walk around and watch which
+//! entities drop in and out of the AI's awareness.
+//!
+//! # Example
+//! ```ignore
+//! #[cfg(debug_assertions)]
+//! app.add_plugins(AiAwarenessGizmoPlugin::default());
+//!
+//! commands.spawn((Name::new("Mira"), AI, Transform::default(), ShowAiAwarenessGizmo));
+//! ```
+
+use bevy::prelude::*;
+
+use crate::context::{AIAware, AiContextGatherConfig};
+use crate::spatial::SpatialPlane;
+
+/// Plugin drawing the awareness gizmos and handling the radius keybinds.
+pub struct AiAwarenessGizmoPlugin {
+    /// Key growing the radius by `step`; `None` disables it.
+    pub increase_key: Option<KeyCode>,
+    /// Key shrinking the radius by `step`; `None` disables it.
+    pub decrease_key: Option<KeyCode>,
+    /// Key toggling the gizmos; `None` disables it.
+    pub toggle_key: Option<KeyCode>,
+    /// Radius change per key press, in world units.
+    pub step: f32,
+}
+
+impl Default for AiAwarenessGizmoPlugin {
+    fn default() -> Self {
+        Self {
+            increase_key: Some(KeyCode::BracketRight),
+            decrease_key: Some(KeyCode::BracketLeft),
+            toggle_key: Some(KeyCode::F10),
+            step: 1.0,
+        }
+    }
+}
+
+/// Marker: draw the awareness radius and gathered entities of this entity.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct ShowAiAwarenessGizmo;
+
+/// Settings of the awareness gizmos, editable at runtime.
+#[derive(Resource, Debug, Clone)]
+pub struct AiAwarenessGizmos {
+    pub enabled: bool,
+    pub increase_key: Option<KeyCode>,
+    pub decrease_key: Option<KeyCode>,
+    pub toggle_key: Option<KeyCode>,
+    pub step: f32,
+    /// Color of the radius circle.
+    pub radius_color: Color,
+    /// Color of lines to entities that are gathered.
+    pub gathered_color: Color,
+    /// Color of lines to entities inside the radius but beyond `max_docs`.
+    pub skipped_color: Color,
+}
+
+impl Plugin for AiAwarenessGizmoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiContextGatherConfig>()
+            .insert_resource(AiAwarenessGizmos {
+                enabled: true,
+                increase_key: self.increase_key,
+                decrease_key: self.decrease_key,
+                toggle_key: self.toggle_key,
+                step: self.step,
+                radius_color: Color::srgb(0.3, 0.8, 1.0),
+                gathered_color: Color::srgb(0.4, 1.0, 0.4),
+                skipped_color: Color::srgba(0.6, 0.6, 0.6, 0.5),
+            })
+            .add_systems(
+                Update,
+                (
+                    adjust_gather_radius,
+                    draw_awareness_gizmos.run_if(resource_exists::<GizmoConfigStore>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn adjust_gather_radius(
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    mut settings: ResMut<AiAwarenessGizmos>,
+    mut config: ResMut<AiContextGatherConfig>,
+) {
+    let Some(keys) = keys else {
+        return;
+    };
+    let pressed = |key: Option<KeyCode>| key.is_some_and(|k| keys.just_pressed(k));
+    if pressed(settings.toggle_key) {
+        settings.enabled = !settings.enabled;
+    }
+    let mut radius = config.radius;
+    if pressed(settings.increase_key) {
+        radius += settings.step;
+    }
+    if pressed(settings.decrease_key) {
+        radius = (radius - settings.step).max(0.0);
+    }
+    if radius != config.radius {
+        config.radius = radius;
+        info!("AI gather radius: {:.1}", radius);
+    }
+}
+
+fn draw_awareness_gizmos(
+    mut gizmos: Gizmos,
+    settings: Res<AiAwarenessGizmos>,
+    config: Res<AiContextGatherConfig>,
+    shown: Query<(Entity, &Transform), With<ShowAiAwarenessGizmo>>,
+    aware: Query<(Entity, &Transform), With<AIAware>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    // Gizmo circles lie in the XY plane by default
+    let rotation = match config.plane {
+        SpatialPlane::Xz => Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+        SpatialPlane::Xy => Quat::IDENTITY,
+    };
+    for (entity, transform) in &shown {
+        let origin = transform.translation;
+        gizmos.circle(
+            Isometry3d::new(origin, rotation),
+            config.radius,
+            settings.radius_color,
+        );
+
+        // Same selection as `AiEntity::collect_nearby`: nearest first, capped at max_docs
+        let mut nearby: Vec<(Vec3, f32)> = aware
+            .iter()
+            .filter(|(other, _)| *other != entity)
+            .map(|(_, t)| (t.translation, origin.distance(t.translation)))
+            .filter(|(_, distance)| *distance <= config.radius)
+            .collect();
+        nearby.sort_by(|a, b| a.1.total_cmp(&b.1));
+        for (i, (position, _)) in nearby.into_iter().enumerate() {
+            let color = if i < config.max_docs {
+                settings.gathered_color
+            } else {
+                settings.skipped_color
+            };
+            gizmos.line(origin, position, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_adjust_the_gather_radius() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(AiAwarenessGizmoPlugin::default())
+            .insert_resource(AiContextGatherConfig::new(1.5, 4))
+            .init_resource::<ButtonInput<KeyCode>>();
+
+        let press = |app: &mut App, key: KeyCode| {
+            let mut keys = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+            keys.reset_all();
+            keys.press(key);
+            app.update();
+        };
+
+        press(&mut app, KeyCode::BracketRight);
+        assert_eq!(app.world().resource::<AiContextGatherConfig>().radius, 2.5);
+        press(&mut app, KeyCode::BracketLeft);
+        press(&mut app, KeyCode::BracketLeft);
+        press(&mut app, KeyCode::BracketLeft);
+        assert_eq!(app.world().resource::<AiContextGatherConfig>().radius, 0.0);
+
+        press(&mut app, KeyCode::F10);
+        assert!(!app.world().resource::<AiAwarenessGizmos>().enabled);
+    }
+}
@@ -0,0 +1,144 @@
+//! Periodic availability checks of the dialogue backend.
+//!
+//! Remote backends fail slowly: a request to a dead server only errors after its timeout. With
+//! [`AiHealthCheckPlugin`] the backend's [`LocalAi::health_check`] (a cheap ping or model-list
+//! call) runs in the background every `interval`, the result is kept in [`BackendAvailability`]
+//! and a [`BackendAvailabilityEvent`] fires whenever it changes, so the game can switch
+//! backends or show an "AI offline" notice before a player waits on a request.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiHealthCheckPlugin::every(Duration::from_secs(15)));
+//!
+//! app.add_observer(|e: On<BackendAvailabilityEvent>, mut handle: ResMut<LocalAiHandle>, local: Res<LocalFallback>| {
+//!     if !e.event().available {
+//!         handle.backend = Some(local.0.clone());
+//!     }
+//! });
+//!
+//! app.add_systems(Update, show_offline_banner.run_if(not(backend_available)));
+//! ```
+//!
+//! [`LocalAi::health_check`]: crate::dialogue::LocalAi::health_check
+
+use bevy::prelude::*;
+use flume::{Receiver, bounded};
+use std::time::Duration;
+
+use crate::dialogue::{AiSystemSet, LocalAiHandle};
+use crate::error::AiError;
+
+/// Plugin running the health checks.
+pub struct AiHealthCheckPlugin {
+    /// Time between the end of one check and the start of the next.
+    pub interval: Duration,
+}
+
+impl Default for AiHealthCheckPlugin {
+    fn default() -> Self {
+        Self::every(Duration::from_secs(30))
+    }
+}
+
+impl AiHealthCheckPlugin {
+    pub fn every(interval: Duration) -> Self {
+        Self { interval }
+    }
+}
+
+/// Result of the latest health check.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct BackendAvailability {
+    /// `None` until the first check finished.
+    pub available: Option<bool>,
+    /// Error of the latest failed check.
+    pub error: Option<AiError>,
+    /// Number of checks finished so far.
+    pub checks: u64,
+}
+
+impl BackendAvailability {
+    /// `false` only once a check has failed.
+    pub fn is_available(&self) -> bool {
+        self.available != Some(false)
+    }
+}
+
+/// Fired after the first check and whenever availability changes.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct BackendAvailabilityEvent {
+    pub available: bool,
+    pub error: Option<AiError>,
+}
+
+/// System condition: `true` unless the latest health check failed.
+pub fn backend_available(status: Option<Res<BackendAvailability>>) -> bool {
+    status.is_none_or(|s| s.is_available())
+}
+
+#[derive(Resource)]
+struct HealthCheckState {
+    interval: Duration,
+    /// Real time at which the next check may start.
+    next_at: Duration,
+    in_flight: Option<Receiver<Result<(), AiError>>>,
+}
+
+impl Plugin for AiHealthCheckPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BackendAvailability>()
+            .insert_resource(HealthCheckState {
+                interval: self.interval,
+                next_at: Duration::ZERO,
+                in_flight: None,
+            })
+            .add_systems(Update, run_health_checks.after(AiSystemSet::PollModelLoads));
+    }
+}
+
+fn run_health_checks(
+    time: Res<Time<Real>>,
+    handle: Option<Res<LocalAiHandle>>,
+    mut state: ResMut<HealthCheckState>,
+    mut status: ResMut<BackendAvailability>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    if let Some(rx) = &state.in_flight {
+        let Ok(result) = rx.try_recv() else {
+            return;
+        };
+        state.in_flight = None;
+        state.next_at = now + state.interval;
+
+        let available = result.is_ok();
+        let changed = status.available != Some(available);
+        status.available = Some(available);
+        status.error = result.err();
+        status.checks += 1;
+        if changed {
+            match &status.error {
+                Some(e) => warn!("AI backend unavailable: {}", e),
+                None => info!("AI backend available"),
+            }
+            commands.trigger(BackendAvailabilityEvent {
+                available,
+                error: status.error.clone(),
+            });
+        }
+        return;
+    }
+
+    if now < state.next_at {
+        return;
+    }
+    let Some(backend) = handle.and_then(|h| h.backend.clone()) else {
+        return;
+    };
+    let (tx, rx) = bounded(1);
+    // A plain thread: backends may block on the shared runtime themselves
+    std::thread::spawn(move || {
+        let _ = tx.send(backend.health_check());
+    });
+    state.in_flight = Some(rx);
+}
@@ -0,0 +1,295 @@
+//! Recent history of the actions the AI triggered.
+//!
+//! Insert an [`ActionHistory`] resource and every action leaving the pending queue is recorded
+//! with its entity, params, time and what became of it: run by a handler, rejected by the
+//! handler's checks, dropped for lack of a handler or for going over its action budget. The history is a ring buffer, so it
+//! answers "what did the AI do in the last 30 seconds" without growing forever, and can be
+//! exported as JSON lines for later digging.
+//!
+//! Dry-run actions are not recorded; they are reported with
+//! [`WouldExecute`](crate::actions::WouldExecute) instead.
+//!
+//! Actions whose handler was registered with an inverse, see
+//! [`register_typed_with_undo`](crate::actions::AiActionRegistry::register_typed_with_undo),
+//! can be taken back with [`ActionHistory::undo_last`], newest first.
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(ActionHistory::new(500));
+//!
+//! fn dump_recent(keys: Res<ButtonInput<KeyCode>>, history: Res<ActionHistory>) {
+//!     if keys.just_pressed(KeyCode::F9) {
+//!         for record in history.within(Duration::from_secs(30)) {
+//!             info!("{:?} {} {} -> {:?}", record.entity, record.name, record.params, record.outcome);
+//!         }
+//!         let _ = history.save_jsonl("logs/actions.jsonl");
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What became of a recorded action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", content = "error", rename_all = "snake_case")]
+pub enum ActionOutcome {
+    /// A handler ran.
+    Executed,
+    /// The params failed the handler's checks, so it did not run.
+    Rejected(String),
+    /// No handler is registered for the action.
+    Unhandled,
+    /// Dropped for going over its [`ActionBudgets`](crate::rate_limit::ActionBudgets) limit.
+    Throttled,
+}
+
+/// One action taken from the pending queue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionRecord {
+    /// Assigned by [`ActionHistory::push`], increasing.
+    pub id: u64,
+    pub entity: Entity,
+    pub name: String,
+    pub params: Value,
+    /// Wall-clock time the action was handled.
+    pub timestamp: SystemTime,
+    pub outcome: ActionOutcome,
+}
+
+/// Inverse of a recorded action.
+type UndoFn = Box<dyn FnOnce(&mut World) + Send + Sync>;
+
+/// Resource keeping the last `capacity` actions, oldest first.
+#[derive(Resource)]
+pub struct ActionHistory {
+    records: VecDeque<ActionRecord>,
+    /// Number of records kept; the oldest are dropped first.
+    pub capacity: usize,
+    /// Inverses of undoable records, by record id.
+    undo: HashMap<u64, UndoFn>,
+    next_id: u64,
+}
+
+impl std::fmt::Debug for ActionHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActionHistory")
+            .field("records", &self.records)
+            .field("capacity", &self.capacity)
+            .field("undoable", &self.undo.len())
+            .finish()
+    }
+}
+
+impl Default for ActionHistory {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl ActionHistory {
+    /// Keep the last `capacity` actions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity,
+            undo: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Append a record under a fresh id, dropping the oldest ones over capacity.
+    pub fn push(&mut self, mut record: ActionRecord) {
+        self.next_id += 1;
+        record.id = self.next_id;
+        self.records.push_back(record);
+        while self.records.len() > self.capacity {
+            if let Some(dropped) = self.records.pop_front() {
+                self.undo.remove(&dropped.id);
+            }
+        }
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        entity: Entity,
+        action: &crate::actions::ActionPayload,
+        outcome: ActionOutcome,
+    ) {
+        self.push(ActionRecord {
+            id: 0,
+            entity,
+            name: action.name.clone(),
+            params: action.params.clone(),
+            timestamp: SystemTime::now(),
+            outcome,
+        });
+    }
+
+    /// All records, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &ActionRecord> {
+        self.records.iter()
+    }
+
+    /// Records handled at or after `time`.
+    pub fn since(&self, time: SystemTime) -> impl Iterator<Item = &ActionRecord> {
+        self.records.iter().filter(move |r| r.timestamp >= time)
+    }
+
+    /// Records handled in the last `window`.
+    pub fn within(&self, window: Duration) -> impl Iterator<Item = &ActionRecord> {
+        let now = SystemTime::now();
+        self.since(now.checked_sub(window).unwrap_or(UNIX_EPOCH))
+    }
+
+    /// Records of actions produced by `entity`.
+    pub fn for_entity(&self, entity: Entity) -> impl Iterator<Item = &ActionRecord> {
+        self.records.iter().filter(move |r| r.entity == entity)
+    }
+
+    /// Records of actions called `name`.
+    pub fn named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ActionRecord> {
+        self.records.iter().filter(move |r| r.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.undo.clear();
+    }
+
+    /// Whether record `id` can still be undone.
+    pub fn can_undo(&self, id: u64) -> bool {
+        self.undo.contains_key(&id)
+    }
+
+    /// Make the newest record undoable with `undo`.
+    pub(crate) fn attach_undo(&mut self, undo: impl FnOnce(&mut World) + Send + Sync + 'static) {
+        if let Some(record) = self.records.back() {
+            self.undo.insert(record.id, Box::new(undo));
+        }
+    }
+
+    /// Undo the last `n` undoable actions, newest first, by running the inverses they were
+    /// registered with. Actions without an inverse are skipped. Returns how many were undone.
+    ///
+    /// # Example
+    /// ```ignore
+    /// commands.queue(|world: &mut World| {
+    ///     ActionHistory::undo_last(world, 3);
+    /// });
+    /// ```
+    pub fn undo_last(world: &mut World, n: usize) -> usize {
+        let Some(mut history) = world.get_resource_mut::<ActionHistory>() else {
+            return 0;
+        };
+        let ids: Vec<u64> = history
+            .records
+            .iter()
+            .rev()
+            .map(|r| r.id)
+            .filter(|id| history.undo.contains_key(id))
+            .take(n)
+            .collect();
+        let undos: Vec<UndoFn> = ids
+            .iter()
+            .filter_map(|id| history.undo.remove(id))
+            .collect();
+        let undone = undos.len();
+        for undo in undos {
+            undo(world);
+        }
+        undone
+    }
+
+    /// Write the records as JSON lines, creating parent directories. Entities are stored as
+    /// `Entity::to_bits` and times as milliseconds since the Unix epoch.
+    pub fn save_jsonl(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut text = String::new();
+        for record in &self.records {
+            let mut line = serde_json::json!({
+                "id": record.id,
+                "time_ms": record
+                    .timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis() as u64),
+                "entity": record.entity.to_bits(),
+                "name": record.name,
+                "params": record.params,
+            });
+            if let (Value::Object(line), Value::Object(outcome)) = (
+                &mut line,
+                serde_json::to_value(&record.outcome).map_err(|e| e.to_string())?,
+            ) {
+                line.extend(outcome);
+            }
+            text.push_str(&line.to_string());
+            text.push('\n');
+        }
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, timestamp: SystemTime) -> ActionRecord {
+        ActionRecord {
+            id: 0,
+            entity: Entity::from_bits(1),
+            name: name.to_string(),
+            params: serde_json::json!({ "speed": 2 }),
+            timestamp,
+            outcome: ActionOutcome::Executed,
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_records_and_exports_them() {
+        let mut history = ActionHistory::new(2);
+        let now = SystemTime::now();
+        history.push(record("wave", now - Duration::from_secs(120)));
+        history.push(record("open_gate", now - Duration::from_secs(60)));
+        history.push(ActionRecord {
+            outcome: ActionOutcome::Rejected("`speed` is missing".to_string()),
+            ..record("open_gate", now)
+        });
+
+        let names: Vec<&str> = history.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["open_gate", "open_gate"]);
+        assert_eq!(history.within(Duration::from_secs(30)).count(), 1);
+        assert_eq!(history.named("wave").count(), 0);
+
+        let path = std::env::temp_dir()
+            .join(format!("ai_history_{}", std::process::id()))
+            .join("actions.jsonl");
+        history.save_jsonl(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["outcome"], "executed");
+        assert_eq!(lines[1]["outcome"], "rejected");
+        assert_eq!(lines[1]["error"], "`speed` is missing");
+        assert_eq!(lines[1]["params"]["speed"], 2);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,306 @@
+//! `LocalAi` backend that forwards prompts to your own HTTP inference server.
+//!
+//! [`HttpLocalAi`] POSTs a JSON body built from a template and reads the reply from the
+//! response JSON with a JSON pointer, so it can talk to almost any simple REST endpoint
+//! (a Python script, llama.cpp's server, a machine on the LAN) without OpenAI-compatible
+//! semantics. Requests run on the shared tokio runtime like the other backends.
+//!
+//! String values in the template may contain these placeholders:
+//!
+//! - `{system}`: the system prompt (persona, context, global prompt),
+//! - `{prompt}`: the user message, after the last assistant message if there are any,
+//! - `{messages}`: when a string is exactly this, it is replaced by an array of
+//!   `{"role": "system" | "user" | "assistant", "content": ...}` objects.
+//!
+//! # Example
+//! ```ignore
+//! let backend = HttpLocalAi::new("http://10.0.0.5:8080/completion")
+//!     .with_request_template(json!({ "prompt": "{system}\n\n{prompt}", "n_predict": 256 }))
+//!     .with_response_pointer("/content")
+//!     .with_bearer_token(SecureString::new(std::env::var("INFER_TOKEN")?));
+//!
+//! app.add_plugins(AIDialoguePlugin::with_backend(Arc::new(backend)));
+//! ```
+
+use serde_json::{Value, json};
+use std::time::Duration;
+
+use crate::dialogue::LocalAi;
+use crate::error::AiError;
+use crate::models::{SecureString, assemble_prompt, run_sync};
+use crate::rag::AiMessage;
+
+/// Backend calling a REST endpoint with a configurable JSON request/response mapping.
+#[derive(Clone)]
+pub struct HttpLocalAi {
+    url: String,
+    client: reqwest::Client,
+    headers: Vec<(String, SecureString)>,
+    request_template: Value,
+    response_pointer: String,
+    timeout: Duration,
+    health_url: Option<String>,
+}
+
+impl HttpLocalAi {
+    /// POST `{"system": ..., "prompt": ...}` to `url` and read the `text` field of the reply.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+            headers: Vec::new(),
+            request_template: json!({ "system": "{system}", "prompt": "{prompt}" }),
+            response_pointer: "/text".to_string(),
+            timeout: Duration::from_secs(120),
+            health_url: None,
+        }
+    }
+
+    /// JSON body to send, with `{system}`, `{prompt}` and `{messages}` placeholders.
+    pub fn with_request_template(mut self, template: Value) -> Self {
+        self.request_template = template;
+        self
+    }
+
+    /// JSON pointer (RFC 6901) to the reply text, e.g. `/choices/0/text`.
+    pub fn with_response_pointer(mut self, pointer: impl Into<String>) -> Self {
+        self.response_pointer = pointer.into();
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers
+            .push((name.into(), SecureString::new(value.into())));
+        self
+    }
+
+    /// Send `Authorization: Bearer <token>`.
+    pub fn with_bearer_token(self, token: SecureString) -> Self {
+        let value = format!("Bearer {}", token.as_str());
+        self.with_header("Authorization", value)
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// URL answering GET with a success status while the server is up, e.g. `/health` or
+    /// `/v1/models`. Without it, health checks only verify the prompt URL is reachable.
+    pub fn with_health_url(mut self, url: impl Into<String>) -> Self {
+        self.health_url = Some(url.into());
+        self
+    }
+
+    /// The JSON body that would be sent for `messages`.
+    pub fn request_body(&self, messages: &[AiMessage]) -> Value {
+        let (system, prompt) = assemble_prompt(messages, None);
+        let chat: Vec<Value> = messages
+            .iter()
+            .filter_map(|m| match m {
+                AiMessage::System(text) => Some(json!({ "role": "system", "content": &**text })),
+                AiMessage::User(text) => Some(json!({ "role": "user", "content": &**text })),
+                AiMessage::Assistant(text) => {
+                    Some(json!({ "role": "assistant", "content": &**text }))
+                }
+                _ => None,
+            })
+            .collect();
+        fill_template(&self.request_template, &system, &prompt, &chat)
+    }
+
+    /// Extract the reply text from a response body.
+    pub fn response_text(&self, body: &Value) -> Result<String, AiError> {
+        match body.pointer(&self.response_pointer) {
+            Some(Value::String(text)) => Ok(text.clone()),
+            Some(other) => Ok(other.to_string()),
+            None => Err(AiError::parse_failure(
+                body.to_string(),
+                format!("Response has no value at '{}'", self.response_pointer),
+            )),
+        }
+    }
+}
+
+/// Classify a failed request: timeouts are reported separately so callers can retry them.
+fn request_error(url: &str, e: reqwest::Error) -> AiError {
+    if e.is_timeout() {
+        AiError::Timeout(format!("no answer from {}", url))
+    } else {
+        AiError::Network(format!("HTTP request to {} failed: {}", url, e))
+    }
+}
+
+/// Error for a non-success status; 503 means the server is up but cannot serve yet.
+fn status_error(url: &str, status: reqwest::StatusCode, body: &str) -> AiError {
+    let mut message = format!("HTTP {} from {}", status, url);
+    if !body.is_empty() {
+        message = format!("{}: {}", message, body);
+    }
+    if status == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+        AiError::BackendUnavailable(message)
+    } else {
+        AiError::Network(message)
+    }
+}
+
+fn fill_template(template: &Value, system: &str, prompt: &str, chat: &[Value]) -> Value {
+    match template {
+        Value::String(s) if s == "{messages}" => Value::Array(chat.to_vec()),
+        Value::String(s) => {
+            Value::String(substitute(s, &[("{system}", system), ("{prompt}", prompt)]))
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|v| fill_template(v, system, prompt, chat))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), fill_template(v, system, prompt, chat)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Replace each placeholder in `text` in one pass, so inserted values are never scanned for
+/// placeholders themselves.
+fn substitute(text: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((at, placeholder, value)) = vars
+        .iter()
+        .filter_map(|(placeholder, value)| {
+            rest.find(placeholder).map(|at| (at, *placeholder, *value))
+        })
+        .min_by_key(|(at, _, _)| *at)
+    {
+        out.push_str(&rest[..at]);
+        out.push_str(value);
+        rest = &rest[at + placeholder.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Upper bound for health check requests, so a hung server is reported quickly.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl LocalAi for HttpLocalAi {
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
+        let body = self.request_body(messages);
+        run_sync(async {
+            let mut request = self
+                .client
+                .post(&self.url)
+                .timeout(self.timeout)
+                .json(&body);
+            for (name, value) in &self.headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| request_error(&self.url, e))?;
+            let status = response.status();
+            let text = response
+                .text()
+                .await
+                .map_err(|e| request_error(&self.url, e))?;
+            if !status.is_success() {
+                return Err(status_error(&self.url, status, &text));
+            }
+            let json: Value = serde_json::from_str(&text).map_err(|e| {
+                AiError::parse_failure(
+                    text.as_str(),
+                    format!("Invalid JSON from {}: {}", self.url, e),
+                )
+            })?;
+            self.response_text(&json)
+        })
+    }
+
+    fn health_check(&self) -> Result<(), AiError> {
+        let url = self.health_url.as_deref().unwrap_or(&self.url);
+        run_sync(async {
+            let mut request = self
+                .client
+                .get(url)
+                .timeout(self.timeout.min(HEALTH_TIMEOUT));
+            for (name, value) in &self.headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            let response = request.send().await.map_err(|e| request_error(url, e))?;
+            let status = response.status();
+            // The prompt URL usually rejects GET, but any answer short of a server error
+            // shows the server is up.
+            let healthy = if self.health_url.is_some() {
+                status.is_success()
+            } else {
+                !status.is_server_error()
+            };
+            if healthy {
+                Ok(())
+            } else {
+                Err(status_error(url, status, ""))
+            }
+        })
+    }
+
+    fn supports_batching(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders_and_reads_pointer() {
+        let ai = HttpLocalAi::new("http://localhost:8080")
+            .with_request_template(json!({
+                "input": "{system}|{prompt}",
+                "chat": "{messages}",
+                "options": { "max_tokens": 64 }
+            }))
+            .with_response_pointer("/choices/0/text");
+        let body = ai.request_body(&[
+            AiMessage::skip_default_context(),
+            AiMessage::system("You are Mira."),
+            AiMessage::user("Hello"),
+        ]);
+        assert_eq!(body["input"], "You are Mira.|Hello");
+        assert_eq!(
+            body["chat"][1],
+            json!({ "role": "user", "content": "Hello" })
+        );
+        assert_eq!(body["chat"].as_array().unwrap().len(), 2);
+        assert_eq!(body["options"]["max_tokens"], 64);
+
+        // Earlier replies are sent as assistant turns; the prompt is the latest user message
+        let body = ai.request_body(&[
+            AiMessage::user("Hello"),
+            AiMessage::assistant("Well met."),
+            AiMessage::user("Bye"),
+        ]);
+        assert_eq!(body["input"], "|Bye");
+        assert_eq!(
+            body["chat"][1],
+            json!({ "role": "assistant", "content": "Well met." })
+        );
+
+        // Player text is inserted as it is, even when it looks like a placeholder
+        let body = ai.request_body(&[
+            AiMessage::system("Ignore {prompt}"),
+            AiMessage::user("Say {system}"),
+        ]);
+        assert_eq!(body["input"], "Ignore {prompt}|Say {system}");
+
+        let reply = json!({ "choices": [{ "text": "Hi there" }] });
+        assert_eq!(ai.response_text(&reply).unwrap(), "Hi there");
+        assert!(ai.response_text(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Runtime inspection of the AI registries.
+//!
+//! Handler and context-system registries hold boxed systems that cannot be reflected, so the
+//! dialogue plugin mirrors their contents into the reflectable [`AiRegistryInfo`] resource every
+//! frame. Together with the reflected [`AiPersona`](crate::persona::AiPersona) component and the
+//! `AiContextGatherConfig`/`AiDryRun` resources, this lets editor and inspector tooling (e.g.
+//! `bevy-inspector-egui`) list what is registered and tweak prompts and personas at runtime.
+//!
+//! Inserting an [`AiDebugLog`] makes the dialogue plugin record the exact messages sent for each
+//! entity and the raw reply, for prompt tuning tools such as `AiDebugUiPlugin`.
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::actions::{ActionPayload, AiActionRegistry};
+use crate::context::AiSystemContextStore;
+use crate::rag::AiMessage;
+use crate::tokenizer::Tokenizer;
+
+/// Read-only snapshot of what is registered, for inspectors.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiRegistryInfo {
+    /// Registered action names, sorted.
+    pub actions: Vec<String>,
+    /// Registered context-gathering systems, in run order.
+    pub context_systems: Vec<String>,
+}
+
+/// Refresh [`AiRegistryInfo`] from the registries, touching it only when something changed.
+pub fn sync_registry_info(
+    registry: Option<Res<AiActionRegistry>>,
+    store: Option<Res<AiSystemContextStore>>,
+    mut info: ResMut<AiRegistryInfo>,
+) {
+    let current = AiRegistryInfo {
+        actions: registry.map(|r| r.names()).unwrap_or_default(),
+        context_systems: store.map(|s| s.system_names()).unwrap_or_default(),
+    };
+    info.set_if_neq(current);
+}
+
+/// The last exchange of one entity, as recorded in [`AiDebugLog`].
+#[derive(Debug, Clone, Default)]
+pub struct AiDebugEntry {
+    pub request_id: Option<u64>,
+    /// Messages sent with the last request, including persona and gathered context.
+    pub prompt: Vec<AiMessage>,
+    /// The reply as received from the backend, before trimming.
+    pub raw_response: Option<String>,
+    pub actions: Vec<ActionPayload>,
+    /// Tokens sent to the model for this entity since the log was inserted.
+    pub prompt_tokens: usize,
+    /// Tokens received from the model for this entity since the log was inserted.
+    pub response_tokens: usize,
+}
+
+/// Opt-in per-entity record of prompts and replies. Recording costs a copy of each prompt, so
+/// only insert it while debugging.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct AiDebugLog {
+    pub entries: HashMap<Entity, AiDebugEntry>,
+}
+
+impl AiDebugLog {
+    pub fn get(&self, entity: Entity) -> Option<&AiDebugEntry> {
+        self.entries.get(&entity)
+    }
+
+    /// Record the messages of request `request_id` sent for `entity`.
+    pub fn record_prompt(
+        &mut self,
+        entity: Entity,
+        request_id: u64,
+        prompt: Vec<AiMessage>,
+        tokenizer: &dyn Tokenizer,
+    ) {
+        let entry = self.entries.entry(entity).or_default();
+        entry.prompt_tokens += prompt
+            .iter()
+            .filter_map(|m| m.text())
+            .map(|text| tokenizer.count(text))
+            .sum::<usize>();
+        entry.request_id = Some(request_id);
+        entry.prompt = prompt;
+    }
+
+    /// Record the reply to request `request_id` and the actions parsed from it.
+    pub fn record_response(
+        &mut self,
+        entity: Entity,
+        request_id: u64,
+        response: &str,
+        actions: &[ActionPayload],
+        tokenizer: &dyn Tokenizer,
+    ) {
+        let entry = self.entries.entry(entity).or_default();
+        entry.response_tokens += tokenizer.count(response);
+        entry.request_id = Some(request_id);
+        entry.raw_response = Some(response.to_string());
+        entry.actions = actions.to_vec();
+    }
+}
@@ -0,0 +1,155 @@
+//! Sandboxed world mutations.
+//!
+//! Handlers that mutate the world through [`AiCommands`] instead of `Commands` can be run in a
+//! sandbox: while [`CommandJournal::sandboxed`] is set, their mutations are recorded as
+//! [`JournalEntry`]s instead of being applied. Tools review the journal, then either apply it
+//! in an explicit step with [`apply_command_journal`] or reject it wholesale.
+//!
+//! # Example
+//! ```ignore
+//! app.register_ai_action::<SpawnAction, _, _>(|In(a): In<SpawnAction>, mut cmds: AiCommands| {
+//!     cmds.spawn(format!("spawn {}", a.name), (Name::new(a.name), Transform::default()));
+//! });
+//! app.world_mut().resource_mut::<CommandJournal>().sandboxed = true;
+//!
+//! // Later, after review:
+//! for entry in app.world().resource::<CommandJournal>().entries() {
+//!     println!("{}", entry.description);
+//! }
+//! apply_command_journal(app.world_mut()); // or: journal.reject();
+//! ```
+
+use bevy::ecs::system::{Command, SystemParam};
+use bevy::ecs::world::CommandQueue;
+use bevy::prelude::*;
+
+/// A recorded world mutation awaiting review.
+pub struct JournalEntry {
+    /// Human-readable description shown to reviewers.
+    pub description: String,
+    queue: CommandQueue,
+}
+
+impl std::fmt::Debug for JournalEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JournalEntry")
+            .field("description", &self.description)
+            .finish()
+    }
+}
+
+/// Resource holding mutations recorded by [`AiCommands`] while sandboxed.
+#[derive(Resource, Default, Debug)]
+pub struct CommandJournal {
+    /// When set, [`AiCommands`] records mutations instead of applying them.
+    pub sandboxed: bool,
+    entries: Vec<JournalEntry>,
+}
+
+impl CommandJournal {
+    /// A journal that starts in sandboxed mode.
+    pub fn sandboxed() -> Self {
+        Self {
+            sandboxed: true,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Record a mutation without applying it.
+    pub fn record(&mut self, description: impl Into<String>, command: impl Command) {
+        let mut queue = CommandQueue::default();
+        queue.push(command);
+        self.entries.push(JournalEntry {
+            description: description.into(),
+            queue,
+        });
+    }
+
+    /// Recorded mutations, in the order they were made.
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Discard every recorded mutation, returning how many were rejected.
+    pub fn reject(&mut self) -> usize {
+        let count = self.entries.len();
+        self.entries.clear();
+        count
+    }
+
+    /// Remove and return every recorded mutation.
+    pub fn take(&mut self) -> Vec<JournalEntry> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+/// Apply every recorded mutation in order, returning how many were applied.
+pub fn apply_command_journal(world: &mut World) -> usize {
+    let Some(mut journal) = world.get_resource_mut::<CommandJournal>() else {
+        return 0;
+    };
+    let entries = journal.take();
+    let count = entries.len();
+    for mut entry in entries {
+        debug!("Applying journaled command: {}", entry.description);
+        entry.queue.apply(world);
+    }
+    count
+}
+
+/// System parameter for handlers whose world mutations may be sandboxed.
+///
+/// Mutations go straight to `Commands` unless the [`CommandJournal`] is sandboxed, in which
+/// case they are recorded for review.
+#[derive(SystemParam)]
+pub struct AiCommands<'w, 's> {
+    commands: Commands<'w, 's>,
+    journal: ResMut<'w, CommandJournal>,
+}
+
+impl AiCommands<'_, '_> {
+    /// Whether mutations are currently being recorded instead of applied.
+    pub fn is_sandboxed(&self) -> bool {
+        self.journal.sandboxed
+    }
+
+    /// Queue a command, or record it if sandboxed.
+    pub fn queue(&mut self, description: impl Into<String>, command: impl Command) {
+        if self.journal.sandboxed {
+            self.journal.record(description, command);
+        } else {
+            self.commands.queue(command);
+        }
+    }
+
+    /// Spawn `bundle`, or record the spawn if sandboxed.
+    pub fn spawn(&mut self, description: impl Into<String>, bundle: impl Bundle) {
+        self.queue(description, move |world: &mut World| {
+            world.spawn(bundle);
+        });
+    }
+
+    /// Insert `bundle` on `entity`, or record the insert if sandboxed.
+    pub fn insert(&mut self, description: impl Into<String>, entity: Entity, bundle: impl Bundle) {
+        self.queue(description, move |world: &mut World| {
+            if let Ok(mut e) = world.get_entity_mut(entity) {
+                e.insert(bundle);
+            }
+        });
+    }
+
+    /// Despawn `entity`, or record the despawn if sandboxed.
+    pub fn despawn(&mut self, description: impl Into<String>, entity: Entity) {
+        self.queue(description, move |world: &mut World| {
+            world.despawn(entity);
+        });
+    }
+}
@@ -0,0 +1,114 @@
+//! What each NPC is allowed to know.
+//!
+//! Context is gathered the same way for every entity, so without limits a random villager
+//! could repeat the dungeon boss's weakness. Knowledge can be tagged in two places:
+//!
+//! - context systems registered with
+//!   [`AiSystemContextStore::add_scoped_system`](crate::context::AiSystemContextStore::add_scoped_system)
+//!   only run for entities allowed to see one of their tags;
+//! - [`SemanticMemory`](crate::memory::SemanticMemory) entries whose metadata has a `"scope"`
+//!   (a tag or a list of tags) are only recalled by such entities.
+//!
+//! A [`KnowledgeScope`] component lists the tags an entity may see. Untagged knowledge is
+//! visible to everyone, and an entity without a scope only sees untagged knowledge.
+//!
+//! # Example
+//! ```ignore
+//! app.world_mut()
+//!     .resource_mut::<AiSystemContextStore>()
+//!     .add_scoped_system(["boss_lore"], |_: AiEntity| {
+//!         Some(AiMessage::system("The Lich fears silver."))
+//!     });
+//!
+//! memory.remember(&embedder, "The vault code is 4-1-7", json!({ "scope": "thieves_guild" }))?;
+//!
+//! commands.spawn((AI, Name::new("Sage"), KnowledgeScope::new(["boss_lore", "town"])));
+//! commands.spawn((AI, Name::new("Villager"), KnowledgeScope::new(["town"])));
+//! ```
+
+use bevy::prelude::*;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Component listing the knowledge tags an entity may see.
+#[derive(Component, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct KnowledgeScope {
+    pub tags: HashSet<String>,
+    /// See all knowledge regardless of tags.
+    pub everything: bool,
+}
+
+impl KnowledgeScope {
+    pub fn new(tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            tags: tags.into_iter().map(Into::into).collect(),
+            everything: false,
+        }
+    }
+
+    /// Scope seeing all knowledge, e.g. for a narrator.
+    pub fn everything() -> Self {
+        Self {
+            everything: true,
+            ..default()
+        }
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    /// Whether knowledge tagged with `tags` is visible: untagged knowledge always is, tagged
+    /// knowledge if one of its tags is in the scope.
+    pub fn allows<S: AsRef<str>>(&self, tags: &[S]) -> bool {
+        self.everything || tags.is_empty() || tags.iter().any(|t| self.tags.contains(t.as_ref()))
+    }
+}
+
+/// Whether an entity with `scope` (or none) may see knowledge tagged with `tags`.
+pub fn visible<S: AsRef<str>>(scope: Option<&KnowledgeScope>, tags: &[S]) -> bool {
+    match scope {
+        Some(scope) => scope.allows(tags),
+        None => tags.is_empty(),
+    }
+}
+
+/// Tags in the `"scope"` field of a memory's metadata: a string or a list of strings.
+pub fn metadata_tags(metadata: &Value) -> Vec<&str> {
+    match metadata.get("scope") {
+        Some(Value::String(tag)) => vec![tag.as_str()],
+        Some(Value::Array(tags)) => tags.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tagged_knowledge_needs_a_matching_scope() {
+        let villager = KnowledgeScope::new(["town"]);
+        let (boss_lore, town, untagged) = (
+            json!({ "scope": ["boss_lore", "sages"] }),
+            json!({ "scope": "town" }),
+            json!({ "source": "ItemStolen" }),
+        );
+        let boss_lore = metadata_tags(&boss_lore);
+        let town = metadata_tags(&town);
+        let untagged = metadata_tags(&untagged);
+
+        assert!(visible(Some(&villager), &town));
+        assert!(!visible(Some(&villager), &boss_lore));
+        assert!(visible(
+            Some(&villager.clone().with_tag("sages")),
+            &boss_lore
+        ));
+        assert!(visible(Some(&KnowledgeScope::everything()), &boss_lore));
+        assert!(visible(None, &untagged));
+        assert!(!visible(None, &town));
+    }
+}
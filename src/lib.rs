@@ -1,10 +1,16 @@
 //! Dialogue plugin for Bevy: lightweight speaker/receiver abstraction + pluggable local AI (gpt4all backend optional)
 pub mod dialogue;
 
+pub mod error;
+
 pub mod rag;
 
 pub mod models;
 
+pub mod model_cache;
+
+mod download;
+
 pub mod actions;
 
 pub mod parse;
@@ -14,30 +20,291 @@ mod app_ext;
 // Test helpers (exposed to tests & dev tooling)
 pub mod test_helpers;
 
+pub mod test_fixture;
+
 pub use crate::test_helpers::{ask_ai_and_wait, assert_ai_response};
 
 pub mod context;
 
+pub mod spatial;
+
+pub mod transcript;
+
+pub mod persona;
+
+pub mod conversation;
+
+pub mod opinion;
+
+pub mod bake;
+
+pub mod embedding;
+
+pub mod memory;
+
+pub mod journal;
+
+pub mod inspect;
+
+pub mod prompts;
+
+pub mod wire;
+
+pub mod budget;
+
+pub mod commands_ext;
+
+pub mod tokenizer;
+
+pub mod server;
+
+pub mod sanitize;
+
+pub mod http;
+
+pub mod attribution;
+
+pub mod clarify;
+
+pub mod log_sink;
+
+pub mod health;
+
+pub mod utility;
+
+pub mod swap;
+
+pub mod status;
+
+pub mod batch;
+
+pub mod rate_limit;
+
+pub mod history;
+
+pub mod completion;
+
+pub mod sequence;
+
+pub mod planner;
+
+pub mod behavior;
+
+pub mod scorer;
+
+pub mod scheduler;
+
+pub mod snapshot;
+
+pub mod faction;
+
+pub mod knowledge;
+
+pub mod documents;
+
+pub mod chunking;
+
+pub mod retrieval;
+
+pub mod vector_index;
+
+pub mod quest;
+
+pub mod generate;
+
+pub mod bark;
+
+pub mod locale;
+
+pub mod moderation;
+
+pub mod postprocess;
+
+pub mod tool_conversation;
+
+pub mod streaming;
+
+#[cfg(feature = "speech")]
+pub mod speech;
+
+#[cfg(feature = "tts")]
+pub mod tts;
+
+#[cfg(feature = "yarnspinner")]
+pub mod yarn;
+
+#[cfg(feature = "control")]
+pub mod control;
+
+#[cfg(feature = "bevy_egui")]
+pub mod debug_ui;
+
+#[cfg(feature = "debug_gizmos")]
+pub mod gizmos;
+
 // Re-export the derive macro
 pub use bevy_real_ai_derive::AiAction;
 
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "inventory")]
+    pub use inventory;
+}
+
+/// Used by `#[derive(AiAction)]` to collect action metadata when the `inventory` feature is on.
+#[doc(hidden)]
+#[cfg(feature = "inventory")]
+#[macro_export]
+macro_rules! __submit_action_metadata {
+    ($metadata:expr) => {
+        $crate::__private::inventory::submit! { $metadata }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "inventory"))]
+#[macro_export]
+macro_rules! __submit_action_metadata {
+    ($metadata:expr) => {};
+}
+
 pub mod prelude {
     pub use crate::AiAction;
     pub use crate::actions::{
-        ActionPayload, AiActionEvent, AiActionRegistry, PendingAiActions, prompt_typed_action,
+        ActionCatalog, ActionCatalogEntry, ActionPayload, ActionScope, AiActionApproval,
+        AiActionAwaitingApproval, AiActionEvent, AiActionMetadata, AiActionRegistry,
+        AiActionRejected, AiActions, AiActionsPlugin, AiDryRun, ApprovalEntry, PendingAiActions,
+        PendingApproval, PendingTypedRequest, WouldExecute, prompt_typed_action,
+        prompt_typed_action_async,
     };
     pub use crate::app_ext::AiAppExt;
+    pub use crate::attribution::{AiAttribution, AiGenerated, ResponseOrigin};
+    pub use crate::bake::{BakeDrift, BakeFingerprint, BakeJob, BakedContent, ContentBaker};
+    pub use crate::bark::{Bark, BarkListener, BarkPlugin, BarkPool, BarkProfile, BarkTrigger};
+    pub use crate::batch::{AiBatching, AiBatchingPlugin};
+    pub use crate::behavior::{
+        AiBehaviorPlugin, AiDecisionNode, AiNodeReplies, AiNodeStatus, AiNodes, AiUtteranceNode,
+    };
+    pub use crate::budget::{AiFrameBudget, AiFrameUsage};
+    pub use crate::chunking::Chunker;
+    pub use crate::clarify::{AiClarificationRequested, NeedsClarification, PendingClarification};
+    pub use crate::commands_ext::AiEntityCommandsExt;
+    pub use crate::completion::{
+        ActionCompletions, ActionTicket, ActionTickets, AiActionCompleted,
+    };
     pub use crate::context::{
-        AI, AIAware, AiContextGatherConfig, AiEntity, AiSystemContextStore, ContextGatherRequest,
+        AI, AIAware, AiContextEmptyEvent, AiContextGatherConfig, AiContextPlugin, AiEntity,
+        AiSystemContextStore, ContextGatherRequest, GatheredContext,
+    };
+    #[cfg(feature = "control")]
+    pub use crate::control::{AiControlPlugin, AiControlServer};
+    pub use crate::conversation::{
+        Conversation, ConversationEndedEvent, ConversationLine, ConversationLineEvent,
+        ConversationPlugin,
     };
+    #[cfg(feature = "bevy_egui")]
+    pub use crate::debug_ui::{AiDebugUi, AiDebugUiPlugin};
     pub use crate::dialogue::{
-        AIDialoguePlugin, AiRequest, DialogueReceiver, DialogueRequest, DialogueResponse, LocalAi,
-        LocalAiHandle, ModelDownloadProgressEvent, ModelLoadCompleteEvent, PendingModelLoad,
-        PendingModelLoads, on_model_load_complete, start_model_load,
+        AIDialoguePlugin, AiBackendSwapped, AiCorePlugin, AiRequest, AiRequestDeduplicated,
+        AiResponseEvent, AiResponseLimit, AiResponseOversized, AiStaleResponse, AiSystemSet,
+        AiTypedResponse, DialogueReceiver, DialogueRequest, DialogueResponse, GlobalSystemPrompt,
+        LocalAi, LocalAiHandle, ModelDownloadProgressEvent, ModelLoadCompleteEvent,
+        ModelUnloadedEvent, OversizePolicy, PendingModelLoad, PendingModelLoads, ResponsePolicy,
+        TypedResponse, on_model_load_complete, start_model_load,
+    };
+    pub use crate::documents::{AiDocument, AiDocumentPlugin, DocumentChunk, DocumentStore};
+    pub use crate::embedding::{AiEmbedder, LocalEmbedder, cosine_similarity};
+    pub use crate::error::AiError;
+    pub use crate::faction::{Faction, FactionPlugin, FactionRelations, Relationships, Stance};
+    pub use crate::generate::{AiGenerate, AiGenerateCache, AiGeneratePlugin, AiGeneratedBatch};
+    #[cfg(feature = "debug_gizmos")]
+    pub use crate::gizmos::{AiAwarenessGizmoPlugin, AiAwarenessGizmos, ShowAiAwarenessGizmo};
+    pub use crate::health::{
+        AiHealthCheckPlugin, BackendAvailability, BackendAvailabilityEvent, backend_available,
+    };
+    pub use crate::history::{ActionHistory, ActionOutcome, ActionRecord};
+    pub use crate::http::HttpLocalAi;
+    pub use crate::inspect::{AiDebugEntry, AiDebugLog, AiRegistryInfo};
+    pub use crate::journal::{AiCommands, CommandJournal, apply_command_journal};
+    pub use crate::knowledge::KnowledgeScope;
+    pub use crate::locale::AiLocale;
+    pub use crate::log_sink::{AiLogEvent, AiLogRecord, AiLogSink, AiLogWriter, JsonlLogWriter};
+    pub use crate::memory::{
+        MemoryCapturePlugin, MemoryConsolidation, MemoryConsolidationPlugin, MemoryDecay,
+        MemoryEntry, MemoryMatch, SemanticMemory, SemanticMemoryPlugin,
+    };
+    pub use crate::model_cache::{CachedModel, ModelCache};
+    pub use crate::models::{
+        AIModel, AiModelBuilder, Device, DownloadState, ModelType, SecureString,
+    };
+    pub use crate::moderation::{
+        AiOutputBlocked, AiOutputFilter, AiOutputModeration, KeywordFilter, ModelClassifier,
+    };
+    pub use crate::opinion::{Deed, DeedKind, OpinionLedger, OpinionPlugin};
+    pub use crate::parse::{
+        AiParsable, JsonStream, LenientJson, build_typed_prompt, extract_and_parse_json,
+    };
+    pub use crate::persona::{AiPersona, AiVoice};
+    pub use crate::planner::{
+        Goal, GoalPlanFailed, GoalPlanner, GoalPlannerPlugin, GoalStatus, PlanState,
+    };
+    pub use crate::postprocess::{
+        AiResponseProcessors, mask_words, strip_emoji, strip_markdown, strip_role_prefix,
+    };
+    pub use crate::prompts::{PromptTemplates, render_template};
+    pub use crate::quest::{
+        Quest, QuestGenerated, QuestGeneration, QuestGenerationFailed, QuestGenerator,
+        QuestObjective, QuestPlugin, QuestReward, QuestSpec,
+    };
+    pub use crate::rag::{AiContext, AiMessage, AiRagPlugin, ChatHistory};
+    pub use crate::rate_limit::{
+        ActionBudgets, ActionLimit, AiActionThrottled, AiCooldown, AiRateLimiter,
+        AiRequestThrottled, ThrottlePolicy,
+    };
+    pub use crate::retrieval::Bm25;
+    pub use crate::sanitize::{
+        AiContextFlagged, ContextInjectionGuard, InjectionPatterns, InputModerator,
+        PlayerInputRejected, PlayerInputSanitizer, quote_untrusted,
+    };
+    pub use crate::scheduler::{AiScheduler, AiSchedulerPlugin, AiThinkTurn, AiThinker};
+    pub use crate::scorer::{AiScorer, AiScorerPlugin, AiScoresUpdated, AiScoring};
+    pub use crate::sequence::{ActionSequence, ActionSequenceFailed, ActionSequenceFinished};
+    pub use crate::server::{AiServerInbox, AiServerOutbox, AiServerPlugin};
+    pub use crate::snapshot::{Snapshot, SnapshotBaselines, SnapshotDiff, WorldSnapshot};
+    pub use crate::spatial::{
+        RelativeDirection, RelativePlacement, SpatialPlane, describe_relative,
+    };
+    #[cfg(feature = "speech")]
+    pub use crate::speech::{
+        AiSpeechInput, AiSpeechInputPlugin, SpeechInputMode, SpeechTranscribed,
+    };
+    pub use crate::status::{
+        AiPipelineStatus, InFlightRequest, ModelSlot, ModelSlotState, PipelineError,
+    };
+    pub use crate::streaming::AiEarlyActions;
+    pub use crate::swap::{AiModelSwapPlugin, AiModelSwapSettings, ConversationRecap};
+    pub use crate::tokenizer::{
+        AiTokenizer, ApproxTokenizer, BpeTokenizer, FnTokenizer, Tokenizer,
+    };
+    pub use crate::tool_conversation::{
+        ToolConversation, ToolConversationPlugin, ToolConversationState,
+        ToolConversationTransition, ToolResult,
+    };
+    pub use crate::transcript::{Transcript, TranscriptEntry, TranscriptRole};
+    #[cfg(feature = "tts")]
+    pub use crate::tts::{
+        AiSpeaker, AiTextToSpeech, AiTextToSpeechPlugin, SpeechClip, SpeechEnded, SpeechStarted,
+        TextToSpeech,
+    };
+    pub use crate::utility::{
+        AiUtility, AiUtilityLimits, AiUtilityPlugin, AiUtilityQueue, AiUtilityStats, AiUtilityTask,
+    };
+    pub use crate::vector_index::EmbedderFingerprint;
+    pub use crate::wire::{
+        WIRE_FORMAT_VERSION, WireActionEvent, WireDialogueRequest, WireDialogueResponse,
     };
-    pub use crate::models::{AIModel, AiModelBuilder, DownloadState, ModelType, SecureString};
-    pub use crate::parse::{AiParsable, build_typed_prompt, extract_and_parse_json};
-    pub use crate::rag::{AiContext, AiMessage, ChatHistory};
+    #[cfg(feature = "yarnspinner")]
+    pub use crate::yarn::{AiYarnBridge, AiYarnBridgePlugin};
     // Keep kalosm exports for backward compatibility
     pub use kalosm::language::{Parse, Parser, Schema};
 }
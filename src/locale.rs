@@ -0,0 +1,100 @@
+//! Translating replies into the player's language.
+//!
+//! Small local models are often good in English only. With an [`AiLocale`] resource, every
+//! plain-text reply generated by the model is sent through a second prompt translating it
+//! into the player's locale before it reaches the `DialogueReceiver`, [`AiResponseEvent`]s
+//! and transcripts. The translation runs on the same background task as the reply, with the
+//! dialogue backend or a dedicated translation model.
+//!
+//! Only text meant for the player is translated: typed, data and classify replies are game
+//! data, and authored (preprogrammed) replies are the game's to localize. Actions in a reply
+//! are parsed before translation. The translated reply is then held to the
+//! [`AiResponseLimit`](crate::dialogue::AiResponseLimit) and checked by any
+//! [`AiOutputModeration`](crate::moderation::AiOutputModeration). When translating fails the
+//! original reply is kept.
+//!
+//! The prompt is the [`TRANSLATE`] template, with `locale` and `text` placeholders.
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(AiLocale::new("German"));
+//!
+//! // Or with a model that translates well
+//! app.insert_resource(AiLocale::new("pt-BR").with_translator(Arc::new(HttpLocalAi::new(url))));
+//! ```
+//!
+//! [`AiResponseEvent`]: crate::dialogue::AiResponseEvent
+
+use bevy::prelude::*;
+use std::sync::Arc;
+
+use crate::dialogue::LocalAi;
+use crate::error::AiError;
+use crate::prompts::{PromptTemplates, TRANSLATE, render_template};
+use crate::rag::AiMessage;
+
+/// Resource asking for replies in the player's language.
+#[derive(Resource, Clone, Default)]
+pub struct AiLocale {
+    /// Language replies are translated into, e.g. "German" or "pt-BR". `None` keeps replies
+    /// as generated.
+    pub locale: Option<String>,
+    /// Backend doing the translation. `None` uses the dialogue backend.
+    pub translator: Option<Arc<dyn LocalAi>>,
+}
+
+impl AiLocale {
+    pub fn new(locale: impl Into<String>) -> Self {
+        Self {
+            locale: Some(locale.into()),
+            translator: None,
+        }
+    }
+
+    pub fn with_translator(mut self, translator: Arc<dyn LocalAi>) -> Self {
+        self.translator = Some(translator);
+        self
+    }
+
+    /// What the background task needs to translate replies, if a locale is set.
+    pub(crate) fn translation(&self, templates: Option<&PromptTemplates>) -> Option<Translation> {
+        let locale = self.locale.clone()?;
+        let template = templates
+            .and_then(|t| t.get(TRANSLATE))
+            .map(str::to_string)
+            .or_else(|| {
+                PromptTemplates::default()
+                    .get(TRANSLATE)
+                    .map(str::to_string)
+            })?;
+        Some(Translation {
+            locale,
+            template,
+            translator: self.translator.clone(),
+        })
+    }
+}
+
+/// Translation settings captured for one request.
+#[derive(Clone)]
+pub(crate) struct Translation {
+    locale: String,
+    template: String,
+    translator: Option<Arc<dyn LocalAi>>,
+}
+
+impl Translation {
+    /// `text` in the locale, asked of the translator or else `backend`.
+    pub(crate) fn translate(&self, backend: &dyn LocalAi, text: &str) -> Result<String, AiError> {
+        let prompt = render_template(&self.template, &[("locale", &self.locale), ("text", text)])
+            .map_err(AiError::Backend)?;
+        let messages = [AiMessage::skip_default_context(), AiMessage::user(prompt)];
+        let backend = self.translator.as_deref().unwrap_or(backend);
+        let translated = backend.prompt(&messages)?;
+        let translated = translated.trim();
+        if translated.is_empty() {
+            return Err(AiError::Backend("empty translation".to_string()));
+        }
+        Ok(translated.to_string())
+    }
+}
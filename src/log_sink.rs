@@ -0,0 +1,455 @@
+//! Audit trail of everything sent to and received from the model.
+//!
+//! Insert an [`AiLogSink`] resource and the dialogue plugin records, for every request:
+//!
+//! 1. the prompt, message by message, with gathered context marked as `context`,
+//! 2. the raw reply and its origin,
+//! 3. the parse result: the actions found in the reply, or the error,
+//! 4. every action handed to a registered handler, with its validation error if any.
+//!
+//! Records go to a background thread, so logging never blocks a frame. The built-in
+//! [`JsonlLogWriter`] appends one JSON object per line and rotates files by size; any
+//! [`AiLogWriter`] (or a closure, via [`AiLogSink::callback`]) can receive them instead.
+//! Redactors run on the main thread before a record leaves it, so secrets never reach the
+//! writer.
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(
+//!     AiLogSink::jsonl("logs/ai")
+//!         .redact_secret(api_key.as_str())
+//!         .with_redactor(|text| EMAIL.replace_all(text, "[email]").into_owned()),
+//! );
+//! ```
+//! ```sh
+//! grep '"type":"action"' logs/ai/ai.jsonl | tail
+//! ```
+
+use bevy::prelude::*;
+use flume::{Sender, unbounded};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::actions::ActionPayload;
+use crate::attribution::ResponseOrigin;
+
+/// Replacement text for redacted secrets.
+pub const REDACTED: &str = "[redacted]";
+
+/// One message of a logged prompt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedMessage {
+    /// `system`, `user`, `context` (gathered for the entity) or `payload`.
+    pub role: String,
+    pub text: String,
+}
+
+/// What happened, per [`AiLogRecord`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AiLogEvent {
+    Prompt {
+        messages: Vec<LoggedMessage>,
+    },
+    Response {
+        raw: String,
+        origin: ResponseOrigin,
+    },
+    Parsed {
+        actions: Vec<ActionPayload>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Action {
+        name: String,
+        params: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// One line of the audit trail.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AiLogRecord {
+    /// Milliseconds since the Unix epoch.
+    pub time_ms: u64,
+    /// `Entity::to_bits` of the requester or action target.
+    pub entity: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    #[serde(flatten)]
+    pub event: AiLogEvent,
+}
+
+impl AiLogRecord {
+    pub fn new(entity: Entity, request_id: Option<u64>, event: AiLogEvent) -> Self {
+        Self {
+            time_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            entity: entity.to_bits(),
+            request_id,
+            event,
+        }
+    }
+}
+
+/// Destination of log records. Runs on the sink's background thread.
+pub trait AiLogWriter: Send + 'static {
+    fn write(&mut self, record: &AiLogRecord) -> Result<(), String>;
+
+    fn flush(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<F> AiLogWriter for F
+where
+    F: FnMut(&AiLogRecord) + Send + 'static,
+{
+    fn write(&mut self, record: &AiLogRecord) -> Result<(), String> {
+        self(record);
+        Ok(())
+    }
+}
+
+/// Writes records as JSON lines to `<dir>/<prefix>.jsonl`.
+///
+/// When the file would grow past `max_bytes` it is renamed to `<prefix>.1.jsonl` (older files
+/// shift to `.2`, `.3`, ...) and a new file is started; at most `max_files` files are kept.
+pub struct JsonlLogWriter {
+    dir: PathBuf,
+    prefix: String,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<File>,
+    written: u64,
+}
+
+impl JsonlLogWriter {
+    /// Rotate at 10 MB, keeping 5 files, named `ai.jsonl`, `ai.1.jsonl`, ...
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: "ai".to_string(),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            file: None,
+            written: 0,
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn with_rotation(mut self, max_bytes: u64, max_files: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.max_files = max_files.max(1);
+        self
+    }
+
+    /// Path of the current file (`index` 0) or of a rotated one.
+    pub fn path(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            self.dir.join(format!("{}.jsonl", self.prefix))
+        } else {
+            self.dir.join(format!("{}.{}.jsonl", self.prefix, index))
+        }
+    }
+
+    fn open(&mut self) -> Result<&mut File, String> {
+        if self.file.is_none() {
+            std::fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+            let path = self.path(0);
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            self.written = file.metadata().map_or(0, |m| m.len());
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("log file was just opened"))
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        self.file = None;
+        let _ = std::fs::remove_file(self.path(self.max_files - 1));
+        for index in (0..self.max_files - 1).rev() {
+            let from = self.path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.path(index + 1))
+                    .map_err(|e| format!("Failed to rotate {}: {}", from.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AiLogWriter for JsonlLogWriter {
+    fn write(&mut self, record: &AiLogRecord) -> Result<(), String> {
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())? + "\n";
+        let len = line.len() as u64;
+        self.open()?;
+        if self.written > 0 && self.written + len > self.max_bytes {
+            self.rotate()?;
+        }
+        self.open()?
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to write AI log: {}", e))?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        match &mut self.file {
+            Some(file) => file.flush().map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+enum SinkMessage {
+    Record(AiLogRecord),
+    Flush(Sender<()>),
+}
+
+type Redactor = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Resource enabling the audit trail. See the [module docs](self).
+#[derive(Resource, Clone)]
+pub struct AiLogSink {
+    tx: Sender<SinkMessage>,
+    redactors: Vec<Redactor>,
+}
+
+impl AiLogSink {
+    /// Send records to `writer` on a background thread.
+    pub fn new(mut writer: impl AiLogWriter) -> Self {
+        let (tx, rx) = unbounded();
+        std::thread::spawn(move || {
+            for message in rx.iter() {
+                match message {
+                    SinkMessage::Record(record) => {
+                        if let Err(e) = writer.write(&record) {
+                            error!("AI log sink: {}", e);
+                        }
+                    }
+                    SinkMessage::Flush(done) => {
+                        if let Err(e) = writer.flush() {
+                            error!("AI log sink: {}", e);
+                        }
+                        let _ = done.send(());
+                    }
+                }
+            }
+            let _ = writer.flush();
+        });
+        Self {
+            tx,
+            redactors: Vec::new(),
+        }
+    }
+
+    /// Log to rotating JSONL files in `dir` (see [`JsonlLogWriter`]).
+    pub fn jsonl(dir: impl Into<PathBuf>) -> Self {
+        Self::new(JsonlLogWriter::new(dir))
+    }
+
+    /// Hand every record to `callback`, on the sink's background thread.
+    pub fn callback(callback: impl FnMut(&AiLogRecord) + Send + 'static) -> Self {
+        Self::new(callback)
+    }
+
+    /// Rewrite every logged string with `redactor`.
+    pub fn with_redactor(
+        mut self,
+        redactor: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.redactors.push(Arc::new(redactor));
+        self
+    }
+
+    /// Replace every occurrence of `secret` with [`REDACTED`].
+    pub fn redact_secret(self, secret: impl Into<String>) -> Self {
+        let secret = crate::models::SecureString::new(secret.into());
+        if secret.is_empty() {
+            return self;
+        }
+        self.with_redactor(move |text| text.replace(secret.as_str(), REDACTED))
+    }
+
+    /// Apply the redactors to `text`.
+    pub fn redact(&self, text: &str) -> String {
+        self.redactors
+            .iter()
+            .fold(text.to_string(), |text, redactor| redactor(&text))
+    }
+
+    fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.redact(s)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| self.redact_value(v)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.redact_value(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Redact and queue a record.
+    pub fn log(&self, entity: Entity, request_id: Option<u64>, event: AiLogEvent) {
+        let event = if self.redactors.is_empty() {
+            event
+        } else {
+            self.redact_event(event)
+        };
+        let _ = self.tx.send(SinkMessage::Record(AiLogRecord::new(
+            entity, request_id, event,
+        )));
+    }
+
+    fn redact_event(&self, event: AiLogEvent) -> AiLogEvent {
+        match event {
+            AiLogEvent::Prompt { messages } => AiLogEvent::Prompt {
+                messages: messages
+                    .into_iter()
+                    .map(|m| LoggedMessage {
+                        role: m.role,
+                        text: self.redact(&m.text),
+                    })
+                    .collect(),
+            },
+            AiLogEvent::Response { raw, origin } => AiLogEvent::Response {
+                raw: self.redact(&raw),
+                origin,
+            },
+            AiLogEvent::Parsed { actions, error } => AiLogEvent::Parsed {
+                actions: actions
+                    .into_iter()
+                    .map(|a| ActionPayload {
+                        name: a.name,
+                        params: self.redact_value(&a.params),
+                    })
+                    .collect(),
+                error: error.map(|e| self.redact(&e)),
+            },
+            AiLogEvent::Action {
+                name,
+                params,
+                error,
+            } => AiLogEvent::Action {
+                name,
+                params: self.redact_value(&params),
+                error: error.map(|e| self.redact(&e)),
+            },
+        }
+    }
+
+    /// Block until every record queued so far has been written and flushed.
+    pub fn flush(&self) {
+        let (done, wait) = unbounded();
+        if self.tx.send(SinkMessage::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+/// Log messages of a prompt; `context` is the range of messages gathered for the entity.
+pub(crate) fn logged_messages(
+    messages: &[crate::rag::AiMessage],
+    context: std::ops::Range<usize>,
+) -> Vec<LoggedMessage> {
+    use crate::rag::AiMessage;
+    messages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| {
+            let role = match m {
+                AiMessage::SkipDefaultContext => return None,
+                _ if context.contains(&i) => "context",
+                AiMessage::System(_) => "system",
+                AiMessage::User(_) => "user",
+                AiMessage::Payload(_) => "payload",
+                AiMessage::Assistant(_) => "assistant",
+            };
+            let text = match m {
+                AiMessage::Payload(p) => serde_json::to_string(p).unwrap_or_default(),
+                other => other.text().unwrap_or_default().to_string(),
+            };
+            Some(LoggedMessage {
+                role: role.to_string(),
+                text,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(text: &str) -> AiLogRecord {
+        AiLogRecord::new(
+            Entity::from_bits(1),
+            Some(1),
+            AiLogEvent::Response {
+                raw: text.to_string(),
+                origin: ResponseOrigin::Generated,
+            },
+        )
+    }
+
+    #[test]
+    fn jsonl_writer_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("ai_log_rotate_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut writer = JsonlLogWriter::new(&dir).with_rotation(200, 3);
+        for i in 0..10 {
+            writer.write(&record(&format!("reply {:0>60}", i))).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert!(writer.path(0).exists() && writer.path(2).exists());
+        assert!(!writer.path(3).exists());
+        let newest = std::fs::read_to_string(writer.path(0)).unwrap();
+        let last: AiLogRecord = serde_json::from_str(newest.lines().last().unwrap()).unwrap();
+        assert!(matches!(last.event, AiLogEvent::Response { raw, .. } if raw.ends_with('9')));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn secrets_are_redacted_before_writing() {
+        let (tx, rx) = unbounded();
+        let sink = AiLogSink::callback(move |r: &AiLogRecord| {
+            let _ = tx.send(serde_json::to_string(r).unwrap());
+        })
+        .redact_secret("sk-123");
+        sink.log(
+            Entity::from_bits(1),
+            None,
+            AiLogEvent::Action {
+                name: "login".to_string(),
+                params: serde_json::json!({ "key": "token sk-123" }),
+                error: None,
+            },
+        );
+        sink.flush();
+        let line = rx.try_recv().unwrap();
+        assert!(line.contains("\"type\":\"action\""));
+        assert!(line.contains("token [redacted]"));
+        assert!(!line.contains("sk-123"));
+    }
+}
@@ -0,0 +1,864 @@
+//! Semantic memory for AI entities.
+//!
+//! A [`SemanticMemory`] component stores remembered facts together with their embeddings.
+//! [`SemanticMemory::recall`] returns the memories closest to a query, and the
+//! [`SemanticMemoryPlugin`] registers a context-gathering system that injects the memories most
+//! relevant to the current prompt into the entity's `AiContext`. Requires an
+//! [`AiEmbedder`] resource.
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(AiModelBuilder::new().build_embedder()?)
+//!     .add_plugins(SemanticMemoryPlugin);
+//!
+//! fn on_theft(embedder: Res<AiEmbedder>, mut npcs: Query<&mut SemanticMemory>) {
+//!     let mut memory = npcs.get_mut(victim).unwrap();
+//!     memory.remember(&embedder, "The player stole my sword.", json!({ "kind": "theft" }))?;
+//! }
+//! ```
+//!
+//! # Capturing game events
+//! Instead of calling `remember` by hand, add the [`MemoryCapturePlugin`] and register events
+//! as memory sources with
+//! [`AiAppExt::capture_event`](crate::app_ext::AiAppExt::capture_event):
+//!
+//! ```ignore
+//! app.add_plugins(MemoryCapturePlugin)
+//!     .capture_event::<DamageEvent, _>(|e| Some((e.target, format!("{} hit me for {} damage", e.source_name, e.amount))));
+//! ```
+//!
+//! # Saving
+//! [`SemanticMemory::save_index`] writes the memories and their embeddings to a file that
+//! [`SemanticMemory::load_index`] reads back, as long as the embedder is the same.
+//!
+//! # Decay and consolidation
+//! Every memory has an importance; combined with its age through the entity's [`MemoryDecay`]
+//! this gives a retention score. The [`MemoryConsolidationPlugin`] periodically drops memories
+//! whose retention fell below a threshold and merges near-duplicates, optionally asking the
+//! model to summarize each merged group into a single memory in the background.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::context::{AiEntity, AiSystemContextStore};
+use crate::dialogue::{LocalAi, LocalAiHandle};
+use crate::embedding::{AiEmbedder, cosine_similarity};
+use crate::error::AiError;
+use crate::knowledge::{KnowledgeScope, metadata_tags};
+use crate::rag::AiMessage;
+use crate::retrieval::{DEFAULT_KEYWORD_WEIGHT, TermCounts, hybrid_scores};
+use crate::vector_index::{read_index, write_index};
+
+/// A single remembered fact.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub text: String,
+    /// Arbitrary game data attached to the memory (`Value::Null` when unused).
+    pub metadata: Value,
+    pub embedding: Vec<f32>,
+    /// Wall-clock time the memory was stored.
+    pub timestamp: SystemTime,
+    /// How much the memory matters, from 0.0 (trivia) to 1.0 (unforgettable).
+    pub importance: f32,
+}
+
+/// Default importance of memories stored without one.
+pub const DEFAULT_IMPORTANCE: f32 = 0.5;
+
+/// How memories fade over time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryDecay {
+    /// Age after which a memory's recency factor halves.
+    pub half_life: Duration,
+}
+
+impl Default for MemoryDecay {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl MemoryDecay {
+    /// Recency factor of `entry` at `now`, from 1.0 (just stored) towards 0.0.
+    pub fn recency(&self, entry: &MemoryEntry, now: SystemTime) -> f32 {
+        let age = now
+            .duration_since(entry.timestamp)
+            .unwrap_or_default()
+            .as_secs_f32();
+        let half_life = self.half_life.as_secs_f32().max(f32::EPSILON);
+        0.5f32.powf(age / half_life)
+    }
+
+    /// Importance × recency score of `entry` at `now`.
+    pub fn retention(&self, entry: &MemoryEntry, now: SystemTime) -> f32 {
+        entry.importance * self.recency(entry, now)
+    }
+}
+
+/// A memory returned by a recall, with its score for the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryMatch<'a> {
+    pub entry: &'a MemoryEntry,
+    /// Cosine similarity in `[-1, 1]`, blended with keyword matching by
+    /// [`SemanticMemory::recall`].
+    pub score: f32,
+}
+
+/// Component holding an entity's memories.
+#[derive(Component, Debug, Clone)]
+pub struct SemanticMemory {
+    entries: Vec<MemoryEntry>,
+    /// Word counts of each memory, in the order of `entries`.
+    terms: Vec<TermCounts>,
+    /// How many memories the gather system injects into the context.
+    pub recall_k: usize,
+    /// Memories scoring lower than this are not recalled.
+    pub min_score: f32,
+    /// Share of keyword matching in recall scores, see [`crate::retrieval`].
+    pub keyword_weight: f32,
+    /// Optional cap on stored memories; the oldest are dropped first.
+    pub max_entries: Option<usize>,
+    /// How this entity's memories fade over time.
+    pub decay: MemoryDecay,
+}
+
+impl Default for SemanticMemory {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            terms: Vec::new(),
+            recall_k: 3,
+            min_score: 0.3,
+            keyword_weight: DEFAULT_KEYWORD_WEIGHT,
+            max_entries: None,
+            decay: MemoryDecay::default(),
+        }
+    }
+}
+
+impl SemanticMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_recall_k(mut self, k: usize) -> Self {
+        self.recall_k = k;
+        self
+    }
+
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    pub fn with_keyword_weight(mut self, keyword_weight: f32) -> Self {
+        self.keyword_weight = keyword_weight;
+        self
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn with_decay(mut self, decay: MemoryDecay) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    /// Embed `text` with `embedder` and store it with the default importance.
+    pub fn remember(
+        &mut self,
+        embedder: &AiEmbedder,
+        text: impl Into<String>,
+        metadata: Value,
+    ) -> Result<(), String> {
+        self.remember_with_importance(embedder, text, metadata, DEFAULT_IMPORTANCE)
+    }
+
+    /// Embed `text` with `embedder` and store it with the given importance (0.0 to 1.0).
+    pub fn remember_with_importance(
+        &mut self,
+        embedder: &AiEmbedder,
+        text: impl Into<String>,
+        metadata: Value,
+        importance: f32,
+    ) -> Result<(), String> {
+        let text = text.into();
+        let embedding = embedder.embed(&text)?;
+        self.insert_entry(MemoryEntry {
+            text,
+            metadata,
+            embedding,
+            timestamp: SystemTime::now(),
+            importance: importance.clamp(0.0, 1.0),
+        });
+        Ok(())
+    }
+
+    /// Store a memory with a precomputed embedding.
+    pub fn insert(&mut self, text: impl Into<String>, metadata: Value, embedding: Vec<f32>) {
+        self.insert_entry(MemoryEntry {
+            text: text.into(),
+            metadata,
+            embedding,
+            timestamp: SystemTime::now(),
+            importance: DEFAULT_IMPORTANCE,
+        });
+    }
+
+    /// Store a complete entry, keeping its timestamp.
+    pub fn insert_entry(&mut self, entry: MemoryEntry) {
+        self.terms.push(TermCounts::new(&entry.text));
+        self.entries.push(entry);
+        if let Some(max) = self.max_entries
+            && self.entries.len() > max
+        {
+            let excess = self.entries.len() - max;
+            self.entries.drain(..excess);
+            self.terms.drain(..excess);
+        }
+    }
+
+    /// The `k` memories best matching `query`, blending similarity and keywords by
+    /// [`keyword_weight`](Self::keyword_weight), best first.
+    pub fn recall(
+        &self,
+        embedder: &AiEmbedder,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<MemoryMatch<'_>>, String> {
+        let embedding = embedder.embed(query)?;
+        let candidates: Vec<(&TermCounts, &[f32])> = self
+            .terms
+            .iter()
+            .zip(&self.entries)
+            .map(|(terms, e)| (terms, e.embedding.as_slice()))
+            .collect();
+        let scores = hybrid_scores(query, &embedding, &candidates, self.keyword_weight);
+        let mut matches: Vec<MemoryMatch> = self
+            .entries
+            .iter()
+            .zip(scores)
+            .map(|(entry, score)| MemoryMatch { entry, score })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(k);
+        Ok(matches)
+    }
+
+    /// The `k` memories most similar to an embedding, best first.
+    pub fn recall_by_embedding(&self, query: &[f32], k: usize) -> Vec<MemoryMatch<'_>> {
+        let mut matches: Vec<MemoryMatch> = self
+            .entries
+            .iter()
+            .map(|entry| MemoryMatch {
+                entry,
+                score: cosine_similarity(query, &entry.embedding),
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(k);
+        matches
+    }
+
+    /// All memories, oldest first.
+    pub fn entries(&self) -> &[MemoryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.terms.clear();
+    }
+
+    /// Write the memories to `path`, see [`crate::vector_index`].
+    pub fn save_index(&self, path: impl AsRef<Path>, embedder: &AiEmbedder) -> Result<(), String> {
+        write_index(path, embedder, &self.entries)
+    }
+
+    /// Replace the memories with those saved by [`save_index`](Self::save_index). Fails,
+    /// keeping the current memories, if the file was written by another embedder or format.
+    /// Returns the number of memories read.
+    pub fn load_index(
+        &mut self,
+        path: impl AsRef<Path>,
+        embedder: &AiEmbedder,
+    ) -> Result<usize, String> {
+        self.entries = read_index(path, embedder)?;
+        self.count_terms();
+        Ok(self.entries.len())
+    }
+
+    /// Retention score of every memory at `now`, in storage order.
+    pub fn retention_scores(&self, now: SystemTime) -> Vec<f32> {
+        self.entries
+            .iter()
+            .map(|e| self.decay.retention(e, now))
+            .collect()
+    }
+
+    /// Drop faded memories and merge near-duplicates, keeping the strongest of each group.
+    pub fn consolidate(
+        &mut self,
+        config: &MemoryConsolidation,
+        now: SystemTime,
+    ) -> ConsolidationReport {
+        let decay = self.decay;
+        self.consolidate_with(config, now, |group| strongest(group, &decay, now))
+    }
+
+    /// Like [`consolidate`](Self::consolidate), but `merge` turns each group of similar
+    /// memories (two or more, oldest first) into the single memory that replaces them.
+    pub fn consolidate_with(
+        &mut self,
+        config: &MemoryConsolidation,
+        now: SystemTime,
+        mut merge: impl FnMut(Vec<MemoryEntry>) -> MemoryEntry,
+    ) -> ConsolidationReport {
+        let mut report = ConsolidationReport::default();
+
+        let before = self.entries.len();
+        let decay = self.decay;
+        self.entries
+            .retain(|e| decay.retention(e, now) >= config.drop_below);
+        report.dropped = before - self.entries.len();
+
+        let mut remaining = std::mem::take(&mut self.entries);
+        let mut kept = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let first = remaining.remove(0);
+            let mut group = vec![first];
+            let mut i = 0;
+            while i < remaining.len() {
+                if cosine_similarity(&group[0].embedding, &remaining[i].embedding)
+                    >= config.merge_similarity
+                {
+                    group.push(remaining.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+            if group.len() > 1 {
+                report.merged += group.len() - 1;
+                kept.push(merge(group));
+            } else {
+                kept.extend(group);
+            }
+        }
+        self.entries = kept;
+        self.count_terms();
+        report
+    }
+
+    /// Replace the memory equal to `old` with `new`. Returns whether it was found.
+    fn replace_entry(&mut self, old: &MemoryEntry, new: MemoryEntry) -> bool {
+        let Some(i) = self.entries.iter().position(|e| e == old) else {
+            return false;
+        };
+        self.terms[i] = TermCounts::new(&new.text);
+        self.entries[i] = new;
+        true
+    }
+
+    /// Count the words of every memory again, after the entries were replaced wholesale.
+    fn count_terms(&mut self) {
+        self.terms = self
+            .entries
+            .iter()
+            .map(|e| TermCounts::new(&e.text))
+            .collect();
+    }
+}
+
+/// The memory with the highest retention in `group`, with the group's highest importance.
+fn strongest(group: Vec<MemoryEntry>, decay: &MemoryDecay, now: SystemTime) -> MemoryEntry {
+    let importance = group.iter().map(|e| e.importance).fold(0.0, f32::max);
+    let mut best = group
+        .into_iter()
+        .max_by(|a, b| decay.retention(a, now).total_cmp(&decay.retention(b, now)))
+        .expect("merge groups are never empty");
+    best.importance = importance;
+    best
+}
+
+/// Merge a group of memories by asking `backend` to summarize them into one sentence.
+/// Falls back to the strongest memory at `now` if prompting or embedding fails.
+pub fn summarize_memories(
+    backend: &dyn LocalAi,
+    embedder: &AiEmbedder,
+    decay: &MemoryDecay,
+    group: Vec<MemoryEntry>,
+    now: SystemTime,
+) -> MemoryEntry {
+    let listing = group
+        .iter()
+        .map(|e| format!("- {}", e.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt = format!(
+        "Combine these related memories into a single short sentence written from the same point of view. Respond with the sentence only.\n{}",
+        listing
+    );
+    let summary = backend
+        .prompt(&[AiMessage::skip_default_context(), AiMessage::user(&prompt)])
+        .map(|text| text.trim().to_string())
+        .and_then(|text| {
+            embedder
+                .embed(&text)
+                .map(|embedding| (text, embedding))
+                .map_err(AiError::from)
+        });
+
+    match summary {
+        Ok((text, embedding)) if !text.is_empty() => MemoryEntry {
+            text,
+            metadata: serde_json::json!({ "merged_from": group.len() }),
+            embedding,
+            timestamp: group.iter().map(|e| e.timestamp).max().unwrap_or(now),
+            importance: group.iter().map(|e| e.importance).fold(0.0, f32::max),
+        },
+        Ok(_) => strongest(group, decay, now),
+        Err(e) => {
+            warn!("Memory summarization failed: {}", e);
+            strongest(group, decay, now)
+        }
+    }
+}
+
+/// Outcome of a consolidation pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConsolidationReport {
+    /// Memories dropped for falling below the retention threshold.
+    pub dropped: usize,
+    /// Memories folded into another one.
+    pub merged: usize,
+}
+
+/// Settings for the periodic consolidation job.
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct MemoryConsolidation {
+    /// Time between consolidation passes.
+    pub interval: Duration,
+    /// Memories whose retention (importance × recency) is below this are dropped.
+    pub drop_below: f32,
+    /// Memories at least this similar are merged.
+    pub merge_similarity: f32,
+    /// Summarize merged groups with the dialogue backend. The model answers in the
+    /// background; until then each group is kept as its strongest memory.
+    pub summarize: bool,
+    #[reflect(ignore)]
+    elapsed: Duration,
+    /// Wall-clock time of the first pass and the real time it ran at. Later passes advance
+    /// it by real time, so a wall clock set back never makes memories younger.
+    #[reflect(ignore)]
+    clock: Option<(SystemTime, Duration)>,
+}
+
+impl Default for MemoryConsolidation {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            drop_below: 0.05,
+            merge_similarity: 0.92,
+            summarize: false,
+            elapsed: Duration::ZERO,
+            clock: None,
+        }
+    }
+}
+
+impl MemoryConsolidation {
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_drop_below(mut self, drop_below: f32) -> Self {
+        self.drop_below = drop_below;
+        self
+    }
+
+    pub fn with_merge_similarity(mut self, merge_similarity: f32) -> Self {
+        self.merge_similarity = merge_similarity;
+        self
+    }
+
+    pub fn with_summarize(mut self, summarize: bool) -> Self {
+        self.summarize = summarize;
+        self
+    }
+}
+
+/// Summaries of merged memory groups generated in the background.
+pub struct MemorySummaries {
+    tx: flume::Sender<MemorySummary>,
+    rx: flume::Receiver<MemorySummary>,
+}
+
+impl Default for MemorySummaries {
+    fn default() -> Self {
+        let (tx, rx) = flume::unbounded();
+        Self { tx, rx }
+    }
+}
+
+struct MemorySummary {
+    entity: Entity,
+    /// The strongest memory of the group, standing in until the summary arrived.
+    merged: MemoryEntry,
+    summary: MemoryEntry,
+}
+
+/// Run a consolidation pass over every [`SemanticMemory`] once per `interval`. With
+/// `summarize`, merged groups are summarized on a background thread and swapped in by a later
+/// run.
+pub fn consolidate_memories(
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+    mut config: ResMut<MemoryConsolidation>,
+    mut memories: Query<(Entity, &mut SemanticMemory)>,
+    ai_handle: Option<Res<LocalAiHandle>>,
+    embedder: Option<Res<AiEmbedder>>,
+    summaries: Local<MemorySummaries>,
+) {
+    for done in summaries.rx.try_iter() {
+        let Ok((_, mut memory)) = memories.get_mut(done.entity) else {
+            continue;
+        };
+        // Gone when the memory was dropped or merged again in the meantime
+        memory.replace_entry(&done.merged, done.summary);
+    }
+
+    config.elapsed += time.delta();
+    if config.elapsed < config.interval {
+        return;
+    }
+    config.elapsed = Duration::ZERO;
+
+    let real_now = real_time.elapsed();
+    let (wall, at) = *config
+        .clock
+        .get_or_insert_with(|| (SystemTime::now(), real_now));
+    let now = wall + real_now.saturating_sub(at);
+    let summarizer = match (config.summarize, &ai_handle, &embedder) {
+        (true, Some(handle), Some(embedder)) => handle
+            .backend
+            .as_ref()
+            .map(|backend| (backend.clone(), (**embedder).clone())),
+        _ => None,
+    };
+
+    for (entity, mut memory) in memories.iter_mut() {
+        let report = match &summarizer {
+            Some((backend, embedder)) => {
+                let decay = memory.decay;
+                memory.consolidate_with(&config, now, |group| {
+                    let merged = strongest(group.clone(), &decay, now);
+                    let (backend, embedder, tx) =
+                        (backend.clone(), embedder.clone(), summaries.tx.clone());
+                    let stand_in = merged.clone();
+                    crate::models::TOKIO_RUNTIME.spawn_blocking(move || {
+                        let summary =
+                            summarize_memories(backend.as_ref(), &embedder, &decay, group, now);
+                        let _ = tx.send(MemorySummary {
+                            entity,
+                            merged: stand_in,
+                            summary,
+                        });
+                    });
+                    merged
+                })
+            }
+            None => memory.consolidate(&config, now),
+        };
+        if report != ConsolidationReport::default() {
+            debug!(
+                "Consolidated memories: dropped {}, merged {}",
+                report.dropped, report.merged
+            );
+        }
+    }
+}
+
+/// Plugin running [`consolidate_memories`] periodically.
+#[derive(Default)]
+pub struct MemoryConsolidationPlugin {
+    pub config: MemoryConsolidation,
+}
+
+impl MemoryConsolidationPlugin {
+    pub fn new(config: MemoryConsolidation) -> Self {
+        Self { config }
+    }
+}
+
+impl Plugin for MemoryConsolidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MemoryConsolidation>()
+            .insert_resource(self.config.clone())
+            .add_systems(Update, consolidate_memories);
+    }
+}
+
+/// Context-gathering system adding the memories most relevant to the current prompt.
+///
+/// Without a prompt (or an embedder) the most recent memories are used instead.
+pub fn gather_memory_context(
+    ai_entity: AiEntity,
+    memories: Query<&SemanticMemory>,
+    scopes: Query<&KnowledgeScope>,
+    embedder: Option<Res<AiEmbedder>>,
+) -> Option<AiMessage> {
+    let memory = memories.get(ai_entity.entity()).ok()?;
+    if memory.is_empty() || memory.recall_k == 0 {
+        return None;
+    }
+    let scope = scopes.get(ai_entity.entity()).ok();
+    let in_scope =
+        |entry: &MemoryEntry| crate::knowledge::visible(scope, &metadata_tags(&entry.metadata));
+
+    let texts: Vec<&str> = match (ai_entity.query(), embedder) {
+        (Some(query), Some(embedder)) => match memory.recall(&embedder, query, memory.len()) {
+            Ok(matches) => matches
+                .into_iter()
+                .filter(|m| m.score >= memory.min_score && in_scope(m.entry))
+                .take(memory.recall_k)
+                .map(|m| m.entry.text.as_str())
+                .collect(),
+            Err(e) => {
+                warn!("Memory recall failed: {}", e);
+                return None;
+            }
+        },
+        _ => memory
+            .entries()
+            .iter()
+            .rev()
+            .filter(|e| in_scope(e))
+            .take(memory.recall_k)
+            .map(|e| e.text.as_str())
+            .collect(),
+    };
+
+    if texts.is_empty() {
+        return None;
+    }
+    Some(AiMessage::system(format!(
+        "You remember:\n- {}",
+        texts.join("\n- ")
+    )))
+}
+
+/// Plugin registering [`gather_memory_context`] with the context store.
+pub struct SemanticMemoryPlugin;
+
+impl Plugin for SemanticMemoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.world_mut()
+            .get_resource_or_init::<AiSystemContextStore>()
+            .add_system(gather_memory_context);
+    }
+}
+
+/// A captured event waiting to be embedded into an entity's memory.
+#[derive(Debug, Clone)]
+pub struct MemoryCapture {
+    /// The entity whose memory receives the text.
+    pub entity: Entity,
+    pub text: String,
+    pub metadata: Value,
+    /// When the event happened.
+    pub timestamp: SystemTime,
+    pub importance: f32,
+}
+
+/// Queue of captured events, drained by [`store_captured_memories`].
+#[derive(Resource, Default, Debug)]
+pub struct PendingMemoryCaptures {
+    pub captures: Vec<MemoryCapture>,
+    /// When captures whose embedding failed are tried again.
+    retry_at: Option<Duration>,
+}
+
+/// How long captures whose embedding failed wait before they are tried again.
+const CAPTURE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Capture batches embedded in the background.
+pub struct CaptureBatches {
+    tx: flume::Sender<CaptureBatch>,
+    rx: flume::Receiver<CaptureBatch>,
+    /// Whether a batch is being embedded.
+    in_flight: bool,
+}
+
+impl Default for CaptureBatches {
+    fn default() -> Self {
+        let (tx, rx) = flume::unbounded();
+        Self {
+            tx,
+            rx,
+            in_flight: false,
+        }
+    }
+}
+
+struct CaptureBatch {
+    captures: Vec<MemoryCapture>,
+    embeddings: Result<Vec<Vec<f32>>, String>,
+}
+
+/// Embed queued captures in one batch on a background thread and store them in their owners'
+/// [`SemanticMemory`] once done, inserting the component when missing. Captures stay queued
+/// until an [`AiEmbedder`] exists, are queued again when embedding them fails, and are dropped
+/// when their entity was despawned in the meantime.
+pub fn store_captured_memories(
+    mut pending: ResMut<PendingMemoryCaptures>,
+    embedder: Option<Res<AiEmbedder>>,
+    time: Res<Time<Real>>,
+    mut batches: Local<CaptureBatches>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    while let Ok(batch) = batches.rx.try_recv() {
+        batches.in_flight = false;
+        let embeddings = match batch.embeddings {
+            Ok(embeddings) => embeddings,
+            Err(e) => {
+                warn!(
+                    "Failed to embed {} captured memories, trying again: {}",
+                    batch.captures.len(),
+                    e
+                );
+                // Keep the failed batch ahead of captures queued since
+                let queued = std::mem::replace(&mut pending.captures, batch.captures);
+                pending.captures.extend(queued);
+                pending.retry_at = Some(now + CAPTURE_RETRY_DELAY);
+                continue;
+            }
+        };
+        pending.retry_at = None;
+
+        for (capture, embedding) in batch.captures.into_iter().zip(embeddings) {
+            let npc = capture.entity;
+            let entry = MemoryEntry {
+                text: capture.text,
+                metadata: capture.metadata,
+                embedding,
+                timestamp: capture.timestamp,
+                importance: capture.importance,
+            };
+            commands.queue(move |world: &mut World| {
+                let Ok(mut npc) = world.get_entity_mut(npc) else {
+                    debug!("Dropping a captured memory of despawned entity {:?}", npc);
+                    return;
+                };
+                npc.entry::<SemanticMemory>()
+                    .or_default()
+                    .into_mut()
+                    .insert_entry(entry);
+            });
+        }
+    }
+
+    if pending.captures.is_empty() || batches.in_flight {
+        return;
+    }
+    let Some(embedder) = embedder else {
+        return;
+    };
+    if pending.retry_at.is_some_and(|at| now < at) {
+        return;
+    }
+
+    let captures = std::mem::take(&mut pending.captures);
+    let (embedder, tx) = ((*embedder).clone(), batches.tx.clone());
+    batches.in_flight = true;
+    crate::models::TOKIO_RUNTIME.spawn_blocking(move || {
+        let texts: Vec<&str> = captures.iter().map(|c| c.text.as_str()).collect();
+        let embeddings = embedder.embed_many(&texts);
+        let _ = tx.send(CaptureBatch {
+            captures,
+            embeddings,
+        });
+    });
+}
+
+/// Plugin storing events registered with
+/// [`AiAppExt::capture_event`](crate::app_ext::AiAppExt::capture_event) into [`SemanticMemory`].
+pub struct MemoryCapturePlugin;
+
+impl Plugin for MemoryCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingMemoryCaptures>()
+            .add_systems(Update, store_captured_memories);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(text: &str, embedding: Vec<f32>, age_secs: u64, importance: f32) -> MemoryEntry {
+        MemoryEntry {
+            text: text.to_string(),
+            metadata: Value::Null,
+            embedding,
+            timestamp: SystemTime::now() - Duration::from_secs(age_secs),
+            importance,
+        }
+    }
+
+    #[test]
+    fn retention_halves_every_half_life() {
+        let decay = MemoryDecay {
+            half_life: Duration::from_secs(100),
+        };
+        let now = SystemTime::now();
+        let fresh = entry("a", vec![1.0], 0, 0.8);
+        let old = entry("b", vec![1.0], 200, 0.8);
+        assert!((decay.retention(&fresh, now) - 0.8).abs() < 0.01);
+        assert!((decay.retention(&old, now) - 0.2).abs() < 0.01);
+    }
+
+    #[test]
+    fn consolidate_drops_faded_and_merges_duplicates() {
+        let mut memory = SemanticMemory::new().with_decay(MemoryDecay {
+            half_life: Duration::from_secs(100),
+        });
+        memory.insert_entry(entry("stole my sword", vec![1.0, 0.0], 0, 0.9));
+        memory.insert_entry(entry("took my sword", vec![0.99, 0.05], 10, 0.4));
+        memory.insert_entry(entry("nice weather", vec![0.0, 1.0], 1000, 0.2));
+        memory.insert_entry(entry("bought bread", vec![0.0, 1.0], 0, 0.5));
+
+        let config = MemoryConsolidation::default()
+            .with_drop_below(0.05)
+            .with_merge_similarity(0.95);
+        let report = memory.consolidate(&config, SystemTime::now());
+
+        assert_eq!(
+            report,
+            ConsolidationReport {
+                dropped: 1,
+                merged: 1
+            }
+        );
+        let texts: Vec<&str> = memory.entries().iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, vec!["stole my sword", "bought bread"]);
+        assert_eq!(memory.entries()[0].importance, 0.9);
+    }
+}
@@ -0,0 +1,217 @@
+//! Inspect and clean up downloaded model files.
+//!
+//! Hugging Face models are downloaded once into a cache directory laid out as
+//! `<dir>/<owner>/<repo>/<revision>/<file>`, and can take several gigabytes. [`ModelCache`]
+//! lists what is stored there and deletes it, e.g. behind a "clear downloaded models" button
+//! in the settings menu. Point downloads at another directory with
+//! [`AiModelBuilder::with_cache_dir`](crate::models::AiModelBuilder::with_cache_dir) and open
+//! the same directory with [`ModelCache::new`]. Models loaded with `with_local` are never
+//! touched.
+//!
+//! # Example
+//! ```ignore
+//! let cache = ModelCache::default();
+//! for model in cache.list()? {
+//!     info!("{}: {} MB", model.name, model.size_bytes / 1_000_000);
+//! }
+//! cache.remove("QuantFactory/Llama-3.2-3B-Instruct-GGUF")?;
+//! ```
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// A downloaded model repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedModel {
+    /// `owner/repo`, as passed to `AiModelBuilder::with_huggingface`.
+    pub name: String,
+    pub path: PathBuf,
+    /// Total size of all files of the repository.
+    pub size_bytes: u64,
+    pub files: usize,
+}
+
+/// Directory holding downloaded models.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelCache {
+    dir: PathBuf,
+}
+
+impl Default for ModelCache {
+    /// The directory models are downloaded to unless `with_cache_dir` is used.
+    fn default() -> Self {
+        Self::new(default_cache_dir())
+    }
+}
+
+impl ModelCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Downloaded models, largest first. Empty when nothing was downloaded yet.
+    pub fn list(&self) -> io::Result<Vec<CachedModel>> {
+        let mut models = Vec::new();
+        for owner in read_dirs(&self.dir)? {
+            for repo in read_dirs(&owner)? {
+                let (size_bytes, files) = dir_size(&repo)?;
+                let name = repo
+                    .strip_prefix(&self.dir)
+                    .unwrap_or(&repo)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                models.push(CachedModel {
+                    name,
+                    path: repo,
+                    size_bytes,
+                    files,
+                });
+            }
+        }
+        models.sort_by_key(|m| std::cmp::Reverse(m.size_bytes));
+        Ok(models)
+    }
+
+    /// Total size of the cache in bytes.
+    pub fn total_size(&self) -> io::Result<u64> {
+        if !self.dir.is_dir() {
+            return Ok(0);
+        }
+        dir_size(&self.dir).map(|(size, _)| size)
+    }
+
+    /// Delete the downloaded files of model `name` (`owner/repo`). Returns `false` if it was
+    /// not cached. The model is downloaded again the next time it is loaded.
+    pub fn remove(&self, name: &str) -> io::Result<bool> {
+        let relative = Path::new(name);
+        if relative.as_os_str().is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a model name", name),
+            ));
+        }
+        let path = self.dir.join(relative);
+        if !path.is_dir() {
+            return Ok(false);
+        }
+        std::fs::remove_dir_all(&path)?;
+        // Drop the owner directory once its last repository is gone
+        if let Some(owner) = path.parent()
+            && owner != self.dir
+            && read_dirs(owner)?.is_empty()
+        {
+            let _ = std::fs::remove_dir(owner);
+        }
+        Ok(true)
+    }
+
+    /// Delete every downloaded model.
+    pub fn clear(&self) -> io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Platform data directory + `kalosm/cache`, where kalosm downloads models by default.
+fn default_cache_dir() -> PathBuf {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let data_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|h| h.join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| home().map(|h| h.join(".local").join("share")))
+    };
+    data_dir
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("kalosm")
+        .join("cache")
+}
+
+/// Subdirectories of `dir`; empty if `dir` does not exist.
+fn read_dirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Total size and number of files below `dir`.
+fn dir_size(dir: &Path) -> io::Result<(u64, usize)> {
+    let mut size = 0;
+    let mut files = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            let (sub_size, sub_files) = dir_size(&entry.path())?;
+            size += sub_size;
+            files += sub_files;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+            files += 1;
+        }
+    }
+    Ok((size, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_removes_cached_models() {
+        let dir = std::env::temp_dir().join(format!("ai_model_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |path: &str, len: usize| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0u8; len]).unwrap();
+        };
+        write("org/big/main/model.gguf", 300);
+        write("org/big/main/tokenizer.json", 20);
+        write("other/small/main/model.gguf", 100);
+
+        let cache = ModelCache::new(&dir);
+        let models = cache.list().unwrap();
+        let names: Vec<_> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["org/big", "other/small"]);
+        assert_eq!((models[0].size_bytes, models[0].files), (320, 2));
+        assert_eq!(cache.total_size().unwrap(), 420);
+
+        assert!(cache.remove("../outside").is_err());
+        assert!(cache.remove("other/small").unwrap());
+        assert!(!cache.remove("other/small").unwrap());
+        assert!(!dir.join("other").exists());
+        assert_eq!(cache.list().unwrap().len(), 1);
+
+        cache.clear().unwrap();
+        assert!(cache.list().unwrap().is_empty());
+        assert_eq!(cache.total_size().unwrap(), 0);
+    }
+}
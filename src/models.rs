@@ -8,7 +8,10 @@ use crossbeam_channel;
 use kalosm::language::*;
 
 use crate::dialogue::LocalAi;
+use crate::embedding::{AiEmbedder, LocalEmbedder};
+use crate::error::AiError;
 use crate::rag::AiMessage;
+use crate::tokenizer::{FnTokenizer, Tokenizer};
 
 /// Global tokio runtime for async operations - creating a runtime per call is very expensive
 pub(crate) static TOKIO_RUNTIME: LazyLock<tokio::runtime::Runtime> = LazyLock::new(|| {
@@ -25,7 +28,7 @@ pub(crate) static TOKIO_RUNTIME: LazyLock<tokio::runtime::Runtime> = LazyLock::n
 /// runtime, use `tokio::task::block_in_place` to move the blocking work to the
 /// blocking pool and then `TOKIO_RUNTIME.block_on` the future there. This
 /// preserves the synchronous API while avoiding nested runtime panics.
-fn run_sync<F, T>(fut: F) -> T
+pub(crate) fn run_sync<F, T>(fut: F) -> T
 where
     F: std::future::Future<Output = T>,
 {
@@ -38,10 +41,86 @@ where
     }
 }
 
+/// Build the system prompt and the user prompt for a request in two buffers sized up front.
+///
+/// System messages are joined with blank lines after the default context (unless the request
+/// carries `AiMessage::SkipDefaultContext`); user messages after the last assistant message
+/// are joined with newlines. Earlier user and assistant messages are [`prior_turns`].
+pub(crate) fn assemble_prompt(
+    messages: &[AiMessage],
+    default_context: Option<&str>,
+) -> (String, String) {
+    let default_context = default_context.filter(|_| !AiMessage::skips_default_context(messages));
+    let current = &messages[current_turn_start(messages)..];
+    let system_texts = || {
+        default_context
+            .into_iter()
+            .chain(messages.iter().filter_map(|m| match m {
+                AiMessage::System(text) => Some(&**text),
+                _ => None,
+            }))
+    };
+    let user_texts = || {
+        current.iter().filter_map(|m| match m {
+            AiMessage::User(text) => Some(&**text),
+            _ => None,
+        })
+    };
+
+    let mut system = String::with_capacity(system_texts().map(|t| t.len() + 2).sum());
+    for text in system_texts() {
+        if !system.is_empty() {
+            system.push_str("\n\n");
+        }
+        system.push_str(text);
+    }
+    let mut user = String::with_capacity(user_texts().map(|t| t.len() + 1).sum());
+    for (i, text) in user_texts().enumerate() {
+        if i > 0 {
+            user.push('\n');
+        }
+        user.push_str(text);
+    }
+    (system, user)
+}
+
+/// Index of the first message after the last assistant message.
+fn current_turn_start(messages: &[AiMessage]) -> usize {
+    messages
+        .iter()
+        .rposition(|m| matches!(m, AiMessage::Assistant(_)))
+        .map_or(0, |i| i + 1)
+}
+
+/// Earlier turns of a request: the user and assistant messages up to the last assistant
+/// message, in order, with consecutive user messages joined by newlines. They are added to
+/// the chat before the prompt, on top of any history already in the session.
+pub(crate) fn prior_turns(messages: &[AiMessage]) -> Vec<(MessageType, String)> {
+    let mut turns: Vec<(MessageType, String)> = Vec::new();
+    for message in &messages[..current_turn_start(messages)] {
+        let (role, text) = match message {
+            AiMessage::User(text) => (MessageType::UserMessage, text),
+            AiMessage::Assistant(text) => (MessageType::ModelAnswer, text),
+            _ => continue,
+        };
+        match turns.last_mut() {
+            Some((MessageType::UserMessage, last)) if role == MessageType::UserMessage => {
+                last.push('\n');
+                last.push_str(text);
+            }
+            _ => turns.push((role, text.to_string())),
+        }
+    }
+    turns
+}
+
 /// Represents the state of a model download operation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DownloadState {
     InProgress,
+    /// The download finished and its checksum is being checked.
+    Verifying,
     Completed,
     Error,
 }
@@ -66,6 +145,43 @@ pub enum ModelType {
     Phi,
 }
 
+/// Hardware a local model runs on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Device {
+    /// The first GPU of the enabled backend (`cuda` feature, Metal on macOS), else the CPU.
+    #[default]
+    Auto,
+    Cpu,
+    /// CUDA GPU with the given ordinal. Requires the `cuda` feature.
+    Cuda(usize),
+    /// The default Metal GPU (macOS).
+    Metal,
+}
+
+impl Device {
+    /// Open the candle device, falling back to the CPU when the GPU is unavailable.
+    fn open(self) -> (Self, kalosm::language::Device) {
+        use kalosm::language::Device as Candle;
+
+        let gpu = match self {
+            Device::Cpu => return (Device::Cpu, Candle::Cpu),
+            Device::Cuda(ordinal) => Candle::new_cuda(ordinal).map(|d| (Device::Cuda(ordinal), d)),
+            Device::Metal => Candle::new_metal(0).map(|d| (Device::Metal, d)),
+            Device::Auto if cfg!(feature = "cuda") => {
+                Candle::new_cuda(0).map(|d| (Device::Cuda(0), d))
+            }
+            Device::Auto if cfg!(target_os = "macos") => {
+                Candle::new_metal(0).map(|d| (Device::Metal, d))
+            }
+            Device::Auto => return (Device::Cpu, Candle::Cpu),
+        };
+        gpu.unwrap_or_else(|e| {
+            bevy::log::warn!("{:?} is unavailable, running on the CPU: {}", self, e);
+            (Device::Cpu, Candle::Cpu)
+        })
+    }
+}
+
 enum ModelSource {
     Llama(Llama),
     GPT(OpenAICompatibleChatModel),
@@ -80,6 +196,11 @@ pub struct AiModelBuilder {
     progress_chan_rx: Option<crossbeam_channel::Receiver<ModelDownloadProgress>>,
     include_default_context: bool,
     seed: Option<u64>,
+    cache_dir: Option<PathBuf>,
+    checksum: Option<String>,
+    device: Device,
+    threads: Option<usize>,
+    warmup: Option<String>,
 }
 
 impl AiModelBuilder {
@@ -97,6 +218,11 @@ impl AiModelBuilder {
             progress_chan_rx: None,
             include_default_context: true,
             seed: None,
+            cache_dir: None,
+            checksum: None,
+            device: Device::Auto,
+            threads: None,
+            warmup: None,
         }
     }
 
@@ -130,6 +256,67 @@ impl AiModelBuilder {
         self
     }
 
+    /// Download models into `dir` instead of the default cache directory.
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Expected SHA-256 (hex) of the model file set with `with_huggingface` or `with_local`.
+    ///
+    /// The file is verified after download (and before loading a cached or local copy); a
+    /// mismatch fails the load instead of running a corrupted or tampered model.
+    pub fn with_checksum(mut self, sha256: impl Into<String>) -> Self {
+        self.checksum = Some(sha256.into());
+        self
+    }
+
+    /// Run local models on `device`. Defaults to [`Device::Auto`].
+    ///
+    /// When the device cannot be opened (no driver, or the crate was built without its
+    /// feature) the model runs on the CPU instead; [`LocalAi::device`] reports where it ended up.
+    pub fn with_device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Number of CPU threads used for inference. Defaults to one per core.
+    ///
+    /// Sizes the global rayon pool inference runs on when the model is built. The pool is
+    /// created once per process, so this only takes effect if nothing used rayon before the
+    /// first model is loaded and `RAYON_NUM_THREADS` is not set.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Run `prompt` once right after a local model is loaded, before the load is reported as
+    /// complete.
+    ///
+    /// The first generation is much slower than the following ones because buffers are
+    /// allocated lazily; warming up moves that cost into the loading screen instead of the first
+    /// NPC interaction. Keep the prompt short. Failures are logged and do not fail the load.
+    pub fn with_warmup(mut self, prompt: impl Into<String>) -> Self {
+        self.warmup = Some(prompt.into());
+        self
+    }
+
+    /// The cache this builder downloads models into.
+    pub fn cache(&self) -> crate::model_cache::ModelCache {
+        match &self.cache_dir {
+            Some(dir) => crate::model_cache::ModelCache::new(dir.clone()),
+            None => crate::model_cache::ModelCache::default(),
+        }
+    }
+
+    /// Apply the cache directory to a model source.
+    fn llama_source(&self, source: LlamaSource) -> LlamaSource {
+        match &self.cache_dir {
+            Some(dir) => source.with_cache(Cache::new(dir.clone())),
+            None => source,
+        }
+    }
+
     /// Enable progress tracking for model downloads.
     ///
     /// If not called, progress updates will be viewed on the terminal only.
@@ -140,6 +327,31 @@ impl AiModelBuilder {
         self
     }
 
+    /// Stable identifier of the model this builder loads (model family or file source).
+    pub fn model_id(&self) -> String {
+        match (&self.model_type, &self.model_file_source) {
+            (_, Some(FileSource::Local(path))) => format!("local:{}", path.display()),
+            (
+                _,
+                Some(FileSource::HuggingFace {
+                    model_id,
+                    revision,
+                    file,
+                }),
+            ) => {
+                format!("hf:{}@{}/{}", model_id, revision, file)
+            }
+            (ModelType::Llama, None) => "llama-3.2-3b-chat".to_string(),
+            (ModelType::Phi, None) => "phi-3.1-mini-4k-instruct".to_string(),
+            (ModelType::GPT(_), None) => "openai:gpt-4o-mini".to_string(),
+        }
+    }
+
+    /// The generation seed, if one was set.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
     /// Extract the progress receiver after enabling with_progress_tracking().
     /// Returns None if with_progress_tracking() was not called.
     pub fn take_progress_receiver(
@@ -161,22 +373,29 @@ impl AiModelBuilder {
             }
         };
 
-        let progress_value = handler.progress() * 100.0;
+        Self::report_progress(
+            progress_chan_tx.as_ref(),
+            ModelDownloadProgress {
+                state: DownloadState::InProgress,
+                message,
+                progress: Some(handler.progress() * 100.0),
+            },
+        );
+    }
 
+    /// Send `update` to the progress channel, or draw it on the terminal without one.
+    pub(crate) fn report_progress(
+        progress_chan_tx: Option<&crossbeam_channel::Sender<ModelDownloadProgress>>,
+        update: ModelDownloadProgress,
+    ) {
         match progress_chan_tx {
             Some(tx) => {
-                match tx.send(ModelDownloadProgress {
-                    state: DownloadState::InProgress,
-                    message,
-                    progress: Some(progress_value),
-                }) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("Failed to send model loading progress: {}", e);
-                    }
+                if let Err(e) = tx.send(update) {
+                    eprintln!("Failed to send model loading progress: {}", e);
                 }
             }
             None => {
+                let message = update.message;
                 // Mutex to serialize terminal writes so updates don't interleave
                 static PROGRESS_LOCK: Mutex<()> = Mutex::new(());
 
@@ -202,7 +421,10 @@ impl AiModelBuilder {
                 };
 
                 // Percentage line
-                let pct = format!("({:.0}%)", progress_value);
+                let pct = match update.progress {
+                    Some(progress) => format!("({:.0}%)", progress),
+                    None => String::new(),
+                };
                 let pct_line = if pct.chars().count() > max_width {
                     let mut s = pct.chars().take(max_width - 1).collect::<String>();
                     s.push_str("...");
@@ -238,7 +460,9 @@ impl AiModelBuilder {
                 PREV_LINE_LEN.store(len, Ordering::SeqCst);
 
                 // On completion, print newline and reset length tracker
-                if progress_value >= 100.0 {
+                if update.state != DownloadState::InProgress
+                    || update.progress.is_some_and(|p| p >= 100.0)
+                {
                     let _ = writeln!(handle, "");
                     PREV_LINE_LEN.store(0, Ordering::SeqCst);
                 }
@@ -246,83 +470,218 @@ impl AiModelBuilder {
         }
     }
 
-    pub fn build(&self) -> Result<Arc<dyn LocalAi>, String> {
+    /// Download (if needed) and load the model, blocking the calling thread.
+    pub fn build(&self) -> Result<Arc<dyn LocalAi>, AiError> {
         // Use global runtime instead of creating a new one
-        run_sync(async {
-            let source = match self.model_type.clone() {
-                ModelType::Llama => match &self.model_file_source {
-                    Some(s) => {
-                        let progress_tx = self.progress_chan_tx.clone();
-                        let model = Llama::builder()
-                            .with_source(LlamaSource::new(s.clone()))
-                            .build_with_loading_handler(move |handler| {
-                                Self::model_loading_handler(progress_tx.clone(), handler.clone());
-                            })
-                            .await
-                            .map_err(|e| format!("Failed to create Llama model source: {}", e))?;
-                        ModelSource::Llama(model)
-                    }
-                    None => {
-                        let progress_tx = self.progress_chan_tx.clone();
-                        let model = Llama::builder()
-                            .with_source(LlamaSource::llama_3_2_3b_chat())
-                            .build_with_loading_handler(move |handler| {
-                                Self::model_loading_handler(progress_tx.clone(), handler.clone());
-                            })
-                            .await
-                            .map_err(|e| format!("Failed to create Llama model source: {}", e))?;
-                        ModelSource::Llama(model)
-                    }
-                },
-                ModelType::GPT(api_key) => {
-                    let model = OpenAICompatibleChatModelBuilder::new()
-                        .with_gpt_4o_mini()
-                        .with_client(
-                            OpenAICompatibleClient::new().with_api_key(api_key.to_string()),
-                        )
-                        .build();
-                    ModelSource::GPT(model)
+        run_sync(self.build_async())
+    }
+
+    /// Local path of the configured model file: Hugging Face files are downloaded into the cache
+    /// (resuming partial downloads) and checked against the expected checksum.
+    async fn resolve_file_source(&self) -> Result<Option<FileSource>, AiError> {
+        let progress = self.progress_chan_tx.as_ref();
+        match &self.model_file_source {
+            Some(FileSource::HuggingFace {
+                model_id,
+                revision,
+                file,
+            }) => {
+                let cache = self.cache();
+                let dest = crate::download::cached_path(cache.dir(), model_id, revision, file);
+                let path = crate::download::download_model_file(
+                    model_id,
+                    revision,
+                    file,
+                    &dest,
+                    self.checksum.as_deref(),
+                    progress,
+                )
+                .await?;
+                Ok(Some(FileSource::Local(path)))
+            }
+            Some(FileSource::Local(path)) => {
+                if let Some(expected) = &self.checksum {
+                    Self::report_progress(
+                        progress,
+                        ModelDownloadProgress {
+                            state: DownloadState::Verifying,
+                            message: path.display().to_string(),
+                            progress: None,
+                        },
+                    );
+                    crate::download::verify(path, expected).await?;
                 }
-                ModelType::Phi => match &self.model_file_source {
-                    Some(s) => {
-                        let progress_tx = self.progress_chan_tx.clone();
-                        let model = Llama::builder()
-                            .with_source(LlamaSource::new(s.clone()))
-                            .build_with_loading_handler(move |handler| {
-                                Self::model_loading_handler(progress_tx.clone(), handler.clone());
-                            })
-                            .await
-                            .map_err(|e| format!("Failed to create Phi model source: {}", e))?;
-                        ModelSource::Phi(model)
-                    }
-                    None => {
-                        let progress_tx = self.progress_chan_tx.clone();
-                        let model = Llama::builder()
-                            .with_source(LlamaSource::phi_3_1_mini_4k_instruct())
-                            .build_with_loading_handler(move |handler| {
-                                Self::model_loading_handler(progress_tx.clone(), handler.clone());
-                            })
-                            .await
-                            .map_err(|e| format!("Failed to create Phi model source: {}", e))?;
-                        ModelSource::Phi(model)
-                    }
-                },
-            };
+                Ok(Some(FileSource::Local(path.clone())))
+            }
+            other => Ok(other.clone()),
+        }
+    }
 
-            let model = match source {
-                ModelSource::Llama(m) => m.boxed_chat_model(),
-                ModelSource::GPT(m) => m.boxed_chat_model(),
-                ModelSource::Phi(m) => m.boxed_chat_model(),
-            };
+    /// Download (if needed) and load the model. Dropping the future stops the download.
+    pub async fn build_async(&self) -> Result<Arc<dyn LocalAi>, AiError> {
+        let file_source = self.resolve_file_source().await?;
+        if let Some(threads) = self.threads
+            && std::env::var_os("RAYON_NUM_THREADS").is_none()
+            && let Err(e) = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+        {
+            bevy::log::debug!("Keeping the existing inference thread pool: {}", e);
+        }
+        let (used_device, device) = self.device.open();
+        let source = match self.model_type.clone() {
+            ModelType::Llama => match &file_source {
+                Some(s) => {
+                    let progress_tx = self.progress_chan_tx.clone();
+                    let model = Llama::builder()
+                        .with_device(device.clone())
+                        .with_source(self.llama_source(LlamaSource::new(s.clone())))
+                        .build_with_loading_handler(move |handler| {
+                            Self::model_loading_handler(progress_tx.clone(), handler.clone());
+                        })
+                        .await
+                        .map_err(|e| {
+                            AiError::ModelLoad(format!(
+                                "Failed to create Llama model source: {}",
+                                e
+                            ))
+                        })?;
+                    ModelSource::Llama(model)
+                }
+                None => {
+                    let progress_tx = self.progress_chan_tx.clone();
+                    let model = Llama::builder()
+                        .with_device(device.clone())
+                        .with_source(self.llama_source(LlamaSource::llama_3_2_3b_chat()))
+                        .build_with_loading_handler(move |handler| {
+                            Self::model_loading_handler(progress_tx.clone(), handler.clone());
+                        })
+                        .await
+                        .map_err(|e| {
+                            AiError::ModelLoad(format!(
+                                "Failed to create Llama model source: {}",
+                                e
+                            ))
+                        })?;
+                    ModelSource::Llama(model)
+                }
+            },
+            ModelType::GPT(api_key) => {
+                let model = OpenAICompatibleChatModelBuilder::new()
+                    .with_gpt_4o_mini()
+                    .with_client(OpenAICompatibleClient::new().with_api_key(api_key.to_string()))
+                    .build();
+                ModelSource::GPT(model)
+            }
+            ModelType::Phi => match &file_source {
+                Some(s) => {
+                    let progress_tx = self.progress_chan_tx.clone();
+                    let model = Llama::builder()
+                        .with_device(device.clone())
+                        .with_source(self.llama_source(LlamaSource::new(s.clone())))
+                        .build_with_loading_handler(move |handler| {
+                            Self::model_loading_handler(progress_tx.clone(), handler.clone());
+                        })
+                        .await
+                        .map_err(|e| {
+                            AiError::ModelLoad(format!("Failed to create Phi model source: {}", e))
+                        })?;
+                    ModelSource::Phi(model)
+                }
+                None => {
+                    let progress_tx = self.progress_chan_tx.clone();
+                    let model = Llama::builder()
+                        .with_device(device.clone())
+                        .with_source(self.llama_source(LlamaSource::phi_3_1_mini_4k_instruct()))
+                        .build_with_loading_handler(move |handler| {
+                            Self::model_loading_handler(progress_tx.clone(), handler.clone());
+                        })
+                        .await
+                        .map_err(|e| {
+                            AiError::ModelLoad(format!("Failed to create Phi model source: {}", e))
+                        })?;
+                    ModelSource::Phi(model)
+                }
+            },
+        };
 
-            let mut ai_model =
-                AIModel::new(model).include_default_context(self.include_default_context);
-            if let Some(seed) = self.seed {
-                ai_model = ai_model.with_seed(seed);
+        // Local models count tokens with their own vocabulary; remote ones have none here.
+        let (model, tokenizer, device) = match source {
+            ModelSource::Llama(m) | ModelSource::Phi(m) => {
+                let tokenizer = llama_tokenizer(&m);
+                (m.boxed_chat_model(), Some(tokenizer), Some(used_device))
             }
-            let arc_model: Arc<dyn LocalAi> = Arc::new(ai_model);
-            Ok(arc_model)
-        })
+            ModelSource::GPT(m) => (m.boxed_chat_model(), None, None),
+        };
+
+        let mut ai_model =
+            AIModel::new(model).include_default_context(self.include_default_context);
+        if let Some(seed) = self.seed {
+            ai_model = ai_model.with_seed(seed);
+        }
+        if let Some(tokenizer) = tokenizer {
+            ai_model = ai_model.with_tokenizer(tokenizer);
+        }
+        ai_model.device = device;
+        let arc_model: Arc<dyn LocalAi> = Arc::new(ai_model);
+        if let Some(prompt) = &self.warmup
+            && device.is_some()
+        {
+            warm_up(arc_model.clone(), prompt).await;
+        }
+        Ok(arc_model)
+    }
+}
+
+/// Run one generation so lazily allocated buffers are ready before the first real prompt.
+async fn warm_up(model: Arc<dyn LocalAi>, prompt: &str) {
+    let messages = [AiMessage::user(prompt)];
+    let started = std::time::Instant::now();
+    match tokio::task::spawn_blocking(move || model.prompt(&messages)).await {
+        Ok(Ok(_)) => bevy::log::debug!("Model warmed up in {:?}", started.elapsed()),
+        Ok(Err(e)) => bevy::log::warn!("Model warm-up failed: {}", e),
+        Err(e) => bevy::log::warn!("Model warm-up stopped: {}", e),
+    }
+}
+
+/// Wrap the Hugging Face tokenizer of a loaded Llama-family model.
+fn llama_tokenizer(model: &Llama) -> FnTokenizer {
+    let tokenizer = model.tokenizer().clone();
+    FnTokenizer::new(move |text| {
+        tokenizer
+            .encode(text, false)
+            .map(|encoding| encoding.len())
+            .unwrap_or(0)
+    })
+}
+
+impl AiModelBuilder {
+    /// Load a Bert embedding model and wrap it in an [`AiEmbedder`] resource.
+    ///
+    /// Embeddings are independent of the chat model type; the same builder can build both.
+    pub fn build_embedder(&self) -> Result<AiEmbedder, AiError> {
+        let bert = run_sync(async { Bert::new().await })
+            .map_err(|e| AiError::ModelLoad(format!("Failed to create Bert embedder: {}", e)))?;
+        Ok(AiEmbedder::new(Arc::new(BertEmbedder { model: bert })))
+    }
+}
+
+/// [`LocalEmbedder`] backed by a kalosm Bert model, run on the shared tokio runtime.
+struct BertEmbedder {
+    model: Bert,
+}
+
+impl LocalEmbedder for BertEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        run_sync(self.model.embed(text))
+            .map(|embedding| embedding.vector().to_vec())
+            .map_err(|e| format!("Embedding failed: {}", e))
+    }
+
+    fn embed_many(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        run_sync(self.model.embed_batch(texts.iter().copied()))
+            .map(|embeddings| embeddings.iter().map(|e| e.vector().to_vec()).collect())
+            .map_err(|e| format!("Embedding failed: {}", e))
     }
 }
 
@@ -332,6 +691,8 @@ pub struct AIModel {
     session: Option<kalosm::language::BoxedChatSession>,
     include_default_context: Option<String>,
     seed: Option<u64>,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+    device: Option<Device>,
 }
 
 impl AIModel {
@@ -341,6 +702,8 @@ impl AIModel {
             session: None,
             include_default_context: Some(DEFAULT_SYSTEM_CONTEXT.trim().to_string()),
             seed: None,
+            tokenizer: None,
+            device: None,
         }
     }
 
@@ -369,6 +732,12 @@ impl AIModel {
         self.seed = Some(seed);
         self
     }
+
+    /// Set the tokenizer reported through `LocalAi::tokenizer`.
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer) -> Self {
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self
+    }
 }
 
 impl AIModel {
@@ -381,7 +750,7 @@ impl AIModel {
         messages: &[AiMessage],
         session: Option<kalosm::language::BoxedChatSession>,
         parser: P,
-    ) -> Result<(T, Option<kalosm::language::BoxedChatSession>), String>
+    ) -> Result<(T, Option<kalosm::language::BoxedChatSession>), AiError>
     where
         P: kalosm::language::Parser<Output = T>
             + kalosm::language::CreateParserState
@@ -399,9 +768,17 @@ impl AIModel {
         let parse_res = parser.parse(&state, text.as_bytes());
 
         match parse_res {
-            Ok(kalosm::language::ParseStatus::Finished { result, .. }) => Ok((result, prompt_res.session)),
-            Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err("Parser reported incomplete result; model output may be truncated or not match the expected shape".to_string()),
-            Err(e) => Err(format!("Parser error: {:?}", e)),
+            Ok(kalosm::language::ParseStatus::Finished { result, .. }) => {
+                Ok((result, prompt_res.session))
+            }
+            Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err(AiError::parse_failure(
+                text.as_str(),
+                "Parser reported incomplete result; model output may be truncated or not match the expected shape",
+            )),
+            Err(e) => Err(AiError::parse_failure(
+                text.as_str(),
+                format!("Parser error: {:?}", e),
+            )),
         }
     }
 
@@ -413,7 +790,7 @@ impl AIModel {
         messages: &[AiMessage],
         session: Option<kalosm::language::BoxedChatSession>,
         parser: kalosm::language::ArcParser<T>,
-    ) -> Result<(T, Option<kalosm::language::BoxedChatSession>), String>
+    ) -> Result<(T, Option<kalosm::language::BoxedChatSession>), AiError>
     where
         T: Clone + Send + 'static,
     {
@@ -424,51 +801,29 @@ impl AIModel {
                     Some(session) => session.clone(),
                     None => match self.model.new_chat_session() {
                         Ok(s) => s,
-                        Err(e) => return Err(format!("Failed to create chat session: {}", e)),
+                        Err(e) => {
+                            return Err(AiError::Backend(format!(
+                                "Failed to create chat session: {}",
+                                e
+                            )));
+                        }
                     },
                 },
             };
 
             let mut chat = self.model.chat().with_session(chat_session.clone());
 
-            // Decide whether to include the default system context. If any System
-            // message sentinel is present, we treat it as a request to skip the default
-            // system context for this request.
-            let skip_default = messages.iter().any(|m| matches!(m, AiMessage::System(text) if text == crate::rag::NO_DEFAULT_SYSTEM_CONTEXT));
-
-            let mut system_parts = if !skip_default {
-                if let Some(context) = &self.include_default_context {
-                    vec![context.clone()]
-                } else {
-                    Vec::new()
-                }
-            } else {
-                Vec::new()
-            };
-
-            for message in messages {
-                if let AiMessage::System(text) = message {
-                    // Filter out the sentinel so it isn't forwarded to the backend
-                    if text == crate::rag::NO_DEFAULT_SYSTEM_CONTEXT {
-                        continue;
-                    }
-                    system_parts.push(text.clone());
-                }
-            }
-            let combined_system_prompt = system_parts.join("\n\n");
+            let (combined_system_prompt, full_prompt) =
+                assemble_prompt(messages, self.include_default_context.as_deref());
             chat = chat.with_system_prompt(&combined_system_prompt);
-
-            // Build user prompt from User messages only (history is in the session)
-            let mut conversation_parts = Vec::new();
-            for message in messages {
-                match message {
-                    AiMessage::User(text) => {
-                        conversation_parts.push(format!("{}", text));
-                    }
-                    _ => {}
-                }
+            let history = prior_turns(messages);
+            if !history.is_empty() {
+                chat = chat.with_history(
+                    history
+                        .into_iter()
+                        .map(|(role, text)| ChatMessage::new(role, text)),
+                );
             }
-            let full_prompt = conversation_parts.join("\n");
 
             // Start generation with constraints and attempt to parse the result.
             // We pass the parser as a constraint (if supported by the backend)
@@ -501,24 +856,27 @@ impl AIModel {
                     };
                     Ok((result, updated_session.or(Some(chat_session))))
                 }
-                Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err("Parser reported incomplete result; model output may be truncated or not match the expected shape".to_string()),
-                Err(e) => Err(format!("Parser error: {:?}", e)),
+                Ok(kalosm::language::ParseStatus::Incomplete { .. }) => {
+                    Err(AiError::parse_failure(
+                        text.as_str(),
+                        "Parser reported incomplete result; model output may be truncated or not match the expected shape",
+                    ))
+                }
+                Err(e) => Err(AiError::parse_failure(
+                    text.as_str(),
+                    format!("Parser error: {:?}", e),
+                )),
             }
         })
     }
-}
-
-impl LocalAi for AIModel {
-    fn prompt(&self, messages: &[AiMessage]) -> Result<String, String> {
-        // Delegate to prompt_with_session without an existing session
-        self.prompt_with_session(messages, None).map(|r| r.response)
-    }
 
-    fn prompt_with_session(
+    /// Generate a reply, passing each piece to `on_text` as it arrives.
+    fn generate(
         &self,
         messages: &[AiMessage],
         session: Option<kalosm::language::BoxedChatSession>,
-    ) -> Result<crate::dialogue::PromptResult, String> {
+        on_text: &mut dyn FnMut(&str),
+    ) -> Result<crate::dialogue::PromptResult, AiError> {
         // Use global runtime instead of creating a new one each call
         run_sync(async {
             let chat_session = match session {
@@ -527,67 +885,39 @@ impl LocalAi for AIModel {
                     Some(session) => session.clone(),
                     None => match self.model.new_chat_session() {
                         Ok(s) => s,
-                        Err(e) => return Err(format!("Failed to create chat session: {}", e)),
+                        Err(e) => {
+                            return Err(AiError::Backend(format!(
+                                "Failed to create chat session: {}",
+                                e
+                            )));
+                        }
                     },
                 },
             };
             let mut chat = self.model.chat().with_session(chat_session.clone());
 
-            // Decide whether to include the default system context. If any System
-            // message sentinel is present, we treat it as a request to skip the default
-            // system context for this request.
-            let skip_default = messages.iter().any(|m| matches!(m, AiMessage::System(text) if text == crate::rag::NO_DEFAULT_SYSTEM_CONTEXT));
-
-            let mut system_parts = if !skip_default {
-                if let Some(context) = &self.include_default_context {
-                    vec![context.clone()]
-                } else {
-                    Vec::new()
-                }
-            } else {
-                Vec::new()
-            };
-
-            for message in messages {
-                if let AiMessage::System(text) = message {
-                    // Filter out the sentinel so it isn't forwarded to the backend
-                    if text == crate::rag::NO_DEFAULT_SYSTEM_CONTEXT {
-                        continue;
-                    }
-                    system_parts.push(text.clone());
-                }
-            }
-            let combined_system_prompt = system_parts.join("\n\n");
+            let (combined_system_prompt, full_prompt) =
+                assemble_prompt(messages, self.include_default_context.as_deref());
             chat = chat.with_system_prompt(&combined_system_prompt);
-
-            // Build user prompt from User messages only (history is in the session)
-            let mut conversation_parts = Vec::new();
-            for message in messages {
-                match message {
-                    AiMessage::User(text) => {
-                        conversation_parts.push(format!("{}", text));
-                    }
-                    AiMessage::System(_) => {
-                        // Already handled above
-                    }
-                    _ => {
-                        // Ignore Assistant messages in prompt
-                    }
-                }
+            let history = prior_turns(messages);
+            if !history.is_empty() {
+                chat = chat.with_history(
+                    history
+                        .into_iter()
+                        .map(|(role, text)| ChatMessage::new(role, text)),
+                );
             }
 
-            // Combine all conversation parts
-            let full_prompt = conversation_parts.join("\n");
-
             // Generate response with optional seed for deterministic output
             let response = if let Some(seed) = self.seed {
                 let sampler = GenerationParameters::default().with_seed(seed);
-                chat.add_message(&full_prompt)
-                    .with_sampler(sampler)
-                    .all_text()
-                    .await
+                collect_text(
+                    chat.add_message(&full_prompt).with_sampler(sampler),
+                    on_text,
+                )
+                .await
             } else {
-                chat.add_message(&full_prompt).all_text().await
+                collect_text(chat.add_message(&full_prompt), on_text).await
             };
 
             let updated_session = match chat.session() {
@@ -604,6 +934,57 @@ impl LocalAi for AIModel {
             })
         })
     }
+}
+
+/// Collect a streamed reply, passing each piece to `on_text`.
+async fn collect_text(
+    stream: impl futures_lite::Stream<Item = String>,
+    on_text: &mut dyn FnMut(&str),
+) -> String {
+    use futures_lite::StreamExt;
+
+    futures_lite::pin!(stream);
+    let mut text = String::new();
+    while let Some(piece) = stream.next().await {
+        on_text(&piece);
+        text.push_str(&piece);
+    }
+    text
+}
+
+impl LocalAi for AIModel {
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
+        // Delegate to prompt_with_session without an existing session
+        self.prompt_with_session(messages, None).map(|r| r.response)
+    }
+
+    fn prompt_with_session(
+        &self,
+        messages: &[AiMessage],
+        session: Option<kalosm::language::BoxedChatSession>,
+    ) -> Result<crate::dialogue::PromptResult, AiError> {
+        self.generate(messages, session, &mut |_| {})
+    }
+
+    fn prompt_streaming(
+        &self,
+        messages: &[AiMessage],
+        on_text: &mut dyn FnMut(&str),
+    ) -> Result<String, AiError> {
+        self.generate(messages, None, on_text).map(|r| r.response)
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn Tokenizer>> {
+        self.tokenizer.clone()
+    }
+
+    fn device(&self) -> Option<Device> {
+        self.device
+    }
+
+    fn supports_batching(&self) -> bool {
+        true
+    }
 
     fn get_model(&self) -> kalosm::language::BoxedChatModel {
         // Provide access to the underlying kalosm model for backends that need it.
@@ -621,7 +1002,7 @@ impl LocalAi for AIModel {
             serde_json::Value,
             Option<kalosm::language::BoxedChatSession>,
         ),
-        String,
+        AiError,
     > {
         // Fast path: use the kalosm-aware JsonParser to extract JSON directly.
         use crate::parse::json_parser::JsonParser;
@@ -661,7 +1042,7 @@ pub fn prompt_with_parser_from_backend<P, T>(
     messages: &[AiMessage],
     session: Option<kalosm::language::BoxedChatSession>,
     parser: P,
-) -> Result<(T, Option<kalosm::language::BoxedChatSession>), String>
+) -> Result<(T, Option<kalosm::language::BoxedChatSession>), AiError>
 where
     P: kalosm::language::Parser<Output = T>
         + kalosm::language::CreateParserState
@@ -679,9 +1060,17 @@ where
     let parse_res = parser.parse(&state, text.as_bytes());
 
     match parse_res {
-        Ok(kalosm::language::ParseStatus::Finished { result, .. }) => Ok((result, prompt_res.session)),
-        Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err("Parser reported incomplete result; model output may be truncated or not match the expected shape".to_string()),
-        Err(e) => Err(format!("Parser error: {:?}", e)),
+        Ok(kalosm::language::ParseStatus::Finished { result, .. }) => {
+            Ok((result, prompt_res.session))
+        }
+        Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err(AiError::parse_failure(
+            text.as_str(),
+            "Parser reported incomplete result; model output may be truncated or not match the expected shape",
+        )),
+        Err(e) => Err(AiError::parse_failure(
+            text.as_str(),
+            format!("Parser error: {:?}", e),
+        )),
     }
 }
 
@@ -693,7 +1082,7 @@ pub fn prompt_with_typed_from_backend<P, T>(
     messages: &[AiMessage],
     session: Option<kalosm::language::BoxedChatSession>,
     parser: P,
-) -> Result<(T, Option<kalosm::language::BoxedChatSession>), String>
+) -> Result<(T, Option<kalosm::language::BoxedChatSession>), AiError>
 where
     P: kalosm::language::Parser<Output = T>
         + kalosm::language::CreateParserState
@@ -704,16 +1093,21 @@ where
 {
     // First, ask the backend to produce a typed JSON value if it supports optimized paths.
     match backend.prompt_typed(messages, session.clone(), &T::schema_description()) {
-        Ok((value, sess)) => match serde_json::from_value::<T>(value) {
-            Ok(parsed) => return Ok((parsed, sess)),
-            Err(e) => {
-                // Fall through to post-generation parsing if conversion fails
-                eprintln!(
-                    "Typed parse failed: {}. Falling back to post-generation parsing.",
-                    e
-                );
+        Ok((mut value, sess)) => {
+            <T as crate::actions::IntoActionPayload>::fill_missing_params(&mut value);
+            let parsed = <T as crate::actions::IntoActionPayload>::validate_params(&value)
+                .and_then(|_| serde_json::from_value::<T>(value).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(parsed) => return Ok((parsed, sess)),
+                Err(e) => {
+                    // Fall through to post-generation parsing if conversion fails
+                    eprintln!(
+                        "Typed parse failed: {}. Falling back to post-generation parsing.",
+                        e
+                    );
+                }
             }
-        },
+        }
         Err(_) => {
             // Backend didn't produce typed result; fall back
         }
@@ -727,13 +1121,23 @@ where
     let parse_res = parser.parse(&state, text.as_bytes());
 
     match parse_res {
-        Ok(kalosm::language::ParseStatus::Finished { result, .. }) => Ok((result, prompt_res.session)),
-        Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err("Parser reported incomplete result; model output may be truncated or not match the expected shape".to_string()),
-        Err(e) => Err(format!("Parser error: {:?}", e)),
+        Ok(kalosm::language::ParseStatus::Finished { result, .. }) => {
+            Ok((result, prompt_res.session))
+        }
+        Ok(kalosm::language::ParseStatus::Incomplete { .. }) => Err(AiError::parse_failure(
+            text.as_str(),
+            "Parser reported incomplete result; model output may be truncated or not match the expected shape",
+        )),
+        Err(e) => Err(AiError::parse_failure(
+            text.as_str(),
+            format!("Parser error: {:?}", e),
+        )),
     }
 }
 
-const DEFAULT_SYSTEM_CONTEXT: &str = "
+/// System context `AIModel` adds to requests unless disabled or replaced by a
+/// [`GlobalSystemPrompt`](crate::dialogue::GlobalSystemPrompt).
+pub const DEFAULT_SYSTEM_CONTEXT: &str = "
 You are in a game world.
 
 Rules:
@@ -741,3 +1145,72 @@ Rules:
 - No other people, places, items or information exist beyond those listed
 - Do not add details, inferences, or explanations
 ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warm_up_runs_one_prompt() {
+        struct Counting(AtomicUsize);
+        impl LocalAi for Counting {
+            fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
+                assert!(matches!(messages, [AiMessage::User(text)] if &**text == "Hi"));
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Err(AiError::Timeout("slow".to_string()))
+            }
+        }
+
+        let model = Arc::new(Counting(AtomicUsize::new(0)));
+        // A failing warm-up is only logged
+        TOKIO_RUNTIME.block_on(warm_up(model.clone(), "Hi"));
+        assert_eq!(model.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn assistant_messages_become_prior_turns() {
+        let messages = [
+            AiMessage::system("You are a guard."),
+            AiMessage::user("Example question"),
+            AiMessage::user("Who goes there?"),
+            AiMessage::assistant("Halt! State your name."),
+            AiMessage::user("Anna."),
+            AiMessage::assistant("Pass, Anna."),
+            AiMessage::user("Where is the inn?"),
+        ];
+        let (system, prompt) = assemble_prompt(&messages, None);
+        assert_eq!(system, "You are a guard.");
+        assert_eq!(prompt, "Where is the inn?");
+        assert_eq!(
+            prior_turns(&messages),
+            [
+                (
+                    MessageType::UserMessage,
+                    "Example question\nWho goes there?".to_string()
+                ),
+                (
+                    MessageType::ModelAnswer,
+                    "Halt! State your name.".to_string()
+                ),
+                (MessageType::UserMessage, "Anna.".to_string()),
+                (MessageType::ModelAnswer, "Pass, Anna.".to_string()),
+            ]
+        );
+        // Without assistant messages every user message is the prompt
+        assert!(prior_turns(&messages[..3]).is_empty());
+        assert_eq!(
+            assemble_prompt(&messages[..3], None).1,
+            "Example question\nWho goes there?"
+        );
+    }
+
+    #[test]
+    fn unavailable_devices_fall_back_to_the_cpu() {
+        assert_eq!(Device::Cpu.open().0, Device::Cpu);
+        // No test machine has a 65th GPU
+        assert_eq!(Device::Cuda(64).open().0, Device::Cpu);
+        if !cfg!(feature = "cuda") && !cfg!(target_os = "macos") {
+            assert_eq!(Device::Auto.open().0, Device::Cpu);
+        }
+    }
+}
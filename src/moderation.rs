@@ -0,0 +1,264 @@
+//! Content filtering of model output.
+//!
+//! Games for younger audiences cannot show whatever a model writes. With an
+//! [`AiOutputModeration`] resource, every generated reply is checked by its
+//! [`AiOutputFilter`]s on the background task, before actions are parsed into the pipeline or
+//! the text reaches a `DialogueReceiver`:
+//!
+//! - [`KeywordFilter`] blocks whole words (case-insensitive) and regular expressions,
+//! - [`ModelClassifier`] asks a model whether the text suits the audience,
+//! - any type implementing [`AiOutputFilter`] can be added for game-specific rules.
+//!
+//! A blocked text reply is replaced by [`AiOutputModeration::replacement`] and its actions are
+//! dropped; a blocked typed, data or classify reply becomes an `(ai error: ...)` reply, since
+//! the replacement would not parse. Each block fires an [`AiOutputBlocked`] event with the
+//! original text. Authored (preprogrammed) replies are not checked, and replies are checked
+//! after any [`AiLocale`](crate::locale::AiLocale) translation, so filters see what the player
+//! reads.
+//!
+//! # Example
+//! ```ignore
+//! let words = KeywordFilter::new(["damn", "blood"]).with_pattern(r"\bkill(ed|ing)?\b")?;
+//! app.insert_resource(
+//!     AiOutputModeration::default()
+//!         .with_filter(words)
+//!         .with_filter(ModelClassifier::new("children under 12"))
+//!         .with_replacement("Hmm, let's talk about something else."),
+//! );
+//!
+//! app.add_observer(|blocked: On<AiOutputBlocked>| {
+//!     warn!("Blocked reply of {:?}: {}", blocked.entity, blocked.reason);
+//! });
+//! ```
+
+use bevy::prelude::*;
+use regex::Regex;
+use std::sync::Arc;
+
+use crate::actions::ActionPayload;
+use crate::dialogue::{DialogueRequestKind, LocalAi};
+use crate::prompts::{MODERATE, PromptTemplates, render_template};
+use crate::rag::AiMessage;
+
+/// Decides whether generated text may be shown. Called on the background task.
+pub trait AiOutputFilter: Send + Sync + 'static {
+    /// `Err(reason)` blocks the text. `backend` is the dialogue backend, for filters asking a
+    /// model.
+    fn check(&self, text: &str, backend: &dyn LocalAi) -> Result<(), String>;
+}
+
+/// Filter blocking listed words and regular expressions.
+#[derive(Debug, Clone, Default)]
+pub struct KeywordFilter {
+    /// Blocked words, matched whole and ignoring case.
+    pub words: Vec<String>,
+    pub patterns: Vec<Regex>,
+}
+
+impl KeywordFilter {
+    pub fn new<S: Into<String>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            words: words.into_iter().map(Into::into).collect(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Also block text matching `pattern`, compiled case-insensitive.
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self, String> {
+        let regex = Regex::new(&format!("(?i){}", pattern))
+            .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+}
+
+impl AiOutputFilter for KeywordFilter {
+    fn check(&self, text: &str, _backend: &dyn LocalAi) -> Result<(), String> {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(str::to_lowercase)
+            .collect();
+        if let Some(word) = self
+            .words
+            .iter()
+            .find(|w| words.contains(&w.to_lowercase()))
+        {
+            return Err(format!("blocked word '{}'", word));
+        }
+        if let Some(pattern) = self.patterns.iter().find(|p| p.is_match(text)) {
+            return Err(format!("matched blocked pattern '{}'", pattern.as_str()));
+        }
+        Ok(())
+    }
+}
+
+/// Filter asking a model whether text is suitable for an audience. The prompt is the
+/// [`MODERATE`] template, with `audience` and `text` placeholders.
+///
+/// Text is blocked when the model answers `UNSAFE`, and also when it cannot be asked.
+#[derive(Clone)]
+pub struct ModelClassifier {
+    pub audience: String,
+    /// Model doing the check. `None` uses the dialogue backend.
+    pub backend: Option<Arc<dyn LocalAi>>,
+    pub template: String,
+}
+
+impl ModelClassifier {
+    pub fn new(audience: impl Into<String>) -> Self {
+        Self {
+            audience: audience.into(),
+            backend: None,
+            template: PromptTemplates::default()
+                .get(MODERATE)
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: Arc<dyn LocalAi>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+}
+
+impl AiOutputFilter for ModelClassifier {
+    fn check(&self, text: &str, backend: &dyn LocalAi) -> Result<(), String> {
+        let prompt = render_template(
+            &self.template,
+            &[("audience", &self.audience), ("text", text)],
+        )?;
+        let messages = [AiMessage::skip_default_context(), AiMessage::user(prompt)];
+        let backend = self.backend.as_deref().unwrap_or(backend);
+        let verdict = backend
+            .prompt(&messages)
+            .map_err(|e| format!("moderation failed: {}", e))?;
+        match crate::parse::match_label(&verdict, &["SAFE", "UNSAFE"]) {
+            Some("SAFE") => Ok(()),
+            Some(_) => Err(format!("not suitable for {}", self.audience)),
+            None => Err(format!("unclear moderation verdict: {}", verdict.trim())),
+        }
+    }
+}
+
+/// Resource checking generated replies, see the [module docs](self).
+#[derive(Resource, Clone)]
+pub struct AiOutputModeration {
+    pub filters: Vec<Arc<dyn AiOutputFilter>>,
+    /// Text shown instead of a blocked text reply.
+    pub replacement: String,
+}
+
+impl Default for AiOutputModeration {
+    fn default() -> Self {
+        Self {
+            filters: Vec::new(),
+            replacement: "...".to_string(),
+        }
+    }
+}
+
+impl AiOutputModeration {
+    pub fn with_filter(mut self, filter: impl AiOutputFilter) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// The first filter's reason to block `text`, if any.
+    pub fn check(&self, text: &str, backend: &dyn LocalAi) -> Result<(), String> {
+        self.filters
+            .iter()
+            .try_for_each(|filter| filter.check(text, backend))
+    }
+
+    /// Check a generated reply, replacing it and dropping its actions when blocked.
+    pub(crate) fn apply(
+        &self,
+        backend: &dyn LocalAi,
+        kind: &DialogueRequestKind,
+        response: &mut String,
+        actions: &mut Option<Vec<ActionPayload>>,
+    ) -> Option<BlockedOutput> {
+        if response.starts_with("(ai error") {
+            return None;
+        }
+        let reason = self.check(response, backend).err()?;
+        let replacement = match kind {
+            DialogueRequestKind::Text { .. } => self.replacement.clone(),
+            _ => format!("(ai error: reply blocked: {})", reason),
+        };
+        *actions = Some(Vec::new());
+        Some(BlockedOutput {
+            text: std::mem::replace(response, replacement),
+            reason,
+        })
+    }
+}
+
+/// A reply blocked by [`AiOutputModeration`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockedOutput {
+    /// The reply as generated.
+    pub text: String,
+    pub reason: String,
+}
+
+/// Event fired for every reply blocked by [`AiOutputModeration`].
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct AiOutputBlocked {
+    pub entity: Entity,
+    pub request_id: u64,
+    /// The reply as generated.
+    pub text: String,
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialogue::MockAi;
+
+    #[test]
+    fn keywords_match_whole_words_and_patterns() {
+        let filter = KeywordFilter::new(["Blood"])
+            .with_pattern(r"\bkill(ed|ing)?\b")
+            .unwrap();
+        let backend = MockAi {};
+        assert_eq!(
+            filter.check("The BLOOD moon rises.", &backend),
+            Err("blocked word 'Blood'".to_string())
+        );
+        assert!(
+            filter
+                .check("Bloodhounds guard the gate.", &backend)
+                .is_ok()
+        );
+        assert!(filter.check("He was killed at dawn.", &backend).is_err());
+        assert!(filter.check("Skills take practice.", &backend).is_ok());
+        assert!(KeywordFilter::default().with_pattern("(").is_err());
+
+        let moderation = AiOutputModeration::default().with_filter(filter);
+        let mut response = "Blood everywhere! {\"name\": \"attack\"}".to_string();
+        let mut actions = None;
+        let text = DialogueRequestKind::Text {
+            message: "hi".into(),
+            include_context: true,
+        };
+        let blocked = moderation
+            .apply(&backend, &text, &mut response, &mut actions)
+            .unwrap();
+        assert_eq!(response, "...");
+        assert_eq!(actions, Some(Vec::new()));
+        assert!(blocked.text.starts_with("Blood everywhere!"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Persistent NPC opinions of other characters' deeds.
+//!
+//! Each NPC can carry an [`OpinionLedger`] recording typed [`Deed`]s (theft, gifts, attacks...)
+//! done to it. Game events are mapped into deeds through
+//! [`AiAppExt::track_deeds`](crate::app_ext::AiAppExt::track_deeds), and the [`OpinionPlugin`]
+//! registers a context-gathering system that summarizes the ledger for the model, so AI
+//! responses reflect what the player actually did.
+//!
+//! # Example
+//! ```ignore
+//! #[derive(Event)]
+//! struct ItemStolen { from: Entity, item: String }
+//!
+//! app.add_plugins(OpinionPlugin)
+//!     .track_deeds::<ItemStolen, _>(|e| {
+//!         Some((e.from, Deed::new("the player", DeedKind::Theft).with_description(format!("stole your {}", e.item))))
+//!     });
+//! ```
+
+use bevy::prelude::*;
+use std::time::SystemTime;
+
+use crate::context::{AiEntity, AiSystemContextStore};
+use crate::rag::AiMessage;
+
+/// Kind of deed, each carrying a default weight on the opinion score.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeedKind {
+    Gift,
+    Help,
+    Trade,
+    Insult,
+    Theft,
+    Attack,
+    /// Game-specific deed with its own label and weight (negative is bad).
+    Custom {
+        label: String,
+        weight: f32,
+    },
+}
+
+impl DeedKind {
+    /// Effect of one such deed on the opinion score (negative is bad).
+    pub fn weight(&self) -> f32 {
+        match self {
+            DeedKind::Gift => 0.3,
+            DeedKind::Help => 0.4,
+            DeedKind::Trade => 0.1,
+            DeedKind::Insult => -0.2,
+            DeedKind::Theft => -0.5,
+            DeedKind::Attack => -0.8,
+            DeedKind::Custom { weight, .. } => *weight,
+        }
+    }
+
+    /// Short human-readable label.
+    pub fn label(&self) -> &str {
+        match self {
+            DeedKind::Gift => "gave you a gift",
+            DeedKind::Help => "helped you",
+            DeedKind::Trade => "traded with you",
+            DeedKind::Insult => "insulted you",
+            DeedKind::Theft => "stole from you",
+            DeedKind::Attack => "attacked you",
+            DeedKind::Custom { label, .. } => label.as_str(),
+        }
+    }
+}
+
+/// Something a character did to the NPC owning the ledger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deed {
+    /// Who did it, as the NPC should refer to them (e.g. "the player").
+    pub subject: String,
+    pub kind: DeedKind,
+    /// Optional specific description replacing the kind's generic label.
+    pub description: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+impl Deed {
+    pub fn new(subject: impl Into<String>, kind: DeedKind) -> Self {
+        Self {
+            subject: subject.into(),
+            kind,
+            description: None,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Text used in summaries, e.g. "stole your sword".
+    pub fn text(&self) -> &str {
+        self.description
+            .as_deref()
+            .unwrap_or_else(|| self.kind.label())
+    }
+}
+
+/// Component holding the deeds done to an NPC.
+#[derive(Component, Debug, Clone)]
+pub struct OpinionLedger {
+    deeds: Vec<Deed>,
+    /// How many recent deeds per subject are listed in the summary.
+    pub recent_in_summary: usize,
+}
+
+impl Default for OpinionLedger {
+    fn default() -> Self {
+        Self {
+            deeds: Vec::new(),
+            recent_in_summary: 3,
+        }
+    }
+}
+
+impl OpinionLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a deed.
+    pub fn record(&mut self, deed: Deed) {
+        self.deeds.push(deed);
+    }
+
+    /// All recorded deeds, oldest first.
+    pub fn deeds(&self) -> &[Deed] {
+        &self.deeds
+    }
+
+    pub fn clear(&mut self) {
+        self.deeds.clear();
+    }
+
+    /// Opinion of `subject` in the range -1.0 (hostile) to 1.0 (devoted).
+    pub fn opinion_of(&self, subject: &str) -> f32 {
+        let total: f32 = self
+            .deeds
+            .iter()
+            .filter(|d| d.subject == subject)
+            .map(|d| d.kind.weight())
+            .sum();
+        total.tanh()
+    }
+
+    /// Distinct subjects in the order they first appear.
+    pub fn subjects(&self) -> Vec<&str> {
+        let mut subjects: Vec<&str> = Vec::new();
+        for deed in &self.deeds {
+            if !subjects.contains(&deed.subject.as_str()) {
+                subjects.push(deed.subject.as_str());
+            }
+        }
+        subjects
+    }
+
+    /// Summarize the opinion of every subject, or `None` when nothing was recorded.
+    pub fn summary(&self) -> Option<String> {
+        let lines: Vec<String> = self
+            .subjects()
+            .into_iter()
+            .map(|subject| {
+                let score = self.opinion_of(subject);
+                let recent: Vec<&str> = self
+                    .deeds
+                    .iter()
+                    .rev()
+                    .filter(|d| d.subject == subject)
+                    .take(self.recent_in_summary)
+                    .map(|d| d.text())
+                    .collect();
+                format!(
+                    "Your opinion of {} is {} ({:.1}). You remember that they {}.",
+                    subject,
+                    opinion_label(score),
+                    score,
+                    recent.join(", and ")
+                )
+            })
+            .collect();
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+}
+
+/// Word describing an opinion score.
+pub fn opinion_label(score: f32) -> &'static str {
+    match score {
+        s if s <= -0.6 => "hostile",
+        s if s <= -0.2 => "distrustful",
+        s if s < 0.2 => "neutral",
+        s if s < 0.6 => "friendly",
+        _ => "devoted",
+    }
+}
+
+/// Context-gathering system adding the requester's opinion summary to its context.
+pub fn gather_opinion_context(
+    ai_entity: AiEntity,
+    ledgers: Query<&OpinionLedger>,
+) -> Option<AiMessage> {
+    ledgers
+        .get(ai_entity.entity())
+        .ok()
+        .and_then(|ledger| ledger.summary())
+        .map(AiMessage::system)
+}
+
+/// Plugin registering [`gather_opinion_context`] with the context store.
+pub struct OpinionPlugin;
+
+impl Plugin for OpinionPlugin {
+    fn build(&self, app: &mut App) {
+        app.world_mut()
+            .get_resource_or_init::<AiSystemContextStore>()
+            .add_system(gather_opinion_context);
+    }
+}
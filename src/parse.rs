@@ -3,9 +3,11 @@
 //! This module provides the `AiParsable` trait and helper functions for extracting
 //! structured data from AI model responses.
 
+use bevy::prelude::Resource;
 use serde::de::DeserializeOwned;
 
 use crate::actions::IntoActionPayload;
+use crate::error::AiError;
 
 /// Trait for types that can be parsed from AI responses.
 ///
@@ -16,20 +18,64 @@ pub trait AiParsable: IntoActionPayload + Clone + Send + Sync + 'static {
     /// This is included in prompts to guide the AI's output format.
     fn schema_description() -> String;
 
+    /// Name and type of each field of the JSON object, in order. Used to flatten this type
+    /// into another action with `#[ai(flatten)]`; empty for labels and hand-written schemas.
+    fn schema_fields() -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    /// A filled-in value matching the schema, shown to the model next to it. Derived types
+    /// build it from field defaults, `one_of` and `range` checks and field types, leaving out
+    /// fields whose placeholder would break a check (e.g. a `regex`); `None` for hand-written
+    /// schemas.
+    fn schema_example() -> Option<serde_json::Value> {
+        None
+    }
+
     /// Returns the type name for schema descriptions.
     fn type_name() -> &'static str;
 
+    /// For label enums, the set of labels the model may answer with. `None` for structs.
+    fn labels() -> Option<&'static [&'static str]> {
+        None
+    }
+
     /// Parse an AI response string into this type.
     /// The response may contain JSON embedded in text; this method extracts and parses it.
-    fn parse_from_ai_response(response: &str) -> Result<Self, String>
+    fn parse_from_ai_response(response: &str) -> Result<Self, AiError>
     where
         Self: Sized + DeserializeOwned;
 }
 
 /// Helper trait for generating type descriptions in schemas.
-/// Implemented for common types to provide human-readable type names.
+/// Implemented for common types to provide human-readable type names, and by
+/// `#[derive(AiAction)]` so derived types can be used as fields of other actions.
 pub trait AiSchemaType {
     fn type_name() -> &'static str;
+
+    /// Description used in schemas. Defaults to [`type_name`](Self::type_name); arrays name
+    /// their element type and derived structs spell out their fields.
+    fn describe() -> String {
+        Self::type_name().to_string()
+    }
+
+    /// Whether the model may leave the field out; `true` for `Option`.
+    fn is_optional() -> bool {
+        false
+    }
+
+    /// Placeholder value for examples, from [`type_name`](Self::type_name) by default.
+    fn example() -> serde_json::Value {
+        match Self::type_name() {
+            "string" => serde_json::json!("text"),
+            "integer" => serde_json::json!(0),
+            "number" => serde_json::json!(0.0),
+            "boolean" => serde_json::json!(false),
+            "array" => serde_json::json!([]),
+            "object" => serde_json::json!({}),
+            _ => serde_json::Value::Null,
+        }
+    }
 }
 
 // Implement AiSchemaType for common types
@@ -109,6 +155,14 @@ impl<T: AiSchemaType> AiSchemaType for Vec<T> {
     fn type_name() -> &'static str {
         "array"
     }
+
+    fn describe() -> String {
+        format!("array of {}", T::describe())
+    }
+
+    fn example() -> serde_json::Value {
+        serde_json::json!([T::example()])
+    }
 }
 
 impl<T: AiSchemaType> AiSchemaType for Option<T> {
@@ -116,6 +170,18 @@ impl<T: AiSchemaType> AiSchemaType for Option<T> {
         // For optional fields, we indicate the inner type
         T::type_name()
     }
+
+    fn describe() -> String {
+        T::describe()
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+
+    fn example() -> serde_json::Value {
+        T::example()
+    }
 }
 
 /// Extract JSON from an AI response and parse it into the target type.
@@ -124,42 +190,254 @@ impl<T: AiSchemaType> AiSchemaType for Option<T> {
 /// - Pure JSON
 /// - JSON wrapped in markdown code blocks (```json ... ```)
 /// - JSON embedded in explanatory text
-pub fn extract_and_parse_json<T: DeserializeOwned>(response: &str) -> Result<T, String> {
+/// - JSON with the mistakes [`LenientJson`] reads, such as single quotes or trailing commas
+pub fn extract_and_parse_json<T: DeserializeOwned>(response: &str) -> Result<T, AiError> {
+    extract_and_parse_json_with(response, &LenientJson::default())
+}
+
+/// Like [`extract_and_parse_json`], reading JSON mistakes only as far as `lenient` allows.
+pub fn extract_and_parse_json_with<T: DeserializeOwned>(
+    response: &str,
+    lenient: &LenientJson,
+) -> Result<T, AiError> {
+    extract_and_parse_with(response, lenient, |json| {
+        serde_json::from_str::<T>(json).map_err(|_| None)
+    })
+}
+
+/// Like [`extract_and_parse_json`], but fields the model left out are filled in with
+/// [`IntoActionPayload::fill_missing_params`] and the result is checked with
+/// [`IntoActionPayload::validate_params`]. A failed check is the reason of the returned error.
+pub fn extract_and_parse_action<T>(response: &str) -> Result<T, AiError>
+where
+    T: IntoActionPayload + DeserializeOwned,
+{
+    extract_and_parse_with(response, &LenientJson::default(), |json| {
+        let mut value = serde_json::from_str::<serde_json::Value>(json).map_err(|_| None)?;
+        T::fill_missing_params(&mut value);
+        T::validate_params(&value).map_err(Some)?;
+        serde_json::from_value(value).map_err(|_| None)
+    })
+}
+
+/// Try `parse` on each JSON candidate found in the response, in order, then on the values
+/// `lenient` reads from them. `parse` fails with `Some(reason)` when the JSON was read but
+/// rejected; the last reason is reported.
+fn extract_and_parse_with<T>(
+    response: &str,
+    lenient: &LenientJson,
+    parse: impl Fn(&str) -> Result<T, Option<String>>,
+) -> Result<T, AiError> {
+    let mut rejected = None;
+    let mut attempt = |json: &str| match parse(json) {
+        Ok(parsed) => Some(parsed),
+        Err(reason) => {
+            rejected = reason.or(rejected.take());
+            None
+        }
+    };
+
     // First, try to parse the entire response as JSON
-    if let Ok(parsed) = serde_json::from_str::<T>(response.trim()) {
+    if let Some(parsed) = attempt(response.trim()) {
         return Ok(parsed);
     }
 
     // Try to find JSON in a code block
-    if let Some(json_str) = extract_json_from_code_block(response) {
-        if let Ok(parsed) = serde_json::from_str::<T>(&json_str) {
+    let code_block = extract_json_from_code_block(response);
+    if let Some(json_str) = &code_block {
+        if let Some(parsed) = attempt(json_str) {
             return Ok(parsed);
         }
     }
 
     // Try to find a JSON object anywhere in the response
     if let Some(json_str) = extract_json_object(response) {
-        if let Ok(parsed) = serde_json::from_str::<T>(&json_str) {
+        if let Some(parsed) = attempt(&json_str) {
             return Ok(parsed);
         }
+    }
 
-        // If parsing failed, attempt a best-effort repair for common issues (missing array brackets etc.)
-        let repaired = try_repair_json(&json_str);
-        if repaired != json_str {
-            if let Ok(parsed) = serde_json::from_str::<T>(&repaired) {
-                return Ok(parsed);
+    // Read the JSON leniently, from the code block or the first bracket on
+    for value in lenient_values(response, code_block.as_deref(), lenient) {
+        if let Some(parsed) = attempt(&value.to_string()) {
+            return Ok(parsed);
+        }
+    }
+
+    Err(AiError::parse_failure(
+        response,
+        rejected.unwrap_or_else(|| "Failed to parse JSON from AI response".to_string()),
+    ))
+}
+
+/// Values `lenient` reads from `response`: from its code block, then from the first `{` and
+/// the first `[` on, so text before the JSON is skipped.
+fn lenient_values<'a>(
+    response: &'a str,
+    code_block: Option<&'a str>,
+    lenient: &'a LenientJson,
+) -> impl Iterator<Item = serde_json::Value> + 'a {
+    code_block
+        .into_iter()
+        .chain(
+            ['{', '[']
+                .into_iter()
+                .filter_map(|bracket| response.find(bracket).map(|i| &response[i..])),
+        )
+        .filter_map(|candidate| lenient.parse(candidate).ok())
+}
+
+/// Like [`lenient_values`], finding the code block itself.
+pub(crate) fn find_lenient_values(response: &str, lenient: &LenientJson) -> Vec<serde_json::Value> {
+    let code_block = extract_json_from_code_block(response);
+    lenient_values(response, code_block.as_deref(), lenient).collect()
+}
+
+/// Schema text for `#[ai(range)]`, e.g. `between 0 and 100`.
+pub fn describe_range(min: Option<f64>, max: Option<f64>) -> String {
+    match (min, max) {
+        (Some(min), Some(max)) => format!("between {} and {}", min, max),
+        (Some(min), None) => format!("at least {}", min),
+        (None, Some(max)) => format!("at most {}", max),
+        (None, None) => String::new(),
+    }
+}
+
+/// Schema text for `#[ai(one_of)]`, e.g. `one of "red", "green"`.
+pub fn describe_one_of(allowed: &[serde_json::Value]) -> String {
+    let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+    format!("one of {}", allowed.join(", "))
+}
+
+/// Example value for a field with `#[ai(range)]`: numbers outside the range are moved to
+/// the nearest bound, keeping integers whole.
+pub fn example_in_range(
+    example: serde_json::Value,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> serde_json::Value {
+    match example {
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .into_iter()
+                .map(|v| example_in_range(v, min, max))
+                .collect(),
+        ),
+        serde_json::Value::Number(n) => {
+            let value = n.as_f64().unwrap_or_default();
+            let bounded = match (min, max) {
+                (Some(min), _) if value < min => min,
+                (_, Some(max)) if value > max => max,
+                _ => return serde_json::Value::Number(n),
+            };
+            if n.is_f64() {
+                serde_json::json!(bounded)
+            } else if value < bounded {
+                serde_json::json!(bounded.ceil() as i64)
+            } else {
+                serde_json::json!(bounded.floor() as i64)
             }
         }
+        other => other,
     }
+}
 
-    Err(format!(
-        "Failed to parse JSON from AI response. Response was: {}",
-        if response.len() > 200 {
-            format!("{}...", &response[..200])
+/// Example value for a field with `#[ai(one_of)]`: the first allowed value, in each element
+/// of arrays.
+pub fn example_one_of(
+    example: serde_json::Value,
+    allowed: &[serde_json::Value],
+) -> serde_json::Value {
+    match (example, allowed.first()) {
+        (serde_json::Value::Array(items), _) => serde_json::Value::Array(
+            items
+                .into_iter()
+                .map(|v| example_one_of(v, allowed))
+                .collect(),
+        ),
+        (_, Some(first)) => first.clone(),
+        (other, None) => other,
+    }
+}
+
+/// Check `#[ai(range)]` on a number or each number of an array.
+pub fn check_range(
+    field: &str,
+    value: &serde_json::Value,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<(), String> {
+    each_value(value, |v| {
+        let Some(n) = v.as_f64() else {
+            return Err(format!("`{}` is {}, expected a number", field, v));
+        };
+        if min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max) {
+            return Err(format!(
+                "`{}` is {}, expected a number {}",
+                field,
+                v,
+                describe_range(min, max)
+            ));
+        }
+        Ok(())
+    })
+}
+
+/// Check `#[ai(one_of)]` on a value or each value of an array.
+pub fn check_one_of(
+    field: &str,
+    value: &serde_json::Value,
+    allowed: &[serde_json::Value],
+) -> Result<(), String> {
+    each_value(value, |v| {
+        if allowed.contains(v) {
+            Ok(())
         } else {
-            response.to_string()
+            Err(format!(
+                "`{}` is {}, expected {}",
+                field,
+                v,
+                describe_one_of(allowed)
+            ))
         }
-    ))
+    })
+}
+
+/// An `#[ai(regex)]` pattern compiled by [`compile_pattern`], or why it failed to compile.
+/// The derive keeps one per field in a static, so each pattern is compiled once.
+pub type CompiledPattern = Result<regex::Regex, String>;
+
+/// Compile the `#[ai(regex)]` pattern of `field`.
+pub fn compile_pattern(field: &str, pattern: &str) -> CompiledPattern {
+    regex::Regex::new(pattern).map_err(|e| format!("invalid pattern for `{}`: {}", field, e))
+}
+
+/// Check `#[ai(regex)]` on a string or each string of an array.
+pub fn check_pattern(
+    field: &str,
+    value: &serde_json::Value,
+    pattern: &CompiledPattern,
+) -> Result<(), String> {
+    let regex = pattern.as_ref().map_err(Clone::clone)?;
+    each_value(value, |v| match v.as_str() {
+        Some(text) if regex.is_match(text) => Ok(()),
+        _ => Err(format!(
+            "`{}` is {}, expected text matching {}",
+            field,
+            v,
+            regex.as_str()
+        )),
+    })
+}
+
+fn each_value(
+    value: &serde_json::Value,
+    mut check: impl FnMut(&serde_json::Value) -> Result<(), String>,
+) -> Result<(), String> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().try_for_each(check),
+        v => check(v),
+    }
 }
 
 /// Extract JSON from markdown code blocks (```json ... ``` or ``` ... ```)
@@ -218,112 +496,500 @@ fn extract_json_object(text: &str) -> Option<String> {
     None
 }
 
-/// Try to repair common JSON mistakes produced by LLMs such as missing closing
-/// array brackets. This is a best-effort heuristic — it attempts to balance
-/// '[' / ']' by inserting missing ']' after the last object in the array.
-fn try_repair_json(input: &str) -> String {
-    // Quick check: if parsing works, return original
-    if serde_json::from_str::<serde_json::Value>(input).is_ok() {
-        return input.to_string();
+/// Which mistakes in model-written JSON are read rather than rejected. All are accepted by
+/// default; [`LenientJson::strict`] accepts only JSON.
+///
+/// [`extract_and_parse_json`] falls back to it when a reply is not valid JSON. Inserted as a
+/// resource, it also reads the actions of dialogue replies such as `{'name': 'wave'}`, which
+/// otherwise yield none.
+///
+/// # Example
+/// ```ignore
+/// let value = LenientJson::default().parse("{name: 'Greta', gold: NaN, // unknown\n}")?;
+/// assert_eq!(value, serde_json::json!({"name": "Greta", "gold": null}));
+/// ```
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LenientJson {
+    /// Strings in single quotes, `'like this'`.
+    pub single_quotes: bool,
+    /// Object keys without quotes, `{name: "Greta"}`.
+    pub unquoted_keys: bool,
+    /// A comma before a closing bracket, `[1, 2,]`.
+    pub trailing_commas: bool,
+    /// `// line` and `/* block */` comments.
+    pub comments: bool,
+    /// `NaN`, `Infinity` and `-Infinity`, read as `null` since JSON numbers cannot hold them.
+    pub non_finite_numbers: bool,
+    /// Missing `]` and `}`, closed where the enclosing container closes or the text ends.
+    pub unclosed_brackets: bool,
+}
+
+impl Default for LenientJson {
+    fn default() -> Self {
+        Self {
+            single_quotes: true,
+            unquoted_keys: true,
+            trailing_commas: true,
+            comments: true,
+            non_finite_numbers: true,
+            unclosed_brackets: true,
+        }
     }
+}
 
-    let mut in_string = false;
-    let mut escape = false;
-    let mut last_open_array_pos: Option<usize> = None;
+impl LenientJson {
+    /// Accept only valid JSON.
+    pub fn strict() -> Self {
+        Self {
+            single_quotes: false,
+            unquoted_keys: false,
+            trailing_commas: false,
+            comments: false,
+            non_finite_numbers: false,
+            unclosed_brackets: false,
+        }
+    }
 
-    for (i, ch) in input.char_indices() {
-        if escape {
-            escape = false;
-            continue;
+    /// Read the JSON value at the start of `text`, ignoring any text after it.
+    pub fn parse(&self, text: &str) -> Result<serde_json::Value, String> {
+        let mut reader = LenientReader {
+            chars: text.chars().collect(),
+            pos: 0,
+            depth: 0,
+            options: *self,
+        };
+        reader.skip_blank();
+        reader.value()
+    }
+}
+
+/// Nesting deeper than this is rejected instead of overflowing the stack.
+const MAX_JSON_DEPTH: usize = 128;
+
+/// Recursive descent reader behind [`LenientJson::parse`].
+struct LenientReader {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+    options: LenientJson,
+}
+
+impl LenientReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
         }
-        if ch == '\\' && in_string {
-            escape = true;
-            continue;
+        found
+    }
+
+    /// Skip whitespace, and comments when allowed.
+    fn skip_blank(&mut self) {
+        loop {
+            while self.peek().is_some_and(char::is_whitespace) {
+                self.pos += 1;
+            }
+            if !self.options.comments {
+                return;
+            }
+            if self.starts_with("//") {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if self.starts_with("/*") {
+                self.pos += 2;
+                while self.peek().is_some() && !self.starts_with("*/") {
+                    self.pos += 1;
+                }
+                self.pos = (self.pos + 2).min(self.chars.len());
+            } else {
+                return;
+            }
         }
-        if ch == '"' {
-            in_string = !in_string;
-            continue;
+    }
+
+    /// A run of identifier characters, for keywords and unquoted keys.
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '$' | '-'))
+        {
+            self.pos += 1;
         }
-        if in_string {
-            continue;
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn value(&mut self) -> Result<serde_json::Value, String> {
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(serde_json::Value::String),
+            Some('\'') if self.options.single_quotes => {
+                self.string().map(serde_json::Value::String)
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_alphabetic() => {
+                let at = self.pos;
+                match self.word().as_str() {
+                    "true" => Ok(serde_json::Value::Bool(true)),
+                    "false" => Ok(serde_json::Value::Bool(false)),
+                    "null" => Ok(serde_json::Value::Null),
+                    "NaN" | "Infinity" if self.options.non_finite_numbers => {
+                        Ok(serde_json::Value::Null)
+                    }
+                    word => Err(format!("unexpected `{}` at {}", word, at)),
+                }
+            }
+            Some(c) => Err(format!("unexpected `{}` at {}", c, self.pos)),
+            None => Err("unexpected end of text".to_string()),
         }
-        if ch == '[' {
-            last_open_array_pos = Some(i);
+    }
+
+    fn nested(
+        &mut self,
+        read: fn(&mut Self) -> Result<serde_json::Value, String>,
+    ) -> Result<serde_json::Value, String> {
+        if self.depth == MAX_JSON_DEPTH {
+            return Err("nested too deeply".to_string());
         }
-        if ch == ']' {
-            // matched an array; clear last_open_array_pos
-            last_open_array_pos = None;
+        self.depth += 1;
+        self.pos += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// The members of an object, after its `{`.
+    fn object(&mut self) -> Result<serde_json::Value, String> {
+        let mut map = serde_json::Map::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(serde_json::Value::Object(map));
+                }
+                Some(']') | None if self.options.unclosed_brackets => {
+                    return Ok(serde_json::Value::Object(map));
+                }
+                Some(']') | None => return Err(format!("expected `}}` at {}", self.pos)),
+                _ => {}
+            }
+            let key = self.key()?;
+            self.skip_blank();
+            if !self.eat(':') {
+                return Err(format!("expected `:` after key `{}`", key));
+            }
+            self.skip_blank();
+            let value = self.value()?;
+            map.insert(key, value);
+            self.skip_blank();
+            if self.eat(',') {
+                self.skip_blank();
+                if self.peek() == Some('}') && !self.options.trailing_commas {
+                    return Err(format!("trailing comma at {}", self.pos));
+                }
+            } else if !matches!(self.peek(), Some('}' | ']') | None) {
+                return Err(format!("expected `,` or `}}` at {}", self.pos));
+            }
         }
     }
 
-    // If there was an unmatched '[', insert a ']' after the last '}' following it.
-    if let Some(open_pos) = last_open_array_pos {
-        // Find the position of the first '}' after the open_pos
-        let mut in_string = false;
-        let mut escape = false;
-        for (i, ch) in input.char_indices().skip(open_pos) {
-            if escape {
-                escape = false;
-                continue;
+    /// The elements of an array, after its `[`.
+    fn array(&mut self) -> Result<serde_json::Value, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(serde_json::Value::Array(items));
+                }
+                Some('}') | None if self.options.unclosed_brackets => {
+                    return Ok(serde_json::Value::Array(items));
+                }
+                Some('}') | None => return Err(format!("expected `]` at {}", self.pos)),
+                _ => {}
             }
-            if ch == '\\' && in_string {
-                escape = true;
-                continue;
+            items.push(self.value()?);
+            self.skip_blank();
+            if self.eat(',') {
+                self.skip_blank();
+                if self.peek() == Some(']') && !self.options.trailing_commas {
+                    return Err(format!("trailing comma at {}", self.pos));
+                }
+            } else if !matches!(self.peek(), Some(']' | '}') | None) {
+                return Err(format!("expected `,` or `]` at {}", self.pos));
             }
-            if ch == '"' {
-                in_string = !in_string;
-                continue;
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => self.string(),
+            Some('\'') if self.options.single_quotes => self.string(),
+            Some(c) if self.options.unquoted_keys && (c.is_alphabetic() || c == '_') => {
+                Ok(self.word())
             }
-            if in_string {
+            _ => Err(format!("expected a key at {}", self.pos)),
+        }
+    }
+
+    /// A string in the quotes at the current position.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.chars[self.pos];
+        let start = self.pos;
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(format!("unterminated string at {}", start));
+            };
+            self.pos += 1;
+            match c {
+                _ if c == quote => return Ok(text),
+                '\\' => {
+                    let Some(escaped) = self.peek() else {
+                        continue;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        'n' => text.push('\n'),
+                        't' => text.push('\t'),
+                        'r' => text.push('\r'),
+                        'b' => text.push('\u{8}'),
+                        'f' => text.push('\u{c}'),
+                        'u' => text.push(self.unicode_escape()),
+                        // `\"`, `\\`, `\/`, `\'` and unknown escapes keep the character
+                        other => text.push(other),
+                    }
+                }
+                _ => text.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, after the `u`; surrogate pairs are joined.
+    fn unicode_escape(&mut self) -> char {
+        let Some(high) = self.hex4() else {
+            return char::REPLACEMENT_CHARACTER;
+        };
+        if (0xD800..0xDC00).contains(&high) && self.starts_with("\\u") {
+            self.pos += 2;
+            let low = self.hex4().unwrap_or_default();
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+            return char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+        }
+        char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits: String = self.chars.iter().skip(self.pos).take(4).collect();
+        let code = u32::from_str_radix(&digits, 16).ok()?;
+        self.pos += 4;
+        Some(code)
+    }
+
+    fn number(&mut self) -> Result<serde_json::Value, String> {
+        let start = self.pos;
+        if self.options.non_finite_numbers && self.starts_with("-Infinity") {
+            self.pos += "-Infinity".len();
+            return Ok(serde_json::Value::Null);
+        }
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        serde_json::from_str(&text).map_err(|_| format!("invalid number `{}` at {}", text, start))
+    }
+}
+
+/// Incremental scanner for JSON streamed piece by piece, e.g. token by token.
+///
+/// Text before the JSON is skipped. Each object or array closing inside a top-level array is
+/// returned by [`push`](Self::push) as soon as its last bracket arrives, so the first action of
+/// `[{"name": "wave"}, {"name": "open_gate"}]` can run while the second is still generated. A
+/// top-level object is returned once it closes. A top-level value that turns out not to be
+/// JSON is dropped (elements already returned stay returned) and scanning goes on after it.
+///
+/// ```ignore
+/// let mut stream = JsonStream::default();
+/// for token in tokens {
+///     for action in stream.push(&token) {
+///         dispatch(action);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JsonStream {
+    buffer: Vec<u8>,
+    /// Bytes of `buffer` scanned so far.
+    scanned: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Start of the top-level value and whether it is an array.
+    top: Option<(usize, bool)>,
+    /// Start of the array element being scanned.
+    element: Option<usize>,
+    /// Offset just past the top-level value once it closed.
+    end: Option<usize>,
+}
+
+impl JsonStream {
+    /// Add the next piece of text, returning the values it completed.
+    pub fn push(&mut self, text: impl AsRef<[u8]>) -> Vec<serde_json::Value> {
+        self.buffer.extend_from_slice(text.as_ref());
+        let mut values = Vec::new();
+        while self.end.is_none() && self.scanned < self.buffer.len() {
+            let i = self.scanned;
+            self.scanned += 1;
+            let byte = self.buffer[i];
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
                 continue;
             }
-            if ch == '}' {
-                // Insert a closing ']' right after this brace
-                let mut repaired = String::with_capacity(input.len() + 2);
-                repaired.push_str(&input[..i + 1]);
-                repaired.push(']');
-                repaired.push_str(&input[i + 1..]);
-                // Also remove any ',]' sequences that can be invalid (trailing commas)
-                repaired = repaired.replace(",]", "]");
-                // If this repairs the JSON, return it; otherwise, fall through to appending at end
-                if serde_json::from_str::<serde_json::Value>(&repaired).is_ok() {
-                    return repaired;
-                } else {
-                    // keep repaired attempt but continue to final append option
-                    return repaired;
+            match byte {
+                // Quotes in the text before the JSON do not start strings
+                b'"' if self.depth > 0 => self.in_string = true,
+                b'{' | b'[' => {
+                    match self.top {
+                        None => self.top = Some((i, byte == b'[')),
+                        Some((_, true)) if self.depth == 1 => self.element = Some(i),
+                        _ => {}
+                    }
+                    self.depth += 1;
+                }
+                b'}' | b']' if self.depth > 0 => {
+                    self.depth -= 1;
+                    let Some((start, is_array)) = self.top else {
+                        continue;
+                    };
+                    if self.depth == 0 {
+                        let whole =
+                            serde_json::from_slice::<serde_json::Value>(&self.buffer[start..=i]);
+                        match whole {
+                            Ok(value) => {
+                                if !is_array {
+                                    values.push(value);
+                                }
+                                self.end = Some(i + 1);
+                            }
+                            // Not JSON after all, look for the next value
+                            Err(_) => self.top = None,
+                        }
+                    } else if self.depth == 1
+                        && is_array
+                        && let Some(element) = self.element.take()
+                        && let Ok(value) = serde_json::from_slice(&self.buffer[element..=i])
+                    {
+                        values.push(value);
+                    }
                 }
+                _ => {}
             }
         }
+        values
+    }
 
-        // If we didn't find a '}', append a ']' just before the final '}' if present
-        if let Some(last_brace_pos) = input.rfind('}') {
-            let mut repaired = String::with_capacity(input.len() + 2);
-            repaired.push_str(&input[..last_brace_pos]);
-            repaired.push(']');
-            repaired.push_str(&input[last_brace_pos..]);
-            repaired = repaired.replace(",]", "]");
-            return repaired;
-        }
+    /// Whether the top-level value has closed; later text is ignored.
+    pub fn is_finished(&self) -> bool {
+        self.end.is_some()
+    }
 
-        // As a last resort, append ']' at the end
-        let mut repaired = input.to_string();
-        repaired.push(']');
-        return repaired;
+    /// The complete top-level value, once it closed.
+    pub fn finished(&self) -> Option<serde_json::Value> {
+        let (start, _) = self.top?;
+        serde_json::from_slice(&self.buffer[start..self.end?]).ok()
     }
+}
 
-    // No repair heuristic applied — return original
-    input.to_string()
+/// Find which of `labels` an AI response refers to (case-insensitive).
+///
+/// An exact match (ignoring surrounding quotes and punctuation) wins; otherwise the label
+/// appearing earliest in the response as a whole word is returned, so `no` does not match
+/// "know".
+pub fn match_label<'a>(response: &str, labels: &[&'a str]) -> Option<&'a str> {
+    let cleaned = response
+        .trim()
+        .trim_matches(|c: char| !c.is_alphanumeric() && c != '_')
+        .to_lowercase();
+    if let Some(label) = labels.iter().find(|l| l.to_lowercase() == cleaned) {
+        return Some(label);
+    }
+
+    let lower = response.to_lowercase();
+    labels
+        .iter()
+        .filter_map(|l| find_word(&lower, &l.to_lowercase()).map(|pos| (pos, *l)))
+        .min_by_key(|(pos, _)| *pos)
+        .map(|(_, l)| l)
+}
+
+/// Byte position of the first occurrence of `word` in `text` not inside a longer word.
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(word).map(|(pos, _)| pos).find(|&pos| {
+        !text[..pos].chars().next_back().is_some_and(is_word)
+            && !text[pos + word.len()..].chars().next().is_some_and(is_word)
+    })
+}
+
+/// Build a short prompt asking the model to classify `utterance` into exactly one of `labels`.
+pub fn build_classification_prompt(utterance: &str, labels: &[&str]) -> String {
+    format!(
+        "Classify the message into exactly one of these labels: {}.\n\nMessage: \"{}\"\n\nAnswer with only the label.",
+        labels.join(", "),
+        utterance
+    )
 }
 
 /// Build a system prompt that instructs the AI to respond with the expected JSON format.
+///
+/// Besides the schema, the prompt shows [`AiParsable::schema_example`] filled in, which small
+/// models follow much more reliably than the field listing alone.
 pub fn build_typed_prompt<T: AiParsable>(user_message: &str) -> String {
+    let example = schema_example_block::<T>();
     format!(
-        "You must respond with ONLY a valid JSON object matching this schema:\n{}\n\nUser request: {}\n\nRespond with only the JSON object, no explanation.",
+        "You must respond with ONLY a valid JSON object matching this schema:\n{}{}\n\nUser request: {}\n\nRespond with only the JSON object, no explanation.",
         T::schema_description(),
+        example,
         user_message
     )
 }
 
+/// [`AiParsable::schema_example`] as a paragraph to follow the schema in a prompt, empty when
+/// `T` has no example.
+pub(crate) fn schema_example_block<T: AiParsable>() -> String {
+    T::schema_example()
+        .map(|example| {
+            format!(
+                "\n\nExample of the format (use your own values):\n{}",
+                example
+            )
+        })
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -371,6 +1037,31 @@ mod tests {
         assert_eq!(json, r#"{"outer": {"inner": 1}, "value": 2}"#);
     }
 
+    #[test]
+    fn test_match_label() {
+        let labels = ["Trade", "Quest", "Gossip", "Goodbye"];
+        assert_eq!(match_label("quest", &labels), Some("Quest"));
+        assert_eq!(match_label("\"Goodbye\".", &labels), Some("Goodbye"));
+        assert_eq!(
+            match_label("The label is Gossip, not trade", &labels),
+            Some("Gossip")
+        );
+        assert_eq!(match_label("no idea", &labels), None);
+
+        // Labels only match whole words
+        assert_eq!(match_label("I don't know", &["yes", "no"]), None);
+        assert_eq!(
+            match_label("Well... no, I know it", &["yes", "no"]),
+            Some("no")
+        );
+        assert_eq!(match_label("this one", &["hi", "bye"]), None);
+        assert_eq!(match_label("Oh, hi there!", &["hi", "bye"]), Some("hi"));
+        assert_eq!(
+            match_label("Verdict: UNSAFE", &["SAFE", "UNSAFE"]),
+            Some("UNSAFE")
+        );
+    }
+
     #[test]
     fn test_repair_missing_array_bracket() {
         // The incoming AI response is missing the closing ']' for the actions array.
@@ -379,16 +1070,90 @@ mod tests {
         let extracted = extract_json_object(broken).expect("should extract");
         assert!(serde_json::from_str::<serde_json::Value>(&extracted).is_err());
 
-        // Try repair
-        let repaired = try_repair_json(&extracted);
-        // Now repaired should parse
-        let v: serde_json::Value =
-            serde_json::from_str(&repaired).expect("repaired json should parse");
+        // Read it leniently
+        let v = LenientJson::default()
+            .parse(&extracted)
+            .expect("repaired json should parse");
         assert_eq!(v["name"], "spawn");
         assert_eq!(v["id"], "goblin_spawn");
         // Ensure actions is an array
         assert!(v["args"]["actions"].is_array());
     }
+
+    #[test]
+    fn lenient_json_reads_common_model_mistakes() {
+        use serde_json::json;
+
+        let lenient = LenientJson::default();
+        let read = |text: &str| lenient.parse(text).expect(text);
+        // Python dict syntax
+        assert_eq!(
+            read("{'name': 'open_door', 'params': {'door': 'north', 'note': \"it's stuck\"}}"),
+            json!({"name": "open_door", "params": {"door": "north", "note": "it's stuck"}})
+        );
+        // JavaScript object literal
+        assert_eq!(
+            read("{name: \"wave\", params: {}, max_hp: 10}"),
+            json!({"name": "wave", "params": {}, "max_hp": 10})
+        );
+        // Trailing commas left by list-style generation
+        assert_eq!(
+            read("{\"items\": [\"sword\", \"shield\",],\n}"),
+            json!({"items": ["sword", "shield"]})
+        );
+        // Comments explaining the fields
+        assert_eq!(
+            read("{\n  \"name\": \"attack\", // the action\n  /* who */ \"target\": \"orc\"\n}"),
+            json!({"name": "attack", "target": "orc"})
+        );
+        // Scores the model could not compute
+        assert_eq!(
+            read("{\"score\": NaN, \"max\": Infinity, \"min\": -Infinity, \"avg\": -0.5e1}"),
+            json!({"score": null, "max": null, "min": null, "avg": -5.0})
+        );
+        // Reply cut off by the length limit, and chatter after the JSON
+        assert_eq!(
+            read("[{\"name\": \"heal\", \"params\": {\"amount\": 5"),
+            json!([{"name": "heal", "params": {"amount": 5}}])
+        );
+        assert_eq!(
+            read("{\"name\": \"heal\"} I hope this helps!"),
+            json!({"name": "heal"})
+        );
+        assert_eq!(read(r#"'café 😀 \'ok\''"#), json!("café 😀 'ok'"));
+
+        assert!(lenient.parse("Sure! {\"name\": \"heal\"}").is_err());
+        assert!(lenient.parse("{\"name\" \"heal\"}").is_err());
+        assert!(lenient.parse("{\"name\": 'heal").is_err());
+
+        let strict = LenientJson::strict();
+        assert!(strict.parse("{'name': 'heal'}").is_err());
+        assert!(strict.parse("{name: \"heal\"}").is_err());
+        assert!(strict.parse("[1, 2,]").is_err());
+        assert!(strict.parse("[1, // two\n 2]").is_err());
+        assert!(strict.parse("[NaN]").is_err());
+        assert!(strict.parse("[1, 2").is_err());
+        assert_eq!(strict.parse("[1, 2] done").unwrap(), json!([1, 2]));
+    }
+
+    #[test]
+    fn extract_and_parse_json_reads_lenient_replies() {
+        let response = "Here you go:\n```json\n{name: 'x', value: 3,}\n```";
+        let result: TestStruct = extract_and_parse_json(response).expect("should parse");
+        assert_eq!(
+            result,
+            TestStruct {
+                name: "x".into(),
+                value: 3
+            }
+        );
+        let result: Vec<TestStruct> =
+            extract_and_parse_json("Done: [{'name': 'x', 'value': 3},]").expect("should parse");
+        assert_eq!(result.len(), 1);
+        assert!(
+            extract_and_parse_json_with::<TestStruct>(response, &LenientJson::strict()).is_err()
+        );
+    }
 }
 
 pub(crate) mod json_parser {
@@ -396,19 +1161,22 @@ pub(crate) mod json_parser {
     use kalosm::language::{CreateParserState, ParseStatus, Parser, ParserError};
     use std::borrow::Cow;
 
-    /// A very small `Parser` implementation that extracts JSON from the accumulated
-    /// input and attempts to parse it with `serde_json`. This is used when callers
-    /// want to constrain model output to JSON without building a complex parser.
+    /// A very small `Parser` implementation that extracts the first JSON object or array from
+    /// the accumulated input and attempts to parse it with `serde_json`. This is used when
+    /// callers want to constrain model output to JSON without building a complex parser.
+    ///
+    /// Input is scanned incrementally with a [`JsonStream`], so feeding a reply token by token
+    /// only looks at each token once.
     pub struct JsonParser;
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, Default)]
     pub struct JsonParserState {
-        buffer: Vec<u8>,
+        stream: JsonStream,
     }
 
     impl CreateParserState for JsonParser {
         fn create_parser_state(&self) -> JsonParserState {
-            JsonParserState { buffer: Vec::new() }
+            JsonParserState::default()
         }
     }
 
@@ -421,67 +1189,23 @@ pub(crate) mod json_parser {
             state: &Self::PartialState,
             input: &'a [u8],
         ) -> Result<ParseStatus<'a, Self::PartialState, Self::Output>, ParserError> {
-            // Combine previous buffer and new input to search for JSON
-            let mut combined = state.buffer.clone();
-            combined.extend_from_slice(input);
-            let text = String::from_utf8_lossy(&combined).to_string();
-
-            // First try code block JSON
-            if let Some(json_str) = extract_json_from_code_block(&text) {
-                match serde_json::from_str::<serde_json::Value>(&json_str) {
-                    Ok(v) => {
-                        // find position to compute remaining bytes in `input`
-                        if let Some(pos) = text.find(&json_str) {
-                            let end = pos + json_str.len();
-                            let buffer_len = state.buffer.len();
-                            let remaining = if end <= buffer_len {
-                                &input[0..0]
-                            } else {
-                                &input[end - buffer_len..]
-                            };
-                            return Ok(ParseStatus::Finished {
-                                result: v,
-                                remaining,
-                            });
-                        }
-                        return Ok(ParseStatus::Finished {
-                            result: v,
-                            remaining: &input[0..0],
-                        });
-                    }
-                    Err(e) => return Err(ParserError::msg(format!("invalid json: {}", e))),
-                }
-            }
+            let mut stream = state.stream.clone();
+            let previous_len = stream.buffer.len();
+            stream.push(input);
 
-            // Try to find a JSON object anywhere in the text
-            if let Some(obj) = extract_json_object(&text) {
-                if let Ok(v) = serde_json::from_str::<serde_json::Value>(&obj) {
-                    if let Some(pos) = text.find(&obj) {
-                        let end = pos + obj.len();
-                        let buffer_len = state.buffer.len();
-                        let remaining = if end <= buffer_len {
-                            &input[0..0]
-                        } else {
-                            &input[end - buffer_len..]
-                        };
-                        return Ok(ParseStatus::Finished {
-                            result: v,
-                            remaining,
-                        });
-                    }
-                    return Ok(ParseStatus::Finished {
-                        result: v,
-                        remaining: &input[0..0],
-                    });
-                } else {
-                    return Err(ParserError::msg("invalid json"));
-                }
+            if let Some(result) = stream.finished() {
+                // The value ends in this input: the rest is left over
+                let end = stream.end.unwrap_or_default().saturating_sub(previous_len);
+                return Ok(ParseStatus::Finished {
+                    result,
+                    remaining: &input[end.min(input.len())..],
+                });
             }
 
-            // No JSON found yet: request more input
+            // No complete JSON yet: request more input
             Ok(ParseStatus::Incomplete {
-                new_state: JsonParserState { buffer: combined },
-                required_next: Cow::Borrowed("}"),
+                new_state: JsonParserState { stream },
+                required_next: Cow::Borrowed(""),
             })
         }
     }
@@ -511,5 +1235,59 @@ pub(crate) mod json_parser {
                 other => panic!("unexpected parse status: {:?}", other),
             }
         }
+
+        #[test]
+        fn json_parser_parses_streamed_tokens() {
+            let parser = JsonParser;
+            let mut state = parser.create_parser_state();
+            let tokens: [&[u8]; 4] = [
+                b"Sure: [{\"name\": \"x\", ",
+                b"\"value\": 3}",
+                b"]",
+                b" done",
+            ];
+            for (i, token) in tokens.into_iter().enumerate() {
+                match parser.parse(&state, token).expect("parse") {
+                    ParseStatus::Incomplete { new_state, .. } => state = new_state,
+                    ParseStatus::Finished { result, remaining } => {
+                        assert_eq!(i, 2);
+                        assert!(remaining.is_empty());
+                        let s: Vec<TestStruct> =
+                            serde_json::from_value(result).expect("deserialize");
+                        assert_eq!(
+                            s,
+                            [TestStruct {
+                                name: "x".into(),
+                                value: 3
+                            }]
+                        );
+                        return;
+                    }
+                }
+            }
+            panic!("the array never finished");
+        }
+
+        #[test]
+        fn json_stream_returns_array_elements_as_they_close() {
+            let mut stream = JsonStream::default();
+            assert!(
+                stream
+                    .push("Options: {not json} then [{\"name\": \"wa")
+                    .is_empty()
+            );
+            let first = stream.push("ve\", \"params\": {\"to\": \"a ]} b\"}}, {\"name\"");
+            assert_eq!(first.len(), 1);
+            assert_eq!(first[0]["params"]["to"], "a ]} b");
+            assert!(!stream.is_finished());
+            assert_eq!(stream.push(": \"bow\"}]")[0]["name"], "bow");
+            assert!(stream.is_finished());
+            assert!(stream.push("[{}]").is_empty());
+            assert_eq!(stream.finished().unwrap().as_array().unwrap().len(), 2);
+
+            let mut object = JsonStream::default();
+            assert!(object.push("{\"name\": [1, ").is_empty());
+            assert_eq!(object.push("2]} trailing")[0]["name"][1], 2);
+        }
     }
 }
@@ -0,0 +1,127 @@
+//! Character identity for AI entities.
+//!
+//! An [`AiPersona`] gives an AI entity a name and a description of who it is. The dialogue
+//! plugin adds the persona as a system message to every request made for that entity that
+//! includes context, so the model answers in character without the game repeating it in each
+//! prompt. Context-free requests (`text_no_context`, classification) go without it.
+//!
+//! A few exemplar lines ([`AiPersona::with_style_examples`]) anchor how the entity talks. They
+//! are part of the persona message rather than the conversation history, so the voice stays the
+//! same across sessions and model swaps without growing the history.
+
+use bevy::prelude::*;
+
+use crate::rag::AiMessage;
+
+/// Component describing who an AI entity is.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+#[non_exhaustive]
+pub struct AiPersona {
+    /// Display name, also used when other entities refer to this one.
+    pub name: String,
+    /// Free-form description of personality, role and speaking style.
+    pub description: String,
+    /// Voice used when replies are spoken aloud (feature `tts`).
+    pub voice: Option<AiVoice>,
+    /// Lines in this persona's voice, sent as style examples with every prompt.
+    pub style_examples: Vec<String>,
+}
+
+/// Text-to-speech voice settings for an entity.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+#[non_exhaustive]
+pub struct AiVoice {
+    /// Engine-specific voice or speaker id.
+    pub id: String,
+    /// Speaking rate multiplier, 1.0 is normal speed.
+    pub rate: f32,
+    /// Pitch multiplier, 1.0 is the voice's natural pitch.
+    pub pitch: f32,
+}
+
+impl AiVoice {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ..default()
+        }
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn with_pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch;
+        self
+    }
+}
+
+impl Default for AiVoice {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            rate: 1.0,
+            pitch: 1.0,
+        }
+    }
+}
+
+impl AiPersona {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            voice: None,
+            style_examples: Vec::new(),
+        }
+    }
+
+    /// Speak this persona's replies with `voice`.
+    pub fn with_voice(mut self, voice: AiVoice) -> Self {
+        self.voice = Some(voice);
+        self
+    }
+
+    /// Anchor the persona's voice with a few lines it would say. Keep them short; three to
+    /// five are usually enough.
+    pub fn with_style_examples<I, S>(mut self, lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.style_examples
+            .extend(lines.into_iter().map(Into::into));
+        self
+    }
+
+    /// The system prompt text injected for this persona.
+    pub fn system_prompt(&self) -> String {
+        let intro = if self.description.is_empty() {
+            format!("You are {}.", self.name)
+        } else {
+            format!("You are {}. {}", self.name, self.description)
+        };
+        if self.description.is_empty() && self.style_examples.is_empty() {
+            return format!("{} Stay in character.", intro);
+        }
+        let mut examples = String::new();
+        if !self.style_examples.is_empty() {
+            examples = format!(
+                "\nExamples of how {} talks (match the style, not the content):",
+                self.name
+            );
+            for line in &self.style_examples {
+                examples.push_str(&format!("\n\"{}\"", line));
+            }
+        }
+        format!("{}{}\nStay in character.", intro, examples)
+    }
+
+    /// The persona as a system message.
+    pub fn to_message(&self) -> AiMessage {
+        AiMessage::System(self.system_prompt().into())
+    }
+}
@@ -0,0 +1,369 @@
+//! Multi-step plans for NPC goals.
+//!
+//! Give an AI entity a [`Goal`] and the [`GoalPlannerPlugin`] asks the model for a plan: the
+//! goal, the [`ActionCatalog`](crate::actions::ActionCatalog) and the entity's gathered context
+//! go into one prompt, and the reply must be a list of actions. Every step is checked against
+//! the registered handlers before anything runs; a valid plan becomes an [`ActionSequence`].
+//! When the model's plan is invalid or a step fails, the planner asks again with the reason,
+//! up to [`GoalPlanner::max_replans`] times. A plan request that is throttled or gets no reply
+//! within [`GoalPlanner::plan_timeout`] gives up on the goal.
+//!
+//! [`GoalStatus`] tracks where each goal stands. Changing the `Goal` starts over.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(GoalPlannerPlugin::default());
+//!
+//! commands.spawn((AI, DialogueReceiver::new(), Goal::new("guard the bridge")));
+//!
+//! app.add_observer(|failed: On<GoalPlanFailed>| {
+//!     warn!("{:?} gave up: {}", failed.entity, failed.reason);
+//! });
+//! ```
+
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::actions::{ActionCatalog, ActionPayload, AiActionEvent, AiActionRegistry};
+use crate::dialogue::{DialogueRequest, DialogueRequestKind, DialogueRequestQueue, render_prompt};
+use crate::prompts::{GOAL_PLAN, PromptTemplates, TYPED_DATA};
+use crate::rate_limit::AiRequestThrottled;
+use crate::sequence::{ActionSequence, ActionSequenceFailed, ActionSequenceFinished};
+
+/// Schema the model's plan must follow.
+pub const PLAN_SCHEMA: &str = "JSON object with fields:\n{\n  \"steps\": [{\"name\": <action name>, \"params\": <action params>}]\n}";
+
+/// Plugin planning [`Goal`]s, see the [module docs](self).
+#[derive(Default)]
+pub struct GoalPlannerPlugin {
+    pub settings: GoalPlanner,
+}
+
+/// Planner settings, editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct GoalPlanner {
+    /// Longest plan accepted.
+    pub max_steps: usize,
+    /// New plans requested after a failure before giving up.
+    pub max_replans: u32,
+    /// Time a plan may take to arrive before the goal is given up.
+    pub plan_timeout: Duration,
+}
+
+impl Default for GoalPlanner {
+    fn default() -> Self {
+        Self {
+            max_steps: 8,
+            max_replans: 2,
+            plan_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// What an entity is trying to achieve, in plain words.
+#[derive(Component, Debug, Clone, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct Goal(pub String);
+
+impl Goal {
+    pub fn new(goal: impl Into<String>) -> Self {
+        Self(goal.into())
+    }
+}
+
+/// Where an entity's [`Goal`] stands.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct GoalStatus {
+    pub state: PlanState,
+    /// Plans requested after a failure so far.
+    pub replans: u32,
+}
+
+impl GoalStatus {
+    /// Whether the plan requested by `request_id` is awaited.
+    fn is_planning(&self, request_id: u64) -> bool {
+        matches!(self.state, PlanState::Planning { request_id: id, .. } if id == request_id)
+    }
+}
+
+/// Stage of a [`GoalStatus`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanState {
+    /// Waiting for the model's plan, requested when `Time<Real>` had `sent_at` elapsed.
+    Planning { request_id: u64, sent_at: Duration },
+    /// Running the plan as an [`ActionSequence`].
+    Executing,
+    /// The plan failed; a new one is requested next frame.
+    Replanning { reason: String },
+    /// Every step of the plan completed.
+    Done,
+    /// No re-plans are left.
+    Failed { reason: String },
+}
+
+/// Event fired when the planner gives up on an entity's goal.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct GoalPlanFailed {
+    pub entity: Entity,
+    pub reason: String,
+}
+
+impl Plugin for GoalPlannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .register_type::<GoalPlanner>()
+            .register_type::<Goal>()
+            .add_systems(
+                Update,
+                request_plans.before(crate::dialogue::AiSystemSet::HandleRequests),
+            )
+            .add_observer(on_plan_reply)
+            .add_observer(on_plan_throttled)
+            .add_observer(on_plan_finished)
+            .add_observer(on_plan_failed);
+    }
+}
+
+#[derive(Deserialize)]
+struct PlanReply {
+    steps: Vec<serde_json::Value>,
+}
+
+/// The planning request for `goal`, mentioning why the last plan failed.
+fn plan_request(
+    templates: Option<&PromptTemplates>,
+    catalog: &ActionCatalog,
+    max_steps: usize,
+    entity: Entity,
+    goal: &str,
+    failure: Option<&str>,
+) -> DialogueRequest {
+    let failure = failure
+        .map(|reason| {
+            format!(
+                "\nThe previous plan failed: {}. Make a different plan.",
+                reason
+            )
+        })
+        .unwrap_or_default();
+    let max_steps = max_steps.to_string();
+    let actions = catalog.prompt_block();
+    let prompt = render_prompt(
+        templates,
+        GOAL_PLAN,
+        &[
+            ("goal", goal),
+            ("actions", &actions),
+            ("max_steps", &max_steps),
+            ("failure", &failure),
+        ],
+    )
+    .unwrap_or_else(|_| format!("Goal: {}\n{}{}", goal, actions, failure));
+    let user_message = render_prompt(
+        templates,
+        TYPED_DATA,
+        &[
+            ("prompt", &prompt),
+            ("schema", PLAN_SCHEMA),
+            ("example", ""),
+        ],
+    )
+    .unwrap_or_else(|_| format!("{}\n{}", prompt, PLAN_SCHEMA));
+    DialogueRequest {
+        id: crate::dialogue::next_request_id(),
+        entity,
+        kind: DialogueRequestKind::Data {
+            user_message,
+            schema_description: PLAN_SCHEMA.to_string(),
+        },
+        player_text: false,
+    }
+}
+
+/// Give up on the goal of `entity`, keeping its re-plan count.
+fn give_up(commands: &mut Commands, entity: Entity, replans: u32, reason: String) {
+    commands.entity(entity).insert(GoalStatus {
+        state: PlanState::Failed {
+            reason: reason.clone(),
+        },
+        replans,
+    });
+    commands.trigger(GoalPlanFailed { entity, reason });
+}
+
+/// Ask for a plan for new and changed goals, and for goals whose plan failed. Gives up on
+/// goals whose plan did not arrive in time.
+fn request_plans(
+    time: Res<Time<Real>>,
+    settings: Res<GoalPlanner>,
+    registry: Res<AiActionRegistry>,
+    templates: Option<Res<PromptTemplates>>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    mut goals: Query<(Entity, Ref<Goal>, Option<&mut GoalStatus>)>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    let mut catalog = None;
+    for (entity, goal, status) in goals.iter_mut() {
+        let (failure, replans) = match status {
+            Some(status) if !goal.is_changed() => match &status.state {
+                PlanState::Replanning { reason } if status.replans < settings.max_replans => {
+                    (Some(reason.clone()), status.replans + 1)
+                }
+                PlanState::Replanning { reason } => {
+                    give_up(&mut commands, entity, status.replans, reason.clone());
+                    continue;
+                }
+                PlanState::Planning { sent_at, .. }
+                    if now.saturating_sub(*sent_at) >= settings.plan_timeout =>
+                {
+                    warn!("No plan arrived for {:?} in time", entity);
+                    let reason = "the plan did not arrive in time".to_string();
+                    give_up(&mut commands, entity, status.replans, reason);
+                    continue;
+                }
+                _ => continue,
+            },
+            _ => (None, 0),
+        };
+        if goal.is_changed() {
+            // A new goal replaces whatever was running for the old one
+            commands.entity(entity).remove::<ActionSequence>();
+        }
+        let catalog = catalog.get_or_insert_with(|| ActionCatalog::from_registry(&registry));
+        let request = plan_request(
+            templates.as_deref(),
+            catalog,
+            settings.max_steps,
+            entity,
+            &goal.0,
+            failure.as_deref(),
+        );
+        debug!("Requesting a plan for {:?}: {}", entity, goal.0);
+        commands.entity(entity).insert(GoalStatus {
+            state: PlanState::Planning {
+                request_id: request.id,
+                sent_at: now,
+            },
+            replans,
+        });
+        queue.push(request);
+    }
+}
+
+/// Check the model's plan and start it, or ask again.
+fn on_plan_reply(
+    response: On<crate::dialogue::AiResponseEvent>,
+    world: &World,
+    mut commands: Commands,
+) {
+    let Some(status) = world.get::<GoalStatus>(response.entity) else {
+        return;
+    };
+    if !status.is_planning(response.request_id) {
+        return;
+    }
+    let max_steps = world
+        .get_resource::<GoalPlanner>()
+        .map_or(usize::MAX, |s| s.max_steps);
+    let plan = if response.error {
+        Err(response.text.clone())
+    } else {
+        validate_plan(world, response.entity, &response.text, max_steps)
+    };
+    let state = match plan {
+        Ok(steps) => {
+            commands
+                .entity(response.entity)
+                .insert(ActionSequence::new(steps));
+            PlanState::Executing
+        }
+        Err(reason) => {
+            debug!("Rejected plan for {:?}: {}", response.entity, reason);
+            PlanState::Replanning { reason }
+        }
+    };
+    commands.entity(response.entity).insert(GoalStatus {
+        state,
+        replans: status.replans,
+    });
+}
+
+/// Parse a plan and check every step against the handler it would run.
+fn validate_plan(
+    world: &World,
+    entity: Entity,
+    reply: &str,
+    max_steps: usize,
+) -> Result<Vec<ActionPayload>, String> {
+    let plan: PlanReply =
+        crate::parse::extract_and_parse_json(reply).map_err(|_| "the reply was not a plan")?;
+    if plan.steps.is_empty() {
+        return Err("the plan has no steps".to_string());
+    }
+    if plan.steps.len() > max_steps {
+        return Err(format!(
+            "the plan has {} steps, at most {} are allowed",
+            plan.steps.len(),
+            max_steps
+        ));
+    }
+    let registry = world
+        .get_resource::<AiActionRegistry>()
+        .ok_or("no actions are registered")?;
+    plan.steps
+        .into_iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let action = crate::actions::value_to_action(step)
+                .ok_or_else(|| format!("step {} has no action name", i + 1))?;
+            let handler = registry
+                .handler_for(&action.name, entity, world)
+                .ok_or_else(|| format!("step {} uses unknown action '{}'", i + 1, action.name))?;
+            let event = AiActionEvent { entity, action };
+            handler
+                .validate(&event)
+                .map_err(|e| format!("step {}: {}", i + 1, e))?;
+            Ok(event.action)
+        })
+        .collect()
+}
+
+/// Give up on a goal whose plan request was dropped by the rate limiter.
+fn on_plan_throttled(
+    throttled: On<AiRequestThrottled>,
+    goals: Query<&GoalStatus>,
+    mut commands: Commands,
+) {
+    if let Ok(status) = goals.get(throttled.entity)
+        && status.is_planning(throttled.request_id)
+    {
+        let reason = "the plan request was throttled".to_string();
+        give_up(&mut commands, throttled.entity, status.replans, reason);
+    }
+}
+
+fn on_plan_finished(finished: On<ActionSequenceFinished>, mut goals: Query<&mut GoalStatus>) {
+    if let Ok(mut status) = goals.get_mut(finished.entity)
+        && status.state == PlanState::Executing
+    {
+        status.state = PlanState::Done;
+    }
+}
+
+fn on_plan_failed(failed: On<ActionSequenceFailed>, mut goals: Query<&mut GoalStatus>) {
+    if let Ok(mut status) = goals.get_mut(failed.entity)
+        && status.state == PlanState::Executing
+    {
+        status.state = PlanState::Replanning {
+            reason: format!(
+                "step {} ('{}') failed: {}",
+                failed.step + 1,
+                failed.action.name,
+                failed.reason
+            ),
+        };
+    }
+}
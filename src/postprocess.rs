@@ -0,0 +1,241 @@
+//! Cleanup of reply text before it is stored.
+//!
+//! Models decorate their replies: "Assistant: Hello!", `**bold**`, emoji, words the game would
+//! rather not show. [`AiResponseProcessors`] runs registered processors in order on every
+//! reply's text before it is stored in `DialogueReceiver::last_response`, sent in an
+//! [`AiResponseEvent`](crate::dialogue::AiResponseEvent) and recorded in transcripts. Actions
+//! are parsed from the reply as generated.
+//!
+//! A processor is any `Fn(&mut String, &DialogueResponse)`; the response tells it the entity
+//! and request kind, so it can leave JSON replies alone. The built-in processors only touch
+//! plain-text replies:
+//!
+//! - [`strip_role_prefix`] removes a leading "Assistant:", "AI:", "NPC:" and the like,
+//! - [`strip_markdown`] removes emphasis, headings, code fences and link targets,
+//! - [`strip_emoji`] removes emoji,
+//! - [`mask_words`] builds a processor replacing listed words with asterisks.
+//!
+//! # Example
+//! ```ignore
+//! app.add_ai_response_processor(strip_role_prefix)
+//!     .add_ai_response_processor(strip_markdown)
+//!     .add_ai_response_processor(mask_words(["damn", "hell"]))
+//!     .add_ai_response_processor(|text: &mut String, _: &DialogueResponse| {
+//!         *text = text.replace("Sir", "Ser");
+//!     });
+//! ```
+
+use bevy::prelude::*;
+use regex::Regex;
+use std::sync::{Arc, LazyLock};
+
+use crate::dialogue::{DialogueRequestKind, DialogueResponse};
+
+/// A step of the [`AiResponseProcessors`] pipeline.
+pub type ResponseProcessor = Arc<dyn Fn(&mut String, &DialogueResponse) + Send + Sync>;
+
+/// Resource holding the response processors, run in the order they were added.
+#[derive(Resource, Clone, Default)]
+pub struct AiResponseProcessors {
+    processors: Vec<ResponseProcessor>,
+}
+
+impl AiResponseProcessors {
+    pub fn add(
+        &mut self,
+        processor: impl Fn(&mut String, &DialogueResponse) + Send + Sync + 'static,
+    ) {
+        self.processors.push(Arc::new(processor));
+    }
+
+    pub fn with(
+        mut self,
+        processor: impl Fn(&mut String, &DialogueResponse) + Send + Sync + 'static,
+    ) -> Self {
+        self.add(processor);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Run every processor on `text`, the text of `response`, then trim it.
+    pub fn apply(&self, text: &mut String, response: &DialogueResponse) {
+        for processor in &self.processors {
+            processor(text, response);
+        }
+        let trimmed = text.trim();
+        if trimmed.len() != text.len() {
+            *text = trimmed.to_string();
+        }
+    }
+}
+
+/// Whether the built-in processors should touch `response`.
+fn is_plain_text(response: &DialogueResponse) -> bool {
+    matches!(response.kind, DialogueRequestKind::Text { .. }) && !response.error
+}
+
+/// Labels models put before their reply.
+const ROLE_PREFIXES: &[&str] = &[
+    "assistant",
+    "ai",
+    "npc",
+    "bot",
+    "character",
+    "response",
+    "reply",
+    "answer",
+];
+
+/// Remove a leading role label such as "Assistant:" or "**NPC:**", repeatedly.
+pub fn strip_role_prefix(text: &mut String, response: &DialogueResponse) {
+    if !is_plain_text(response) {
+        return;
+    }
+    loop {
+        let trimmed = text.trim_start();
+        let Some((label, rest)) = trimmed.split_once(':') else {
+            return;
+        };
+        let label = label.trim_matches(|c: char| c == '*' || c == '_' || c.is_whitespace());
+        if !ROLE_PREFIXES.iter().any(|p| p.eq_ignore_ascii_case(label)) {
+            return;
+        }
+        let rest = rest.trim_start_matches(['*', '_']).trim_start();
+        *text = rest.to_string();
+    }
+}
+
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").expect("valid link pattern"));
+static MARKDOWN_HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\s{0,3}#{1,6}\s+").expect("valid heading pattern"));
+
+/// Remove markdown formatting: emphasis markers, inline code and code fences, headings, and
+/// link targets (keeping the link text).
+pub fn strip_markdown(text: &mut String, response: &DialogueResponse) {
+    if !is_plain_text(response) {
+        return;
+    }
+    let without_fences: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect();
+    let mut out = without_fences.join("\n");
+    out = MARKDOWN_LINK.replace_all(&out, "$1").into_owned();
+    out = MARKDOWN_HEADING.replace_all(&out, "").into_owned();
+    out = out
+        .replace("**", "")
+        .replace("__", "")
+        .replace(['`', '*'], "");
+    *text = out;
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE00..=0xFE0F | 0x200D
+    )
+}
+
+/// Remove emoji, and the spaces they leave doubled.
+pub fn strip_emoji(text: &mut String, response: &DialogueResponse) {
+    if !is_plain_text(response) || !text.chars().any(is_emoji) {
+        return;
+    }
+    let stripped: String = text.chars().filter(|&c| !is_emoji(c)).collect();
+    let mut out = String::with_capacity(stripped.len());
+    for c in stripped.chars() {
+        if c == ' ' && out.ends_with(' ') {
+            continue;
+        }
+        out.push(c);
+    }
+    *text = out;
+}
+
+/// Processor replacing each of `words` (whole words, ignoring case) with asterisks.
+pub fn mask_words<S: AsRef<str>>(
+    words: impl IntoIterator<Item = S>,
+) -> impl Fn(&mut String, &DialogueResponse) + Send + Sync + 'static {
+    let alternatives: Vec<String> = words
+        .into_iter()
+        .map(|w| regex::escape(w.as_ref()))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let pattern = (!alternatives.is_empty()).then(|| {
+        Regex::new(&format!(r"(?i)\b({})\b", alternatives.join("|"))).expect("escaped words")
+    });
+    move |text, response| {
+        let Some(pattern) = &pattern else {
+            return;
+        };
+        if !is_plain_text(response) {
+            return;
+        }
+        let masked = pattern.replace_all(text, |caps: &regex::Captures| {
+            "*".repeat(caps[0].chars().count())
+        });
+        if let std::borrow::Cow::Owned(masked) = masked {
+            *text = masked;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(text: &str) -> DialogueResponse {
+        DialogueResponse {
+            request_id: 1,
+            entity: Entity::PLACEHOLDER,
+            response: text.to_string(),
+            kind: DialogueRequestKind::Text {
+                message: "hi".to_string(),
+                include_context: true,
+            },
+            actions: None,
+            oversized: None,
+            origin: crate::attribution::ResponseOrigin::Generated,
+            clarification: None,
+            blocked: None,
+            dispatched: 0,
+            error: false,
+        }
+    }
+
+    #[test]
+    fn built_in_processors_clean_text_replies() {
+        let processors = AiResponseProcessors::default()
+            .with(strip_role_prefix)
+            .with(strip_markdown)
+            .with(strip_emoji)
+            .with(mask_words(["damn"]));
+        let raw = "**Assistant:** NPC: # Welcome\n```\nHello, **damn** traveller 👋 see [the map](http://x)!\n```";
+        let mut text = raw.to_string();
+        processors.apply(&mut text, &reply(raw));
+        assert_eq!(text, "Welcome\nHello, **** traveller see the map!");
+
+        // Labels that are not roles stay
+        let mut note = "Note: the gate closes at dusk.".to_string();
+        strip_role_prefix(&mut note, &reply("Note: the gate closes at dusk."));
+        assert_eq!(note, "Note: the gate closes at dusk.");
+
+        // JSON replies are left alone
+        let mut data = reply("{\"name\": \"**x**\"}");
+        data.kind = DialogueRequestKind::Data {
+            user_message: "x".to_string(),
+            schema_description: "x".to_string(),
+        };
+        let mut json = data.response.clone();
+        processors.apply(&mut json, &data);
+        assert_eq!(json, "{\"name\": \"**x**\"}");
+    }
+}
@@ -0,0 +1,256 @@
+//! Named prompt templates.
+//!
+//! [`PromptTemplates`] holds prompt text with `{placeholder}`s so prompts can be tuned per game
+//! (or at runtime, through an inspector) instead of being scattered across `format!` calls.
+//! The built-in prompts used by the crate live here too and can be overridden by registering a
+//! template under the same name.
+//!
+//! # Example
+//! ```ignore
+//! app.world_mut().resource_mut::<PromptTemplates>().register(
+//!     "npc_reply",
+//!     "The player says: \"{utterance}\". Answer as {name}, in one sentence.",
+//! );
+//!
+//! fn talk(mut ai: AiRequest, npc: Single<Entity, With<Npc>>) {
+//!     ai.inquire_template(*npc, "npc_reply", &[("utterance", "Hello!"), ("name", "Greta")])
+//!         .unwrap();
+//! }
+//! ```
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Suffix asking for a plain-text answer. Placeholders: `prompt`.
+pub const PLAIN_TEXT: &str = "plain_text";
+/// Prompt for a typed JSON action. Placeholders: `prompt`, `schema`, `example` (a filled-in
+/// value as its own paragraph, or empty).
+pub const TYPED_ACTION: &str = "typed_action";
+/// Prompt for structured data that is not an action. Placeholders: `prompt`, `schema`,
+/// `example`.
+pub const TYPED_DATA: &str = "typed_data";
+/// Prompt for one line of an NPC conversation. Placeholders: `body`.
+pub const CONVERSATION_LINE: &str = "conversation_line";
+
+/// Recap of an earlier conversation replayed after a model swap. Placeholders: `lines`.
+pub const CONVERSATION_RECAP: &str = "conversation_recap";
+
+/// Several short prompts answered in one reply. Placeholders: `count`, `items`.
+pub const BATCH: &str = "batch";
+
+/// Request for a multi-step plan toward a goal. Placeholders: `goal`, `actions`, `max_steps`,
+/// `failure`.
+pub const GOAL_PLAN: &str = "goal_plan";
+
+/// Classification of a message into one label, see [`AiRequest::classify`](crate::dialogue::AiRequest::classify).
+/// Placeholders: `utterance`, `labels`.
+pub const CLASSIFY: &str = "classify";
+
+/// Choice between branches of a behavior tree. Placeholders: `question`, `branches`.
+pub const DECISION: &str = "decision";
+
+/// Rating of candidate behaviors for utility AI. Placeholders: `candidates`.
+pub const SCORE: &str = "score";
+
+/// Request for a generated quest. Placeholders: `constraints`, `npcs`, `failure`.
+pub const QUEST: &str = "quest";
+
+/// Batch of short generated texts. Placeholders: `count`, `what`, `taken`.
+pub const GENERATE: &str = "generate";
+
+/// Translation of a reply into the player's language. Placeholders: `locale`, `text`.
+pub const TRANSLATE: &str = "translate";
+
+/// Check of generated text against an audience. Placeholders: `audience`, `text`.
+pub const MODERATE: &str = "moderate";
+
+/// Results of the actions a reply asked for, fed back to the model. Placeholders: `results`.
+pub const TOOL_RESULTS: &str = "tool_results";
+
+/// Resource mapping template names to template text.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct PromptTemplates {
+    templates: HashMap<String, String>,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        let mut templates = Self::empty();
+        templates.register(
+            PLAIN_TEXT,
+            "{prompt}\n\nPlease respond in plain text only (no JSON or code blocks).",
+        );
+        templates.register(
+            TYPED_ACTION,
+            "{prompt}\nProvide a JSON action matching the following schema:\n{schema}{example}\n\
+             If required information is missing or ambiguous, respond with \
+             {{\"needs_clarification\": {{\"question\": \"...\"}}}} instead.",
+        );
+        templates.register(
+            TYPED_DATA,
+            "{prompt}\nRespond with only a JSON value matching the following schema:\n{schema}\
+             {example}",
+        );
+        templates.register(
+            CONVERSATION_LINE,
+            "{body}\n\nRespond with a single short spoken line in plain text only (no JSON, no narration).",
+        );
+        templates.register(
+            CONVERSATION_RECAP,
+            "Earlier in this conversation:\n{lines}\n\nContinue it consistently.",
+        );
+        templates.register(
+            BATCH,
+            "Answer each of the following {count} numbered items separately.\n\n{items}\n\n\
+             Reply with exactly one line per item, in order, starting with its number \
+             (\"1. ...\").",
+        );
+        templates.register(
+            GOAL_PLAN,
+            "Your goal: {goal}\n\n{actions}\n\nPlan how to reach the goal using only the \
+             actions above, in the order they should run, with at most {max_steps} steps.{failure}",
+        );
+        templates.register(
+            CLASSIFY,
+            "Classify the message into exactly one of these labels: {labels}.\n\n\
+             Message: \"{utterance}\"\n\nAnswer with only the label.",
+        );
+        templates.register(
+            DECISION,
+            "{question}\n\nAnswer with exactly one of these options and nothing else: {branches}.",
+        );
+        templates.register(
+            SCORE,
+            "Given your situation, rate how good each of these behaviors is for you right now, \
+             from 0 (pointless) to 1 (exactly right): {candidates}.",
+        );
+        templates.register(GENERATE, "Give {count} {what}.{taken}");
+        templates.register(
+            MODERATE,
+            "Would the following text be suitable for {audience}? Answer with exactly one \
+             word, SAFE or UNSAFE.\n\n{text}",
+        );
+        templates.register(
+            TOOL_RESULTS,
+            "Results of the actions you took:\n{results}\n\nContinue. Take more actions only if \
+             they are still needed, otherwise answer in plain text.",
+        );
+        templates.register(
+            TRANSLATE,
+            "Translate the following text into {locale}. Keep names as they are, and keep the \
+             tone. Reply with the translation only.\n\n{text}",
+        );
+        templates.register(
+            QUEST,
+            "Write a quest you could give the player.\nRequirements: {constraints}\n\
+             Characters who may be involved, by exact name: {npcs}. Do not involve anyone \
+             else.{failure}",
+        );
+        templates
+    }
+}
+
+impl PromptTemplates {
+    /// A registry without the built-in templates.
+    pub fn empty() -> Self {
+        Self {
+            templates: HashMap::new(),
+        }
+    }
+
+    /// Register (or replace) a template.
+    pub fn register(&mut self, name: impl Into<String>, template: impl Into<String>) {
+        self.templates.insert(name.into(), template.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(String::as_str)
+    }
+
+    /// Registered template names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// Render template `name` with `vars`.
+    pub fn render(&self, name: &str, vars: &[(&str, &str)]) -> Result<String, String> {
+        let template = self
+            .get(name)
+            .ok_or_else(|| format!("Unknown prompt template '{}'", name))?;
+        render_template(template, vars).map_err(|e| format!("Template '{}': {}", name, e))
+    }
+}
+
+/// Substitute `{placeholder}`s in `template` with `vars`. `{{` and `}}` produce literal braces.
+///
+/// Fails if the template uses a placeholder missing from `vars` or has an unclosed `{`.
+pub fn render_template(template: &str, vars: &[(&str, &str)]) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut key = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(k) => key.push(k),
+                        None => return Err(format!("unclosed placeholder '{{{}'", key)),
+                    }
+                }
+                let key = key.trim();
+                let value = vars
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| *v)
+                    .ok_or_else(|| format!("missing value for placeholder '{}'", key))?;
+                out.push_str(value);
+            }
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_substitutes_placeholders() {
+        let out = render_template("Hi {name}, {{literal}} {name}!", &[("name", "Bob")]).unwrap();
+        assert_eq!(out, "Hi Bob, {literal} Bob!");
+        assert!(render_template("Hi {name}", &[]).is_err());
+        assert!(render_template("Hi {name", &[("name", "Bob")]).is_err());
+    }
+
+    #[test]
+    fn builtin_templates_can_be_overridden() {
+        let mut templates = PromptTemplates::default();
+        assert!(
+            templates
+                .render(PLAIN_TEXT, &[("prompt", "Hello")])
+                .unwrap()
+                .starts_with("Hello\n\nPlease respond in plain text only")
+        );
+        templates.register(PLAIN_TEXT, "{prompt} (short answer)");
+        assert_eq!(
+            templates
+                .render(PLAIN_TEXT, &[("prompt", "Hello")])
+                .unwrap(),
+            "Hello (short answer)"
+        );
+        assert!(templates.render("missing", &[]).is_err());
+    }
+}
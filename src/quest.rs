@@ -0,0 +1,411 @@
+//! Procedural quests from the model.
+//!
+//! [`QuestGenerator::generate_quest`] asks a quest giver's model for a quest following a
+//! [`QuestSpec`]: a type saying what a quest holds and how it is checked. The built-in
+//! [`Quest`] has a title, objectives, rewards and the NPCs involved. The prompt lists the
+//! named AI characters that exist, and the reply must only involve them; a quest naming
+//! anyone else, failing [`QuestSpec::validate`] or not parsing is sent back with the reason,
+//! up to [`QuestGeneration::max_retries`] times. A reply dropped as stale is asked for again
+//! too, and uses up a retry as well.
+//!
+//! A valid quest is delivered as a [`QuestGenerated`] event; when no retries are left, or the
+//! request was dropped by the rate limiter or deduplication, a [`QuestGenerationFailed`] is
+//! fired instead.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(QuestPlugin::default())
+//!     .add_observer(|quest: On<QuestGenerated<Quest>>, mut log: ResMut<QuestLog>| {
+//!         log.add(quest.quest.clone());
+//!     });
+//!
+//! fn offer_quest(mut quests: QuestGenerator, innkeeper: Single<Entity, With<Innkeeper>>) {
+//!     quests.generate_quest::<Quest>(*innkeeper, "a short errand in the village, no combat");
+//! }
+//! ```
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::context::AI;
+use crate::dialogue::{
+    AiRequestDeduplicated, AiResponseEvent, AiStaleResponse, DialogueRequest, DialogueRequestKind,
+    DialogueRequestQueue, render_prompt,
+};
+use crate::prompts::{PromptTemplates, QUEST, TYPED_DATA};
+use crate::rate_limit::AiRequestThrottled;
+
+/// What a generated quest holds, and how it is checked before it reaches the game.
+pub trait QuestSpec: DeserializeOwned + Clone + Send + Sync + 'static {
+    /// Description of the JSON the model must answer with.
+    fn schema() -> String;
+
+    /// Names of the characters the quest involves. Each must be the [`Name`] of an existing
+    /// [`AI`] entity.
+    fn involved_npcs(&self) -> Vec<&str>;
+
+    /// Game-specific checks run after the character check. The error is shown to the model.
+    fn validate(&self, _world: &World) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Schema of the built-in [`Quest`].
+pub const QUEST_SCHEMA: &str = "JSON object with fields:\n{\n  \"title\": string,\n  \"description\": string,\n  \"objectives\": [{\"description\": string, \"npc\": <name of an involved character, optional>}],\n  \"rewards\": [{\"item\": string, \"amount\": number}],\n  \"npcs\": [<names of the characters involved>]\n}";
+
+/// A quest with objectives and rewards.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quest {
+    pub title: String,
+    pub description: String,
+    pub objectives: Vec<QuestObjective>,
+    #[serde(default)]
+    pub rewards: Vec<QuestReward>,
+    /// Characters the quest involves.
+    #[serde(default)]
+    pub npcs: Vec<String>,
+}
+
+/// One step of a [`Quest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestObjective {
+    pub description: String,
+    /// Character the objective is about, if any.
+    #[serde(default)]
+    pub npc: Option<String>,
+}
+
+/// Something handed out when a [`Quest`] is done.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestReward {
+    pub item: String,
+    pub amount: u32,
+}
+
+impl QuestSpec for Quest {
+    fn schema() -> String {
+        QUEST_SCHEMA.to_string()
+    }
+
+    fn involved_npcs(&self) -> Vec<&str> {
+        self.npcs
+            .iter()
+            .map(String::as_str)
+            .chain(self.objectives.iter().filter_map(|o| o.npc.as_deref()))
+            .collect()
+    }
+
+    fn validate(&self, _world: &World) -> Result<(), String> {
+        if self.objectives.is_empty() {
+            return Err("the quest has no objectives".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Event fired with a quest that passed validation.
+#[derive(Event, Debug, Clone)]
+pub struct QuestGenerated<Q: QuestSpec> {
+    /// The quest giver.
+    pub entity: Entity,
+    /// Id returned by [`QuestGenerator::generate_quest`].
+    pub quest_id: u64,
+    pub quest: Q,
+}
+
+/// Event fired when no valid quest came back within the retries.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct QuestGenerationFailed {
+    pub entity: Entity,
+    pub quest_id: u64,
+    /// Why the last reply was rejected.
+    pub reason: String,
+}
+
+/// Quest generation settings, editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct QuestGeneration {
+    /// Times a rejected quest is asked for again before giving up.
+    pub max_retries: u32,
+}
+
+impl Default for QuestGeneration {
+    fn default() -> Self {
+        Self { max_retries: 2 }
+    }
+}
+
+/// Plugin generating quests, see the [module docs](self).
+#[derive(Default)]
+pub struct QuestPlugin {
+    pub settings: QuestGeneration,
+}
+
+impl Plugin for QuestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .register_type::<QuestGeneration>()
+            .init_resource::<PendingQuests>()
+            .add_observer(on_quest_reply)
+            .add_observer(on_quest_stale)
+            .add_observer(on_quest_throttled)
+            .add_observer(on_quest_deduplicated);
+    }
+}
+
+/// Checks a reply and fires [`QuestGenerated`] for the right quest type.
+type QuestCheck = Box<dyn Fn(&mut World, Entity, u64, &str) -> Result<(), String> + Send + Sync>;
+
+/// A quest waiting for the model.
+struct PendingQuest {
+    quest_id: u64,
+    entity: Entity,
+    constraints: String,
+    schema: String,
+    retries: u32,
+    check: QuestCheck,
+}
+
+/// Quests waiting for the model, by request id.
+#[derive(Resource, Default)]
+struct PendingQuests {
+    by_request: HashMap<u64, PendingQuest>,
+}
+
+/// System parameter starting quest generation.
+#[derive(SystemParam)]
+pub struct QuestGenerator<'w, 's> {
+    queue: ResMut<'w, DialogueRequestQueue>,
+    pending: ResMut<'w, PendingQuests>,
+    templates: Option<Res<'w, PromptTemplates>>,
+    npcs: Query<'w, 's, &'static Name, With<AI>>,
+}
+
+impl QuestGenerator<'_, '_> {
+    /// Ask `entity`'s model for a quest of type `Q` meeting `constraints`. Returns the quest
+    /// id carried by the resulting [`QuestGenerated`] or [`QuestGenerationFailed`].
+    pub fn generate_quest<Q: QuestSpec>(
+        &mut self,
+        entity: Entity,
+        constraints: impl Into<String>,
+    ) -> u64 {
+        let names = npc_names(self.npcs.iter());
+        let pending = PendingQuest {
+            quest_id: crate::dialogue::next_request_id(),
+            entity,
+            constraints: constraints.into(),
+            schema: Q::schema(),
+            retries: 0,
+            check: Box::new(check_quest::<Q>),
+        };
+        let request = quest_request(self.templates.as_deref(), &pending, &names, None);
+        let quest_id = pending.quest_id;
+        self.pending.by_request.insert(request.id, pending);
+        self.queue.push(request);
+        quest_id
+    }
+}
+
+fn npc_names<'a>(names: impl Iterator<Item = &'a Name>) -> Vec<String> {
+    let mut names: Vec<String> = names.map(|n| n.as_str().to_string()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The request for `quest`, mentioning why the last reply was rejected.
+fn quest_request(
+    templates: Option<&PromptTemplates>,
+    quest: &PendingQuest,
+    npcs: &[String],
+    failure: Option<&str>,
+) -> DialogueRequest {
+    let failure = failure
+        .map(|reason| {
+            format!(
+                "\nYour previous quest was rejected: {}. Write a corrected quest.",
+                reason
+            )
+        })
+        .unwrap_or_default();
+    let npcs = if npcs.is_empty() {
+        "nobody".to_string()
+    } else {
+        npcs.join(", ")
+    };
+    let prompt = render_prompt(
+        templates,
+        QUEST,
+        &[
+            ("constraints", &quest.constraints),
+            ("npcs", &npcs),
+            ("failure", &failure),
+        ],
+    )
+    .unwrap_or_else(|_| {
+        format!(
+            "Quest: {}\nCharacters: {}{}",
+            quest.constraints, npcs, failure
+        )
+    });
+    let user_message = render_prompt(
+        templates,
+        TYPED_DATA,
+        &[
+            ("prompt", &prompt),
+            ("schema", &quest.schema),
+            ("example", ""),
+        ],
+    )
+    .unwrap_or_else(|_| format!("{}\n{}", prompt, quest.schema));
+    DialogueRequest {
+        id: crate::dialogue::next_request_id(),
+        entity: quest.entity,
+        kind: DialogueRequestKind::Data {
+            user_message,
+            schema_description: quest.schema.clone(),
+        },
+        player_text: false,
+    }
+}
+
+/// Parse and validate a reply as `Q`, firing [`QuestGenerated`] if it passes.
+fn check_quest<Q: QuestSpec>(
+    world: &mut World,
+    entity: Entity,
+    quest_id: u64,
+    reply: &str,
+) -> Result<(), String> {
+    if reply.starts_with("(ai error") {
+        return Err(reply.to_string());
+    }
+    let quest: Q =
+        crate::parse::extract_and_parse_json(reply).map_err(|_| "the reply was not valid JSON")?;
+    let known = world
+        .try_query_filtered::<&Name, With<AI>>()
+        .map(|mut q| npc_names(q.iter(world)))
+        .unwrap_or_default();
+    let unknown: Vec<&str> = quest
+        .involved_npcs()
+        .into_iter()
+        .filter(|name| !known.iter().any(|k| k == name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "these characters do not exist: {}",
+            unknown.join(", ")
+        ));
+    }
+    quest.validate(world)?;
+    world.trigger(QuestGenerated {
+        entity,
+        quest_id,
+        quest,
+    });
+    Ok(())
+}
+
+/// Check a quest reply, and ask again or give up if it is rejected.
+fn on_quest_reply(
+    response: On<AiResponseEvent>,
+    mut pending: ResMut<PendingQuests>,
+    mut commands: Commands,
+) {
+    let Some(quest) = pending.by_request.remove(&response.request_id) else {
+        return;
+    };
+    let text = response.text.clone();
+    commands.queue(move |world: &mut World| {
+        if let Err(reason) = (quest.check)(world, quest.entity, quest.quest_id, &text) {
+            retry_quest(world, quest, Some(reason));
+        }
+    });
+}
+
+/// Ask again for quests whose reply was dropped as stale.
+fn on_quest_stale(
+    stale: On<AiStaleResponse>,
+    mut pending: ResMut<PendingQuests>,
+    mut commands: Commands,
+) {
+    if let Some(quest) = pending.by_request.remove(&stale.request_id) {
+        commands.queue(move |world: &mut World| retry_quest(world, quest, None));
+    }
+}
+
+/// Give up on quests whose request was throttled.
+fn on_quest_throttled(
+    throttled: On<AiRequestThrottled>,
+    mut pending: ResMut<PendingQuests>,
+    mut commands: Commands,
+) {
+    if let Some(quest) = pending.by_request.remove(&throttled.request_id) {
+        let reason = "the quest request was throttled".to_string();
+        commands.queue(move |world: &mut World| fail_quest(world, quest, reason));
+    }
+}
+
+/// Give up on quests whose request was dropped as a duplicate.
+fn on_quest_deduplicated(
+    deduplicated: On<AiRequestDeduplicated>,
+    mut pending: ResMut<PendingQuests>,
+    mut commands: Commands,
+) {
+    if let Some(quest) = pending.by_request.remove(&deduplicated.request_id) {
+        let reason = "the quest request was dropped as a duplicate".to_string();
+        commands.queue(move |world: &mut World| fail_quest(world, quest, reason));
+    }
+}
+
+fn fail_quest(world: &mut World, quest: PendingQuest, reason: String) {
+    warn!(
+        "Giving up on quest {} for {:?}: {}",
+        quest.quest_id, quest.entity, reason
+    );
+    world.trigger(QuestGenerationFailed {
+        entity: quest.entity,
+        quest_id: quest.quest_id,
+        reason,
+    });
+}
+
+/// Ask for `quest` again with the reason the last reply was rejected (`None` for a reply
+/// dropped as stale), or give up when no retries are left.
+fn retry_quest(world: &mut World, quest: PendingQuest, reason: Option<String>) {
+    let retries = quest.retries + 1;
+    let max_retries = world
+        .get_resource::<QuestGeneration>()
+        .map_or(0, |s| s.max_retries);
+    if retries > max_retries {
+        let reason = reason.unwrap_or_else(|| "the reply was dropped as stale".to_string());
+        fail_quest(world, quest, reason);
+        return;
+    }
+    match &reason {
+        Some(reason) => debug!("Rejected quest {}: {}", quest.quest_id, reason),
+        None => debug!(
+            "Asking again for quest {} after a stale reply",
+            quest.quest_id
+        ),
+    }
+    let names = world
+        .try_query_filtered::<&Name, With<AI>>()
+        .map(|mut q| npc_names(q.iter(world)))
+        .unwrap_or_default();
+    let retry = PendingQuest { retries, ..quest };
+    let request = quest_request(
+        world.get_resource::<PromptTemplates>(),
+        &retry,
+        &names,
+        reason.as_deref(),
+    );
+    world
+        .resource_mut::<PendingQuests>()
+        .by_request
+        .insert(request.id, retry);
+    world.resource_mut::<DialogueRequestQueue>().push(request);
+}
@@ -1,37 +1,61 @@
-use bevy::prelude::Component;
+use bevy::prelude::{App, Component, Plugin};
+use std::sync::Arc;
 
+/// A message sent to the model.
+///
+/// Text is stored as `Arc<str>`, so cloning messages (e.g. copying an entity's `AiContext` into
+/// every request) only bumps a reference count instead of copying the text.
 #[derive(Debug, Clone, PartialEq)]
 pub enum AiMessage {
     /// System-level message (context, instructions, etc)
-    System(String),
+    System(Arc<str>),
     /// User message (from human/user)
-    User(String),
-    /// Assistant message (from AI)
-    #[deprecated(note = "Assistant messages are not currently used in prompt construction")]
-    Assistant(String),
+    User(Arc<str>),
+    /// Assistant message (from AI). Sent to the model as an earlier reply, after the user
+    /// messages before it, e.g. for few-shot examples or history rebuilt without a session.
+    Assistant(Arc<str>),
     /// A pre-parsed action payload (used to pass actions without reparsing text)
     Payload(crate::actions::ActionPayload),
+    /// Marker asking the backend to omit its default system context for this request.
+    /// Carries no text and is never forwarded to the model.
+    SkipDefaultContext,
 }
 
 impl AiMessage {
-    pub fn system(text: &str) -> Self {
-        AiMessage::System(text.to_string())
+    pub fn system(text: impl AsRef<str>) -> Self {
+        AiMessage::System(text.as_ref().into())
     }
 
-    /// Special sentinel system message which, when present in the messages sent
-    /// to the model, instructs the model layer to omit the default system context
-    /// (the `DEFAULT_SYSTEM_CONTEXT` string) for that single request.
-    pub(crate) fn no_default_system_context() -> Self {
-        AiMessage::System(NO_DEFAULT_SYSTEM_CONTEXT.to_string())
+    /// Marker which, when present in the messages sent to the model, instructs the model
+    /// layer to omit the default system context (`DEFAULT_SYSTEM_CONTEXT`) for that single
+    /// request.
+    pub fn skip_default_context() -> Self {
+        AiMessage::SkipDefaultContext
     }
 
-    pub fn user(text: &str) -> Self {
-        AiMessage::User(text.to_string())
+    /// Whether `messages` ask the backend to omit its default system context.
+    pub fn skips_default_context(messages: &[AiMessage]) -> bool {
+        messages
+            .iter()
+            .any(|m| matches!(m, AiMessage::SkipDefaultContext))
     }
 
-    #[allow(deprecated)]
-    pub fn assistant(text: &str) -> Self {
-        AiMessage::Assistant(text.to_string())
+    pub fn user(text: impl AsRef<str>) -> Self {
+        AiMessage::User(text.as_ref().into())
+    }
+
+    pub fn assistant(text: impl AsRef<str>) -> Self {
+        AiMessage::Assistant(text.as_ref().into())
+    }
+
+    /// The message text, if this is a text message.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            AiMessage::System(text) | AiMessage::User(text) | AiMessage::Assistant(text) => {
+                Some(text)
+            }
+            AiMessage::Payload(_) | AiMessage::SkipDefaultContext => None,
+        }
     }
 
     /// Create an AiMessage that contains a pre-parsed `ActionPayload`.
@@ -42,7 +66,7 @@ impl AiMessage {
 
 impl From<String> for AiMessage {
     fn from(s: String) -> Self {
-        AiMessage::User(s)
+        AiMessage::User(s.into())
     }
 }
 
@@ -51,16 +75,13 @@ impl std::fmt::Display for AiMessage {
         match self {
             AiMessage::System(text) => write!(f, "System: {}", text),
             AiMessage::User(text) => write!(f, "User: {}", text),
-            #[allow(deprecated)]
             AiMessage::Assistant(text) => write!(f, "Assistant: {}", text),
             AiMessage::Payload(p) => write!(f, "Payload: {} {}", p.name, p.params),
+            AiMessage::SkipDefaultContext => write!(f, "(skip default context)"),
         }
     }
 }
 
-/// Sentinel used to suppress the default system context for a single request.
-pub const NO_DEFAULT_SYSTEM_CONTEXT: &str = "Forget the context.";
-
 /// Component storing AI context messages for an entity.
 #[derive(Debug, Clone, Component)]
 pub struct AiContext {
@@ -150,7 +171,12 @@ impl AiContext {
 
     /// Add context as a system message from an opaque text string.
     pub fn add_context(&mut self, text: impl Into<String>) {
-        self.messages.push(AiMessage::system(text.into().as_str()));
+        self.messages.push(AiMessage::System(text.into().into()));
+    }
+
+    /// Add a message as-is. Text messages are shared, not copied.
+    pub fn add_message(&mut self, message: AiMessage) {
+        self.messages.push(message);
     }
 
     /// Access internal messages (primarily for backend/internal framework use).
@@ -163,3 +189,36 @@ impl AiContext {
         self.messages.clear();
     }
 }
+
+/// Retrieval without the dialogue pipeline: [`SemanticMemory`](crate::memory::SemanticMemory)
+/// recall and memories captured from game events (see
+/// [`MemoryCapturePlugin`](crate::memory::MemoryCapturePlugin)). With the
+/// [`AiContextPlugin`](crate::context::AiContextPlugin) the memories relevant to a prompt are
+/// also gathered into it.
+#[derive(Clone, Default)]
+pub struct AiRagPlugin {
+    embedder: Option<Arc<dyn crate::embedding::LocalEmbedder>>,
+}
+
+impl AiRagPlugin {
+    /// Also insert an [`AiEmbedder`](crate::embedding::AiEmbedder) for `embedder`.
+    pub fn with_embedder(embedder: Arc<dyn crate::embedding::LocalEmbedder>) -> Self {
+        Self {
+            embedder: Some(embedder),
+        }
+    }
+}
+
+impl Plugin for AiRagPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(embedder) = &self.embedder {
+            app.insert_resource(crate::embedding::AiEmbedder::new(embedder.clone()));
+        }
+        if !app.is_plugin_added::<crate::memory::SemanticMemoryPlugin>() {
+            app.add_plugins(crate::memory::SemanticMemoryPlugin);
+        }
+        if !app.is_plugin_added::<crate::memory::MemoryCapturePlugin>() {
+            app.add_plugins(crate::memory::MemoryCapturePlugin);
+        }
+    }
+}
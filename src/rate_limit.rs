@@ -0,0 +1,330 @@
+//! Per-entity request rate limits.
+//!
+//! A system that calls `ask_text` every frame would otherwise fill the
+//! [`DialogueRequestQueue`] faster than any model can answer. The [`AiRateLimiter`] resource
+//! caps how many requests each entity may queue per time window, and an [`AiCooldown`]
+//! component overrides the cap for one entity. Requests over the cap are dropped, or with
+//! [`ThrottlePolicy::Coalesce`] the newest one is held and queued once the entity is allowed
+//! again. Every discarded request fires [`AiRequestThrottled`]; a held request that duplicates
+//! a pending one once it is queued fires
+//! [`AiRequestDeduplicated`](crate::dialogue::AiRequestDeduplicated) instead.
+//!
+//! The limiter is off until `max_requests` is set; entities with an [`AiCooldown`] are always
+//! limited.
+//!
+//! Actions are limited separately, by name: [`ActionBudgets`] caps how often each entity may
+//! run an action such as `spawn_entity`, since models love to spam their favorite one. Actions
+//! over the cap are dropped before their handler runs and fire [`AiActionThrottled`].
+//!
+//! # Example
+//! ```ignore
+//! app.insert_resource(AiRateLimiter::per_second(2).with_policy(ThrottlePolicy::Coalesce));
+//!
+//! // Merchants haggle a lot, but only one reply every five seconds
+//! commands.spawn((AI, DialogueReceiver::new(), AiCooldown::new(1, Duration::from_secs(5))));
+//!
+//! // Each entity spawns at most once per five seconds
+//! app.limit_ai_action("spawn_entity", ActionLimit::once_per(Duration::from_secs(5)));
+//! ```
+
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use crate::actions::{ActionPayload, AiActionEvent};
+use crate::dialogue::{DialogueRequest, DialogueRequestQueue};
+
+/// What happens to requests over an entity's limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum ThrottlePolicy {
+    /// Discard them.
+    #[default]
+    Drop,
+    /// Keep the newest one and queue it when the entity is allowed again; older ones are
+    /// discarded.
+    Coalesce,
+}
+
+/// Limit of one entity, overriding the [`AiRateLimiter`] defaults.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct AiCooldown {
+    /// Requests the entity may queue per `window`.
+    pub max_requests: u32,
+    pub window: Duration,
+}
+
+impl AiCooldown {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+        }
+    }
+
+    pub fn per_second(max_requests: u32) -> Self {
+        Self::new(max_requests, Duration::from_secs(1))
+    }
+}
+
+/// Event fired for every request discarded by the rate limiter. A discarded request is never
+/// answered: code waiting for a request id should treat this as that request's end.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AiRequestThrottled {
+    pub entity: Entity,
+    pub request_id: u64,
+}
+
+/// Resource limiting how many requests each entity may queue.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct AiRateLimiter {
+    /// Requests an entity may queue per `window`; `None` leaves entities without an
+    /// [`AiCooldown`] unlimited.
+    pub max_requests: Option<u32>,
+    pub window: Duration,
+    pub policy: ThrottlePolicy,
+    /// When each entity's recent requests were accepted, oldest first.
+    accepted: HashMap<Entity, VecDeque<Duration>>,
+    /// Requests held back by [`ThrottlePolicy::Coalesce`].
+    held: HashMap<Entity, DialogueRequest>,
+    /// Queued requests already checked.
+    seen: HashSet<u64>,
+}
+
+impl AiRateLimiter {
+    /// Allow each entity `max_requests` per `window`.
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests: Some(max_requests),
+            window,
+            ..default()
+        }
+    }
+
+    /// Allow each entity `max_requests` per second.
+    pub fn per_second(max_requests: u32) -> Self {
+        Self::new(max_requests, Duration::from_secs(1))
+    }
+
+    pub fn with_policy(mut self, policy: ThrottlePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Number of requests held back for later, one per entity at most.
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+}
+
+/// Check requests queued since the last frame against their entity's limit.
+pub(crate) fn throttle_requests(
+    mut limiter: ResMut<AiRateLimiter>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    cooldowns: Query<&AiCooldown>,
+    time: Res<Time<Real>>,
+    mut commands: Commands,
+) {
+    let limiter = &mut *limiter;
+    let default_limit = limiter.max_requests.map(|max| (max, limiter.window));
+    let limit_of = |entity: Entity| {
+        cooldowns
+            .get(entity)
+            .ok()
+            .map(|cooldown| (cooldown.max_requests, cooldown.window))
+            .or(default_limit)
+    };
+    if default_limit.is_none() && cooldowns.is_empty() && limiter.held.is_empty() {
+        return;
+    }
+    let now = time.elapsed();
+
+    // Forget requests that left their window
+    limiter.accepted.retain(|entity, times| {
+        let window = limit_of(*entity).map_or(Duration::ZERO, |(_, window)| window);
+        while times
+            .front()
+            .is_some_and(|t| now.saturating_sub(*t) >= window)
+        {
+            times.pop_front();
+        }
+        !times.is_empty()
+    });
+    let has_room = |accepted: &mut HashMap<Entity, VecDeque<Duration>>, entity: Entity| {
+        let Some((max, _)) = limit_of(entity) else {
+            return true;
+        };
+        let times = accepted.entry(entity).or_default();
+        if times.len() < max as usize {
+            times.push_back(now);
+            true
+        } else {
+            false
+        }
+    };
+
+    // Held requests go first, they were asked earlier
+    let held: Vec<Entity> = limiter.held.keys().copied().collect();
+    for entity in held {
+        if has_room(&mut limiter.accepted, entity) {
+            let request = limiter.held.remove(&entity).unwrap();
+            limiter.seen.insert(request.id);
+            queue.push(request);
+        }
+    }
+
+    let seen = &limiter.seen;
+    let new = queue.take_matching(|request| !seen.contains(&request.id));
+    for request in new {
+        if has_room(&mut limiter.accepted, request.entity) {
+            limiter.seen.insert(request.id);
+            queue.push(request);
+            continue;
+        }
+        let discarded = match limiter.policy {
+            ThrottlePolicy::Drop => Some(request),
+            ThrottlePolicy::Coalesce => limiter.held.insert(request.entity, request),
+        };
+        if let Some(request) = discarded {
+            debug!(
+                "Rate limit: dropped request {} of {:?}",
+                request.id, request.entity
+            );
+            commands.trigger(AiRequestThrottled {
+                entity: request.entity,
+                request_id: request.id,
+            });
+        }
+    }
+    // Only remember requests still waiting in the queue
+    let queued: HashSet<u64> = queue.iter().map(|request| request.id).collect();
+    limiter.seen.retain(|id| queued.contains(id));
+}
+
+/// How often an entity may run one action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub struct ActionLimit {
+    /// Actions the entity may run per `window`.
+    pub max_actions: u32,
+    pub window: Duration,
+}
+
+impl ActionLimit {
+    pub fn new(max_actions: u32, window: Duration) -> Self {
+        Self {
+            max_actions,
+            window,
+        }
+    }
+
+    /// Once per `window`, a cooldown.
+    pub fn once_per(window: Duration) -> Self {
+        Self::new(1, window)
+    }
+}
+
+/// Event fired for every action dropped by [`ActionBudgets`].
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct AiActionThrottled {
+    pub entity: Entity,
+    pub action: ActionPayload,
+}
+
+/// Resource limiting how often each entity may run an action, by action name.
+///
+/// Actions without a limit are never dropped. Set limits with [`set`](Self::set) or
+/// `App::limit_ai_action`.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ActionBudgets {
+    limits: HashMap<String, ActionLimit>,
+    /// When each entity's recent actions ran, by action name, oldest first.
+    accepted: HashMap<(String, Entity), VecDeque<Duration>>,
+}
+
+impl ActionBudgets {
+    /// Limit action `name`, replacing any previous limit.
+    pub fn set(&mut self, name: impl Into<String>, limit: ActionLimit) {
+        self.limits.insert(name.into(), limit);
+    }
+
+    pub fn with(mut self, name: impl Into<String>, limit: ActionLimit) -> Self {
+        self.set(name, limit);
+        self
+    }
+
+    /// Remove the limit of action `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.limits.remove(name);
+        self.accepted.retain(|(action, _), _| action != name);
+    }
+
+    pub fn limit(&self, name: &str) -> Option<&ActionLimit> {
+        self.limits.get(name)
+    }
+
+    /// Whether `entity` may run action `name` at `now`; counts the action if so.
+    fn allow(&mut self, name: &str, entity: Entity, now: Duration) -> bool {
+        let Some(limit) = self.limits.get(name) else {
+            return true;
+        };
+        let times = self.accepted.entry((name.to_string(), entity)).or_default();
+        while times
+            .front()
+            .is_some_and(|t| now.saturating_sub(*t) >= limit.window)
+        {
+            times.pop_front();
+        }
+        if times.len() < limit.max_actions as usize {
+            times.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Drop the actions over their [`ActionBudgets`] limit and return the rest.
+pub(crate) fn throttle_actions(
+    world: &mut World,
+    pending: Vec<AiActionEvent>,
+) -> Vec<AiActionEvent> {
+    if world
+        .get_resource::<ActionBudgets>()
+        .is_none_or(|budgets| budgets.limits.is_empty())
+    {
+        return pending;
+    }
+    let now = world
+        .get_resource::<Time<Real>>()
+        .map_or(Duration::ZERO, |time| time.elapsed());
+    let (allowed, dropped): (Vec<_>, Vec<_>) =
+        world.resource_scope(|_, mut budgets: Mut<ActionBudgets>| {
+            pending
+                .into_iter()
+                .partition(|evt| budgets.allow(&evt.action.name, evt.entity, now))
+        });
+    for evt in dropped {
+        debug!(
+            "Action budget: dropped '{}' of {:?}",
+            evt.action.name, evt.entity
+        );
+        if let Some(mut history) = world.get_resource_mut::<crate::history::ActionHistory>() {
+            history.record(
+                evt.entity,
+                &evt.action,
+                crate::history::ActionOutcome::Throttled,
+            );
+        }
+        world.trigger(AiActionThrottled {
+            entity: evt.entity,
+            action: evt.action.clone(),
+        });
+        world.trigger(crate::completion::AiActionCompleted {
+            entity: evt.entity,
+            action: evt.action,
+            ticket: None,
+            result: Err("over its action budget".to_string()),
+        });
+    }
+    allowed
+}
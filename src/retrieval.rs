@@ -0,0 +1,178 @@
+//! Keyword scoring blended with embedding similarity.
+//!
+//! Embeddings match meaning but blur names: "Where is Greta?" lands near every text about
+//! people and places, not the one line mentioning Greta. [`Bm25`] scores texts by the query
+//! words they contain, weighting rare words (names, places) over common ones, and
+//! [`hybrid_scores`] mixes the two so both a paraphrase and an exact name can win.
+//!
+//! [`DocumentStore`](crate::documents::DocumentStore) and
+//! [`SemanticMemory`](crate::memory::SemanticMemory) blend with their `keyword_weight`: `0.0`
+//! ranks by embeddings only, `1.0` by keywords only.
+
+use crate::embedding::cosine_similarity;
+use std::collections::HashMap;
+
+/// Keyword weight used by stores unless set.
+pub const DEFAULT_KEYWORD_WEIGHT: f32 = 0.3;
+
+/// Words too common to say anything about a text.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "did", "do", "does", "for", "from", "has",
+    "have", "he", "her", "his", "how", "i", "in", "is", "it", "its", "me", "my", "of", "on", "or",
+    "she", "that", "the", "their", "them", "they", "this", "to", "was", "we", "were", "what",
+    "when", "where", "which", "who", "why", "with", "you", "your",
+];
+
+/// Lowercased words of `text`, without punctuation and stopwords.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Word counts of one text, computed once when the text is stored so [`Bm25`] does not
+/// tokenize it again for every query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TermCounts {
+    counts: HashMap<String, u32>,
+    len: usize,
+}
+
+impl TermCounts {
+    pub fn new(text: &str) -> Self {
+        let words = tokenize(text);
+        let len = words.len();
+        let mut counts = HashMap::new();
+        for word in words {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        Self { counts, len }
+    }
+
+    /// How often `term` (as returned by [`tokenize`]) occurs.
+    pub fn count(&self, term: &str) -> u32 {
+        self.counts.get(term).copied().unwrap_or(0)
+    }
+
+    /// Number of words, stopwords excluded.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Okapi BM25 keyword scoring.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25 {
+    /// How quickly repeating a word stops adding to the score.
+    pub k1: f32,
+    /// How much long texts are penalized, from 0.0 (not at all) to 1.0.
+    pub b: f32,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    /// Score of each of `texts` for `query`, in input order. Scores are `0.0` for texts sharing
+    /// no word with the query and unbounded above.
+    pub fn scores(&self, query: &str, texts: &[&str]) -> Vec<f32> {
+        let docs: Vec<TermCounts> = texts.iter().map(|t| TermCounts::new(t)).collect();
+        self.scores_counted(query, &docs.iter().collect::<Vec<_>>())
+    }
+
+    /// Like [`scores`](Self::scores), for texts counted beforehand.
+    pub fn scores_counted(&self, query: &str, docs: &[&TermCounts]) -> Vec<f32> {
+        let mut terms = tokenize(query);
+        terms.sort_unstable();
+        terms.dedup();
+        if docs.is_empty() || terms.is_empty() {
+            return vec![0.0; docs.len()];
+        }
+        let n = docs.len() as f32;
+        let avg_len = (docs.iter().map(|d| d.len()).sum::<usize>() as f32 / n).max(1.0);
+        let idf: HashMap<&str, f32> = terms
+            .iter()
+            .map(|term| {
+                let with = docs.iter().filter(|d| d.count(term) > 0).count() as f32;
+                (term.as_str(), (1.0 + (n - with + 0.5) / (with + 0.5)).ln())
+            })
+            .collect();
+        docs.iter()
+            .map(|doc| {
+                let len_norm = 1.0 - self.b + self.b * doc.len() as f32 / avg_len;
+                terms
+                    .iter()
+                    .map(|term| {
+                        let tf = doc.count(term) as f32;
+                        idf[term.as_str()] * tf * (self.k1 + 1.0) / (tf + self.k1 * len_norm)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Blend of embedding similarity and BM25 for each `(terms, embedding)` candidate, in input
+/// order. Keyword scores are scaled so the best candidate gets 1.0, then mixed as
+/// `(1 - keyword_weight) * similarity + keyword_weight * keywords`.
+pub fn hybrid_scores(
+    query: &str,
+    query_embedding: &[f32],
+    candidates: &[(&TermCounts, &[f32])],
+    keyword_weight: f32,
+) -> Vec<f32> {
+    let weight = keyword_weight.clamp(0.0, 1.0);
+    let keywords = if weight > 0.0 {
+        let docs: Vec<&TermCounts> = candidates.iter().map(|(terms, _)| *terms).collect();
+        Bm25::default().scores_counted(query, &docs)
+    } else {
+        vec![0.0; candidates.len()]
+    };
+    let best = keywords.iter().copied().fold(0.0f32, f32::max);
+    candidates
+        .iter()
+        .zip(keywords)
+        .map(|((_, embedding), keyword)| {
+            let keyword = if best > 0.0 { keyword / best } else { 0.0 };
+            (1.0 - weight) * cosine_similarity(query_embedding, embedding) + weight * keyword
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_in_the_query_lift_texts_mentioning_them() {
+        let texts = [
+            "Greta runs the inn by the river.",
+            "The innkeeper by the river is kind to travellers.",
+            "Wolves roam the northern woods.",
+        ];
+        let scores = Bm25::default().scores("Where is Greta?", &texts);
+        assert!(scores[0] > 0.0);
+        assert_eq!(scores[1..], [0.0, 0.0]);
+        assert_eq!(tokenize("Where is GRETA's inn?"), ["greta", "s", "inn"]);
+
+        // Embeddings alone prefer the paraphrase, the blend finds the name
+        let query = [1.0, 0.0];
+        let counts = texts.map(TermCounts::new);
+        let candidates: [(&TermCounts, &[f32]); 2] =
+            [(&counts[0], &[0.6, 0.8]), (&counts[1], &[0.9, 0.436])];
+        let semantic = hybrid_scores("Where is Greta?", &query, &candidates, 0.0);
+        assert!(semantic[1] > semantic[0]);
+        let hybrid = hybrid_scores("Where is Greta?", &query, &candidates, 0.5);
+        assert!(hybrid[0] > hybrid[1]);
+        assert!((hybrid[0] - 0.8).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,222 @@
+//! Thinking turns for crowds of NPCs.
+//!
+//! Give each NPC an [`AiThinker`] with a standing prompt ("Decide what to do next.") and a
+//! tier, and the [`AiScheduler`] sends that prompt on the tier's interval: with the defaults,
+//! `"hero"` NPCs think every 5 seconds and `"ambient"` ones every 60. Turns are handed out
+//! round-robin, most overdue first, and at most [`AiScheduler::turns_per_frame`] start in one
+//! frame, so a crowd spawned together spreads its turns instead of flooding the backend. An
+//! NPC whose last turn is still unanswered is skipped until the reply arrives, its request is
+//! throttled or goes stale, or [`AiThinker::timeout`] passes.
+//!
+//! Replies arrive like any other response: in the `DialogueReceiver`, as
+//! [`AiResponseEvent`]s, and with their actions queued.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiSchedulerPlugin::default());
+//!
+//! commands.spawn((AI, DialogueReceiver::new(), AiThinker::new("hero", "Decide what to do next.")));
+//! for _ in 0..200 {
+//!     commands.spawn((AI, DialogueReceiver::new(), AiThinker::new("ambient", "What are you doing?")));
+//! }
+//!
+//! app.world_mut().resource_mut::<AiScheduler>().set_tier("boss", Duration::from_secs(2));
+//! ```
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::context::AI;
+use crate::dialogue::{AiResponseEvent, AiStaleResponse, DialogueRequest, DialogueRequestQueue};
+use crate::rate_limit::AiRequestThrottled;
+
+/// Plugin running the [`AiScheduler`].
+#[derive(Default)]
+pub struct AiSchedulerPlugin {
+    pub settings: AiScheduler,
+}
+
+/// Resource with the think interval of each tier, editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiScheduler {
+    /// Time between two turns of an NPC, by tier.
+    pub tiers: HashMap<String, Duration>,
+    /// Turns started in one frame at most.
+    pub turns_per_frame: usize,
+    /// Stop handing out turns, e.g. while the game is paused.
+    pub paused: bool,
+}
+
+impl Default for AiScheduler {
+    fn default() -> Self {
+        Self {
+            tiers: HashMap::from([
+                ("hero".to_string(), Duration::from_secs(5)),
+                ("ambient".to_string(), Duration::from_secs(60)),
+            ]),
+            turns_per_frame: 2,
+            paused: false,
+        }
+    }
+}
+
+impl AiScheduler {
+    pub fn with_tier(mut self, tier: impl Into<String>, interval: Duration) -> Self {
+        self.set_tier(tier, interval);
+        self
+    }
+
+    pub fn set_tier(&mut self, tier: impl Into<String>, interval: Duration) {
+        self.tiers.insert(tier.into(), interval);
+    }
+
+    /// Think interval of `tier`, `None` for unknown tiers, whose NPCs never get a turn.
+    pub fn interval(&self, tier: &str) -> Option<Duration> {
+        self.tiers.get(tier).copied()
+    }
+}
+
+/// Component giving an `AI` entity regular thinking turns.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct AiThinker {
+    /// Tier in [`AiScheduler::tiers`].
+    pub tier: String,
+    /// Prompt sent on every turn.
+    pub prompt: String,
+    /// Time a turn may wait for its reply before the NPC is free to think again.
+    pub timeout: Duration,
+    /// `Time<Real>` elapsed when the next turn is due; `None` until the first one.
+    next_at: Option<Duration>,
+    /// The turn not answered yet and when it started.
+    request: Option<(u64, Duration)>,
+    turns: u64,
+}
+
+impl AiThinker {
+    pub fn new(tier: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            tier: tier.into(),
+            prompt: prompt.into(),
+            timeout: Duration::from_secs(60),
+            next_at: None,
+            request: None,
+            turns: 0,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of turns taken so far.
+    pub fn turns(&self) -> u64 {
+        self.turns
+    }
+
+    /// Whether the last turn is still waiting for the model.
+    pub fn is_thinking(&self) -> bool {
+        self.request.is_some()
+    }
+
+    /// Make the next turn due now, e.g. when something happened near the NPC.
+    pub fn wake(&mut self) {
+        self.next_at = Some(Duration::ZERO);
+    }
+
+    /// Whether the NPC may take a turn, i.e. its last one was answered, dropped or timed out.
+    fn is_free(&self, now: Duration) -> bool {
+        self.request
+            .is_none_or(|(_, sent)| now.saturating_sub(sent) >= self.timeout)
+    }
+
+    /// End the turn `request_id`, if it is the one waiting.
+    fn end(&mut self, request_id: u64) {
+        if self.request.is_some_and(|(id, _)| id == request_id) {
+            self.request = None;
+        }
+    }
+}
+
+/// Event fired when an NPC's turn starts.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct AiThinkTurn {
+    pub entity: Entity,
+    pub request_id: u64,
+}
+
+impl Plugin for AiSchedulerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .register_type::<AiScheduler>()
+            .register_type::<AiThinker>()
+            .add_systems(
+                Update,
+                hand_out_turns.before(crate::dialogue::AiSystemSet::HandleRequests),
+            )
+            .add_observer(end_turn)
+            .add_observer(end_stale_turn)
+            .add_observer(end_throttled_turn);
+    }
+}
+
+fn hand_out_turns(
+    time: Res<Time<Real>>,
+    scheduler: Res<AiScheduler>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    mut thinkers: Query<(Entity, &mut AiThinker), With<AI>>,
+    mut commands: Commands,
+) {
+    if scheduler.paused {
+        return;
+    }
+    let now = time.elapsed();
+    let mut due: Vec<(Entity, Duration)> = thinkers
+        .iter()
+        .filter(|(_, thinker)| thinker.is_free(now) && scheduler.interval(&thinker.tier).is_some())
+        .map(|(entity, thinker)| (entity, thinker.next_at.unwrap_or_default()))
+        .filter(|(_, next_at)| *next_at <= now)
+        .collect();
+    // Most overdue first; entity order keeps ties stable between frames
+    due.sort_by_key(|(entity, next_at)| (*next_at, *entity));
+
+    for (entity, _) in due.into_iter().take(scheduler.turns_per_frame) {
+        let Ok((_, mut thinker)) = thinkers.get_mut(entity) else {
+            continue;
+        };
+        let interval = scheduler.interval(&thinker.tier).unwrap_or_default();
+        let request = DialogueRequest::text(entity, thinker.prompt.clone());
+        if let Some((lost, _)) = thinker.request {
+            debug!("No reply to turn {} of {:?}, thinking again", lost, entity);
+        }
+        thinker.request = Some((request.id, now));
+        thinker.next_at = Some(now + interval);
+        thinker.turns += 1;
+        commands.trigger(AiThinkTurn {
+            entity,
+            request_id: request.id,
+        });
+        queue.push(request);
+    }
+}
+
+fn end_turn(response: On<AiResponseEvent>, mut thinkers: Query<&mut AiThinker>) {
+    if let Ok(mut thinker) = thinkers.get_mut(response.entity) {
+        thinker.end(response.request_id);
+    }
+}
+
+fn end_stale_turn(stale: On<AiStaleResponse>, mut thinkers: Query<&mut AiThinker>) {
+    if let Ok(mut thinker) = thinkers.get_mut(stale.entity) {
+        thinker.end(stale.request_id);
+    }
+}
+
+fn end_throttled_turn(throttled: On<AiRequestThrottled>, mut thinkers: Query<&mut AiThinker>) {
+    if let Ok(mut thinker) = thinkers.get_mut(throttled.entity) {
+        thinker.end(throttled.request_id);
+    }
+}
@@ -0,0 +1,295 @@
+//! Utility-AI scores from the model, refreshed now and then.
+//!
+//! Utility AI picks the behavior with the highest score, and those scores are read every frame.
+//! An [`AiScorer`] lets the model rate a small set of candidate behaviors between 0 and 1 for
+//! its entity, with the entity's gathered context, and keeps the scores until the next refresh.
+//! The game reads them as often as it likes; the model is asked again only once
+//! [`AiScorer::refresh`] has passed, and [`AiScoring::rescores_per_second`] caps how many
+//! entities are re-scored each second across the world, oldest scores first. A rating whose
+//! request was throttled, went stale or got no reply within [`AiScorer::timeout`] is asked
+//! again.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiScorerPlugin::default());
+//!
+//! commands.spawn((
+//!     AI,
+//!     DialogueReceiver::new(),
+//!     AiScorer::new(["patrol", "rest", "hunt"]).with_refresh(Duration::from_secs(20)),
+//! ));
+//!
+//! fn pick_behavior(mut scorers: Query<(&AiScorer, &mut Behavior)>) {
+//!     for (scorer, mut behavior) in scorers.iter_mut() {
+//!         if let Some((best, _)) = scorer.best() {
+//!             behavior.set(best);
+//!         }
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::dialogue::{
+    AiResponseEvent, AiStaleResponse, DialogueRequest, DialogueRequestKind, DialogueRequestQueue,
+    render_prompt,
+};
+use crate::prompts::{PromptTemplates, SCORE, TYPED_DATA};
+use crate::rate_limit::AiRequestThrottled;
+
+/// Plugin refreshing [`AiScorer`]s, see the [module docs](self).
+#[derive(Default)]
+pub struct AiScorerPlugin {
+    pub settings: AiScoring,
+}
+
+/// Scoring budget, editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiScoring {
+    /// Entities re-scored per second at most, across the world.
+    pub rescores_per_second: f32,
+}
+
+impl Default for AiScoring {
+    fn default() -> Self {
+        Self {
+            rescores_per_second: 2.0,
+        }
+    }
+}
+
+/// Component holding model-given scores for candidate behaviors.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct AiScorer {
+    pub candidates: Vec<String>,
+    /// Time the scores are kept before asking again.
+    pub refresh: Duration,
+    /// Time a rating may take before it is given up and asked again.
+    pub timeout: Duration,
+    /// Score of each candidate, once rated.
+    scores: HashMap<String, f32>,
+    /// `Time<Real>` elapsed at the last rating.
+    scored_at: Option<Duration>,
+    /// The rating being asked for and when it was sent.
+    request: Option<(u64, Duration)>,
+}
+
+impl AiScorer {
+    /// Score `candidates`, refreshed every 30 seconds.
+    pub fn new(candidates: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            candidates: candidates.into_iter().map(Into::into).collect(),
+            refresh: Duration::from_secs(30),
+            timeout: Duration::from_secs(60),
+            scores: HashMap::new(),
+            scored_at: None,
+            request: None,
+        }
+    }
+
+    pub fn with_refresh(mut self, refresh: Duration) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Score of `candidate`, `None` until the model rated it.
+    pub fn score(&self, candidate: &str) -> Option<f32> {
+        self.scores.get(candidate).copied()
+    }
+
+    /// The highest scored candidate and its score.
+    pub fn best(&self) -> Option<(&str, f32)> {
+        self.candidates
+            .iter()
+            .filter_map(|c| Some((c.as_str(), self.score(c)?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Whether the model rated the candidates at least once.
+    pub fn is_scored(&self) -> bool {
+        self.scored_at.is_some()
+    }
+
+    /// Ask again as soon as the budget allows, keeping the current scores until then.
+    pub fn refresh_now(&mut self) {
+        self.scored_at = None;
+    }
+
+    fn is_due(&self, now: Duration) -> bool {
+        self.request
+            .is_none_or(|(_, sent)| now.saturating_sub(sent) >= self.timeout)
+            && self
+                .scored_at
+                .is_none_or(|at| now.saturating_sub(at) >= self.refresh)
+    }
+}
+
+/// Event fired when an entity's scores were refreshed.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct AiScoresUpdated {
+    pub entity: Entity,
+}
+
+/// Re-scores the budget allows right now.
+#[derive(Resource, Debug, Default)]
+struct ScoringAllowance(f32);
+
+impl Plugin for AiScorerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .insert_resource(ScoringAllowance(1.0))
+            .register_type::<AiScoring>()
+            .register_type::<AiScorer>()
+            .add_systems(
+                Update,
+                request_scores.before(crate::dialogue::AiSystemSet::HandleRequests),
+            )
+            .add_observer(on_score_reply)
+            .add_observer(on_score_throttled)
+            .add_observer(on_score_stale);
+    }
+}
+
+#[derive(Deserialize)]
+struct ScoreReply {
+    scores: HashMap<String, f32>,
+}
+
+fn score_schema(candidates: &[String]) -> String {
+    let fields: Vec<String> = candidates
+        .iter()
+        .map(|c| {
+            format!(
+                "    {}: <number between 0 and 1>",
+                serde_json::Value::from(c.as_str())
+            )
+        })
+        .collect();
+    format!(
+        "JSON object with fields:\n{{\n  \"scores\": {{\n{}\n  }}\n}}",
+        fields.join(",\n")
+    )
+}
+
+fn request_scores(
+    time: Res<Time<Real>>,
+    settings: Res<AiScoring>,
+    templates: Option<Res<PromptTemplates>>,
+    mut allowance: ResMut<ScoringAllowance>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    mut scorers: Query<(Entity, &mut AiScorer)>,
+) {
+    let per_second = settings.rescores_per_second.max(0.0);
+    allowance.0 = (allowance.0 + per_second * time.delta_secs()).min(per_second.max(1.0));
+
+    let now = time.elapsed();
+    let mut due: Vec<(Entity, Option<Duration>)> = scorers
+        .iter()
+        .filter(|(_, scorer)| scorer.is_due(now) && !scorer.candidates.is_empty())
+        .map(|(entity, scorer)| (entity, scorer.scored_at))
+        .collect();
+    // Never scored first, then the oldest scores
+    due.sort_by_key(|(_, scored_at)| *scored_at);
+
+    for (entity, _) in due {
+        if allowance.0 < 1.0 {
+            break;
+        }
+        let Ok((_, mut scorer)) = scorers.get_mut(entity) else {
+            continue;
+        };
+        allowance.0 -= 1.0;
+        let schema = score_schema(&scorer.candidates);
+        let candidates = scorer.candidates.join(", ");
+        let prompt = render_prompt(templates.as_deref(), SCORE, &[("candidates", &candidates)])
+            .unwrap_or_else(|_| format!("Rate each of these from 0 to 1: {}", candidates));
+        let user_message = render_prompt(
+            templates.as_deref(),
+            TYPED_DATA,
+            &[("prompt", &prompt), ("schema", &schema), ("example", "")],
+        )
+        .unwrap_or_else(|_| format!("{}\n{}", prompt, schema));
+        let request = DialogueRequest {
+            id: crate::dialogue::next_request_id(),
+            entity,
+            kind: DialogueRequestKind::Data {
+                user_message,
+                schema_description: schema,
+            },
+            player_text: false,
+        };
+        if let Some((lost, _)) = scorer.request {
+            debug!(
+                "No scores for {:?} from request {}, asking again",
+                entity, lost
+            );
+        }
+        scorer.request = Some((request.id, now));
+        queue.push(request);
+    }
+}
+
+fn on_score_reply(
+    response: On<AiResponseEvent>,
+    time: Res<Time<Real>>,
+    mut scorers: Query<&mut AiScorer>,
+    mut commands: Commands,
+) {
+    let Ok(mut scorer) = scorers.get_mut(response.entity) else {
+        return;
+    };
+    if scorer
+        .request
+        .is_none_or(|(id, _)| id != response.request_id)
+    {
+        return;
+    }
+    scorer.request = None;
+    // A failed rating keeps the old scores until the next refresh
+    scorer.scored_at = Some(time.elapsed());
+    let reply = match crate::parse::extract_and_parse_json::<ScoreReply>(&response.text) {
+        Ok(reply) => reply,
+        Err(e) => {
+            debug!("Unusable scores for {:?}: {}", response.entity, e);
+            return;
+        }
+    };
+    let scorer = &mut *scorer;
+    for candidate in &scorer.candidates {
+        if let Some(score) = reply.scores.get(candidate).filter(|s| s.is_finite()) {
+            scorer
+                .scores
+                .insert(candidate.clone(), score.clamp(0.0, 1.0));
+        }
+    }
+    commands.trigger(AiScoresUpdated {
+        entity: response.entity,
+    });
+}
+
+/// Free the scorer of `entity` if `request_id` was its rating, to ask again when due.
+fn forget_request(scorers: &mut Query<&mut AiScorer>, entity: Entity, request_id: u64) {
+    if let Ok(mut scorer) = scorers.get_mut(entity)
+        && scorer.request.is_some_and(|(id, _)| id == request_id)
+    {
+        scorer.request = None;
+    }
+}
+
+fn on_score_throttled(throttled: On<AiRequestThrottled>, mut scorers: Query<&mut AiScorer>) {
+    forget_request(&mut scorers, throttled.entity, throttled.request_id);
+}
+
+fn on_score_stale(stale: On<AiStaleResponse>, mut scorers: Query<&mut AiScorer>) {
+    forget_request(&mut scorers, stale.entity, stale.request_id);
+}
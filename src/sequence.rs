@@ -0,0 +1,144 @@
+//! Run several actions one after another.
+//!
+//! An [`ActionSequence`] on an entity queues its steps one at a time: a step is only queued once
+//! the previous one has completed (see [`AiActionCompleted`]), so a step whose handler takes a
+//! ticket ("walk to the bridge") holds back the next one until the NPC arrives. The sequence
+//! stops at the first step that fails and removes itself when done, firing
+//! [`ActionSequenceFinished`] or [`ActionSequenceFailed`].
+//!
+//! A step held for approval by [`AiActionApproval`](crate::actions::AiActionApproval) and
+//! rejected never completes; remove the sequence to give up on it.
+//!
+//! # Example
+//! ```ignore
+//! commands.entity(guard).insert(
+//!     ActionSequence::new([
+//!         ActionPayload::new("walk_to").with_param("target", "bridge".into()),
+//!         ActionPayload::new("stand_guard"),
+//!     ]),
+//! );
+//!
+//! app.add_observer(|failed: On<ActionSequenceFailed>| {
+//!     warn!("{} failed: {}", failed.action.name, failed.reason);
+//! });
+//! ```
+
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::actions::{ActionPayload, AiActionEvent, PendingAiActions};
+use crate::completion::AiActionCompleted;
+
+/// Component running its steps in order, see the [module docs](self).
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct ActionSequence {
+    steps: VecDeque<ActionPayload>,
+    /// The step queued and not completed yet.
+    current: Option<ActionPayload>,
+    completed: usize,
+}
+
+impl ActionSequence {
+    pub fn new(steps: impl IntoIterator<Item = ActionPayload>) -> Self {
+        Self {
+            steps: steps.into_iter().collect(),
+            ..default()
+        }
+    }
+
+    /// Append a step.
+    pub fn then(mut self, step: ActionPayload) -> Self {
+        self.steps.push_back(step);
+        self
+    }
+
+    /// The step running now, if any.
+    pub fn current(&self) -> Option<&ActionPayload> {
+        self.current.as_ref()
+    }
+
+    /// Steps not started yet.
+    pub fn remaining(&self) -> impl Iterator<Item = &ActionPayload> {
+        self.steps.iter()
+    }
+
+    /// Number of steps completed so far.
+    pub fn completed(&self) -> usize {
+        self.completed
+    }
+}
+
+/// Event fired when every step of an entity's sequence completed.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ActionSequenceFinished {
+    pub entity: Entity,
+    /// Number of steps run.
+    pub steps: usize,
+}
+
+/// Event fired when a step of an entity's sequence failed; the rest are not run.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ActionSequenceFailed {
+    pub entity: Entity,
+    /// Index of the failed step.
+    pub step: usize,
+    pub action: ActionPayload,
+    pub reason: String,
+}
+
+/// Queue the next step of every sequence whose current step completed.
+pub(crate) fn advance_sequences(
+    mut sequences: Query<(Entity, &mut ActionSequence)>,
+    mut pending: ResMut<PendingAiActions>,
+    mut commands: Commands,
+) {
+    for (entity, mut sequence) in sequences.iter_mut() {
+        if sequence.current.is_some() {
+            continue;
+        }
+        match sequence.steps.pop_front() {
+            Some(step) => {
+                pending.actions.push(AiActionEvent {
+                    entity,
+                    action: step.clone(),
+                });
+                sequence.current = Some(step);
+            }
+            None => {
+                commands.entity(entity).remove::<ActionSequence>();
+                commands.trigger(ActionSequenceFinished {
+                    entity,
+                    steps: sequence.completed,
+                });
+            }
+        }
+    }
+}
+
+pub(crate) fn on_step_completed(
+    completed: On<AiActionCompleted>,
+    mut sequences: Query<&mut ActionSequence>,
+    mut commands: Commands,
+) {
+    let Ok(mut sequence) = sequences.get_mut(completed.entity) else {
+        return;
+    };
+    if sequence.current.as_ref() != Some(&completed.action) {
+        return;
+    }
+    match &completed.result {
+        Ok(()) => {
+            sequence.current = None;
+            sequence.completed += 1;
+        }
+        Err(reason) => {
+            commands.entity(completed.entity).remove::<ActionSequence>();
+            commands.trigger(ActionSequenceFailed {
+                entity: completed.entity,
+                step: sequence.completed,
+                action: completed.action.clone(),
+                reason: reason.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Headless preset for running the AI on a dedicated server.
+//!
+//! The dialogue pipeline only needs `MinimalPlugins`: nothing in dialogue, context gathering or
+//! actions reads render, window or asset resources. [`AiServerPlugin`] adds `MinimalPlugins`
+//! (ticking at a fixed rate) unless they are already present, then the [`AIDialoguePlugin`].
+//!
+//! A network layer talks to it through two resources holding [`wire`](crate::wire) records:
+//! push client requests into [`AiServerInbox`] and drain [`AiServerOutbox`] for the replies.
+//! Entities in the records are the server's; clients map them to their own copies. Request ids
+//! are the server's too: [`AiServerInbox::push`] replaces the client's id with a fresh one and
+//! returns it, so requests from different clients never collide; send it back to the client to
+//! match the reply.
+//!
+//! # Example
+//! ```ignore
+//! App::new()
+//!     .add_plugins(AiServerPlugin::new(AIDialoguePlugin::with_builder(builder)))
+//!     .add_systems(Update, (read_sockets, write_sockets))
+//!     .run();
+//!
+//! fn read_sockets(mut inbox: ResMut<AiServerInbox>, net: Res<Net>) {
+//!     for line in net.incoming() {
+//!         match serde_json::from_str(&line.text) {
+//!             Ok(request) => net.send(line.client, inbox.push(request)),
+//!             Err(e) => warn!("bad request: {}", e),
+//!         }
+//!     }
+//! }
+//!
+//! fn write_sockets(mut outbox: ResMut<AiServerOutbox>, net: Res<Net>) {
+//!     for response in outbox.drain() {
+//!         net.broadcast(serde_json::to_string(&response).unwrap());
+//!     }
+//! }
+//! ```
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::dialogue::{
+    AIDialoguePlugin, AiResponseEvent, AiSystemSet, DialogueRequest, DialogueRequestQueue,
+};
+use crate::wire::{WireDialogueRequest, WireDialogueResponse};
+
+/// Requests received from clients, moved into the `DialogueRequestQueue` every frame.
+#[derive(Resource, Debug, Default)]
+pub struct AiServerInbox {
+    /// Requests with the id the server assigned them.
+    requests: Vec<(u64, WireDialogueRequest)>,
+}
+
+impl AiServerInbox {
+    /// Queue `request` under a new server-side id, returned for the client to match the reply
+    /// with. The id the client sent is ignored.
+    pub fn push(&mut self, request: WireDialogueRequest) -> u64 {
+        let id = crate::dialogue::next_request_id();
+        self.requests.push((id, request));
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+/// Replies waiting to be sent to clients, one per applied response.
+#[derive(Resource, Debug, Default)]
+pub struct AiServerOutbox {
+    responses: Vec<WireDialogueResponse>,
+}
+
+impl AiServerOutbox {
+    /// Take every reply collected so far.
+    pub fn drain(&mut self) -> std::vec::Drain<'_, WireDialogueResponse> {
+        self.responses.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+}
+
+/// Headless AI server: `MinimalPlugins`, the dialogue plugin and the network inbox/outbox.
+pub struct AiServerPlugin {
+    pub dialogue: AIDialoguePlugin,
+    /// Interval between frames when `MinimalPlugins` are added by this plugin.
+    pub tick_rate: Duration,
+}
+
+impl AiServerPlugin {
+    /// Serve `dialogue` at 60 ticks per second.
+    pub fn new(dialogue: AIDialoguePlugin) -> Self {
+        Self {
+            dialogue,
+            tick_rate: Duration::from_secs_f64(1.0 / 60.0),
+        }
+    }
+
+    pub fn with_tick_rate(mut self, tick_rate: Duration) -> Self {
+        self.tick_rate = tick_rate;
+        self
+    }
+}
+
+impl Plugin for AiServerPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<TaskPoolPlugin>() {
+            app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(self.tick_rate)));
+        }
+        app.add_plugins(self.dialogue.clone())
+            .init_resource::<AiServerInbox>()
+            .init_resource::<AiServerOutbox>()
+            .add_systems(
+                Update,
+                accept_server_requests.before(AiSystemSet::HandleRequests),
+            )
+            .add_observer(collect_server_responses);
+    }
+}
+
+fn accept_server_requests(
+    mut inbox: ResMut<AiServerInbox>,
+    mut queue: ResMut<DialogueRequestQueue>,
+) {
+    for (id, request) in inbox.requests.drain(..) {
+        match request.into_request() {
+            Ok(request) => queue.push(DialogueRequest { id, ..request }),
+            Err(e) => warn!("Dropping AI server request: {}", e),
+        }
+    }
+}
+
+fn collect_server_responses(event: On<AiResponseEvent>, mut outbox: ResMut<AiServerOutbox>) {
+    outbox
+        .responses
+        .push(WireDialogueResponse::from(event.event()));
+}
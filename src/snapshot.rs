@@ -0,0 +1,382 @@
+//! Compact digests of world state for prompts.
+//!
+//! A [`WorldSnapshot`] lists which components describe the world (positions, health,
+//! factions...) and how each one is written down. Taking it walks every entity holding one of
+//! them and produces a [`Snapshot`]: entities sorted by name, fields sorted by section, so the
+//! same world always gives the same text. Snapshots render as short lines or as JSON, and
+//! [`Snapshot::diff`] keeps only what changed since an earlier one.
+//!
+//! Added with [`AiAppExt::add_world_snapshot`](crate::app_ext::AiAppExt::add_world_snapshot),
+//! the snapshot becomes part of every gathered context. With [`WorldSnapshot::with_diffs`] an
+//! entity gets the full snapshot once and then only the changes since the last one sent to it
+//! (see [`SnapshotBaselines`]).
+//!
+//! # Example
+//! ```ignore
+//! app.add_world_snapshot(
+//!     WorldSnapshot::new()
+//!         .positions()
+//!         .component::<Health>("health", |h| h.current)
+//!         .component::<Faction>("faction", |f| f.0.clone())
+//!         .serialized::<Inventory>("inventory")
+//!         .within(30.0)
+//!         .with_diffs(),
+//! );
+//!
+//! // guard (4v1): faction=watch health=80 position=[1.5,0.0,-3.0]
+//! // you (2v1): faction=bandits health=35 inventory=["rope","knife"] position=[0.0,0.0,0.0]
+//! ```
+
+use bevy::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::context::AiCurrentContextEntity;
+use crate::rag::AiMessage;
+
+/// Reads one component of every entity holding it.
+type Section = Box<dyn Fn(&World) -> Vec<(Entity, Value)> + Send + Sync>;
+
+/// Resource describing what a [`Snapshot`] contains, see the [module docs](self).
+#[derive(Resource, Default)]
+pub struct WorldSnapshot {
+    sections: Vec<(String, Section)>,
+    /// Only entities this close to the snapshot's origin are listed.
+    pub radius: Option<f32>,
+    /// Send each entity only the changes since its last snapshot.
+    pub diffs: bool,
+}
+
+impl std::fmt::Debug for WorldSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorldSnapshot")
+            .field(
+                "sections",
+                &self.sections.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            )
+            .field("radius", &self.radius)
+            .field("diffs", &self.diffs)
+            .finish()
+    }
+}
+
+impl WorldSnapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List component `C` under `name`, written down by `describe`.
+    pub fn component<C, V>(
+        mut self,
+        name: impl Into<String>,
+        describe: impl Fn(&C) -> V + Send + Sync + 'static,
+    ) -> Self
+    where
+        C: Component,
+        V: Serialize,
+    {
+        self.sections.push((
+            name.into(),
+            Box::new(move |world: &World| {
+                let Some(mut query) = world.try_query::<(Entity, &C)>() else {
+                    return Vec::new();
+                };
+                query
+                    .iter(world)
+                    .filter_map(|(entity, c)| {
+                        Some((entity, serde_json::to_value(describe(c)).ok()?))
+                    })
+                    .collect()
+            }),
+        ));
+        self
+    }
+
+    /// List component `C` under `name` as it serializes.
+    pub fn serialized<C>(self, name: impl Into<String>) -> Self
+    where
+        C: Component + Serialize,
+    {
+        self.component::<C, _>(name, |c: &C| serde_json::to_value(c).unwrap_or(Value::Null))
+    }
+
+    /// List `Transform` translations under `position`, rounded to a tenth.
+    pub fn positions(self) -> Self {
+        self.component::<Transform, _>("position", |t: &Transform| {
+            t.translation
+                .to_array()
+                .map(|v| (v as f64 * 10.0).round() / 10.0)
+        })
+    }
+
+    /// Only list entities within `radius` of the origin, usually the entity asking.
+    pub fn within(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    pub fn with_diffs(mut self) -> Self {
+        self.diffs = true;
+        self
+    }
+
+    /// Snapshot of the world. `origin` is the entity the snapshot is for: it is listed as
+    /// `you`, and [`radius`](Self::radius) is measured from it.
+    pub fn take(&self, world: &World, origin: Option<Entity>) -> Snapshot {
+        let center = origin
+            .and_then(|e| world.get::<GlobalTransform>(e).map(|t| t.translation()))
+            .or_else(|| origin.and_then(|e| world.get::<Transform>(e).map(|t| t.translation)));
+        let in_range = |entity: Entity| {
+            let (Some(radius), Some(center)) = (self.radius, center) else {
+                return true;
+            };
+            world
+                .get::<GlobalTransform>(entity)
+                .map(|t| t.translation())
+                .or_else(|| world.get::<Transform>(entity).map(|t| t.translation))
+                .is_none_or(|pos| pos.distance(center) <= radius)
+        };
+
+        let mut snapshot = Snapshot::default();
+        for (name, section) in &self.sections {
+            for (entity, value) in section(world) {
+                if !in_range(entity) {
+                    continue;
+                }
+                let label = if Some(entity) == origin {
+                    format!("you ({})", entity)
+                } else {
+                    match world.get::<Name>(entity) {
+                        Some(n) => format!("{} ({})", n.as_str(), entity),
+                        None => entity.to_string(),
+                    }
+                };
+                snapshot
+                    .entities
+                    .entry(label)
+                    .or_default()
+                    .insert(name.clone(), value);
+            }
+        }
+        snapshot
+    }
+}
+
+/// Fields of every listed entity, sorted for stable output.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Snapshot {
+    pub entities: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl Snapshot {
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// One line per entity: `guard (4v1): faction=watch health=80`.
+    pub fn to_text(&self) -> String {
+        self.entities
+            .iter()
+            .map(|(label, fields)| {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Value::String(s) => format!("{}={}", name, s),
+                        other => format!("{}={}", name, other),
+                    })
+                    .collect();
+                format!("{}: {}", label, fields.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    /// What changed since `previous`: new and changed fields, and entities no longer listed.
+    pub fn diff(&self, previous: &Snapshot) -> SnapshotDiff {
+        let mut changed = Snapshot::default();
+        for (label, fields) in &self.entities {
+            let before = previous.entities.get(label);
+            let fields: BTreeMap<String, Value> = fields
+                .iter()
+                .filter(|(name, value)| before.and_then(|b| b.get(*name)) != Some(*value))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            if !fields.is_empty() {
+                changed.entities.insert(label.clone(), fields);
+            }
+        }
+        let removed = previous
+            .entities
+            .keys()
+            .filter(|label| !self.entities.contains_key(*label))
+            .cloned()
+            .collect();
+        SnapshotDiff { changed, removed }
+    }
+}
+
+/// Difference between two [`Snapshot`]s.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnapshotDiff {
+    /// Entities with new or changed fields, holding only those fields.
+    pub changed: Snapshot,
+    /// Entities listed before and not anymore.
+    pub removed: Vec<String>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// The changed lines, then a `gone:` line for removed entities.
+    pub fn to_text(&self) -> String {
+        let mut text = self.changed.to_text();
+        if !self.removed.is_empty() {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("gone: {}", self.removed.join(", ")));
+        }
+        text
+    }
+}
+
+/// The snapshot each entity's model last saw, used by [`WorldSnapshot::with_diffs`].
+///
+/// A snapshot gathered for a prompt only becomes the baseline once that prompt is sent, so a
+/// request dropped before dispatch does not hide changes from the next one. Baselines of
+/// despawned entities are dropped, and all of them are when the backend is swapped, since the
+/// new model never saw them.
+#[derive(Resource, Debug, Default)]
+pub struct SnapshotBaselines {
+    sent: HashMap<Entity, Snapshot>,
+    gathered: HashMap<Entity, Snapshot>,
+}
+
+impl SnapshotBaselines {
+    /// The snapshot last sent to `entity`.
+    pub fn baseline(&self, entity: Entity) -> Option<&Snapshot> {
+        self.sent.get(&entity)
+    }
+
+    /// Send `entity` the full snapshot again with its next prompt, e.g. after resetting its
+    /// conversation.
+    pub fn forget(&mut self, entity: Entity) {
+        self.sent.remove(&entity);
+        self.gathered.remove(&entity);
+    }
+
+    /// Send every entity the full snapshot again.
+    pub fn clear(&mut self) {
+        self.sent.clear();
+        self.gathered.clear();
+    }
+
+    /// The prompt of `entity` was sent with the snapshot gathered for it.
+    pub(crate) fn prompt_sent(&mut self, entity: Entity) {
+        if let Some(snapshot) = self.gathered.remove(&entity) {
+            self.sent.insert(entity, snapshot);
+        }
+    }
+}
+
+/// Context-gathering system adding the [`WorldSnapshot`] to the requester's context.
+pub fn gather_world_snapshot(world: &mut World) -> Option<AiMessage> {
+    let entity = world.get_resource::<AiCurrentContextEntity>()?.0;
+    let config = world.get_resource::<WorldSnapshot>()?;
+    let snapshot = config.take(world, Some(entity));
+    if !config.diffs {
+        return (!snapshot.is_empty())
+            .then(|| AiMessage::system(format!("World state:\n{}", snapshot.to_text())));
+    }
+    let despawned: Vec<Entity> = world
+        .get_resource::<SnapshotBaselines>()?
+        .sent
+        .keys()
+        .filter(|e| world.get_entity(**e).is_err())
+        .copied()
+        .collect();
+    let mut baselines = world.get_resource_mut::<SnapshotBaselines>()?;
+    for e in despawned {
+        baselines.forget(e);
+    }
+    let message = match baselines.sent.get(&entity) {
+        Some(previous) => {
+            let diff = snapshot.diff(previous);
+            (!diff.is_empty())
+                .then(|| format!("World changes since your last report:\n{}", diff.to_text()))
+        }
+        None => (!snapshot.is_empty()).then(|| format!("World state:\n{}", snapshot.to_text())),
+    };
+    baselines.gathered.insert(entity, snapshot);
+    message.map(AiMessage::system)
+}
+
+/// A new model has not seen any snapshot yet.
+pub(crate) fn forget_snapshots_on_swap(
+    _swap: On<crate::dialogue::AiBackendSwapped>,
+    mut baselines: ResMut<SnapshotBaselines>,
+) {
+    baselines.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component)]
+    struct Health(u32);
+
+    /// Transforms as they are once propagated.
+    fn at(x: f32, z: f32) -> (Transform, GlobalTransform) {
+        (
+            Transform::from_xyz(x, 0.0, z),
+            GlobalTransform::from_xyz(x, 0.0, z),
+        )
+    }
+
+    #[test]
+    fn snapshots_are_stable_and_diff_by_field() {
+        let mut world = World::new();
+        let config = WorldSnapshot::new()
+            .positions()
+            .component::<Health, _>("health", |h| h.0)
+            .within(10.0);
+        let me = world
+            .spawn((Name::new("guard"), at(0.0, 0.0), Health(80)))
+            .id();
+        let wolf = world.spawn((Name::new("wolf"), at(3.0, 4.04))).id();
+        world.spawn((Name::new("far"), at(50.0, 0.0), Health(5)));
+
+        let before = config.take(&world, Some(me));
+        assert_eq!(
+            before.to_text(),
+            format!(
+                "wolf ({}): position=[3.0,0.0,4.0]\nyou ({}): health=80 position=[0.0,0.0,0.0]",
+                wolf, me
+            )
+        );
+        assert_eq!(config.take(&world, Some(me)), before);
+
+        world.get_mut::<Health>(me).unwrap().0 = 60;
+        world.despawn(wolf);
+        let diff = config.take(&world, Some(me)).diff(&before);
+        assert_eq!(
+            diff.to_text(),
+            format!("you ({}): health=60\ngone: wolf ({})", me, wolf)
+        );
+        assert!(
+            config
+                .take(&world, Some(me))
+                .diff(&config.take(&world, Some(me)))
+                .is_empty()
+        );
+    }
+}
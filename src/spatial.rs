@@ -0,0 +1,203 @@
+//! Relative spatial phrasing helpers.
+//!
+//! Converts world positions into short, human-readable descriptions from an AI entity's
+//! point of view ("Bob is 5m to your left"), using the observer's `Transform` and facing.
+//! Gather systems can use these instead of dumping raw coordinates into the context.
+//!
+//! # Example
+//! ```ignore
+//! store.add_system(|ai_entity: AiEntity, npcs: Query<(&Npc, &Transform), With<AIAware>>| {
+//!     let lines: Vec<String> = npcs
+//!         .iter()
+//!         .filter_map(|(npc, t)| ai_entity.describe_relative(&npc.name, t.translation))
+//!         .collect();
+//!     (!lines.is_empty()).then(|| AiMessage::system(&lines.join("\n")))
+//! });
+//! ```
+
+use bevy::prelude::*;
+
+/// Which plane the observer moves in, used to decide what "forward" and "left" mean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect)]
+pub enum SpatialPlane {
+    /// 3D worlds: movement on the XZ plane, Y is up and the observer faces its local `-Z`.
+    #[default]
+    Xz,
+    /// Top-down 2D worlds: movement on the XY plane and the observer faces its local `+Y`.
+    Xy,
+}
+
+/// Coarse horizontal direction of a target relative to the observer's facing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeDirection {
+    Ahead,
+    AheadRight,
+    Right,
+    BehindRight,
+    Behind,
+    BehindLeft,
+    Left,
+    AheadLeft,
+    /// The target is (almost) at the observer's position.
+    Here,
+}
+
+impl RelativeDirection {
+    /// Phrase used after the distance, e.g. "to your left".
+    pub fn phrase(&self) -> &'static str {
+        match self {
+            RelativeDirection::Ahead => "ahead of you",
+            RelativeDirection::AheadRight => "ahead of you to the right",
+            RelativeDirection::Right => "to your right",
+            RelativeDirection::BehindRight => "behind you to the right",
+            RelativeDirection::Behind => "behind you",
+            RelativeDirection::BehindLeft => "behind you to the left",
+            RelativeDirection::Left => "to your left",
+            RelativeDirection::AheadLeft => "ahead of you to the left",
+            RelativeDirection::Here => "right next to you",
+        }
+    }
+}
+
+/// Vertical offset of a target, only reported when it is significant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeHeight {
+    Above,
+    Below,
+}
+
+/// Where a target is relative to an observer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativePlacement {
+    /// Straight-line distance in world units.
+    pub distance: f32,
+    pub direction: RelativeDirection,
+    pub height: Option<RelativeHeight>,
+}
+
+/// Targets closer than this (world units) are described as "right next to you".
+const NEARBY_EPSILON: f32 = 0.5;
+
+/// Vertical offsets smaller than this (world units) are not mentioned.
+const HEIGHT_THRESHOLD: f32 = 1.5;
+
+impl RelativePlacement {
+    /// Compute the placement of `target` as seen from `observer`.
+    pub fn compute(observer: &Transform, target: Vec3, plane: SpatialPlane) -> Self {
+        let offset = target - observer.translation;
+        let distance = offset.length();
+
+        let (forward, right, up) = match plane {
+            SpatialPlane::Xz => (*observer.forward(), *observer.right(), Vec3::Y),
+            SpatialPlane::Xy => (*observer.up(), *observer.right(), Vec3::Z),
+        };
+
+        let vertical = offset.dot(up);
+        let height = if vertical > HEIGHT_THRESHOLD {
+            Some(RelativeHeight::Above)
+        } else if vertical < -HEIGHT_THRESHOLD {
+            Some(RelativeHeight::Below)
+        } else {
+            None
+        };
+
+        let planar = offset - up * vertical;
+        let direction = if planar.length() < NEARBY_EPSILON {
+            RelativeDirection::Here
+        } else {
+            // Angle from forward, positive to the right, in the range (-180, 180]
+            let angle = planar.dot(right).atan2(planar.dot(forward)).to_degrees();
+            let right_side = angle > 0.0;
+            match angle.abs() {
+                a if a <= 22.5 => RelativeDirection::Ahead,
+                a if a <= 67.5 && right_side => RelativeDirection::AheadRight,
+                a if a <= 67.5 => RelativeDirection::AheadLeft,
+                a if a <= 112.5 && right_side => RelativeDirection::Right,
+                a if a <= 112.5 => RelativeDirection::Left,
+                a if a <= 157.5 && right_side => RelativeDirection::BehindRight,
+                a if a <= 157.5 => RelativeDirection::BehindLeft,
+                _ => RelativeDirection::Behind,
+            }
+        };
+
+        Self {
+            distance,
+            direction,
+            height,
+        }
+    }
+
+    /// Describe the placement without a subject, e.g. "5m to your left, above you".
+    pub fn phrase(&self) -> String {
+        let mut text = match self.direction {
+            RelativeDirection::Here => self.direction.phrase().to_string(),
+            _ => format!("{:.0}m {}", self.distance, self.direction.phrase()),
+        };
+        match self.height {
+            Some(RelativeHeight::Above) => text.push_str(", above you"),
+            Some(RelativeHeight::Below) => text.push_str(", below you"),
+            None => {}
+        }
+        text
+    }
+
+    /// Describe the placement of a named subject, e.g. "Bob is 5m to your left".
+    pub fn describe(&self, name: &str) -> String {
+        format!("{} is {}", name, self.phrase())
+    }
+}
+
+/// Convenience wrapper returning "`name` is <distance> <direction>" for `target` seen from `observer`.
+pub fn describe_relative(
+    observer: &Transform,
+    name: &str,
+    target: Vec3,
+    plane: SpatialPlane,
+) -> String {
+    RelativePlacement::compute(observer, target, plane).describe(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_transform_faces_negative_z() {
+        let observer = Transform::default();
+        let ahead =
+            RelativePlacement::compute(&observer, Vec3::new(0.0, 0.0, -5.0), SpatialPlane::Xz);
+        assert_eq!(ahead.direction, RelativeDirection::Ahead);
+        let left =
+            RelativePlacement::compute(&observer, Vec3::new(-5.0, 0.0, 0.0), SpatialPlane::Xz);
+        assert_eq!(left.direction, RelativeDirection::Left);
+        assert_eq!(left.describe("Bob"), "Bob is 5m to your left");
+        let behind =
+            RelativePlacement::compute(&observer, Vec3::new(0.0, 0.0, 3.0), SpatialPlane::Xz);
+        assert_eq!(behind.direction, RelativeDirection::Behind);
+    }
+
+    #[test]
+    fn rotation_changes_direction() {
+        // Turn to face +X; something at +X is now ahead, something at -Z is to the left.
+        let observer = Transform::default().looking_to(Vec3::X, Vec3::Y);
+        let ahead =
+            RelativePlacement::compute(&observer, Vec3::new(4.0, 0.0, 0.0), SpatialPlane::Xz);
+        assert_eq!(ahead.direction, RelativeDirection::Ahead);
+        let left =
+            RelativePlacement::compute(&observer, Vec3::new(0.0, 0.0, -4.0), SpatialPlane::Xz);
+        assert_eq!(left.direction, RelativeDirection::Left);
+    }
+
+    #[test]
+    fn top_down_plane_uses_local_y_and_reports_height() {
+        let observer = Transform::default();
+        let right =
+            RelativePlacement::compute(&observer, Vec3::new(2.0, 2.0, 0.0), SpatialPlane::Xy);
+        assert_eq!(right.direction, RelativeDirection::AheadRight);
+
+        let above =
+            RelativePlacement::compute(&observer, Vec3::new(0.0, 4.0, -4.0), SpatialPlane::Xz);
+        assert_eq!(above.height, Some(RelativeHeight::Above));
+        assert!(above.phrase().ends_with("above you"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Voice input: transcribe the microphone with Whisper and send it to an AI entity.
+//!
+//! Requires the `speech` feature. [`AiSpeechInputPlugin`] loads a Whisper model in the
+//! background, listens to the default microphone and pushes every transcript as a
+//! [`DialogueRequest`] for [`AiSpeechInput::target`]. Two capture modes are supported:
+//!
+//! - [`SpeechInputMode::PushToTalk`]: record while a key is held, transcribe on release.
+//! - [`SpeechInputMode::VoiceActivity`]: transcribe every utterance detected by voice
+//!   activity detection (VAD) while [`AiSpeechInput::listening`] is set.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiSpeechInputPlugin::push_to_talk(KeyCode::KeyV));
+//!
+//! fn talk_to(mut speech: ResMut<AiSpeechInput>, npc: Single<Entity, With<AI>>) {
+//!     speech.target = Some(*npc);
+//! }
+//! ```
+
+use bevy::prelude::*;
+use flume::{Receiver, Sender, unbounded};
+use futures_lite::StreamExt;
+use kalosm::sound::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::dialogue::{DialogueRequest, DialogueRequestQueue};
+
+/// How microphone audio is segmented before transcription.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeechInputMode {
+    /// Record while the key is held and transcribe the whole recording on release.
+    PushToTalk(KeyCode),
+    /// Transcribe each utterance found by voice activity detection.
+    /// `threshold` is the speech probability (0.0..=1.0) that starts an utterance.
+    VoiceActivity { threshold: f32 },
+}
+
+/// Runtime state of the speech input.
+#[derive(Resource, Debug, Clone)]
+pub struct AiSpeechInput {
+    /// Entity that receives transcripts as `DialogueRequest`s. Transcripts are dropped while `None`.
+    pub target: Option<Entity>,
+    /// Whether the microphone is currently captured. Driven by the key in push-to-talk mode.
+    pub listening: bool,
+    /// Whether the queued requests include gathered context.
+    pub include_context: bool,
+    /// Set once the Whisper model finished loading.
+    pub ready: bool,
+}
+
+/// Fired for every non-empty transcript, whether or not a target was set.
+#[derive(Event, Debug, Clone)]
+pub struct SpeechTranscribed {
+    /// The target the transcript was sent to, if any.
+    pub entity: Option<Entity>,
+    pub text: String,
+}
+
+enum SpeechMessage {
+    Ready,
+    Failed(String),
+    Transcript(String),
+}
+
+#[derive(Resource)]
+struct SpeechChannel {
+    listening: Arc<AtomicBool>,
+    receiver: Receiver<SpeechMessage>,
+}
+
+/// Plugin adding Whisper-based voice input.
+pub struct AiSpeechInputPlugin {
+    pub mode: SpeechInputMode,
+    pub source: WhisperSource,
+    pub include_context: bool,
+}
+
+impl AiSpeechInputPlugin {
+    /// Record while `key` is held.
+    pub fn push_to_talk(key: KeyCode) -> Self {
+        Self {
+            mode: SpeechInputMode::PushToTalk(key),
+            source: WhisperSource::default(),
+            include_context: true,
+        }
+    }
+
+    /// Transcribe utterances detected by VAD. Listening starts enabled.
+    pub fn voice_activity() -> Self {
+        Self {
+            mode: SpeechInputMode::VoiceActivity { threshold: 0.5 },
+            source: WhisperSource::default(),
+            include_context: true,
+        }
+    }
+
+    /// Set the VAD speech probability threshold. Ignored in push-to-talk mode.
+    pub fn with_vad_threshold(mut self, threshold: f32) -> Self {
+        if let SpeechInputMode::VoiceActivity { threshold: t } = &mut self.mode {
+            *t = threshold.clamp(0.0, 1.0);
+        }
+        self
+    }
+
+    /// Use a different Whisper model size.
+    pub fn with_source(mut self, source: WhisperSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Whether transcripts are sent with gathered context (default `true`).
+    pub fn with_context(mut self, include_context: bool) -> Self {
+        self.include_context = include_context;
+        self
+    }
+}
+
+impl Plugin for AiSpeechInputPlugin {
+    fn build(&self, app: &mut App) {
+        let listening_at_start = matches!(self.mode, SpeechInputMode::VoiceActivity { .. });
+        let listening = Arc::new(AtomicBool::new(listening_at_start));
+        let (tx, rx) = unbounded();
+        spawn_speech_worker(self.mode, self.source.clone(), listening.clone(), tx);
+
+        app.insert_resource(AiSpeechInput {
+            target: None,
+            listening: listening_at_start,
+            include_context: self.include_context,
+            ready: false,
+        })
+        .insert_resource(SpeechChannel {
+            listening,
+            receiver: rx,
+        });
+
+        if let SpeechInputMode::PushToTalk(key) = self.mode {
+            app.add_systems(
+                PreUpdate,
+                (move |keys: Res<ButtonInput<KeyCode>>, mut speech: ResMut<AiSpeechInput>| {
+                    speech.listening = keys.pressed(key);
+                })
+                .before(sync_speech_listening),
+            );
+        }
+        app.add_systems(
+            PreUpdate,
+            (sync_speech_listening, poll_speech_transcripts).chain(),
+        );
+    }
+}
+
+fn sync_speech_listening(speech: Res<AiSpeechInput>, channel: Res<SpeechChannel>) {
+    if speech.is_changed() {
+        channel.listening.store(speech.listening, Ordering::Relaxed);
+    }
+}
+
+fn poll_speech_transcripts(
+    mut commands: Commands,
+    channel: Res<SpeechChannel>,
+    mut speech: ResMut<AiSpeechInput>,
+    mut queue: ResMut<DialogueRequestQueue>,
+) {
+    for message in channel.receiver.try_iter() {
+        match message {
+            SpeechMessage::Ready => speech.ready = true,
+            SpeechMessage::Failed(err) => error!("Speech input unavailable: {}", err),
+            SpeechMessage::Transcript(text) => {
+                let text = text.trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+                if let Some(entity) = speech.target {
+                    let request = if speech.include_context {
+                        DialogueRequest::text(entity, text.clone())
+                    } else {
+                        DialogueRequest::text_no_context(entity, text.clone())
+                    };
+                    // Transcripts are whatever the player said, so treat them like typed chat
+                    queue.push(request.sanitized());
+                } else {
+                    debug!("Dropping speech transcript without target: {}", text);
+                }
+                commands.trigger(SpeechTranscribed {
+                    entity: speech.target,
+                    text,
+                });
+            }
+        }
+    }
+}
+
+/// The microphone stream is not `Send`, so capture runs on its own thread.
+fn spawn_speech_worker(
+    mode: SpeechInputMode,
+    source: WhisperSource,
+    listening: Arc<AtomicBool>,
+    tx: Sender<SpeechMessage>,
+) {
+    std::thread::spawn(move || {
+        crate::models::TOKIO_RUNTIME.block_on(async move {
+            let model = match Whisper::builder().with_source(source).build().await {
+                Ok(model) => model,
+                Err(e) => {
+                    let _ = tx.send(SpeechMessage::Failed(e.to_string()));
+                    return;
+                }
+            };
+            let _ = tx.send(SpeechMessage::Ready);
+            let mic = MicInput::default();
+            match mode {
+                SpeechInputMode::PushToTalk(_) => {
+                    push_to_talk_loop(&model, &mic, &listening, &tx).await
+                }
+                SpeechInputMode::VoiceActivity { threshold } => {
+                    voice_activity_loop(&model, &mic, threshold, &listening, &tx).await
+                }
+            }
+        });
+    });
+}
+
+async fn push_to_talk_loop(
+    model: &Whisper,
+    mic: &MicInput,
+    listening: &AtomicBool,
+    tx: &Sender<SpeechMessage>,
+) {
+    loop {
+        while !listening.load(Ordering::Relaxed) {
+            if tx.is_disconnected() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let stream = mic.stream();
+        while listening.load(Ordering::Relaxed) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let audio = stream.read_all_samples();
+        if send_transcript(model.transcribe(audio), tx).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn voice_activity_loop(
+    model: &Whisper,
+    mic: &MicInput,
+    threshold: f32,
+    listening: &AtomicBool,
+    tx: &Sender<SpeechMessage>,
+) {
+    let mut chunks = mic
+        .stream()
+        .voice_activity_stream()
+        .rechunk_voice_activity()
+        .with_start_threshold(threshold);
+    while let Some(chunk) = chunks.next().await {
+        // VAD keeps consuming the microphone so stale audio doesn't pile up while muted.
+        if !listening.load(Ordering::Relaxed) {
+            continue;
+        }
+        if send_transcript(model.transcribe(chunk), tx).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn send_transcript(
+    mut segments: impl futures_lite::Stream<Item = Segment> + Unpin,
+    tx: &Sender<SpeechMessage>,
+) -> Result<(), ()> {
+    let mut text = String::new();
+    while let Some(segment) = segments.next().await {
+        text.push_str(segment.text());
+    }
+    tx.send(SpeechMessage::Transcript(text)).map_err(|_| ())
+}
@@ -0,0 +1,255 @@
+//! A summary of what the AI pipeline is doing.
+//!
+//! [`AiPipelineStatus`] is kept up to date by the dialogue plugin: queued and in-flight
+//! requests, the last failed reply, the state of each model load and the hit rate of the
+//! [`AiUtility`](crate::utility::AiUtility) reply cache. Read it from game UI or tests instead of
+//! reaching into the queue, the pending loads and the backend handle separately.
+//!
+//! # Example
+//! ```ignore
+//! fn ai_status_text(status: Res<AiPipelineStatus>, mut text: Single<&mut Text, With<AiStatusLabel>>) {
+//!     text.0 = format!("{} queued, {} thinking", status.queued, status.in_flight.len());
+//!     if let Some(slow) = status.in_flight.iter().find(|r| r.elapsed.as_secs() > 10) {
+//!         warn!("{:?} has been waiting for {:?}", slow.entity, slow.elapsed);
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::dialogue::{
+    DialogueRequestQueue, LocalAiHandle, ModelDownloadProgressEvent, ModelLoadCompleteEvent,
+    ModelUnloadedEvent, PendingModelLoads,
+};
+
+/// A request the backend is working on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InFlightRequest {
+    pub request_id: u64,
+    pub entity: Entity,
+    /// Time since the request was sent to the backend, as of the last update.
+    pub elapsed: Duration,
+    started: Instant,
+}
+
+/// The last reply that was an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineError {
+    pub request_id: u64,
+    pub entity: Entity,
+    pub message: String,
+}
+
+/// State of a model loaded through [`start_model_load`](crate::dialogue::start_model_load).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelSlotState {
+    /// Downloading or loading; `progress` is the last reported percentage.
+    Loading {
+        progress: Option<f32>,
+    },
+    /// Serving requests.
+    Loaded,
+    /// Replaced by another model or unloaded.
+    Unloaded,
+    Failed(String),
+}
+
+/// A model by the name it was loaded under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSlot {
+    pub name: String,
+    pub state: ModelSlotState,
+}
+
+/// Snapshot of the dialogue pipeline, updated every frame.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct AiPipelineStatus {
+    /// Requests waiting to be sent to the backend.
+    pub queued: usize,
+    /// Requests sent to the backend and not answered yet, oldest first. Requests of despawned
+    /// entities are left out.
+    pub in_flight: Vec<InFlightRequest>,
+    pub last_error: Option<PipelineError>,
+    /// Whether a backend is available, including backends set without a model load.
+    pub backend_loaded: bool,
+    pub models: Vec<ModelSlot>,
+    /// Share of utility prompts answered from the cache, `None` before the first one.
+    pub cache_hit_rate: Option<f32>,
+}
+
+impl AiPipelineStatus {
+    /// Whether nothing is queued or in flight.
+    pub fn is_idle(&self) -> bool {
+        self.queued == 0 && self.in_flight.is_empty()
+    }
+
+    /// The slot of model `name`, if it was ever loaded.
+    pub fn model(&self, name: &str) -> Option<&ModelSlot> {
+        self.models.iter().find(|slot| slot.name == name)
+    }
+
+    pub(crate) fn request_sent(&mut self, request_id: u64, entity: Entity) {
+        self.in_flight.push(InFlightRequest {
+            request_id,
+            entity,
+            elapsed: Duration::ZERO,
+            started: Instant::now(),
+        });
+    }
+
+    pub(crate) fn response_received(&mut self, request_id: u64, entity: Entity, text: &str) {
+        self.in_flight.retain(|r| r.request_id != request_id);
+        if text.starts_with("(ai error") {
+            self.last_error = Some(PipelineError {
+                request_id,
+                entity,
+                message: text.to_string(),
+            });
+        }
+    }
+
+    /// Stop waiting for `request_id`, which will not be answered.
+    pub(crate) fn request_lost(&mut self, request_id: u64) {
+        self.in_flight.retain(|r| r.request_id != request_id);
+    }
+
+    fn set_model(&mut self, name: &str, state: ModelSlotState) {
+        match self.models.iter_mut().find(|slot| slot.name == name) {
+            Some(slot) => slot.state = state,
+            None => self.models.push(ModelSlot {
+                name: name.to_string(),
+                state,
+            }),
+        }
+    }
+
+    fn unload_models(&mut self) {
+        for slot in self.models.iter_mut() {
+            if slot.state == ModelSlotState::Loaded {
+                slot.state = ModelSlotState::Unloaded;
+            }
+        }
+    }
+}
+
+/// Refresh the counters that are read rather than reported.
+pub(crate) fn update_pipeline_status(
+    mut status: ResMut<AiPipelineStatus>,
+    queue: Res<DialogueRequestQueue>,
+    handle: Res<LocalAiHandle>,
+    pending: Res<PendingModelLoads>,
+    utility: Option<Res<crate::utility::AiUtilityStats>>,
+) {
+    status.queued = queue.len();
+    status.backend_loaded = handle.is_loaded();
+    for request in status.in_flight.iter_mut() {
+        request.elapsed = request.started.elapsed();
+    }
+    for loader in pending.loaders.iter() {
+        if status
+            .model(&loader.model_name)
+            .is_none_or(|slot| !matches!(slot.state, ModelSlotState::Loading { .. }))
+        {
+            status.set_model(
+                &loader.model_name,
+                ModelSlotState::Loading { progress: None },
+            );
+        }
+    }
+    status.cache_hit_rate = utility.and_then(|stats| {
+        let total = stats.cache_hits + stats.requests;
+        (total > 0).then(|| stats.cache_hits as f32 / total as f32)
+    });
+}
+
+pub(crate) fn track_model_progress(
+    progress: On<ModelDownloadProgressEvent>,
+    mut status: ResMut<AiPipelineStatus>,
+) {
+    status.set_model(
+        &progress.model_name,
+        ModelSlotState::Loading {
+            progress: progress.progress,
+        },
+    );
+}
+
+pub(crate) fn track_model_loads(
+    complete: On<ModelLoadCompleteEvent>,
+    mut status: ResMut<AiPipelineStatus>,
+) {
+    let state = match &complete.error_message {
+        None if complete.success => {
+            status.unload_models();
+            ModelSlotState::Loaded
+        }
+        error => ModelSlotState::Failed(error.clone().unwrap_or_default()),
+    };
+    status.set_model(&complete.model_name, state);
+}
+
+pub(crate) fn track_model_unloads(_: On<ModelUnloadedEvent>, mut status: ResMut<AiPipelineStatus>) {
+    status.unload_models();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_slots_follow_load_events() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(crate::dialogue::AIDialoguePlugin::default());
+        let world = app.world_mut();
+        world.trigger(ModelDownloadProgressEvent {
+            model_name: "small".to_string(),
+            state: crate::models::DownloadState::InProgress,
+            message: String::new(),
+            progress: Some(40.0),
+        });
+        let loaded = |name: &str| ModelLoadCompleteEvent {
+            model_name: name.to_string(),
+            success: true,
+            error_message: None,
+        };
+        assert_eq!(
+            world
+                .resource::<AiPipelineStatus>()
+                .model("small")
+                .unwrap()
+                .state,
+            ModelSlotState::Loading {
+                progress: Some(40.0)
+            }
+        );
+        world.trigger(loaded("small"));
+        world.trigger(loaded("large"));
+        world.trigger(ModelLoadCompleteEvent {
+            model_name: "broken".to_string(),
+            success: false,
+            error_message: Some("no such file".to_string()),
+        });
+
+        let status = world.resource::<AiPipelineStatus>();
+        let states: Vec<_> = status.models.iter().map(|m| m.state.clone()).collect();
+        assert_eq!(
+            states,
+            [
+                ModelSlotState::Unloaded,
+                ModelSlotState::Loaded,
+                ModelSlotState::Failed("no such file".to_string())
+            ]
+        );
+        world.trigger(ModelUnloadedEvent);
+        assert_eq!(
+            world
+                .resource::<AiPipelineStatus>()
+                .model("large")
+                .unwrap()
+                .state,
+            ModelSlotState::Unloaded
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Running the actions of a reply while it is still generated.
+//!
+//! A reply listing several actions, `[{"name": "draw_sword"}, {"name": "charge"}]`, normally
+//! runs them once the whole reply is generated. With the [`AiEarlyActions`] resource, text
+//! requests are prompted with [`LocalAi::prompt_streaming`] and each action is dispatched as
+//! soon as its object closes, while the model is still writing the next one. The reply is
+//! scanned with a [`JsonStream`].
+//!
+//! Only replies that are JSON from their first character dispatch early, as only those yield
+//! actions at all. Actions dispatched early are not dispatched again when the reply arrives;
+//! [`AiResponseEvent::actions`] still lists them. Early dispatch is off while
+//! [`AiOutputModeration`] has filters, since actions must not run before their reply is
+//! checked, and an action dispatched early has run even if its reply arrives stale.
+//!
+//! # Example
+//! ```ignore
+//! app.init_resource::<AiEarlyActions>();
+//! ```
+//!
+//! [`LocalAi::prompt_streaming`]: crate::dialogue::LocalAi::prompt_streaming
+//! [`AiResponseEvent::actions`]: crate::dialogue::AiResponseEvent::actions
+//! [`AiOutputModeration`]: crate::moderation::AiOutputModeration
+
+use bevy::prelude::*;
+
+use crate::actions::{ActionPayload, AiActionEvent, PendingAiActions};
+use crate::dialogue::{DialogueRequestKind, actions_from_value};
+use crate::parse::JsonStream;
+
+/// Resource enabling early action dispatch, see the [module docs](self).
+#[derive(Resource, Clone)]
+pub struct AiEarlyActions {
+    tx: flume::Sender<AiActionEvent>,
+    rx: flume::Receiver<AiActionEvent>,
+}
+
+impl Default for AiEarlyActions {
+    fn default() -> Self {
+        let (tx, rx) = flume::unbounded();
+        Self { tx, rx }
+    }
+}
+
+impl AiEarlyActions {
+    /// Queue the actions dispatched since the last call, like those of a finished reply.
+    pub(crate) fn dispatch(
+        &self,
+        mut pending: Option<&mut PendingAiActions>,
+        commands: &mut Commands,
+    ) {
+        for event in self.rx.try_iter() {
+            debug!(
+                "Enqueuing early AI action '{}' for entity {:?} with params: {}",
+                event.action.name, event.entity, event.action.params
+            );
+            if let Some(p) = pending.as_mut() {
+                p.actions.push(event.clone());
+            }
+            commands.trigger(event);
+        }
+    }
+}
+
+/// Sends the actions of a streamed reply as they close. Runs on the background task.
+pub(crate) struct EarlyDispatch {
+    tx: flume::Sender<AiActionEvent>,
+    entity: Entity,
+    kind: DialogueRequestKind,
+    stream: JsonStream,
+    /// Whether the reply starts with JSON, `None` until its first non-blank character.
+    json: Option<bool>,
+    /// Actions sent so far, in order.
+    pub(crate) actions: Vec<ActionPayload>,
+}
+
+impl EarlyDispatch {
+    pub(crate) fn new(early: &AiEarlyActions, entity: Entity, kind: DialogueRequestKind) -> Self {
+        Self {
+            tx: early.tx.clone(),
+            entity,
+            kind,
+            stream: JsonStream::default(),
+            json: None,
+            actions: Vec::new(),
+        }
+    }
+
+    /// Scan the next piece of the reply.
+    pub(crate) fn push(&mut self, text: &str) {
+        if self.json.is_none() {
+            let text = text.trim_start();
+            if text.is_empty() {
+                return;
+            }
+            self.json = Some(text.starts_with(['[', '{']));
+        }
+        if self.json != Some(true) {
+            return;
+        }
+        for value in self.stream.push(text) {
+            for action in actions_from_value(value, &self.kind) {
+                let _ = self.tx.send(AiActionEvent {
+                    entity: self.entity,
+                    action: action.clone(),
+                });
+                self.actions.push(action);
+            }
+        }
+    }
+
+    /// The actions of the finished reply: `parsed` if it starts with the actions already sent,
+    /// else the sent ones, e.g. when the reply was truncated.
+    pub(crate) fn finish(&self, parsed: Vec<ActionPayload>) -> Vec<ActionPayload> {
+        if parsed.starts_with(&self.actions) {
+            parsed
+        } else {
+            self.actions.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_replies_starting_with_json_dispatch_early() {
+        let early = AiEarlyActions::default();
+        let kind = DialogueRequestKind::text(String::new());
+        let mut dispatch = EarlyDispatch::new(&early, Entity::PLACEHOLDER, kind.clone());
+        for piece in [" ", r#"[{"name": "wave"}, "#, r#"{"name": "bow""#] {
+            dispatch.push(piece);
+        }
+        let sent: Vec<_> = early.rx.try_iter().map(|e| e.action.name).collect();
+        assert_eq!(sent, ["wave"]);
+        // A truncated reply parses to nothing, yet the sent action ran
+        assert_eq!(dispatch.finish(Vec::new()), [ActionPayload::new("wave")]);
+
+        let mut prose = EarlyDispatch::new(&early, Entity::PLACEHOLDER, kind);
+        prose.push(r#"Sure! {"name": "wave"}"#);
+        assert!(prose.actions.is_empty());
+        assert!(early.rx.is_empty());
+    }
+}
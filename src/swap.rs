@@ -0,0 +1,161 @@
+//! Keep NPC conversations going when the model is swapped.
+//!
+//! Dialogue prompts are rebuilt for every request from the
+//! [`AiPersona`](crate::persona::AiPersona), the
+//! [`GlobalSystemPrompt`](crate::dialogue::GlobalSystemPrompt) and gathered context, so the
+//! system context is pinned and reaches a new backend unchanged. What the new model does not
+//! have is the conversation so far. When a model load replaces a loaded backend
+//! ([`AiBackendSwapped`]), [`AiModelSwapPlugin`] drops every [`ChatHistory`] session (they
+//! belong to the old model) and gives each entity with a [`Transcript`] a [`ConversationRecap`]
+//! of its last `replay_entries` lines, which is sent with the entity's following prompts.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiModelSwapPlugin { replay_entries: 8 });
+//!
+//! // Later, e.g. when the player picks a bigger model in the settings menu
+//! fn upgrade(mut pending: ResMut<PendingModelLoads>) {
+//!     start_model_load(&mut pending, "Large".into(), AiModelBuilder::new().with_huggingface(..));
+//! }
+//! ```
+
+use bevy::prelude::*;
+
+use crate::dialogue::AiBackendSwapped;
+use crate::prompts::{CONVERSATION_RECAP, PromptTemplates};
+use crate::rag::{AiMessage, ChatHistory};
+use crate::transcript::Transcript;
+
+/// Plugin carrying conversations over to a newly loaded backend.
+pub struct AiModelSwapPlugin {
+    /// Transcript entries replayed to the new model; `0` only resets chat sessions and drops
+    /// earlier recaps.
+    pub replay_entries: usize,
+}
+
+impl Default for AiModelSwapPlugin {
+    fn default() -> Self {
+        Self { replay_entries: 10 }
+    }
+}
+
+/// Settings of the [`AiModelSwapPlugin`], editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiModelSwapSettings {
+    pub replay_entries: usize,
+}
+
+/// Summary of the conversation an entity had before the backend was swapped.
+///
+/// Sent as a system message with every prompt of the entity until it is removed. Each swap
+/// rebuilds the recap of entities with a [`Transcript`] from their latest lines, or removes it
+/// when there is nothing to replay. Insert one yourself to seed a conversation, e.g. after
+/// loading a save; on entities without a `Transcript` it is kept across swaps.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct ConversationRecap {
+    pub text: String,
+}
+
+impl ConversationRecap {
+    /// Recap of the last `max_entries` lines of `transcript`, or `None` if it is empty.
+    pub fn from_transcript(
+        transcript: &Transcript,
+        max_entries: usize,
+        templates: Option<&PromptTemplates>,
+    ) -> Option<Self> {
+        let entries = transcript.entries();
+        let recent = &entries[entries.len().saturating_sub(max_entries)..];
+        if recent.is_empty() {
+            return None;
+        }
+        let lines = recent
+            .iter()
+            .map(|e| format!("{}: {}", e.role, e.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let render = |templates: &PromptTemplates| {
+            templates.render(CONVERSATION_RECAP, &[("lines", &lines)])
+        };
+        let text = match templates {
+            Some(templates) => render(templates),
+            None => render(&PromptTemplates::default()),
+        }
+        .unwrap_or(lines);
+        Some(Self { text })
+    }
+
+    /// The recap as a system message.
+    pub fn to_message(&self) -> AiMessage {
+        AiMessage::system(&self.text)
+    }
+}
+
+impl Plugin for AiModelSwapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AiModelSwapSettings {
+            replay_entries: self.replay_entries,
+        })
+        .register_type::<AiModelSwapSettings>()
+        .add_observer(carry_over_conversations);
+    }
+}
+
+fn carry_over_conversations(
+    swap: On<AiBackendSwapped>,
+    settings: Res<AiModelSwapSettings>,
+    templates: Option<Res<PromptTemplates>>,
+    histories: Query<&ChatHistory>,
+    transcripts: Query<(Entity, &Transcript)>,
+    mut commands: Commands,
+) {
+    debug!(
+        "Backend swapped to '{}', carrying conversations over",
+        swap.model_name
+    );
+    for history in histories.iter() {
+        history.take_session();
+    }
+    // Recaps from an earlier swap are rebuilt from the transcript, which now holds more lines
+    for (entity, transcript) in transcripts.iter() {
+        let recap = (settings.replay_entries > 0)
+            .then(|| {
+                ConversationRecap::from_transcript(
+                    transcript,
+                    settings.replay_entries,
+                    templates.as_deref(),
+                )
+            })
+            .flatten();
+        match recap {
+            Some(recap) => {
+                commands.entity(entity).insert(recap);
+            }
+            None => {
+                commands.entity(entity).remove::<ConversationRecap>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recap_keeps_the_last_lines() {
+        let mut transcript = Transcript::new();
+        transcript.push_user(1, "Who are you?");
+        transcript.push_assistant(1, "The blacksmith.");
+        transcript.push_user(2, "Can you fix my sword?");
+
+        let recap = ConversationRecap::from_transcript(&transcript, 2, None).unwrap();
+        assert!(
+            recap
+                .text
+                .contains("Assistant: The blacksmith.\nUser: Can you fix my sword?")
+        );
+        assert!(!recap.text.contains("Who are you?"));
+        assert!(ConversationRecap::from_transcript(&Transcript::new(), 2, None).is_none());
+    }
+}
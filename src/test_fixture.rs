@@ -0,0 +1,214 @@
+//! A ready-made app for whole-pipeline tests.
+//!
+//! [`ai_test_app`] assembles `MinimalPlugins`, the [`AIDialoguePlugin`] with the given backend
+//! and a registered [`SampleAction`] whose runs are collected in [`SampleActionLog`].
+//! [`ScriptedAi`] answers with prepared replies and records every prompt it receives, and
+//! [`AiTestApp`] adds helpers to ask and to step the app until every request is answered.
+//!
+//! # Example
+//! ```ignore
+//! let ai = ScriptedAi::new(["Welcome!", r#"{"name": "sample_action", "params": {"target": "gate"}}"#]);
+//! let mut app = ai_test_app(ai.clone());
+//! let npc = app.spawn_ai_entity();
+//!
+//! app.ask(npc, "Hello");
+//! assert!(app.run_until_idle(100));
+//! assert_eq!(app.last_reply(npc).as_deref(), Some("Welcome!"));
+//!
+//! app.ask(npc, "Open the gate");
+//! assert!(app.run_until_idle(100));
+//! assert_eq!(app.world().resource::<SampleActionLog>().0[0].target, "gate");
+//! assert!(ai.prompts()[0].iter().any(|m| matches!(m, AiMessage::User(text) if &**text == "Hello")));
+//! ```
+
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::actions::{ActionPayload, IntoActionPayload};
+use crate::app_ext::AiAppExt;
+use crate::context::AI;
+use crate::dialogue::{
+    AIDialoguePlugin, AiResponseEvent, AiStaleResponse, DialogueReceiver, DialogueRequest,
+    DialogueRequestQueue, LocalAi,
+};
+use crate::error::AiError;
+use crate::rag::AiMessage;
+
+/// Backend answering with prepared replies, in order.
+///
+/// Requests run concurrently, so wait for a reply before asking again when the order matters.
+/// Once the script runs out it answers `scripted: <user message>`.
+#[derive(Default)]
+pub struct ScriptedAi {
+    replies: Mutex<VecDeque<Result<String, AiError>>>,
+    prompts: Mutex<Vec<Vec<AiMessage>>>,
+}
+
+impl ScriptedAi {
+    /// A backend answering with `replies`.
+    pub fn new<I, S>(replies: I) -> Arc<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Arc::new(Self {
+            replies: Mutex::new(replies.into_iter().map(|r| Ok(r.into())).collect()),
+            prompts: Mutex::default(),
+        })
+    }
+
+    /// Queue another reply.
+    pub fn push_reply(&self, reply: impl Into<String>) {
+        self.lock_replies().push_back(Ok(reply.into()));
+    }
+
+    /// Queue a failure, e.g. to test error handling.
+    pub fn push_error(&self, error: AiError) {
+        self.lock_replies().push_back(Err(error));
+    }
+
+    /// Messages of every prompt received so far, oldest first.
+    pub fn prompts(&self) -> Vec<Vec<AiMessage>> {
+        self.prompts
+            .lock()
+            .expect("ScriptedAi mutex poisoned")
+            .clone()
+    }
+
+    fn lock_replies(&self) -> std::sync::MutexGuard<'_, VecDeque<Result<String, AiError>>> {
+        self.replies.lock().expect("ScriptedAi mutex poisoned")
+    }
+}
+
+impl LocalAi for ScriptedAi {
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
+        self.prompts
+            .lock()
+            .expect("ScriptedAi mutex poisoned")
+            .push(messages.to_vec());
+        self.lock_replies().pop_front().unwrap_or_else(|| {
+            let user = messages
+                .iter()
+                .filter_map(|m| match m {
+                    AiMessage::User(text) => Some(&**text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok(format!("scripted: {}", user))
+        })
+    }
+
+    fn supports_batching(&self) -> bool {
+        true
+    }
+}
+
+/// Action registered by [`ai_test_app`]: `{"name": "sample_action", "params": {"target": ".."}}`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SampleAction {
+    pub target: String,
+}
+
+impl IntoActionPayload for SampleAction {
+    fn action_name() -> &'static str {
+        "sample_action"
+    }
+
+    fn into_action_payload(self) -> ActionPayload {
+        ActionPayload::new(Self::action_name()).with_param("target", self.target.into())
+    }
+}
+
+/// Every [`SampleAction`] run so far, oldest first.
+#[derive(Resource, Debug, Default)]
+pub struct SampleActionLog(pub Vec<SampleAction>);
+
+/// Ids of requests sent with [`AiTestApp::ask`] that have not been answered yet.
+#[derive(Resource, Debug, Default)]
+pub struct AiTestRequests {
+    pub unanswered: HashSet<u64>,
+}
+
+/// A `MinimalPlugins` app running the dialogue pipeline on `backend`.
+pub fn ai_test_app(backend: Arc<dyn LocalAi>) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(AIDialoguePlugin::with_backend(backend))
+        .init_resource::<SampleActionLog>()
+        .init_resource::<AiTestRequests>()
+        .register_ai_action::<SampleAction, _, _>(
+            |In(action): In<SampleAction>, mut log: ResMut<SampleActionLog>| {
+                log.0.push(action);
+            },
+        )
+        .add_observer(
+            |response: On<AiResponseEvent>, mut requests: ResMut<AiTestRequests>| {
+                requests.unanswered.remove(&response.request_id);
+            },
+        )
+        .add_observer(
+            |stale: On<AiStaleResponse>, mut requests: ResMut<AiTestRequests>| {
+                requests.unanswered.remove(&stale.request_id);
+            },
+        );
+    app
+}
+
+/// Test helpers for apps built with [`ai_test_app`].
+pub trait AiTestApp {
+    /// Spawn an entity that can be asked, with `AI` and a `DialogueReceiver`.
+    fn spawn_ai_entity(&mut self) -> Entity;
+
+    /// Queue a text request for `entity`. Returns the request id.
+    fn ask(&mut self, entity: Entity, prompt: impl Into<String>) -> u64;
+
+    /// Update until the request queue is empty and every [`ask`](AiTestApp::ask) is answered.
+    /// Returns `false` if that did not happen within `max_updates`.
+    fn run_until_idle(&mut self, max_updates: usize) -> bool;
+
+    /// The last reply received by `entity`.
+    fn last_reply(&self, entity: Entity) -> Option<String>;
+}
+
+impl AiTestApp for App {
+    fn spawn_ai_entity(&mut self) -> Entity {
+        self.world_mut().spawn((AI, DialogueReceiver::new())).id()
+    }
+
+    fn ask(&mut self, entity: Entity, prompt: impl Into<String>) -> u64 {
+        let request = DialogueRequest::text(entity, prompt);
+        let id = request.id;
+        self.world_mut()
+            .resource_mut::<AiTestRequests>()
+            .unanswered
+            .insert(id);
+        self.world_mut()
+            .resource_mut::<DialogueRequestQueue>()
+            .push(request);
+        id
+    }
+
+    fn run_until_idle(&mut self, max_updates: usize) -> bool {
+        for _ in 0..max_updates {
+            self.update();
+            let world = self.world();
+            if world.resource::<DialogueRequestQueue>().len() == 0
+                && world.resource::<AiTestRequests>().unanswered.is_empty()
+            {
+                return true;
+            }
+            // Replies are produced on the background runtime
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    fn last_reply(&self, entity: Entity) -> Option<String> {
+        self.world()
+            .get::<DialogueReceiver>(entity)
+            .and_then(|receiver| receiver.last_response.clone())
+    }
+}
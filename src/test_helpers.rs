@@ -87,3 +87,304 @@ pub fn ask_ai_with_context(
     set_ai_context(app, entity, contexts);
     ask_ai_and_wait(app, entity, prompt, max_updates)
 }
+
+/// Environment variable that makes [`assert_ai_schema!`](crate::assert_ai_schema) rewrite snapshots.
+pub const UPDATE_AI_SCHEMAS_ENV: &str = "UPDATE_AI_SCHEMAS";
+
+/// JSON snapshot of the schema `T` presents to the model.
+pub fn ai_schema_snapshot<T>() -> serde_json::Value
+where
+    T: crate::parse::AiParsable + crate::actions::IntoActionPayload,
+{
+    serde_json::json!({
+        "action": T::action_name(),
+        "type": T::type_name(),
+        "schema": T::schema_description(),
+        "labels": T::labels(),
+    })
+}
+
+/// Compare `T`'s schema against the snapshot at `path`.
+///
+/// A missing snapshot fails the check like a changed one. Set `UPDATE_AI_SCHEMAS=1` to write
+/// new snapshots, or rewrite them after an intentional change.
+pub fn check_ai_schema_snapshot<T>(path: &std::path::Path) -> Result<(), String>
+where
+    T: crate::parse::AiParsable + crate::actions::IntoActionPayload,
+{
+    let current = ai_schema_snapshot::<T>();
+    let pretty = serde_json::to_string_pretty(&current).map_err(|e| e.to_string())? + "\n";
+    let update = std::env::var_os(UPDATE_AI_SCHEMAS_ENV).is_some();
+
+    if update {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        std::fs::write(path, pretty)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        return Ok(());
+    }
+
+    if !path.exists() {
+        return Err(format!(
+            "No AI schema snapshot of {} at {}.\nactual:\n{}\nRe-run with {}=1 to write it.",
+            T::type_name(),
+            path.display(),
+            pretty.trim_end(),
+            UPDATE_AI_SCHEMAS_ENV
+        ));
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let snapshot: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    if snapshot == current {
+        Ok(())
+    } else {
+        Err(format!(
+            "AI schema of {} no longer matches {}.\nexpected:\n{}\nactual:\n{}\nRe-run with {}=1 if the change is intentional.",
+            T::type_name(),
+            path.display(),
+            text.trim_end(),
+            pretty.trim_end(),
+            UPDATE_AI_SCHEMAS_ENV
+        ))
+    }
+}
+
+/// Assert that the schema derived for an action type matches a checked-in snapshot.
+///
+/// The path is relative to the calling crate's manifest directory. Catches accidental field
+/// renames that would break saved prompts or tools.
+///
+/// # Example
+/// ```ignore
+/// #[test]
+/// fn spawn_action_schema_is_stable() {
+///     assert_ai_schema!(SpawnAction, "tests/fixtures/spawn_action.schema.json");
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_ai_schema {
+    ($ty:ty, $path:expr $(,)?) => {{
+        let path = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($path);
+        if let Err(e) = $crate::test_helpers::check_ai_schema_snapshot::<$ty>(&path) {
+            panic!("{}", e);
+        }
+    }};
+}
+
+/// One context message captured by [`capture_context`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextEntry {
+    /// Name of the context system that produced the message (needs bevy's default `debug`
+    /// feature; otherwise all names are a placeholder).
+    pub source: String,
+    pub text: String,
+}
+
+impl ContextEntry {
+    /// Whether this entry came from system `name`, given by full path or by its last segment.
+    pub fn is_from(&self, name: &str) -> bool {
+        self.source == name || self.source.ends_with(&format!("::{}", name))
+    }
+}
+
+/// The context gathered for one entity, with the names and positions of the world's entities
+/// so assertions can refer to entities instead of strings.
+#[derive(Debug, Clone)]
+pub struct ContextSnapshot {
+    pub requester: Entity,
+    pub entries: Vec<ContextEntry>,
+    names: Vec<(Entity, String)>,
+    positions: Vec<(Entity, Vec3)>,
+}
+
+/// Entries that differ between two snapshots; see [`ContextSnapshot::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextDiff {
+    pub added: Vec<ContextEntry>,
+    pub removed: Vec<ContextEntry>,
+}
+
+impl ContextDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Run the registered context systems for `entity` and capture what they produce.
+///
+/// The world is left as it was apart from what the systems themselves change; the entity's
+/// `AiContext` is not touched.
+///
+/// # Example
+/// ```ignore
+/// let snapshot = capture_context(&mut app, npc, Some("who is here?"));
+/// snapshot
+///     .assert_source("nearby_characters")
+///     .assert_excludes(far_away)
+///     .assert_ordered_by_distance(&[bob, alice]);
+/// ```
+pub fn capture_context(app: &mut App, entity: Entity, query: Option<&str>) -> ContextSnapshot {
+    let world = app.world_mut();
+    let entries = crate::context::run_context_systems(world, entity, query.map(str::to_string))
+        .into_iter()
+        .map(|(source, message)| ContextEntry {
+            source,
+            text: message
+                .text()
+                .map(str::to_string)
+                .unwrap_or_else(|| message.to_string()),
+        })
+        .collect();
+    let names = world
+        .query::<(Entity, &Name)>()
+        .iter(world)
+        .map(|(e, name)| (e, name.as_str().to_string()))
+        .collect();
+    let positions = world
+        .query::<(Entity, &Transform)>()
+        .iter(world)
+        .map(|(e, t)| (e, t.translation))
+        .collect();
+    ContextSnapshot {
+        requester: entity,
+        entries,
+        names,
+        positions,
+    }
+}
+
+impl ContextSnapshot {
+    /// All entries joined, one per line.
+    pub fn text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|e| e.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The entry produced by system `name`, if it produced one.
+    pub fn from_source(&self, name: &str) -> Option<&ContextEntry> {
+        self.entries.iter().find(|e| e.is_from(name))
+    }
+
+    /// Whether any entry mentions `entity` by its `Name`.
+    pub fn mentions(&self, entity: Entity) -> bool {
+        let name = self.name_of(entity);
+        self.entries.iter().any(|e| e.text.contains(name))
+    }
+
+    /// Entries added and removed going from `self` to `other`.
+    pub fn diff(&self, other: &ContextSnapshot) -> ContextDiff {
+        ContextDiff {
+            added: other
+                .entries
+                .iter()
+                .filter(|e| !self.entries.contains(e))
+                .cloned()
+                .collect(),
+            removed: self
+                .entries
+                .iter()
+                .filter(|e| !other.entries.contains(e))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Panic unless system `name` contributed an entry.
+    #[track_caller]
+    pub fn assert_source(&self, name: &str) -> &Self {
+        assert!(
+            self.from_source(name).is_some(),
+            "no context entry from '{}'\n{}",
+            name,
+            self.describe()
+        );
+        self
+    }
+
+    /// Panic unless some entry contains `text`.
+    #[track_caller]
+    pub fn assert_contains(&self, text: &str) -> &Self {
+        assert!(
+            self.entries.iter().any(|e| e.text.contains(text)),
+            "no context entry contains '{}'\n{}",
+            text,
+            self.describe()
+        );
+        self
+    }
+
+    /// Panic if any entry mentions `entity` by its `Name`.
+    #[track_caller]
+    pub fn assert_excludes(&self, entity: Entity) -> &Self {
+        assert!(
+            !self.mentions(entity),
+            "context mentions '{}'\n{}",
+            self.name_of(entity),
+            self.describe()
+        );
+        self
+    }
+
+    /// Panic unless all `entities` are mentioned, nearest to the requester first.
+    ///
+    /// `entities` may be given in any order; they are sorted by distance before checking.
+    #[track_caller]
+    pub fn assert_ordered_by_distance(&self, entities: &[Entity]) -> &Self {
+        let origin = self.position_of(self.requester);
+        let mut expected: Vec<(f32, Entity)> = entities
+            .iter()
+            .map(|&e| (self.position_of(e).distance(origin), e))
+            .collect();
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let text = self.text();
+        let mut last = 0;
+        for (_, entity) in expected {
+            let name = self.name_of(entity);
+            let Some(at) = text.find(name) else {
+                panic!("context does not mention '{}'\n{}", name, self.describe());
+            };
+            assert!(
+                at >= last,
+                "'{}' is mentioned before a nearer entity\n{}",
+                name,
+                self.describe()
+            );
+            last = at;
+        }
+        self
+    }
+
+    #[track_caller]
+    fn name_of(&self, entity: Entity) -> &str {
+        self.names
+            .iter()
+            .find(|(e, _)| *e == entity)
+            .map(|(_, name)| name.as_str())
+            .unwrap_or_else(|| panic!("{} has no Name to look for in the context", entity))
+    }
+
+    #[track_caller]
+    fn position_of(&self, entity: Entity) -> Vec3 {
+        self.positions
+            .iter()
+            .find(|(e, _)| *e == entity)
+            .map(|(_, p)| *p)
+            .unwrap_or_else(|| panic!("{} has no Transform", entity))
+    }
+
+    fn describe(&self) -> String {
+        let mut out = format!("context of {}:", self.requester);
+        for entry in &self.entries {
+            out.push_str(&format!("\n  [{}] {}", entry.source, entry.text));
+        }
+        out
+    }
+}
@@ -0,0 +1,316 @@
+//! Token counting.
+//!
+//! Anything that limits or measures prompt and reply sizes goes through the [`Tokenizer`]
+//! trait so counts match the model actually in use. The dialogue plugin keeps the current one
+//! in the [`AiTokenizer`] resource: it starts as the byte-based [`ApproxTokenizer`] and is
+//! replaced by the backend's own tokenizer (see `LocalAi::tokenizer`) once a model is loaded.
+//! For remote models, load the provider's vocabulary into a [`BpeTokenizer`].
+//!
+//! # Example
+//! ```ignore
+//! let ranks = std::fs::read_to_string("assets/o200k_base.tiktoken")?;
+//! app.insert_resource(AiTokenizer::new(BpeTokenizer::from_tiktoken(&ranks)?));
+//!
+//! fn show(tokens: Res<AiTokenizer>, q: Query<&DialogueReceiver>) {
+//!     for r in &q {
+//!         let text = r.last_response.as_deref().unwrap_or("");
+//!         info!("{} tokens", tokens.count(text));
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Counts tokens the way a specific model does.
+pub trait Tokenizer: Send + Sync + 'static {
+    /// Number of tokens in `text`.
+    fn count(&self, text: &str) -> usize;
+
+    /// Byte index at which to cut `text` so the kept prefix has at most `max_tokens` tokens.
+    /// Always a char boundary.
+    ///
+    /// The default binary-searches over char boundaries with [`Tokenizer::count`].
+    fn truncate_index(&self, text: &str, max_tokens: usize) -> usize {
+        if self.count(text) <= max_tokens {
+            return text.len();
+        }
+        // `ends[k]` is the end of the first `k` chars; find the largest `k` that fits.
+        let ends: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect();
+        let (mut lo, mut hi) = (0, ends.len() - 1);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if self.count(&text[..ends[mid]]) <= max_tokens {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        ends[lo]
+    }
+}
+
+/// Estimates tokens from the byte length, for when the model's tokenizer is unavailable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApproxTokenizer {
+    pub bytes_per_token: f32,
+}
+
+impl Default for ApproxTokenizer {
+    /// About 4 bytes per token, typical for English text.
+    fn default() -> Self {
+        Self {
+            bytes_per_token: 4.0,
+        }
+    }
+}
+
+impl Tokenizer for ApproxTokenizer {
+    fn count(&self, text: &str) -> usize {
+        (text.len() as f32 / self.bytes_per_token.max(0.01)).ceil() as usize
+    }
+
+    fn truncate_index(&self, text: &str, max_tokens: usize) -> usize {
+        let mut end = ((max_tokens as f32 * self.bytes_per_token) as usize).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        end
+    }
+}
+
+/// Tokenizer backed by a counting function, e.g. one wrapping a model's native tokenizer.
+pub struct FnTokenizer(Box<dyn Fn(&str) -> usize + Send + Sync>);
+
+impl FnTokenizer {
+    pub fn new(count: impl Fn(&str) -> usize + Send + Sync + 'static) -> Self {
+        Self(Box::new(count))
+    }
+}
+
+impl Tokenizer for FnTokenizer {
+    fn count(&self, text: &str) -> usize {
+        (self.0)(text)
+    }
+}
+
+/// Byte-level BPE tokenizer using a tiktoken rank file (`<base64 token> <rank>` per line),
+/// as published for OpenAI-style remote models.
+///
+/// Text is pre-split into words, numbers, punctuation runs and whitespace (with a leading
+/// space kept on the following word), which closely matches tiktoken's own splitting.
+#[derive(Debug, Clone)]
+pub struct BpeTokenizer {
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl BpeTokenizer {
+    /// Build from explicit byte-sequence ranks; lower ranks merge first.
+    pub fn new(ranks: HashMap<Vec<u8>, u32>) -> Self {
+        Self { ranks }
+    }
+
+    /// Parse the contents of a `.tiktoken` rank file.
+    pub fn from_tiktoken(contents: &str) -> Result<Self, String> {
+        let mut ranks = HashMap::new();
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (token, rank) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("line {}: expected '<token> <rank>'", n + 1))?;
+            let token =
+                decode_base64(token).ok_or_else(|| format!("line {}: invalid base64", n + 1))?;
+            let rank = rank
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+            ranks.insert(token, rank);
+        }
+        Ok(Self { ranks })
+    }
+
+    /// Token ids for `text`. Bytes missing from the vocabulary are skipped.
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut out = Vec::new();
+        for piece in pre_split(text) {
+            if let Some(&rank) = self.ranks.get(piece.as_bytes()) {
+                out.push(rank);
+                continue;
+            }
+            let bytes = piece.as_bytes();
+            // Byte ranges of the current parts, starting from single bytes
+            let mut parts: Vec<(usize, usize)> = (0..bytes.len()).map(|i| (i, i + 1)).collect();
+            // Merge the lowest-ranked adjacent pair until no pair is in the vocabulary
+            loop {
+                let best = parts
+                    .windows(2)
+                    .enumerate()
+                    .filter_map(|(i, w)| self.ranks.get(&bytes[w[0].0..w[1].1]).map(|&r| (r, i)))
+                    .min();
+                let Some((_, i)) = best else {
+                    break;
+                };
+                parts[i].1 = parts[i + 1].1;
+                parts.remove(i + 1);
+            }
+            out.extend(
+                parts
+                    .iter()
+                    .filter_map(|&(start, end)| self.ranks.get(&bytes[start..end]).copied()),
+            );
+        }
+        out
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn count(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum CharClass {
+    Letter,
+    Digit,
+    Space,
+    Other,
+}
+
+fn classify(c: char) -> CharClass {
+    if c.is_alphabetic() || c == '\'' {
+        CharClass::Letter
+    } else if c.is_numeric() {
+        CharClass::Digit
+    } else if c.is_whitespace() {
+        CharClass::Space
+    } else {
+        CharClass::Other
+    }
+}
+
+/// Split into runs of the same character class; a single space before a run joins that run.
+fn pre_split(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for k in 1..chars.len() {
+        let (i, c) = chars[k];
+        let (prev_i, prev) = chars[k - 1];
+        let (pc, cc) = (classify(prev), classify(c));
+        if pc == cc {
+            continue;
+        }
+        // Hand the last space of a whitespace run to the following piece
+        let split = if pc == CharClass::Space && prev == ' ' {
+            prev_i
+        } else {
+            i
+        };
+        if split > start {
+            pieces.push(&text[start..split]);
+        }
+        start = split;
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+    let bytes = input.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(bytes.len() * 3 / 4);
+    for chunk in bytes.chunks(4) {
+        let mut acc = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            acc |= value(c)? << (18 - 6 * i);
+        }
+        let n = match chunk.len() {
+            4 => 3,
+            3 => 2,
+            2 => 1,
+            _ => return None,
+        };
+        out.extend_from_slice(&acc.to_be_bytes()[1..1 + n]);
+    }
+    Some(out)
+}
+
+/// The tokenizer used by the dialogue plugin for limits and measurements.
+#[derive(Resource, Clone)]
+pub struct AiTokenizer(pub Arc<dyn Tokenizer>);
+
+impl AiTokenizer {
+    pub fn new(tokenizer: impl Tokenizer) -> Self {
+        Self(Arc::new(tokenizer))
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.0.count(text)
+    }
+
+    /// The longest prefix of `text` with at most `max_tokens` tokens.
+    pub fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        &text[..self.0.truncate_index(text, max_tokens)]
+    }
+}
+
+impl Default for AiTokenizer {
+    fn default() -> Self {
+        Self::new(ApproxTokenizer::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approx_counts_and_truncates_on_char_boundaries() {
+        let tok = ApproxTokenizer::default();
+        assert_eq!(tok.count("abcdefgh"), 2);
+        assert_eq!(tok.count("abcdefghi"), 3);
+        let text = "ééééé";
+        let end = tok.truncate_index(text, 1);
+        assert!(text.is_char_boundary(end) && end <= 4);
+    }
+
+    #[test]
+    fn default_truncation_uses_count() {
+        let words = FnTokenizer::new(|t| t.split_whitespace().count());
+        assert_eq!(
+            words.truncate_index("one two three four", 2),
+            "one two ".len()
+        );
+        assert_eq!(words.truncate_index("one two", 5), "one two".len());
+    }
+
+    #[test]
+    fn bpe_merges_by_rank() {
+        // "a"=0 "b"=1 " "=2 "ab"=3 " ab"=4
+        let ranks = "YQ== 0\nYg== 1\nIA== 2\nYWI= 3\nIGFi 4\n";
+        let bpe = BpeTokenizer::from_tiktoken(ranks).unwrap();
+        assert_eq!(bpe.encode("ab ab"), vec![3, 4]);
+        assert_eq!(bpe.encode("ba"), vec![1, 0]);
+        assert_eq!(bpe.count("ab ab"), 2);
+    }
+}
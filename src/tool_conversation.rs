@@ -0,0 +1,411 @@
+//! Multi-turn exchanges where the model uses actions as tools.
+//!
+//! A plain text request ends with the reply: its actions run, but the model never hears how
+//! they went. A [`ToolConversation`] on an AI entity keeps going instead. It sends its message,
+//! runs the actions of the reply, waits for each [`AiActionCompleted`], then sends the results
+//! back (the [`TOOL_RESULTS`] template) and repeats until the model answers without actions
+//! or [`ToolConversation::max_rounds`] is reached.
+//!
+//! [`ToolConversationState`] makes every stage explicit, and each change fires a
+//! [`ToolConversationTransition`], so a UI can show "thinking..." while waiting on the model
+//! and "opening the gate..." while a tool runs, instead of only the final text.
+//!
+//! With [`AiEarlyActions`](crate::streaming::AiEarlyActions), actions may complete before
+//! their reply arrives; they are matched once it does. A reply kept from the receiver as stale
+//! (see [`ResponsePolicy`](crate::dialogue::ResponsePolicy)) ends the conversation without
+//! running its actions, and so does a request dropped by the
+//! [`AiRateLimiter`](crate::rate_limit::AiRateLimiter) or deduplication.
+//!
+//! An action held for approval by [`AiActionApproval`](crate::actions::AiActionApproval)
+//! waits for the decision; a rejected one completes with an error, which is fed back to the
+//! model like any other failure.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(ToolConversationPlugin);
+//!
+//! commands.entity(smith).insert(ToolConversation::new("Can you repair my sword?"));
+//!
+//! app.add_observer(|step: On<ToolConversationTransition>, mut ui: ResMut<StatusLine>| {
+//!     ui.text = match &step.state {
+//!         ToolConversationState::AwaitingModel | ToolConversationState::AwaitingFollowUp => {
+//!             "Thinking...".into()
+//!         }
+//!         ToolConversationState::ExecutingTool(name) => format!("Using {}...", name),
+//!         ToolConversationState::Done => String::new(),
+//!     };
+//! });
+//! ```
+//!
+//! [`TOOL_RESULTS`]: crate::prompts::TOOL_RESULTS
+
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::actions::ActionPayload;
+use crate::completion::AiActionCompleted;
+use crate::dialogue::{
+    AiRequestDeduplicated, AiResponseEvent, AiStaleResponse, DialogueRequest, DialogueRequestQueue,
+    render_prompt,
+};
+use crate::prompts::{PromptTemplates, TOOL_RESULTS};
+use crate::rate_limit::AiRequestThrottled;
+
+/// Plugin driving [`ToolConversation`]s, see the [module docs](self).
+pub struct ToolConversationPlugin;
+
+impl Plugin for ToolConversationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            start_tool_conversations.before(crate::dialogue::AiSystemSet::HandleRequests),
+        )
+        .add_observer(on_tool_reply)
+        .add_observer(on_tool_stale)
+        .add_observer(on_tool_throttled)
+        .add_observer(on_tool_deduplicated)
+        .add_observer(on_tool_completed);
+    }
+}
+
+/// Stage of a [`ToolConversation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolConversationState {
+    /// Waiting for the model's reply to the message.
+    AwaitingModel,
+    /// Waiting for the named action of the reply to complete.
+    ExecutingTool(String),
+    /// Waiting for the model's reply to the action results.
+    AwaitingFollowUp,
+    /// The model answered without actions, or no rounds are left.
+    Done,
+}
+
+/// Outcome of an action the model asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolResult {
+    pub action: ActionPayload,
+    pub result: Result<(), String>,
+}
+
+/// Component running an exchange with the entity's model, see the [module docs](self).
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct ToolConversation {
+    pub message: String,
+    /// Follow-up requests sent at most. Actions of the last reply still run, but their results
+    /// are not sent back.
+    pub max_rounds: u32,
+    state: ToolConversationState,
+    /// Follow-up requests sent so far.
+    rounds: u32,
+    /// Request whose reply is awaited, `None` until the message is sent.
+    request_id: Option<u64>,
+    /// Actions of the last reply that have not completed yet, in order.
+    running: VecDeque<ActionPayload>,
+    /// Every action completed so far.
+    results: Vec<ToolResult>,
+    /// Results not sent to the model yet.
+    unsent: usize,
+    /// Actions completed while a reply was awaited, matched against it once it arrives.
+    early: Vec<ToolResult>,
+    reply: Option<String>,
+}
+
+impl ToolConversation {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            max_rounds: 3,
+            state: ToolConversationState::AwaitingModel,
+            rounds: 0,
+            request_id: None,
+            running: VecDeque::new(),
+            results: Vec::new(),
+            unsent: 0,
+            early: Vec::new(),
+            reply: None,
+        }
+    }
+
+    pub fn with_max_rounds(mut self, max_rounds: u32) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    pub fn state(&self) -> &ToolConversationState {
+        &self.state
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == ToolConversationState::Done
+    }
+
+    /// Every action completed so far, in completion order.
+    pub fn results(&self) -> &[ToolResult] {
+        &self.results
+    }
+
+    /// The model's last reply text. Once done, its final answer.
+    pub fn reply(&self) -> Option<&str> {
+        self.reply.as_deref()
+    }
+
+    /// Move to `state`, returning the transition event.
+    fn transition(
+        &mut self,
+        entity: Entity,
+        state: ToolConversationState,
+    ) -> ToolConversationTransition {
+        let previous = std::mem::replace(&mut self.state, state.clone());
+        ToolConversationTransition {
+            entity,
+            previous: Some(previous),
+            state,
+        }
+    }
+
+    /// Record the result of a running action, returning whether it was one.
+    fn record(&mut self, result: ToolResult) -> bool {
+        let Some(index) = self.running.iter().position(|a| *a == result.action) else {
+            return false;
+        };
+        self.running.remove(index);
+        self.results.push(result);
+        self.unsent += 1;
+        true
+    }
+
+    /// The stage after a recorded result: the next running action, else the results are sent
+    /// back while rounds are left.
+    fn advance(
+        &mut self,
+        entity: Entity,
+        queue: &mut DialogueRequestQueue,
+        templates: Option<&PromptTemplates>,
+    ) -> ToolConversationState {
+        if let Some(action) = self.running.front() {
+            ToolConversationState::ExecutingTool(action.name.clone())
+        } else if self.rounds < self.max_rounds {
+            let message = self.follow_up(templates);
+            let request = DialogueRequest::text(entity, message);
+            self.request_id = Some(request.id);
+            self.rounds += 1;
+            queue.push(request);
+            ToolConversationState::AwaitingFollowUp
+        } else {
+            ToolConversationState::Done
+        }
+    }
+
+    /// The follow-up message listing the results not sent yet.
+    fn follow_up(&mut self, templates: Option<&PromptTemplates>) -> String {
+        let results = self.results[self.results.len() - self.unsent..]
+            .iter()
+            .map(|r| {
+                let outcome = match &r.result {
+                    Ok(()) => "done".to_string(),
+                    Err(reason) => format!("failed: {}", reason),
+                };
+                if r.action.params.is_null() {
+                    format!("- {}: {}", r.action.name, outcome)
+                } else {
+                    format!("- {} {}: {}", r.action.name, r.action.params, outcome)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.unsent = 0;
+        render_prompt(templates, TOOL_RESULTS, &[("results", &results)])
+            .unwrap_or_else(|_| format!("Results of the actions you took:\n{}", results))
+    }
+}
+
+/// Event fired when a [`ToolConversation`] changes stage.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ToolConversationTransition {
+    pub entity: Entity,
+    /// The stage left, `None` when the conversation starts.
+    pub previous: Option<ToolConversationState>,
+    pub state: ToolConversationState,
+}
+
+/// Send the message of conversations that have not started.
+fn start_tool_conversations(
+    mut conversations: Query<(Entity, &mut ToolConversation)>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    mut commands: Commands,
+) {
+    for (entity, mut conversation) in conversations.iter_mut() {
+        if conversation.request_id.is_some() || conversation.is_done() {
+            continue;
+        }
+        let request = DialogueRequest::text(entity, conversation.message.clone());
+        conversation.request_id = Some(request.id);
+        queue.push(request);
+        commands.trigger(ToolConversationTransition {
+            entity,
+            previous: None,
+            state: ToolConversationState::AwaitingModel,
+        });
+    }
+}
+
+/// Start running the reply's actions, or finish when there are none.
+fn on_tool_reply(
+    response: On<AiResponseEvent>,
+    mut conversations: Query<&mut ToolConversation>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    templates: Option<Res<PromptTemplates>>,
+    mut commands: Commands,
+) {
+    let Ok(mut conversation) = conversations.get_mut(response.entity) else {
+        return;
+    };
+    if conversation.request_id != Some(response.request_id)
+        || !matches!(
+            conversation.state,
+            ToolConversationState::AwaitingModel | ToolConversationState::AwaitingFollowUp
+        )
+    {
+        return;
+    }
+    conversation.reply = Some(response.text.clone());
+    conversation.running = response.actions.iter().cloned().collect();
+    for result in std::mem::take(&mut conversation.early) {
+        conversation.record(result);
+    }
+    let next = if response.actions.is_empty() {
+        ToolConversationState::Done
+    } else {
+        conversation.advance(response.entity, &mut queue, templates.as_deref())
+    };
+    commands.trigger(conversation.transition(response.entity, next));
+}
+
+/// End a conversation whose awaited reply went stale.
+fn on_tool_stale(
+    stale: On<AiStaleResponse>,
+    mut conversations: Query<&mut ToolConversation>,
+    mut commands: Commands,
+) {
+    end_awaiting(
+        &mut conversations,
+        stale.entity,
+        stale.request_id,
+        "went stale",
+        &mut commands,
+    );
+}
+
+/// End a conversation whose awaited request was throttled.
+fn on_tool_throttled(
+    throttled: On<AiRequestThrottled>,
+    mut conversations: Query<&mut ToolConversation>,
+    mut commands: Commands,
+) {
+    end_awaiting(
+        &mut conversations,
+        throttled.entity,
+        throttled.request_id,
+        "was throttled",
+        &mut commands,
+    );
+}
+
+/// End a conversation whose awaited request was dropped as a duplicate.
+fn on_tool_deduplicated(
+    deduplicated: On<AiRequestDeduplicated>,
+    mut conversations: Query<&mut ToolConversation>,
+    mut commands: Commands,
+) {
+    end_awaiting(
+        &mut conversations,
+        deduplicated.entity,
+        deduplicated.request_id,
+        "was dropped as a duplicate",
+        &mut commands,
+    );
+}
+
+/// End `entity`'s conversation if it awaits the reply to `request_id`, which will not come.
+fn end_awaiting(
+    conversations: &mut Query<&mut ToolConversation>,
+    entity: Entity,
+    request_id: u64,
+    why: &str,
+    commands: &mut Commands,
+) {
+    let Ok(mut conversation) = conversations.get_mut(entity) else {
+        return;
+    };
+    if conversation.request_id != Some(request_id) || conversation.is_done() {
+        return;
+    }
+    debug!(
+        "Ending tool conversation of {:?}: request {} {}",
+        entity, request_id, why
+    );
+    commands.trigger(conversation.transition(entity, ToolConversationState::Done));
+}
+
+/// Record the result of a running action; once all completed, send the results back.
+fn on_tool_completed(
+    completed: On<AiActionCompleted>,
+    mut conversations: Query<&mut ToolConversation>,
+    mut queue: ResMut<DialogueRequestQueue>,
+    templates: Option<Res<PromptTemplates>>,
+    mut commands: Commands,
+) {
+    let Ok(mut conversation) = conversations.get_mut(completed.entity) else {
+        return;
+    };
+    let result = ToolResult {
+        action: completed.action.clone(),
+        result: completed.result.clone(),
+    };
+    match conversation.state {
+        ToolConversationState::ExecutingTool(_) => {}
+        // Dispatched early, before the reply listing it arrived
+        ToolConversationState::AwaitingModel | ToolConversationState::AwaitingFollowUp
+            if conversation.request_id.is_some() =>
+        {
+            conversation.early.push(result);
+            return;
+        }
+        _ => return,
+    }
+    if !conversation.record(result) {
+        return;
+    }
+
+    let entity = completed.entity;
+    let next = conversation.advance(entity, &mut queue, templates.as_deref());
+    if conversation.state != next {
+        commands.trigger(conversation.transition(entity, next));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_up_lists_unsent_results() {
+        let mut conversation = ToolConversation::new("Open the gate");
+        conversation.results = vec![
+            ToolResult {
+                action: ActionPayload::new("wave"),
+                result: Ok(()),
+            },
+            ToolResult {
+                action: ActionPayload::new("open_gate").with_param("gate", "north".into()),
+                result: Err("the gate is locked".into()),
+            },
+        ];
+        conversation.unsent = 1;
+        let message = conversation.follow_up(None);
+        assert!(message.contains("- open_gate {\"gate\":\"north\"}: failed: the gate is locked"));
+        assert!(!message.contains("wave"));
+        assert_eq!(conversation.unsent, 0);
+    }
+}
@@ -0,0 +1,171 @@
+//! Structured conversation transcripts.
+//!
+//! The kalosm chat session stored in [`ChatHistory`](crate::rag::ChatHistory) is opaque and
+//! `DialogueReceiver::last_response` only keeps the final reply. Add a [`Transcript`] component
+//! to an AI entity and the dialogue plugin will append every prompt and reply to it, so games
+//! can render chat logs or export conversations.
+//!
+//! # Example
+//! ```ignore
+//! commands.spawn((AI, DialogueReceiver::new(), Transcript::default()));
+//!
+//! fn show_log(q: Query<&Transcript, Changed<Transcript>>) {
+//!     for transcript in q.iter() {
+//!         println!("{}", transcript.to_plain_text());
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Who produced a transcript entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum TranscriptRole {
+    /// The prompt sent on behalf of the player or game.
+    User,
+    /// The AI reply.
+    Assistant,
+}
+
+impl std::fmt::Display for TranscriptRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranscriptRole::User => write!(f, "User"),
+            TranscriptRole::Assistant => write!(f, "Assistant"),
+        }
+    }
+}
+
+/// A single line of a conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TranscriptEntry {
+    pub role: TranscriptRole,
+    pub text: String,
+    /// Wall-clock time the entry was recorded.
+    pub timestamp: SystemTime,
+    /// Id of the `DialogueRequest` this entry belongs to.
+    pub request_id: u64,
+}
+
+/// Component recording every prompt and reply for an AI entity.
+///
+/// Only entities carrying this component are recorded. Set `max_entries` to keep
+/// the log bounded; the oldest entries are dropped first.
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+    /// Optional cap on the number of stored entries.
+    pub max_entries: Option<usize>,
+}
+
+impl Transcript {
+    /// Create an empty, unbounded transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty transcript that keeps at most `max_entries` entries.
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            max_entries: Some(max_entries),
+        }
+    }
+
+    /// Append an entry, trimming the oldest entries if over capacity.
+    pub fn push(&mut self, role: TranscriptRole, request_id: u64, text: impl Into<String>) {
+        self.entries.push(TranscriptEntry {
+            role,
+            text: text.into(),
+            timestamp: SystemTime::now(),
+            request_id,
+        });
+        if let Some(max) = self.max_entries
+            && self.entries.len() > max
+        {
+            let excess = self.entries.len() - max;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Append a user prompt.
+    pub fn push_user(&mut self, request_id: u64, text: impl Into<String>) {
+        self.push(TranscriptRole::User, request_id, text);
+    }
+
+    /// Append an AI reply.
+    pub fn push_assistant(&mut self, request_id: u64, text: impl Into<String>) {
+        self.push(TranscriptRole::Assistant, request_id, text);
+    }
+
+    /// All recorded entries, oldest first.
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    /// The most recent entry, if any.
+    pub fn last(&self) -> Option<&TranscriptEntry> {
+        self.entries.last()
+    }
+
+    /// Entries belonging to a given request (usually one prompt and one reply).
+    pub fn for_request(&self, request_id: u64) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.request_id == request_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Render the conversation as "Role: text" lines.
+    pub fn to_plain_text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|e| format!("{}: {}", e.role, e.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Export the transcript as pretty-printed JSON, tagged with the wire format version.
+    pub fn to_json(&self) -> Result<String, String> {
+        let doc = VersionedTranscript {
+            version: Some(crate::wire::WIRE_FORMAT_VERSION),
+            transcript: self.clone(),
+        };
+        serde_json::to_string_pretty(&doc).map_err(|e| e.to_string())
+    }
+
+    /// Load a transcript written by [`Transcript::to_json`].
+    ///
+    /// Files written before versioning was added (no `version` field) are accepted as-is.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let doc: VersionedTranscript = serde_json::from_str(text)
+            .map_err(|e| format!("Failed to decode transcript: {}", e))?;
+        if let Some(version) = doc.version {
+            crate::wire::check_version(version)?;
+        }
+        Ok(doc.transcript)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VersionedTranscript {
+    #[serde(default)]
+    version: Option<u32>,
+    #[serde(flatten)]
+    transcript: Transcript,
+}
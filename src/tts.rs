@@ -0,0 +1,335 @@
+//! Speak AI replies aloud.
+//!
+//! Requires the `tts` feature. Add [`AiTextToSpeechPlugin`] with a [`TextToSpeech`] engine and
+//! put an [`AiSpeaker`] on every AI entity that should talk. Each new reply on the entity's
+//! `DialogueReceiver` is split into sentences, synthesized in the background one sentence at a
+//! time and played through `bevy_audio`, so playback starts as soon as the first sentence is
+//! ready instead of after the whole reply. The voice comes from the entity's
+//! [`AiPersona::voice`](crate::persona::AiPersona), falling back to [`AiVoice::default`].
+//!
+//! [`SpeechStarted`] and [`SpeechEnded`] fire around every spoken sentence, e.g. to animate mouths.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiTextToSpeechPlugin::new(MyPiperEngine::load("voices/")?));
+//!
+//! commands.spawn((
+//!     AI,
+//!     DialogueReceiver::new(),
+//!     AiPersona::new("Mira", "A cheerful merchant").with_voice(AiVoice::new("en_GB-alba")),
+//!     AiSpeaker::default(),
+//! ));
+//!
+//! app.add_observer(|started: On<SpeechStarted>, mut mouths: Query<&mut Mouth>| {
+//!     if let Ok(mut mouth) = mouths.get_mut(started.entity) {
+//!         mouth.talking = true;
+//!     }
+//! });
+//! ```
+
+use bevy::prelude::*;
+use flume::{Receiver, Sender, unbounded};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::dialogue::DialogueReceiver;
+use crate::persona::{AiPersona, AiVoice};
+
+/// A synthesized clip of interleaved PCM samples in `-1.0..=1.0`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SpeechClip {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl SpeechClip {
+    pub fn new(samples: Vec<f32>, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            channels,
+        }
+    }
+
+    pub fn mono(samples: Vec<f32>, sample_rate: u32) -> Self {
+        Self::new(samples, sample_rate, 1)
+    }
+
+    /// Encode the clip as a 16-bit PCM WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len = (self.samples.len() * 2) as u32;
+        let block_align = self.channels * 2;
+        let mut out = Vec::with_capacity(44 + data_len as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&self.channels.to_le_bytes());
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        out.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        out.extend_from_slice(&block_align.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for s in &self.samples {
+            let v = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// A speech synthesis engine. Called from a background thread.
+pub trait TextToSpeech: Send + Sync + 'static {
+    fn synthesize(&self, text: &str, voice: &AiVoice) -> Result<SpeechClip, String>;
+}
+
+/// The engine used by [`AiTextToSpeechPlugin`].
+#[derive(Resource, Clone)]
+pub struct AiTextToSpeech(pub Arc<dyn TextToSpeech>);
+
+/// Speaks new replies of this entity's `DialogueReceiver` aloud.
+#[derive(Component, Debug, Default)]
+pub struct AiSpeaker {
+    /// Request id of the last reply handed to the engine.
+    last_spoken: Option<u64>,
+    queue: VecDeque<QueuedSentence>,
+    playing: Option<(Entity, u64)>,
+}
+
+impl AiSpeaker {
+    /// Whether a sentence is playing or waiting to be played.
+    pub fn is_speaking(&self) -> bool {
+        self.playing.is_some() || !self.queue.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct QueuedSentence {
+    request_id: u64,
+    text: String,
+    audio: Handle<AudioSource>,
+}
+
+/// Marks the audio entity playing a sentence.
+#[derive(Component)]
+struct SpeechPlayback;
+
+/// Fired when a sentence starts playing for `entity`.
+#[derive(Event, Debug, Clone)]
+#[non_exhaustive]
+pub struct SpeechStarted {
+    pub entity: Entity,
+    pub request_id: u64,
+    pub text: String,
+}
+
+/// Fired when a sentence finished playing for `entity`.
+#[derive(Event, Debug, Clone)]
+#[non_exhaustive]
+pub struct SpeechEnded {
+    pub entity: Entity,
+    pub request_id: u64,
+}
+
+struct SynthesizedSentence {
+    entity: Entity,
+    request_id: u64,
+    text: String,
+    clip: Result<SpeechClip, String>,
+}
+
+#[derive(Resource)]
+struct SpeechChannel {
+    tx: Sender<SynthesizedSentence>,
+    rx: Receiver<SynthesizedSentence>,
+}
+
+/// Plugin speaking replies of [`AiSpeaker`] entities with a [`TextToSpeech`] engine.
+pub struct AiTextToSpeechPlugin {
+    engine: Arc<dyn TextToSpeech>,
+}
+
+impl AiTextToSpeechPlugin {
+    pub fn new(engine: impl TextToSpeech) -> Self {
+        Self {
+            engine: Arc::new(engine),
+        }
+    }
+}
+
+impl Plugin for AiTextToSpeechPlugin {
+    fn build(&self, app: &mut App) {
+        let (tx, rx) = unbounded();
+        app.insert_resource(AiTextToSpeech(self.engine.clone()))
+            .insert_resource(SpeechChannel { tx, rx })
+            .register_type::<AiVoice>()
+            .add_systems(
+                PostUpdate,
+                (synthesize_replies, receive_sentences, play_sentences).chain(),
+            );
+    }
+}
+
+/// Split a reply into sentences, keeping the closing punctuation.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        current.push(c);
+        let boundary = matches!(c, '.' | '!' | '?' | '\n')
+            && chars.peek().is_none_or(|next| next.is_whitespace());
+        if boundary {
+            let sentence = current.trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+            current.clear();
+        }
+    }
+    let rest = current.trim();
+    if !rest.is_empty() {
+        sentences.push(rest.to_string());
+    }
+    sentences
+}
+
+fn synthesize_replies(
+    tts: Res<AiTextToSpeech>,
+    channel: Res<SpeechChannel>,
+    mut speakers: Query<
+        (
+            Entity,
+            &DialogueReceiver,
+            &mut AiSpeaker,
+            Option<&AiPersona>,
+        ),
+        Changed<DialogueReceiver>,
+    >,
+) {
+    for (entity, receiver, mut speaker, persona) in speakers.iter_mut() {
+        let (Some(text), Some(request_id)) = (&receiver.last_response, receiver.last_request_id)
+        else {
+            continue;
+        };
+        if speaker.last_spoken == Some(request_id) || text.starts_with("(ai error") {
+            continue;
+        }
+        speaker.last_spoken = Some(request_id);
+
+        let engine = tts.0.clone();
+        let tx = channel.tx.clone();
+        let voice = persona.and_then(|p| p.voice.clone()).unwrap_or_default();
+        let sentences = split_sentences(text);
+        // Sentences are synthesized in order and sent one by one so playback can start early.
+        crate::models::TOKIO_RUNTIME.spawn_blocking(move || {
+            for text in sentences {
+                let clip = engine.synthesize(&text, &voice);
+                let sent = tx.send(SynthesizedSentence {
+                    entity,
+                    request_id,
+                    text,
+                    clip,
+                });
+                if sent.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+fn receive_sentences(
+    channel: Res<SpeechChannel>,
+    mut audio: ResMut<Assets<AudioSource>>,
+    mut speakers: Query<&mut AiSpeaker>,
+) {
+    for sentence in channel.rx.try_iter() {
+        let clip = match sentence.clip {
+            Ok(clip) => clip,
+            Err(e) => {
+                warn!("Speech synthesis failed for {:?}: {}", sentence.entity, e);
+                continue;
+            }
+        };
+        let Ok(mut speaker) = speakers.get_mut(sentence.entity) else {
+            continue;
+        };
+        let handle = audio.add(AudioSource {
+            bytes: clip.to_wav().into(),
+        });
+        speaker.queue.push_back(QueuedSentence {
+            request_id: sentence.request_id,
+            text: sentence.text,
+            audio: handle,
+        });
+    }
+}
+
+fn play_sentences(
+    mut commands: Commands,
+    mut speakers: Query<(Entity, &mut AiSpeaker)>,
+    playbacks: Query<(), With<SpeechPlayback>>,
+) {
+    for (entity, mut speaker) in speakers.iter_mut() {
+        // `PlaybackSettings::DESPAWN` removes the audio entity once the clip finished.
+        if let Some((playback, request_id)) = speaker.playing
+            && playbacks.get(playback).is_err()
+        {
+            speaker.playing = None;
+            commands.trigger(SpeechEnded { entity, request_id });
+        }
+        if speaker.playing.is_some() {
+            continue;
+        }
+        let Some(next) = speaker.queue.pop_front() else {
+            continue;
+        };
+        let playback = commands
+            .spawn((
+                SpeechPlayback,
+                AudioPlayer::new(next.audio),
+                PlaybackSettings::DESPAWN,
+                ChildOf(entity),
+            ))
+            .id();
+        speaker.playing = Some((playback, next.request_id));
+        commands.trigger(SpeechStarted {
+            entity,
+            request_id: next.request_id,
+            text: next.text,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sentences_on_terminal_punctuation() {
+        assert_eq!(
+            split_sentences("Hello there! Need a sword? We have 2.5 swords.\nBye"),
+            vec![
+                "Hello there!",
+                "Need a sword?",
+                "We have 2.5 swords.",
+                "Bye"
+            ]
+        );
+        assert!(split_sentences("   ").is_empty());
+    }
+
+    #[test]
+    fn wav_header_matches_clip() {
+        let wav = SpeechClip::mono(vec![0.0, 1.0, -1.0], 22_050).to_wav();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 22_050);
+        assert_eq!(wav.len(), 44 + 6);
+    }
+}
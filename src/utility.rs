@@ -0,0 +1,250 @@
+//! Context-free prompts for tooling and non-dialogue features.
+//!
+//! Summarizing a patch note or classifying a bug report has nothing to do with an entity, a
+//! persona or gathered context. [`AiUtility::complete`] sends the prompt as-is (without the
+//! default context) and returns an [`AiUtilityTask`] to poll for the reply. Requests still go
+//! through the [`AiUtilityPlugin`] limits: at most `max_in_flight` run at once, new ones start
+//! at least `min_interval` apart, and repeated prompts are answered from a small cache.
+//! [`AiUtilityStats`] counts what happened.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins(AiUtilityPlugin::default());
+//!
+//! fn summarize(mut ai: AiUtility, mut task: Local<Option<AiUtilityTask>>, notes: Res<PatchNotes>) {
+//!     let task = task.get_or_insert_with(|| ai.complete(format!("Summarize in one line:\n{}", notes.0)));
+//!     if let Some(Ok(summary)) = task.poll() {
+//!         info!("{}", summary);
+//!     }
+//! }
+//! ```
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use flume::{Receiver, Sender, TryRecvError, bounded};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::dialogue::{AiSystemSet, LocalAiHandle};
+use crate::error::AiError;
+use crate::rag::AiMessage;
+
+/// Plugin running utility prompts with the given limits.
+pub struct AiUtilityPlugin {
+    pub max_in_flight: usize,
+    pub min_interval: Duration,
+    /// Number of replies kept for repeated prompts; `0` disables the cache.
+    pub cache_size: usize,
+}
+
+impl Default for AiUtilityPlugin {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 2,
+            min_interval: Duration::ZERO,
+            cache_size: 64,
+        }
+    }
+}
+
+/// Limits applied to utility prompts, editable at runtime.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiUtilityLimits {
+    /// Prompts sent to the backend at the same time.
+    pub max_in_flight: usize,
+    /// Minimum time between the start of two prompts.
+    pub min_interval: Duration,
+    pub cache_size: usize,
+}
+
+/// Counters for utility prompts.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct AiUtilityStats {
+    /// Prompts sent to the backend.
+    pub requests: u64,
+    /// Prompts answered from the cache.
+    pub cache_hits: u64,
+    /// Prompts that failed.
+    pub failures: u64,
+    /// Prompts waiting for a free slot.
+    pub queued: usize,
+    /// Prompts the backend is working on.
+    pub in_flight: usize,
+}
+
+/// Handle to a prompt started with [`AiUtility::complete`].
+pub struct AiUtilityTask {
+    rx: Receiver<Result<String, AiError>>,
+    finished: bool,
+}
+
+impl AiUtilityTask {
+    /// The reply once it is ready. Returns `None` while waiting and after the reply was taken.
+    pub fn poll(&mut self) -> Option<Result<String, AiError>> {
+        if self.finished {
+            return None;
+        }
+        let result = match self.rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(AiError::Cancelled),
+        };
+        self.finished = true;
+        Some(result)
+    }
+
+    /// Whether the reply has already been returned by `poll`.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+struct UtilityJob {
+    prompt: String,
+    reply: Sender<Result<String, AiError>>,
+}
+
+/// Waiting and running utility prompts, plus the reply cache.
+#[derive(Resource, Default)]
+pub struct AiUtilityQueue {
+    waiting: VecDeque<UtilityJob>,
+    running: Vec<(UtilityJob, Receiver<Result<String, AiError>>)>,
+    cache: HashMap<String, String>,
+    /// Cached prompts, oldest first.
+    cache_order: VecDeque<String>,
+    next_start: Duration,
+}
+
+impl AiUtilityQueue {
+    fn cached(&self, prompt: &str) -> Option<String> {
+        self.cache.get(prompt).cloned()
+    }
+
+    fn remember(&mut self, prompt: String, reply: String, capacity: usize) {
+        if capacity == 0 || self.cache.contains_key(&prompt) {
+            return;
+        }
+        while self.cache_order.len() >= capacity {
+            if let Some(oldest) = self.cache_order.pop_front() {
+                self.cache.remove(&oldest);
+            }
+        }
+        self.cache_order.push_back(prompt.clone());
+        self.cache.insert(prompt, reply);
+    }
+
+    /// Forget all cached replies.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+        self.cache_order.clear();
+    }
+}
+
+/// System parameter for context-free prompts.
+#[derive(SystemParam)]
+pub struct AiUtility<'w> {
+    queue: ResMut<'w, AiUtilityQueue>,
+    stats: ResMut<'w, AiUtilityStats>,
+}
+
+impl AiUtility<'_> {
+    /// Send `prompt` to the backend without any entity, persona or context.
+    pub fn complete(&mut self, prompt: impl Into<String>) -> AiUtilityTask {
+        let prompt = prompt.into();
+        let (tx, rx) = bounded(1);
+        if let Some(reply) = self.queue.cached(&prompt) {
+            self.stats.cache_hits += 1;
+            let _ = tx.send(Ok(reply));
+        } else {
+            self.queue
+                .waiting
+                .push_back(UtilityJob { prompt, reply: tx });
+            self.stats.queued = self.queue.waiting.len();
+        }
+        AiUtilityTask {
+            rx,
+            finished: false,
+        }
+    }
+}
+
+impl Plugin for AiUtilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AiUtilityLimits {
+            max_in_flight: self.max_in_flight,
+            min_interval: self.min_interval,
+            cache_size: self.cache_size,
+        })
+        .init_resource::<AiUtilityQueue>()
+        .init_resource::<AiUtilityStats>()
+        .register_type::<AiUtilityLimits>()
+        .register_type::<AiUtilityStats>()
+        .add_systems(
+            Update,
+            run_utility_requests.in_set(AiSystemSet::HandleRequests),
+        );
+    }
+}
+
+fn run_utility_requests(
+    time: Res<Time<Real>>,
+    handle: Option<Res<LocalAiHandle>>,
+    limits: Res<AiUtilityLimits>,
+    mut queue: ResMut<AiUtilityQueue>,
+    mut stats: ResMut<AiUtilityStats>,
+) {
+    // Hand finished replies to their tasks
+    let running = std::mem::take(&mut queue.running);
+    for (job, rx) in running {
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => {
+                queue.running.push((job, rx));
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => Err(AiError::Cancelled),
+        };
+        match &result {
+            Ok(reply) => queue.remember(job.prompt.clone(), reply.clone(), limits.cache_size),
+            Err(_) => stats.failures += 1,
+        }
+        let _ = job.reply.send(result);
+    }
+
+    // Start waiting prompts as the limits allow
+    let now = time.elapsed();
+    let backend = handle.and_then(|h| h.backend.clone());
+    while let Some(backend) = &backend
+        && queue.running.len() < limits.max_in_flight
+        && now >= queue.next_start
+    {
+        let Some(job) = queue.waiting.pop_front() else {
+            break;
+        };
+        // The task was dropped before the prompt started
+        if job.reply.is_disconnected() {
+            continue;
+        }
+        // An identical prompt may have finished while this one waited
+        if let Some(reply) = queue.cached(&job.prompt) {
+            stats.cache_hits += 1;
+            let _ = job.reply.send(Ok(reply));
+            continue;
+        }
+        let (tx, rx) = bounded(1);
+        let backend = backend.clone();
+        let prompt = job.prompt.clone();
+        crate::models::TOKIO_RUNTIME.spawn(async move {
+            let messages = [AiMessage::skip_default_context(), AiMessage::user(&prompt)];
+            let _ = tx.send(backend.prompt(&messages));
+        });
+        queue.running.push((job, rx));
+        queue.next_start = now + limits.min_interval;
+        stats.requests += 1;
+    }
+
+    stats.queued = queue.waiting.len();
+    stats.in_flight = queue.running.len();
+}
@@ -0,0 +1,110 @@
+//! Embedding indexes saved to disk.
+//!
+//! Embedding a large lore set takes a while, so [`DocumentStore`](crate::documents::DocumentStore)
+//! and [`SemanticMemory`](crate::memory::SemanticMemory) can write their entries to a single
+//! JSON file and read them back on the next launch. The file records the format version and
+//! an [`EmbedderFingerprint`]; vectors from another embedder mean nothing to the current one,
+//! so loading a file written by a different model (or an older format) fails and the caller
+//! starts from an empty index instead.
+//!
+//! # Example
+//! ```ignore
+//! let mut store = DocumentStore::default();
+//! if let Err(e) = store.load_index("cache/lore.index.json", &embedder) {
+//!     info!("Re-embedding lore: {}", e);
+//! }
+//! // Documents unchanged since the save are not embedded again
+//! store.insert(&embedder, "lore/history.lore.md", &history)?;
+//! store.save_index("cache/lore.index.json", &embedder)?;
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::embedding::AiEmbedder;
+
+/// Version of the index file layout. Files with another version are not loaded.
+pub const INDEX_FORMAT: u32 = 1;
+
+/// What produced the vectors in an index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbedderFingerprint {
+    /// [`LocalEmbedder::model_id`](crate::embedding::LocalEmbedder::model_id) of the backend.
+    pub model_id: String,
+    /// Length of its vectors.
+    pub dimensions: usize,
+}
+
+impl EmbedderFingerprint {
+    /// Fingerprint of `embedder`, embedding a probe text to learn its dimensions.
+    pub fn of(embedder: &AiEmbedder) -> Result<Self, String> {
+        Ok(Self {
+            model_id: embedder.backend().model_id(),
+            dimensions: embedder.embed("fingerprint")?.len(),
+        })
+    }
+}
+
+/// Layout of an index file.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct IndexFile<T> {
+    pub format: u32,
+    pub embedder: EmbedderFingerprint,
+    pub entries: T,
+}
+
+/// Write `entries` with the format version and `embedder`'s fingerprint, creating parent
+/// directories.
+pub(crate) fn write_index<T: Serialize>(
+    path: impl AsRef<Path>,
+    embedder: &AiEmbedder,
+    entries: T,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let file = IndexFile {
+        format: INDEX_FORMAT,
+        embedder: EmbedderFingerprint::of(embedder)?,
+        entries,
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let text = serde_json::to_string(&file).map_err(|e| e.to_string())?;
+    std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Read entries written by [`write_index`], failing if the format or embedder differ.
+pub(crate) fn read_index<T: for<'de> Deserialize<'de>>(
+    path: impl AsRef<Path>,
+    embedder: &AiEmbedder,
+) -> Result<T, String> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    // Check the header before the entries, whose layout may have changed
+    let header: IndexFile<serde::de::IgnoredAny> = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    if header.format != INDEX_FORMAT {
+        return Err(format!(
+            "{} has index format {}, expected {}",
+            path.display(),
+            header.format,
+            INDEX_FORMAT
+        ));
+    }
+    let current = EmbedderFingerprint::of(embedder)?;
+    if header.embedder != current {
+        return Err(format!(
+            "{} was embedded by {} ({} dimensions), not {} ({} dimensions)",
+            path.display(),
+            header.embedder.model_id,
+            header.embedder.dimensions,
+            current.model_id,
+            current.dimensions
+        ));
+    }
+    let file: IndexFile<T> = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    Ok(file.entries)
+}
@@ -0,0 +1,306 @@
+//! Versioned wire format for dialogue requests, responses and actions.
+//!
+//! In-memory types like [`DialogueResponse`] and [`AiActionEvent`] may change between crate
+//! versions. Anything persisted or sent over the network (transcripts, recorded sessions,
+//! replays, multiplayer) should use the serde representations here instead: each record
+//! carries a `version` so old recordings keep loading after upgrades.
+//!
+//! Entities are stored as `Entity::to_bits`; when loading into a different world, remap them.
+//!
+//! # Example
+//! ```ignore
+//! let line = wire::encode_response(&response)?;
+//! // ... later, possibly after a crate upgrade:
+//! let response = wire::decode_response(&line)?;
+//! ```
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::{ActionPayload, AiActionEvent};
+use crate::attribution::ResponseOrigin;
+use crate::clarify::NeedsClarification;
+use crate::dialogue::{AiResponseEvent, DialogueRequest, DialogueRequestKind, DialogueResponse};
+
+/// Current wire format version, written into every record.
+pub const WIRE_FORMAT_VERSION: u32 = 1;
+
+/// Fail if `version` is not a format this build can read.
+pub fn check_version(version: u32) -> Result<(), String> {
+    if version == 0 || version > WIRE_FORMAT_VERSION {
+        Err(format!(
+            "Unsupported wire format version {} (this build reads 1..={})",
+            version, WIRE_FORMAT_VERSION
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn entity_from_bits(bits: u64) -> Result<Entity, String> {
+    Entity::try_from_bits(bits).ok_or_else(|| format!("Invalid entity bits {}", bits))
+}
+
+/// Stable representation of a [`DialogueRequest`], e.g. sent by a client to an AI server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WireDialogueRequest {
+    pub version: u32,
+    pub request_id: u64,
+    /// `Entity::to_bits` of the AI entity that should answer.
+    pub entity: u64,
+    pub kind: DialogueRequestKind,
+    /// Whether the message is raw player input to be sanitized.
+    #[serde(default)]
+    pub player_text: bool,
+}
+
+impl From<&DialogueRequest> for WireDialogueRequest {
+    fn from(req: &DialogueRequest) -> Self {
+        Self {
+            version: WIRE_FORMAT_VERSION,
+            request_id: req.id,
+            entity: req.entity.to_bits(),
+            kind: req.kind.clone(),
+            player_text: req.player_text,
+        }
+    }
+}
+
+impl WireDialogueRequest {
+    /// Convert back into a [`DialogueRequest`], checking the version.
+    /// The request id is kept so the sender can match the response.
+    pub fn into_request(self) -> Result<DialogueRequest, String> {
+        check_version(self.version)?;
+        Ok(DialogueRequest {
+            id: self.request_id,
+            entity: entity_from_bits(self.entity)?,
+            kind: self.kind,
+            player_text: self.player_text,
+        })
+    }
+}
+
+/// Stable representation of a [`DialogueResponse`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WireDialogueResponse {
+    pub version: u32,
+    pub request_id: u64,
+    /// `Entity::to_bits` of the responding entity.
+    pub entity: u64,
+    pub response: String,
+    pub kind: DialogueRequestKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actions: Option<Vec<ActionPayload>>,
+    /// Records written before the origin was tracked read as `Generated`.
+    #[serde(default)]
+    pub origin: ResponseOrigin,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clarification: Option<NeedsClarification>,
+    /// Set when `response` is an `(ai error: ...)` message instead of an answer.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub error: bool,
+}
+
+impl From<&DialogueResponse> for WireDialogueResponse {
+    fn from(resp: &DialogueResponse) -> Self {
+        Self {
+            version: WIRE_FORMAT_VERSION,
+            request_id: resp.request_id,
+            entity: resp.entity.to_bits(),
+            response: resp.response.clone(),
+            kind: resp.kind.clone(),
+            actions: resp.actions.clone(),
+            origin: resp.origin,
+            clarification: resp.clarification.clone(),
+            error: resp.error,
+        }
+    }
+}
+
+impl From<&AiResponseEvent> for WireDialogueResponse {
+    fn from(event: &AiResponseEvent) -> Self {
+        Self {
+            version: WIRE_FORMAT_VERSION,
+            request_id: event.request_id,
+            entity: event.entity.to_bits(),
+            response: event.text.clone(),
+            kind: event.kind.clone(),
+            actions: Some(event.actions.clone()),
+            origin: event.origin,
+            clarification: event.clarification.clone(),
+            error: event.error,
+        }
+    }
+}
+
+impl WireDialogueResponse {
+    /// Convert back into a [`DialogueResponse`], checking the version.
+    pub fn into_response(self) -> Result<DialogueResponse, String> {
+        check_version(self.version)?;
+        Ok(DialogueResponse {
+            request_id: self.request_id,
+            entity: entity_from_bits(self.entity)?,
+            response: self.response,
+            kind: self.kind,
+            actions: self.actions,
+            oversized: None,
+            origin: self.origin,
+            clarification: self.clarification,
+            blocked: None,
+            dispatched: 0,
+            error: self.error,
+        })
+    }
+}
+
+/// Stable representation of an [`AiActionEvent`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WireActionEvent {
+    pub version: u32,
+    /// `Entity::to_bits` of the target entity.
+    pub entity: u64,
+    pub action: ActionPayload,
+}
+
+impl From<&AiActionEvent> for WireActionEvent {
+    fn from(event: &AiActionEvent) -> Self {
+        Self {
+            version: WIRE_FORMAT_VERSION,
+            entity: event.entity.to_bits(),
+            action: event.action.clone(),
+        }
+    }
+}
+
+impl WireActionEvent {
+    /// Convert back into an [`AiActionEvent`], checking the version.
+    pub fn into_event(self) -> Result<AiActionEvent, String> {
+        check_version(self.version)?;
+        Ok(AiActionEvent {
+            entity: entity_from_bits(self.entity)?,
+            action: self.action,
+        })
+    }
+}
+
+/// Serialize a request as a single JSON line.
+pub fn encode_request(req: &DialogueRequest) -> Result<String, String> {
+    serde_json::to_string(&WireDialogueRequest::from(req)).map_err(|e| e.to_string())
+}
+
+/// Deserialize a request written by [`encode_request`] (any supported version).
+pub fn decode_request(text: &str) -> Result<DialogueRequest, String> {
+    serde_json::from_str::<WireDialogueRequest>(text)
+        .map_err(|e| format!("Failed to decode dialogue request: {}", e))?
+        .into_request()
+}
+
+/// Serialize a response as a single JSON line.
+pub fn encode_response(resp: &DialogueResponse) -> Result<String, String> {
+    serde_json::to_string(&WireDialogueResponse::from(resp)).map_err(|e| e.to_string())
+}
+
+/// Deserialize a response written by [`encode_response`] (any supported version).
+pub fn decode_response(text: &str) -> Result<DialogueResponse, String> {
+    serde_json::from_str::<WireDialogueResponse>(text)
+        .map_err(|e| format!("Failed to decode dialogue response: {}", e))?
+        .into_response()
+}
+
+/// Serialize an action event as a single JSON line.
+pub fn encode_action(event: &AiActionEvent) -> Result<String, String> {
+    serde_json::to_string(&WireActionEvent::from(event)).map_err(|e| e.to_string())
+}
+
+/// Deserialize an action event written by [`encode_action`] (any supported version).
+pub fn decode_action(text: &str) -> Result<AiActionEvent, String> {
+    serde_json::from_str::<WireActionEvent>(text)
+        .map_err(|e| format!("Failed to decode action event: {}", e))?
+        .into_event()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_round_trips_and_rejects_future_versions() {
+        let resp = DialogueResponse {
+            request_id: 7,
+            entity: Entity::from_bits(42),
+            response: "{\"x\":1}".to_string(),
+            kind: DialogueRequestKind::Typed {
+                user_message: "move".to_string(),
+                schema_description: "x".to_string(),
+                action_name: "move_to".to_string(),
+            },
+            actions: Some(vec![
+                ActionPayload::new("move_to").with_param("x", serde_json::json!(1)),
+            ]),
+            oversized: None,
+            origin: ResponseOrigin::Authored,
+            clarification: None,
+            blocked: None,
+            dispatched: 0,
+            error: false,
+        };
+        let line = encode_response(&resp).unwrap();
+        assert!(line.contains("\"version\":1"));
+        let back = decode_response(&line).unwrap();
+        assert_eq!(back.request_id, 7);
+        assert_eq!(back.entity, resp.entity);
+        assert_eq!(back.kind, resp.kind);
+        assert_eq!(back.actions, resp.actions);
+        assert_eq!(back.origin, ResponseOrigin::Authored);
+        let legacy = line.replace(",\"origin\":\"authored\"", "");
+        assert_eq!(
+            decode_response(&legacy).unwrap().origin,
+            ResponseOrigin::Generated
+        );
+
+        let future = line.replace("\"version\":1", "\"version\":99");
+        assert!(decode_response(&future).is_err());
+    }
+
+    #[test]
+    fn request_round_trips() {
+        let req = DialogueRequest::text_no_context(Entity::from_bits(9), "hi");
+        let back = decode_request(&encode_request(&req).unwrap()).unwrap();
+        assert_eq!(back.id, req.id);
+        assert_eq!(back.entity, req.entity);
+        assert_eq!(back.kind, req.kind);
+    }
+
+    #[test]
+    fn action_event_round_trips() {
+        let event = AiActionEvent {
+            entity: Entity::from_bits(5),
+            action: ActionPayload::new("wave"),
+        };
+        let back = decode_action(&encode_action(&event).unwrap()).unwrap();
+        assert_eq!(back.entity, event.entity);
+        assert_eq!(back.action, event.action);
+    }
+
+    #[test]
+    fn transcript_json_is_versioned_and_accepts_legacy_files() {
+        let mut transcript = crate::transcript::Transcript::new();
+        transcript.push_user(1, "hi");
+        transcript.push_assistant(1, "hello");
+        let json = transcript.to_json().unwrap();
+        assert!(json.contains("\"version\": 1"));
+        let back = crate::transcript::Transcript::from_json(&json).unwrap();
+        assert_eq!(back.entries(), transcript.entries());
+
+        let legacy = serde_json::to_string(&transcript).unwrap();
+        assert_eq!(
+            crate::transcript::Transcript::from_json(&legacy)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let future = json.replace("\"version\": 1", "\"version\": 2");
+        assert!(crate::transcript::Transcript::from_json(&future).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Bridge between authored Yarn Spinner dialogue and AI-generated lines.
+//!
+//! Requires the `yarnspinner` feature. Add [`AiYarnBridgePlugin`] next to `YarnSpinnerPlugin`
+//! and put an [`AiYarnBridge`] on every `DialogueRunner` entity that may call the AI. The
+//! runner then gets an `ai_line` command:
+//!
+//! ```yarn
+//! <<set $greeting = "Welcome, traveler.">>
+//! <<ai_line "$greeting" "Greet the player, mention the storm last night">>
+//! Mira: {$greeting}
+//! <<if $ai_action == "give_item">>
+//!     <<give {$ai_action_item}>>
+//! <<endif>>
+//! ```
+//!
+//! The runner waits until the reply arrives and stores it in the named variable. On failure the
+//! variable keeps its previous value, so the authored line doubles as a fallback. Error and stale
+//! replies, throttled requests and requests without a reply within [`AiYarnBridge::timeout`]
+//! count as failures, so the runner never waits forever. Actions the AI
+//! emitted with the reply are still executed by the registered handlers, and the first one is
+//! also exposed to the script as `$ai_action` plus one `$ai_action_<param>` per scalar parameter,
+//! so authored branches and commands can react to them.
+//!
+//! # Example
+//! ```ignore
+//! app.add_plugins((YarnSpinnerPlugin::new(), AiYarnBridgePlugin));
+//!
+//! let runner = project.create_dialogue_runner(&mut commands);
+//! commands.entity(npc).insert((runner, AiYarnBridge::default()));
+//! ```
+
+use bevy::prelude::*;
+use bevy_yarnspinner::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::actions::ActionPayload;
+use crate::dialogue::{AiResponseEvent, AiStaleResponse, DialogueRequest, DialogueRequestQueue};
+use crate::rate_limit::AiRequestThrottled;
+
+/// Variable holding the name of the first action of the last AI line (empty if none).
+pub const AI_ACTION_VARIABLE: &str = "$ai_action";
+
+/// Lets the `DialogueRunner` on this entity call the AI through Yarn commands.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct AiYarnBridge {
+    /// AI entity answering the runner's requests. `None` uses the runner entity itself, which
+    /// then needs `AI` and `DialogueReceiver` components.
+    pub ai: Option<Entity>,
+    /// Time the runner waits for a line before continuing without it.
+    pub timeout: Duration,
+}
+
+impl Default for AiYarnBridge {
+    fn default() -> Self {
+        Self {
+            ai: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl AiYarnBridge {
+    /// Send the runner's requests to another entity.
+    pub fn to(ai: Entity) -> Self {
+        Self {
+            ai: Some(ai),
+            ..default()
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+struct PendingLine {
+    runner: Entity,
+    variable: String,
+    done: Arc<AtomicBool>,
+    /// `Time<Real>` elapsed after which the runner stops waiting.
+    expires_at: Duration,
+}
+
+/// AI lines the runners are waiting for, keyed by request id.
+#[derive(Resource, Default)]
+struct PendingYarnLines(HashMap<u64, PendingLine>);
+
+/// Plugin registering the AI commands on bridged dialogue runners.
+pub struct AiYarnBridgePlugin;
+
+impl Plugin for AiYarnBridgePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingYarnLines>()
+            .add_systems(Update, (register_ai_commands, expire_yarn_lines))
+            .add_observer(resolve_yarn_lines)
+            .add_observer(fail_stale_yarn_lines)
+            .add_observer(fail_throttled_yarn_lines);
+    }
+}
+
+fn register_ai_commands(
+    mut runners: Query<
+        (Entity, &mut DialogueRunner),
+        (
+            With<AiYarnBridge>,
+            Or<(Added<DialogueRunner>, Added<AiYarnBridge>)>,
+        ),
+    >,
+) {
+    for (runner, mut dialogue_runner) in runners.iter_mut() {
+        let ai_line = move |In((variable, prompt)): In<(String, String)>,
+                            bridges: Query<&AiYarnBridge>,
+                            time: Res<Time<Real>>,
+                            mut queue: ResMut<DialogueRequestQueue>,
+                            mut pending: ResMut<PendingYarnLines>|
+              -> Arc<AtomicBool> {
+            let done = Arc::new(AtomicBool::new(false));
+            let bridge = bridges.get(runner).copied().unwrap_or_default();
+            let request = DialogueRequest::text(bridge.ai.unwrap_or(runner), prompt);
+            pending.0.insert(
+                request.id,
+                PendingLine {
+                    runner,
+                    variable: yarn_variable(&variable),
+                    done: done.clone(),
+                    expires_at: time.elapsed() + bridge.timeout,
+                },
+            );
+            queue.push(request);
+            done
+        };
+        dialogue_runner
+            .commands_mut()
+            .add_command("ai_line", ai_line);
+    }
+}
+
+fn resolve_yarn_lines(
+    event: On<AiResponseEvent>,
+    mut pending: ResMut<PendingYarnLines>,
+    mut runners: Query<&mut DialogueRunner>,
+) {
+    let event = event.event();
+    let Some(line) = pending.0.remove(&event.request_id) else {
+        return;
+    };
+    let reply = if event.error {
+        Err(event.text.as_str())
+    } else {
+        Ok((event.text.as_str(), event.actions.first()))
+    };
+    resume_line(line, &mut runners, reply);
+}
+
+fn fail_stale_yarn_lines(
+    stale: On<AiStaleResponse>,
+    mut pending: ResMut<PendingYarnLines>,
+    mut runners: Query<&mut DialogueRunner>,
+) {
+    if let Some(line) = pending.0.remove(&stale.request_id) {
+        resume_line(line, &mut runners, Err("the reply was stale"));
+    }
+}
+
+fn fail_throttled_yarn_lines(
+    throttled: On<AiRequestThrottled>,
+    mut pending: ResMut<PendingYarnLines>,
+    mut runners: Query<&mut DialogueRunner>,
+) {
+    if let Some(line) = pending.0.remove(&throttled.request_id) {
+        resume_line(line, &mut runners, Err("the request was throttled"));
+    }
+}
+
+/// Let runners continue when their line did not arrive in time.
+fn expire_yarn_lines(
+    time: Res<Time<Real>>,
+    mut pending: ResMut<PendingYarnLines>,
+    mut runners: Query<&mut DialogueRunner>,
+) {
+    let now = time.elapsed();
+    let expired: Vec<u64> = pending
+        .0
+        .iter()
+        .filter(|(_, line)| line.expires_at <= now)
+        .map(|(id, _)| *id)
+        .collect();
+    for id in expired {
+        if let Some(line) = pending.0.remove(&id) {
+            resume_line(line, &mut runners, Err("no reply arrived in time"));
+        }
+    }
+}
+
+/// Store the line and its first action in the runner's variables, or only clear the action
+/// when `reply` failed, then let the runner continue.
+fn resume_line(
+    line: PendingLine,
+    runners: &mut Query<&mut DialogueRunner>,
+    reply: Result<(&str, Option<&ActionPayload>), &str>,
+) {
+    if let Ok(mut runner) = runners.get_mut(line.runner) {
+        let mut values = Vec::new();
+        let action = match reply {
+            Ok((text, action)) => {
+                values.push((line.variable.clone(), YarnValue::from(text.to_string())));
+                action
+            }
+            Err(reason) => {
+                warn!("AI line for {} failed: {}", line.variable, reason);
+                None
+            }
+        };
+        values.extend(action_variables(action));
+
+        let storage = runner.variable_storage_mut();
+        for (name, value) in values {
+            if let Err(e) = storage.set(name.clone(), value) {
+                warn!("Could not set Yarn variable {}: {}", name, e);
+            }
+        }
+    }
+    // Let the runner continue even if it was despawned or the variable couldn't be set.
+    line.done.store(true, Ordering::Relaxed);
+}
+
+/// Yarn variable names always start with `$`; accept them with or without it.
+fn yarn_variable(name: &str) -> String {
+    let name = name.trim();
+    if name.starts_with('$') {
+        name.to_string()
+    } else {
+        format!("${}", name)
+    }
+}
+
+/// `$ai_action` and one `$ai_action_<param>` per string, number or bool parameter.
+fn action_variables(action: Option<&ActionPayload>) -> Vec<(String, YarnValue)> {
+    let Some(action) = action else {
+        return vec![(
+            AI_ACTION_VARIABLE.to_string(),
+            YarnValue::from(String::new()),
+        )];
+    };
+    let mut values = vec![(
+        AI_ACTION_VARIABLE.to_string(),
+        YarnValue::from(action.name.clone()),
+    )];
+    if let Some(params) = action.params.as_object() {
+        for (key, value) in params {
+            let value = match value {
+                serde_json::Value::String(s) => YarnValue::from(s.clone()),
+                serde_json::Value::Number(n) => match n.as_f64() {
+                    Some(n) => YarnValue::from(n as f32),
+                    None => continue,
+                },
+                serde_json::Value::Bool(b) => YarnValue::from(*b),
+                _ => continue,
+            };
+            values.push((format!("{}_{}", AI_ACTION_VARIABLE, key), value));
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_names_get_a_dollar_prefix() {
+        assert_eq!(yarn_variable("greeting"), "$greeting");
+        assert_eq!(yarn_variable(" $greeting "), "$greeting");
+    }
+
+    #[test]
+    fn first_action_becomes_variables() {
+        let action = ActionPayload {
+            name: "give_item".to_string(),
+            params: serde_json::json!({"item": "sword", "count": 2, "nested": {"x": 1}}),
+        };
+        let vars: HashMap<String, YarnValue> =
+            action_variables(Some(&action)).into_iter().collect();
+        assert_eq!(vars["$ai_action"], YarnValue::from("give_item".to_string()));
+        assert_eq!(
+            vars["$ai_action_item"],
+            YarnValue::from("sword".to_string())
+        );
+        assert_eq!(vars["$ai_action_count"], YarnValue::from(2.0f32));
+        assert!(!vars.contains_key("$ai_action_nested"));
+
+        assert_eq!(
+            action_variables(None),
+            vec![("$ai_action".to_string(), YarnValue::from(String::new()))]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use bevy_real_ai::prelude::*;
+use std::sync::Arc;
+
+struct UpperAi;
+impl LocalAi for UpperAi {
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
+        match messages.last() {
+            Some(AiMessage::User(text)) if text.contains("fail") => {
+                Err(AiError::Backend("boom".to_string()))
+            }
+            Some(AiMessage::User(text)) => Ok(text.to_uppercase()),
+            _ => Ok(String::new()),
+        }
+    }
+}
+
+/// A fresh directory for one test, so tests running in parallel never share files.
+fn temp_dir(test: &str) -> std::path::PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("bevy_real_ai_bake_{}_{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn baker_runs_jobs_and_round_trips_file() {
+    let baked = ContentBaker::new(Arc::new(UpperAi))
+        .with_max_parallel(3)
+        .text("a", "hello")
+        .text("b", "world")
+        .text("c", "please fail")
+        .run()
+        .expect("unique keys");
+
+    assert_eq!(baked.get_text("a"), Some("HELLO"));
+    assert_eq!(baked.get_text("b"), Some("WORLD"));
+    assert_eq!(baked.failed().count(), 1);
+
+    let dir = temp_dir("round_trip");
+    let path = dir.join("content.json");
+    baked.save(&path).expect("save");
+    let loaded = BakedContent::load(&path).expect("load");
+    assert_eq!(loaded, baked);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn duplicate_keys_are_rejected() {
+    let baker = ContentBaker::new(Arc::new(UpperAi))
+        .text("greeting", "hello")
+        .text("greeting", "hi there");
+    let err = baker.run().unwrap_err();
+    assert!(err.contains("greeting"));
+
+    let dir = temp_dir("duplicates");
+    assert!(baker.run_to_file(dir.join("content.json")).is_err());
+    assert!(!dir.exists());
+}
+
+#[test]
+fn verify_detects_configuration_drift() {
+    let fingerprint = BakeFingerprint::new("test-model", Some(7));
+    let baker = ContentBaker::new(Arc::new(UpperAi))
+        .with_fingerprint(fingerprint.clone())
+        .text("a", "hello")
+        .text("b", "world");
+    let baked = baker.run().unwrap();
+    assert!(baker.verify(&baked).is_empty());
+
+    // Same jobs, different seed and an edited prompt
+    let changed = ContentBaker::new(Arc::new(UpperAi))
+        .with_fingerprint(BakeFingerprint::new("test-model", Some(8)))
+        .text("a", "hello there")
+        .text("c", "new");
+    let drift = changed.verify(&baked);
+    assert!(drift.contains(&BakeDrift::SeedChanged {
+        baked: Some(7),
+        current: Some(8)
+    }));
+    assert!(drift.contains(&BakeDrift::PromptChanged { key: "a".into() }));
+    assert!(drift.contains(&BakeDrift::Missing { key: "c".into() }));
+    assert!(drift.contains(&BakeDrift::Stale { key: "b".into() }));
+
+    // Without a fingerprint model changes cannot be checked, which is not a missing seed
+    let unfingerprinted = ContentBaker::new(Arc::new(UpperAi))
+        .text("a", "hello")
+        .text("b", "world");
+    assert_eq!(
+        unfingerprinted.verify(&baked),
+        [BakeDrift::NoCurrentFingerprint]
+    );
+}
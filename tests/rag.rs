@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 struct EchoAi;
 impl LocalAi for EchoAi {
-    fn prompt(&self, messages: &[AiMessage]) -> Result<String, String> {
+    fn prompt(&self, messages: &[AiMessage]) -> Result<String, AiError> {
         // Render messages via Debug so tests can assert they contain system/user pieces
         let combined = messages
             .iter()
@@ -39,3 +39,147 @@ fn retrieval_augmented_ai_includes_context_in_prompt() {
         .expect("expected response");
     assert!(resp.contains("tavern"));
 }
+
+#[test]
+fn cloned_context_messages_share_their_text() {
+    let mut context = bevy_real_ai::rag::AiContext::new();
+    context.add_context("A very long description of the surrounding area.");
+
+    let copy = context.messages().to_vec();
+    match (&context.messages()[0], &copy[0]) {
+        (AiMessage::System(a), AiMessage::System(b)) => assert!(Arc::ptr_eq(a, b)),
+        other => panic!("unexpected messages: {:?}", other),
+    }
+    assert_eq!(
+        copy[0].text(),
+        Some("A very long description of the surrounding area.")
+    );
+}
+
+/// Embeds every text the same way, like a model that can't tell names apart.
+struct BlurryEmbedder;
+impl LocalEmbedder for BlurryEmbedder {
+    fn embed(&self, _text: &str) -> Result<Vec<f32>, String> {
+        Ok(vec![1.0, 1.0])
+    }
+}
+
+#[test]
+fn hybrid_recall_finds_names_embeddings_miss() {
+    let embedder = AiEmbedder::new(Arc::new(BlurryEmbedder));
+    let mut memory = SemanticMemory::new();
+    for text in [
+        "The smith sold a lantern to a traveller.",
+        "Greta hid the key under the mill.",
+        "A traveller asked about the road north.",
+    ] {
+        memory
+            .remember(&embedder, text, serde_json::Value::Null)
+            .unwrap();
+    }
+
+    let found = memory.recall(&embedder, "What did Greta hide?", 1).unwrap();
+    assert_eq!(found[0].entry.text, "Greta hid the key under the mill.");
+
+    memory.keyword_weight = 0.0;
+    let found = memory.recall(&embedder, "What did Greta hide?", 3).unwrap();
+    assert!(found.iter().all(|m| (m.score - 1.0).abs() < 1e-6));
+}
+
+#[test]
+fn keyword_recall_follows_trimmed_memories() {
+    let embedder = AiEmbedder::new(Arc::new(BlurryEmbedder));
+    let mut memory = SemanticMemory::new().with_max_entries(2);
+    for text in [
+        "The smith sold a lantern to a traveller.",
+        "Greta hid the key under the mill.",
+        "A traveller asked about the road north.",
+    ] {
+        memory
+            .remember(&embedder, text, serde_json::Value::Null)
+            .unwrap();
+    }
+
+    // The oldest memory was dropped, its word counts with it
+    let found = memory.recall(&embedder, "What did Greta hide?", 1).unwrap();
+    assert_eq!(found[0].entry.text, "Greta hid the key under the mill.");
+    let found = memory.recall(&embedder, "Who sold a lantern?", 2).unwrap();
+    assert!(found.iter().all(|m| m.score == found[0].score));
+}
+
+/// Counts embedded texts, with a configurable vector length.
+struct CountingEmbedder {
+    dimensions: usize,
+    embedded: std::sync::atomic::AtomicUsize,
+}
+impl LocalEmbedder for CountingEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        self.embedded
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(vec![text.len() as f32; self.dimensions])
+    }
+}
+
+#[test]
+fn saved_indexes_skip_re_embedding_until_the_embedder_changes() {
+    let counting = Arc::new(CountingEmbedder {
+        dimensions: 4,
+        embedded: Default::default(),
+    });
+    let embedder = AiEmbedder::new(counting.clone());
+    let embedded = || counting.embedded.load(std::sync::atomic::Ordering::SeqCst);
+    let dir = std::env::temp_dir().join(format!("ai_index_{}", std::process::id()));
+    let lore = AiDocument::parse("# Greta\nGreta runs the inn.\n\n# Mill\nThe mill is old.");
+
+    let mut store = DocumentStore::default();
+    assert_eq!(store.insert(&embedder, "lore/town.md", &lore), Ok(2));
+    store.save_index(dir.join("lore.json"), &embedder).unwrap();
+
+    let mut reloaded = DocumentStore::default();
+    assert_eq!(reloaded.load_index(dir.join("lore.json"), &embedder), Ok(2));
+    let before = embedded();
+    assert_eq!(reloaded.insert(&embedder, "lore/town.md", &lore), Ok(2));
+    assert_eq!(embedded(), before);
+    assert_eq!(reloaded.chunks(), store.chunks());
+
+    // An edited document is embedded again
+    let edited = AiDocument::parse("# Greta\nGreta sold the inn.");
+    assert_eq!(reloaded.insert(&embedder, "lore/town.md", &edited), Ok(1));
+    assert_eq!(embedded(), before + 1);
+
+    // Vectors from another embedder are not reused
+    let other = AiEmbedder::new(Arc::new(CountingEmbedder {
+        dimensions: 8,
+        embedded: Default::default(),
+    }));
+    let err = DocumentStore::default()
+        .load_index(dir.join("lore.json"), &other)
+        .unwrap_err();
+    assert!(err.contains("4 dimensions"), "got: {}", err);
+
+    let mut memory = SemanticMemory::new();
+    memory
+        .remember(
+            &embedder,
+            "Greta owes me gold.",
+            serde_json::json!({ "kind": "debt" }),
+        )
+        .unwrap();
+    memory
+        .save_index(dir.join("memory.json"), &embedder)
+        .unwrap();
+    let mut restored = SemanticMemory::new();
+    assert_eq!(
+        restored.load_index(dir.join("memory.json"), &embedder),
+        Ok(1)
+    );
+    assert_eq!(restored.entries(), memory.entries());
+    assert!(
+        restored
+            .load_index(dir.join("memory.json"), &other)
+            .is_err()
+    );
+    assert_eq!(restored.len(), 1);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use bevy_real_ai::dialogue::DialogueRequest;
+use bevy_real_ai::prelude::*;
+
+#[test]
+fn headless_server_answers_wire_requests() {
+    let mut app = App::new();
+    app.add_plugins(AiServerPlugin::new(AIDialoguePlugin::default()));
+    let npc = app.world_mut().spawn((AI, DialogueReceiver::new())).id();
+
+    // Round-trip through JSON like a network layer would
+    let line = bevy_real_ai::wire::encode_request(&DialogueRequest::text(npc, "Hello")).unwrap();
+    let request: WireDialogueRequest = serde_json::from_str(&line).unwrap();
+    let client_id = request.request_id;
+    let request_id = app
+        .world_mut()
+        .resource_mut::<AiServerInbox>()
+        .push(request);
+    // The server numbers requests itself, whatever id the client sent
+    assert_ne!(request_id, client_id);
+
+    let mut replies = Vec::new();
+    for _ in 0..50 {
+        app.update();
+        replies.extend(app.world_mut().resource_mut::<AiServerOutbox>().drain());
+        if !replies.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].request_id, request_id);
+    assert_eq!(replies[0].entity, npc.to_bits());
+    assert!(!replies[0].response.is_empty());
+}
+
+#[test]
+fn server_drops_requests_with_unsupported_versions() {
+    let mut app = App::new();
+    app.add_plugins(AiServerPlugin::new(AIDialoguePlugin::default()));
+    let npc = app.world_mut().spawn((AI, DialogueReceiver::new())).id();
+
+    let mut request = WireDialogueRequest::from(&DialogueRequest::text(npc, "Hello"));
+    request.version = 99;
+    app.world_mut()
+        .resource_mut::<AiServerInbox>()
+        .push(request);
+    app.update();
+
+    assert!(app.world().resource::<AiServerInbox>().is_empty());
+    let queue = app
+        .world()
+        .resource::<bevy_real_ai::dialogue::DialogueRequestQueue>();
+    assert_eq!(queue.len(), 0);
+}
@@ -4,7 +4,7 @@
 
 use bevy::prelude::Entity;
 use bevy_real_ai::AiAction;
-use bevy_real_ai::actions::{PendingAiActions, prompt_typed_action};
+use bevy_real_ai::actions::{IntoActionPayload, PendingAiActions, prompt_typed_action};
 use bevy_real_ai::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -123,3 +123,391 @@ fn parse_extracts_json_from_various_formats() {
     assert_eq!(result.x, -5);
     assert_eq!(result.y, 15);
 }
+
+/// Shared field group, flattened into actions below.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+struct MoveTo {
+    #[ai(flatten)]
+    #[serde(flatten)]
+    pub position: Position,
+    pub speed: f32,
+}
+
+#[test]
+fn flattened_fields_join_the_parent_schema_and_payload() {
+    let schema = MoveTo::schema_description();
+    assert!(schema.contains("\"x\": <number>"));
+    assert!(schema.contains("\"y\": <number>"));
+    assert!(schema.contains("\"speed\": <number>"));
+    assert!(!schema.contains("position"));
+
+    let parsed = MoveTo::parse_from_ai_response(r#"{"x": 1.0, "y": 2.0, "speed": 3.0}"#)
+        .expect("flattened fields should parse");
+    assert_eq!(parsed.position, Position { x: 1.0, y: 2.0 });
+
+    let payload = parsed.into_action_payload();
+    assert_eq!(
+        payload.params,
+        serde_json::json!({"x": 1.0, "y": 2.0, "speed": 3.0})
+    );
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+enum Pace {
+    Walk,
+    Run,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+struct Patrol {
+    pub start: Position,
+    pub waypoints: Vec<Position>,
+    pub pace: Option<Pace>,
+}
+
+#[test]
+fn nested_and_array_fields_describe_their_contents() {
+    let schema = Patrol::schema_description();
+    assert!(schema.contains(r#""start": <{"x": <number>, "y": <number>}>"#));
+    assert!(schema.contains(r#""waypoints": <array of {"x": <number>, "y": <number>}>"#));
+    assert!(schema.contains(r#""pace": <one of "Walk", "Run", optional>"#));
+
+    let parsed = Patrol::parse_from_ai_response(
+        r#"{"start": {"x": 0, "y": 0}, "waypoints": [{"x": 1, "y": 2}], "pace": "Run"}"#,
+    )
+    .expect("nested fields should parse");
+    assert_eq!(parsed.waypoints, vec![Position { x: 1.0, y: 2.0 }]);
+    assert_eq!(parsed.pace, Some(Pace::Run));
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+struct TagItem {
+    pub item: String,
+    #[ai(optional)]
+    pub tags: Vec<String>,
+    #[ai(default = 1)]
+    pub count: u32,
+    pub note: Option<String>,
+}
+
+#[test]
+fn optional_and_defaulted_fields_may_be_left_out() {
+    let schema = TagItem::schema_description();
+    assert!(schema.contains(r#""item": <string>"#));
+    assert!(schema.contains(r#""tags": <array of string, optional>"#));
+    assert!(schema.contains(r#""count": <integer, optional, default 1>"#));
+    assert!(schema.contains(r#""note": <string, optional>"#));
+
+    let parsed = TagItem::parse_from_ai_response(r#"Sure: {"item": "rope"}"#)
+        .expect("missing optional fields should be filled");
+    assert_eq!(parsed.item, "rope");
+    assert!(parsed.tags.is_empty());
+    assert_eq!(parsed.count, 1);
+    assert_eq!(parsed.note, None);
+
+    let parsed = TagItem::parse_from_ai_response(r#"{"item": "rope", "count": 3}"#).unwrap();
+    assert_eq!(parsed.count, 3);
+    assert!(TagItem::parse_from_ai_response(r#"{"count": 3}"#).is_err());
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+struct PaintWall {
+    #[ai(one_of("red", "green", "blue"))]
+    pub color: String,
+    #[ai(range(min = 0.0, max = 100.0))]
+    pub coverage: f32,
+    #[ai(regex = "^[A-Z][0-9]$")]
+    pub walls: Vec<String>,
+}
+
+#[test]
+fn checked_fields_are_described_and_validated() {
+    let schema = PaintWall::schema_description();
+    assert!(schema.contains(r#""color": <string, one of "red", "green", "blue">"#));
+    assert!(schema.contains(r#""coverage": <number, between 0 and 100>"#));
+    assert!(schema.contains(r#""walls": <array of string, matching ^[A-Z][0-9]$>"#));
+
+    let parsed = PaintWall::parse_from_ai_response(
+        r#"{"color": "green", "coverage": 55.5, "walls": ["A1", "B2"]}"#,
+    )
+    .expect("valid values should parse");
+    assert_eq!(parsed.walls, ["A1", "B2"]);
+
+    let reason = |reply: &str| match PaintWall::parse_from_ai_response(reply) {
+        Err(AiError::ParseFailure { reason, .. }) => reason,
+        other => panic!("expected a parse failure, got {:?}", other),
+    };
+    assert_eq!(
+        reason(r#"{"color": "green", "coverage": 140, "walls": []}"#),
+        "`coverage` is 140, expected a number between 0 and 100"
+    );
+    assert_eq!(
+        reason(r#"{"color": "pink", "coverage": 10, "walls": []}"#),
+        r#"`color` is "pink", expected one of "red", "green", "blue""#
+    );
+    assert!(
+        reason(r#"{"color": "red", "coverage": 10, "walls": ["A1", "hall"]}"#)
+            .starts_with(r#"`walls` is "hall""#)
+    );
+}
+
+#[test]
+fn typed_prompts_show_a_filled_in_example() {
+    use serde_json::json;
+
+    assert_eq!(
+        SpawnAction::schema_example(),
+        Some(json!({"name": "text", "x": 0, "y": 0}))
+    );
+    // Checks and defaults pick the example values
+    assert_eq!(
+        PaintWall::schema_example(),
+        Some(json!({"color": "red", "coverage": 0.0}))
+    );
+    assert_eq!(
+        TagItem::schema_example(),
+        Some(json!({"item": "text", "tags": ["text"], "count": 1, "note": "text"}))
+    );
+    assert_eq!(
+        Patrol::schema_example(),
+        Some(json!({
+            "start": {"x": 0.0, "y": 0.0},
+            "waypoints": [{"x": 0.0, "y": 0.0}],
+            "pace": "Walk"
+        }))
+    );
+    assert_eq!(
+        MoveTo::schema_example(),
+        Some(json!({"x": 0.0, "y": 0.0, "speed": 0.0}))
+    );
+    // Examples parse back into their type; fields a placeholder cannot fit are left out
+    SpawnAction::parse_from_ai_response(&SpawnAction::schema_example().unwrap().to_string())
+        .expect("example should parse");
+    Patrol::parse_from_ai_response(&Patrol::schema_example().unwrap().to_string())
+        .expect("example should parse");
+
+    let prompt = build_typed_prompt::<SpawnAction>("spawn a goblin");
+    assert!(prompt.contains(r#"{"name":"text","x":0,"y":0}"#));
+    assert!(prompt.contains("\"x\": <integer>"));
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+#[serde(rename_all = "kebab-case")]
+enum Stance {
+    HoldGround,
+    FallBack,
+    #[serde(skip)]
+    Internal,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+#[serde(rename_all = "camelCase")]
+struct Command {
+    pub squad_name: String,
+    #[serde(rename = "hp")]
+    pub max_health: u32,
+    pub stance: Stance,
+    #[serde(skip)]
+    pub issued_at: u64,
+}
+
+#[test]
+fn schema_and_payload_follow_serde_names() {
+    let schema = Command::schema_description();
+    assert!(schema.contains(r#""squadName": <string>"#));
+    assert!(schema.contains(r#""hp": <integer>"#));
+    assert!(schema.contains(r#""stance": <one of "hold-ground", "fall-back">"#));
+    assert!(!schema.contains("issued"));
+    assert!(!schema.contains("max_health"));
+
+    let parsed =
+        Command::parse_from_ai_response(r#"{"squadName": "Red", "hp": 40, "stance": "fall-back"}"#)
+            .expect("serde names should parse");
+    assert_eq!(parsed.stance, Stance::FallBack);
+    assert_eq!(parsed.issued_at, 0);
+    assert_eq!(Stance::labels(), Some(&["hold-ground", "fall-back"][..]));
+    assert!(serde_json::to_value(Stance::Internal).is_err());
+    assert_eq!(
+        Stance::parse_from_ai_response("Hold-ground!").unwrap(),
+        Stance::HoldGround
+    );
+
+    let payload = parsed.into_action_payload();
+    assert_eq!(
+        payload.params,
+        serde_json::json!({"squadName": "Red", "hp": 40, "stance": "fall-back"})
+    );
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+#[serde(rename_all = "lowercase")]
+enum Posture {
+    HoldGround,
+    FallBack,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+#[serde(rename_all = "lowercase")]
+struct QuietOrder {
+    pub max_hp: u32,
+    pub posture: Posture,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+#[serde(rename_all = "UPPERCASE")]
+enum Alarm {
+    HighAlert,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+#[serde(rename_all = "UPPERCASE")]
+struct LoudOrder {
+    pub max_hp: u32,
+    pub alarm: Alarm,
+}
+
+#[test]
+fn lowercase_and_uppercase_keep_field_underscores() {
+    assert!(QuietOrder::schema_description().contains(r#""max_hp": <integer>"#));
+    assert_eq!(Posture::labels(), Some(&["holdground", "fallback"][..]));
+    let quiet = QuietOrder::parse_from_ai_response(r#"{"max_hp": 5, "posture": "fallback"}"#)
+        .expect("lowercase names should parse");
+    assert_eq!(quiet.posture, Posture::FallBack);
+
+    assert!(LoudOrder::schema_description().contains(r#""MAX_HP": <integer>"#));
+    assert_eq!(Alarm::labels(), Some(&["HIGHALERT"][..]));
+    let loud = LoudOrder::parse_from_ai_response(r#"{"MAX_HP": 9, "ALARM": "HIGHALERT"}"#)
+        .expect("uppercase names should parse");
+    assert_eq!(loud.max_hp, 9);
+}
+
+/// Implements `Default` itself, which the derive must not clash with.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, AiAction)]
+struct Rest {
+    pub hours: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+#[ai_action(default)]
+struct Wander {
+    pub radius: f32,
+    pub note: String,
+}
+
+#[test]
+fn default_is_only_derived_on_request() {
+    assert_eq!(Rest::default(), Rest { hours: 0 });
+    assert_eq!(
+        Wander::default(),
+        Wander {
+            radius: 0.0,
+            note: String::new()
+        }
+    );
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+struct OpenInventoryAction;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+struct Shout(String);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, AiAction)]
+struct Teleport(f32, f32, String);
+
+#[test]
+fn unit_and_tuple_structs_have_schemas() {
+    assert_eq!(
+        OpenInventoryAction::schema_description(),
+        "JSON object with no fields: {}"
+    );
+    assert_eq!(
+        OpenInventoryAction::parse_from_ai_response("Opening your bag.").unwrap(),
+        OpenInventoryAction
+    );
+    let payload = OpenInventoryAction.into_action_payload();
+    assert_eq!(payload.name, "open_inventory_action");
+    assert_eq!(payload.params, serde_json::json!({}));
+    // Handlers receive the payload params, which serde reads after filling
+    let mut params = payload.params;
+    OpenInventoryAction::fill_missing_params(&mut params);
+    assert_eq!(
+        serde_json::from_value::<OpenInventoryAction>(params).unwrap(),
+        OpenInventoryAction
+    );
+
+    assert_eq!(Shout::schema_description(), "JSON value: <string>");
+    assert_eq!(
+        Shout::parse_from_ai_response(r#""Halt!""#).unwrap(),
+        Shout("Halt!".to_string())
+    );
+    assert_eq!(
+        Shout("Halt!".to_string()).into_action_payload().params,
+        serde_json::json!("Halt!")
+    );
+
+    assert_eq!(
+        Teleport::schema_description(),
+        "JSON array: [<number>, <number>, <string>]"
+    );
+    let parsed = Teleport::parse_from_ai_response(r#"[1.5, 2, "tower"]"#).unwrap();
+    assert_eq!(parsed, Teleport(1.5, 2.0, "tower".to_string()));
+    assert_eq!(
+        parsed.into_action_payload().params,
+        serde_json::json!([1.5, 2.0, "tower"])
+    );
+}
+
+/// Opens a door by name.
+#[derive(Clone, Debug, Serialize, Deserialize, AiAction)]
+#[ai_action(version = 2)]
+struct OpenDoor {
+    pub door: String,
+}
+
+#[test]
+fn derive_emits_action_metadata() {
+    let metadata = OpenDoor::metadata();
+    assert_eq!(metadata.name, "open_door");
+    assert_eq!(metadata.type_name, "OpenDoor");
+    assert_eq!(metadata.version, 2);
+    assert_eq!(metadata.doc, "Opens a door by name.");
+    assert_eq!(metadata.schema(), OpenDoor::schema_description());
+    assert!(
+        metadata
+            .describe()
+            .starts_with("open_door (v2): Opens a door by name.")
+    );
+
+    #[cfg(feature = "inventory")]
+    assert!(
+        bevy_real_ai::actions::all_action_metadata()
+            .iter()
+            .any(|m| m.name == "open_door")
+    );
+}
+
+#[test]
+fn spawn_action_schema_matches_snapshot() {
+    bevy_real_ai::assert_ai_schema!(SpawnAction, "tests/fixtures/spawn_action.schema.json");
+}
+
+#[test]
+fn missing_schema_snapshots_fail_the_check() {
+    if std::env::var_os(bevy_real_ai::test_helpers::UPDATE_AI_SCHEMAS_ENV).is_some() {
+        return;
+    }
+    let path = std::env::temp_dir().join(format!(
+        "bevy_real_ai_missing_schema_{}.json",
+        std::process::id()
+    ));
+    let result = bevy_real_ai::test_helpers::check_ai_schema_snapshot::<SpawnAction>(&path);
+    assert!(result.unwrap_err().contains("UPDATE_AI_SCHEMAS=1"));
+    assert!(!path.exists());
+}